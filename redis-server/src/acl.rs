@@ -0,0 +1,652 @@
+//! Users and what they are allowed to do, set with `ACL SETUSER` or, for the `default` user,
+//! with `requirepass`.
+//!
+//! Like in redis, a client starts out authenticated as the `default` user when that user needs
+//! no password, and has to `AUTH` otherwise. A user can only run the commands its rules allow,
+//! on the keys matching one of its patterns.
+
+use std::{collections::BTreeMap, sync::RwLock};
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::{frame::Frame, glob};
+
+/// The users, by name. There is always a `default` user.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    enabled: bool,
+    // Any password is accepted.
+    nopass: bool,
+    // The SHA-256 digests of the passwords, in hex.
+    passwords: Vec<String>,
+    // Applied in order, starting from no command being allowed: the last rule matching a
+    // command decides whether it is allowed.
+    commands: Vec<(bool, CommandRule)>,
+    // The glob-style patterns of the keys the user can access.
+    keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CommandRule {
+    Category(&'static str),
+    Command(&'static str),
+}
+
+/// Where the keys are among the arguments of a command.
+#[derive(Clone, Copy)]
+enum Keys {
+    None,
+    /// From the `first` argument to the `last` one, counting from the end when negative.
+    Range(usize, isize),
+    /// The number of keys is given right before them, at this position, like with
+    /// `EVAL script numkeys key ...`.
+    Counted(usize),
+    /// Every other argument from the first one, like with `MSET key value [key value ...]`.
+    Pairs,
+    /// The first half of the arguments after `STREAMS`, like with
+    /// `XREAD STREAMS key [key ...] id [id ...]`.
+    Streams,
+    /// The key of `MIGRATE host port key db timeout`, or the arguments after `KEYS` when that
+    /// key is empty.
+    Migrate,
+}
+
+struct CommandSpec {
+    name: &'static str,
+    categories: &'static [&'static str],
+    keys: Keys,
+}
+
+const CATEGORIES: &[&str] = &[
+    "all",
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "list",
+    "hash",
+    "set",
+    "sortedset",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+const ONE_KEY: Keys = Keys::Range(1, 1);
+const ALL_KEYS: Keys = Keys::Range(1, -1);
+
+macro_rules! commands {
+    ($($name:literal: [$($category:literal),*] $keys:expr,)*) => {
+        &[$(CommandSpec { name: $name, categories: &[$($category),*], keys: $keys },)*]
+    };
+}
+
+const COMMANDS: &[CommandSpec] = commands! {
+    "acl": ["admin", "slow", "dangerous"] Keys::None,
+    "append": ["write", "string", "fast"] ONE_KEY,
+    "asking": ["fast", "connection"] Keys::None,
+    "auth": ["fast", "connection"] Keys::None,
+    "bgrewriteaof": ["admin", "slow", "dangerous"] Keys::None,
+    "bgsave": ["admin", "slow", "dangerous"] Keys::None,
+    "blmove": ["write", "list", "slow", "blocking"] Keys::Range(1, 2),
+    "blpop": ["write", "list", "slow", "blocking"] Keys::Range(1, -2),
+    "brpop": ["write", "list", "slow", "blocking"] Keys::Range(1, -2),
+    "client": ["admin", "slow", "dangerous", "connection"] Keys::None,
+    "cluster": ["slow"] Keys::None,
+    "config": ["admin", "slow", "dangerous"] Keys::None,
+    "dbsize": ["keyspace", "read", "fast"] Keys::None,
+    "decr": ["write", "string", "fast"] ONE_KEY,
+    "decrby": ["write", "string", "fast"] ONE_KEY,
+    "del": ["keyspace", "write", "slow"] ALL_KEYS,
+    "discard": ["fast", "transaction"] Keys::None,
+    "dump": ["keyspace", "read", "slow"] ONE_KEY,
+    "echo": ["fast", "connection"] Keys::None,
+    "eval": ["slow", "scripting"] Keys::Counted(2),
+    "evalsha": ["slow", "scripting"] Keys::Counted(2),
+    "exec": ["slow", "transaction"] Keys::None,
+    "exists": ["keyspace", "read", "fast"] ALL_KEYS,
+    "expire": ["keyspace", "write", "fast"] ONE_KEY,
+    "expireat": ["keyspace", "write", "fast"] ONE_KEY,
+    "flushall": ["keyspace", "write", "slow", "dangerous"] Keys::None,
+    "flushdb": ["keyspace", "write", "slow", "dangerous"] Keys::None,
+    "get": ["read", "string", "fast"] ONE_KEY,
+    "getdel": ["write", "string", "fast"] ONE_KEY,
+    "getex": ["write", "string", "fast"] ONE_KEY,
+    "getrange": ["read", "string", "slow"] ONE_KEY,
+    "getset": ["write", "string", "fast"] ONE_KEY,
+    "hdel": ["write", "hash", "fast"] ONE_KEY,
+    "hello": ["fast", "connection"] Keys::None,
+    "hexists": ["read", "hash", "fast"] ONE_KEY,
+    "hget": ["read", "hash", "fast"] ONE_KEY,
+    "hgetall": ["read", "hash", "slow"] ONE_KEY,
+    "hincrby": ["write", "hash", "fast"] ONE_KEY,
+    "hkeys": ["read", "hash", "slow"] ONE_KEY,
+    "hlen": ["read", "hash", "fast"] ONE_KEY,
+    "hmget": ["read", "hash", "fast"] ONE_KEY,
+    "hset": ["write", "hash", "fast"] ONE_KEY,
+    "hvals": ["read", "hash", "slow"] ONE_KEY,
+    "incr": ["write", "string", "fast"] ONE_KEY,
+    "incrby": ["write", "string", "fast"] ONE_KEY,
+    "incrbyfloat": ["write", "string", "fast"] ONE_KEY,
+    "info": ["slow", "dangerous"] Keys::None,
+    "keys": ["keyspace", "read", "slow", "dangerous"] Keys::None,
+    "lastsave": ["fast", "dangerous"] Keys::None,
+    "lindex": ["read", "list", "slow"] ONE_KEY,
+    "linsert": ["write", "list", "slow"] ONE_KEY,
+    "llen": ["read", "list", "fast"] ONE_KEY,
+    "lpop": ["write", "list", "fast"] ONE_KEY,
+    "lpush": ["write", "list", "fast"] ONE_KEY,
+    "lrange": ["read", "list", "slow"] ONE_KEY,
+    "lrem": ["write", "list", "slow"] ONE_KEY,
+    "lset": ["write", "list", "slow"] ONE_KEY,
+    "ltrim": ["write", "list", "slow"] ONE_KEY,
+    "mget": ["read", "string", "fast"] ALL_KEYS,
+    "migrate": ["keyspace", "write", "slow", "dangerous"] Keys::Migrate,
+    "monitor": ["admin", "slow", "dangerous"] Keys::None,
+    "mset": ["write", "string", "slow"] Keys::Pairs,
+    "msetnx": ["write", "string", "slow"] Keys::Pairs,
+    "multi": ["fast", "transaction"] Keys::None,
+    "persist": ["keyspace", "write", "fast"] ONE_KEY,
+    "pexpire": ["keyspace", "write", "fast"] ONE_KEY,
+    "pexpireat": ["keyspace", "write", "fast"] ONE_KEY,
+    "ping": ["fast", "connection"] Keys::None,
+    "psetex": ["write", "string", "slow"] ONE_KEY,
+    "psubscribe": ["pubsub", "slow"] Keys::None,
+    "psync": ["admin", "slow", "dangerous"] Keys::None,
+    "pttl": ["keyspace", "read", "fast"] ONE_KEY,
+    "publish": ["pubsub", "fast"] Keys::None,
+    "pubsub": ["pubsub", "slow"] Keys::None,
+    "punsubscribe": ["pubsub", "slow"] Keys::None,
+    "randomkey": ["keyspace", "read", "slow"] Keys::None,
+    "rename": ["keyspace", "write", "slow"] Keys::Range(1, 2),
+    "renamenx": ["keyspace", "write", "fast"] Keys::Range(1, 2),
+    "replconf": ["admin", "slow", "dangerous"] Keys::None,
+    "replicaof": ["admin", "slow", "dangerous"] Keys::None,
+    "restore": ["keyspace", "write", "slow", "dangerous"] ONE_KEY,
+    "restore-asking": ["keyspace", "write", "slow", "dangerous"] ONE_KEY,
+    "role": ["admin", "fast", "dangerous"] Keys::None,
+    "rpop": ["write", "list", "fast"] ONE_KEY,
+    "rpush": ["write", "list", "fast"] ONE_KEY,
+    "sadd": ["write", "set", "fast"] ONE_KEY,
+    "save": ["admin", "slow", "dangerous"] Keys::None,
+    "scan": ["keyspace", "read", "slow"] Keys::None,
+    "scard": ["read", "set", "fast"] ONE_KEY,
+    "script": ["slow", "scripting"] Keys::None,
+    "sdiff": ["read", "set", "slow"] ALL_KEYS,
+    "sdiffstore": ["write", "set", "slow"] ALL_KEYS,
+    "select": ["fast", "connection"] Keys::None,
+    "set": ["write", "string", "slow"] ONE_KEY,
+    "setex": ["write", "string", "slow"] ONE_KEY,
+    "setnx": ["write", "string", "fast"] ONE_KEY,
+    "setrange": ["write", "string", "slow"] ONE_KEY,
+    "sinter": ["read", "set", "slow"] ALL_KEYS,
+    "sintercard": ["read", "set", "slow"] Keys::Counted(1),
+    "sinterstore": ["write", "set", "slow"] ALL_KEYS,
+    "sismember": ["read", "set", "fast"] ONE_KEY,
+    "slaveof": ["admin", "slow", "dangerous"] Keys::None,
+    "slowlog": ["admin", "slow", "dangerous"] Keys::None,
+    "smembers": ["read", "set", "slow"] ONE_KEY,
+    "smismember": ["read", "set", "fast"] ONE_KEY,
+    "smove": ["write", "set", "fast"] Keys::Range(1, 2),
+    "spop": ["write", "set", "fast"] ONE_KEY,
+    "srandmember": ["read", "set", "slow"] ONE_KEY,
+    "srem": ["write", "set", "fast"] ONE_KEY,
+    "strlen": ["read", "string", "fast"] ONE_KEY,
+    "subscribe": ["pubsub", "slow"] Keys::None,
+    "sunion": ["read", "set", "slow"] ALL_KEYS,
+    "sunionstore": ["write", "set", "slow"] ALL_KEYS,
+    "ttl": ["keyspace", "read", "fast"] ONE_KEY,
+    "type": ["keyspace", "read", "fast"] ONE_KEY,
+    "unsubscribe": ["pubsub", "slow"] Keys::None,
+    "unwatch": ["fast", "transaction"] Keys::None,
+    "watch": ["fast", "transaction"] ALL_KEYS,
+    "xack": ["write", "stream", "fast"] ONE_KEY,
+    "xadd": ["write", "stream", "fast"] ONE_KEY,
+    "xclaim": ["write", "stream", "fast"] ONE_KEY,
+    "xdel": ["write", "stream", "fast"] ONE_KEY,
+    "xgroup": ["write", "stream", "slow"] Keys::Range(2, 2),
+    "xlen": ["read", "stream", "fast"] ONE_KEY,
+    "xpending": ["read", "stream", "slow"] ONE_KEY,
+    "xrange": ["read", "stream", "slow"] ONE_KEY,
+    "xread": ["read", "stream", "slow", "blocking"] Keys::Streams,
+    "xreadgroup": ["write", "stream", "slow", "blocking"] Keys::Streams,
+    "xrevrange": ["read", "stream", "slow"] ONE_KEY,
+    "xtrim": ["write", "stream", "slow"] ONE_KEY,
+    "zadd": ["write", "sortedset", "fast"] ONE_KEY,
+    "zcard": ["read", "sortedset", "fast"] ONE_KEY,
+    "zincrby": ["write", "sortedset", "fast"] ONE_KEY,
+    "zrange": ["read", "sortedset", "slow"] ONE_KEY,
+    "zrangebyscore": ["read", "sortedset", "slow"] ONE_KEY,
+    "zrank": ["read", "sortedset", "fast"] ONE_KEY,
+    "zrem": ["write", "sortedset", "fast"] ONE_KEY,
+    "zscore": ["read", "sortedset", "fast"] ONE_KEY,
+};
+
+impl Default for Acl {
+    fn default() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert(
+            "default".to_owned(),
+            User {
+                enabled: true,
+                nopass: true,
+                commands: vec![(true, CommandRule::Category("all"))],
+                keys: vec!["*".to_owned()],
+                ..User::default()
+            },
+        );
+        Self {
+            users: RwLock::new(users),
+        }
+    }
+}
+
+impl Acl {
+    /// The user clients are authenticated as when they connect, unless they have to `AUTH`.
+    pub fn initial_user(&self) -> Option<String> {
+        let users = self.users.read().unwrap();
+        let user = &users["default"];
+        (user.enabled && user.nopass).then(|| "default".to_owned())
+    }
+
+    /// Whether the `default` user accepts any password, e.g. because there is no `requirepass`.
+    pub fn default_user_needs_no_password(&self) -> bool {
+        self.users.read().unwrap()["default"].nopass
+    }
+
+    /// Sets the only password of the `default` user, or lets it in without one when empty.
+    pub fn set_requirepass(&self, password: &str) {
+        let mut users = self.users.write().unwrap();
+        let user = users
+            .get_mut("default")
+            .expect("the default user is never removed");
+        user.passwords.clear();
+        user.nopass = password.is_empty();
+        if !password.is_empty() {
+            user.passwords.push(digest(password.as_bytes()));
+        }
+    }
+
+    /// Whether the user exists, is enabled and has this password.
+    pub fn authenticate(&self, name: &str, password: &[u8]) -> bool {
+        let users = self.users.read().unwrap();
+        users.get(name).is_some_and(|user| {
+            user.enabled && (user.nopass || user.passwords.contains(&digest(password)))
+        })
+    }
+
+    /// Creates the user if needed, and applies the rules to it. Nothing changes if one of the
+    /// rules is invalid.
+    pub fn set_user(&self, name: &str, rules: &[String]) -> anyhow::Result<()> {
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_default();
+        for rule in rules {
+            user.apply(rule)
+                .map_err(|err| anyhow!("ERR Error in ACL SETUSER modifier '{rule}': {err}"))?;
+        }
+        users.insert(name.to_owned(), user);
+        Ok(())
+    }
+
+    /// Removes the users, returning how many existed.
+    pub fn delete_users(&self, names: &[String]) -> anyhow::Result<usize> {
+        if names.iter().any(|name| name == "default") {
+            bail!("ERR The 'default' user cannot be removed");
+        }
+        let mut users = self.users.write().unwrap();
+        Ok(names
+            .iter()
+            .filter(|name| users.remove(name.as_str()).is_some())
+            .count())
+    }
+
+    /// Describes every user with the rules that recreate it, in the format of `ACL LIST`.
+    pub fn list(&self) -> Vec<String> {
+        self.users
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, user)| format!("user {name} {}", user.describe()))
+            .collect()
+    }
+
+    /// Fails unless `user` is allowed to run the command, given as the frames the client sent,
+    /// on its keys. `None` means the client hasn't authenticated yet.
+    pub fn check(&self, user: Option<&str>, command: &Frame) -> anyhow::Result<()> {
+        let Frame::Array(args) = command else {
+            return Ok(());
+        };
+        let Some(name) = args.first().and_then(arg) else {
+            return Ok(());
+        };
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        // Authenticating is always allowed, and unknown commands fail on their own.
+        let Some(spec) = spec(&name).filter(|_| !matches!(name.as_str(), "auth" | "hello")) else {
+            return Ok(());
+        };
+        let Some(user) = user else {
+            bail!("NOAUTH Authentication required.");
+        };
+
+        let users = self.users.read().unwrap();
+        let Some(permissions) = users.get(user).filter(|user| user.allows_command(spec)) else {
+            bail!("NOPERM User {user} has no permissions to run the '{name}' command");
+        };
+        if !permissions.allows_all_keys()
+            && !keys(spec.keys, args).all(|key| permissions.allows_key(key))
+        {
+            bail!("NOPERM No permissions to access a key");
+        }
+        Ok(())
+    }
+}
+
+impl Default for User {
+    /// A new user: disabled, and allowed nothing.
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            commands: vec![],
+            keys: vec![],
+        }
+    }
+}
+
+impl User {
+    fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".to_owned()],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" => self.allow_commands(true, CommandRule::Category("all")),
+            "nocommands" => self.allow_commands(false, CommandRule::Category("all")),
+            "reset" => *self = Self::default(),
+            _ => return self.apply_with_argument(rule),
+        }
+        Ok(())
+    }
+
+    fn apply_with_argument(&mut self, rule: &str) -> Result<(), &'static str> {
+        let Some(prefix) = rule.chars().next() else {
+            return Err("Syntax error");
+        };
+        let argument = &rule[prefix.len_utf8()..];
+        match prefix {
+            '>' => self.add_password(digest(argument.as_bytes())),
+            '<' => self.remove_password(&digest(argument.as_bytes()))?,
+            '#' | '!' => {
+                if argument.len() != 64
+                    || !argument
+                        .bytes()
+                        .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+                {
+                    return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+                }
+                if prefix == '#' {
+                    self.add_password(argument.to_owned());
+                } else {
+                    self.remove_password(argument)?;
+                }
+            }
+            '~' => {
+                if !self.keys.iter().any(|pattern| pattern == argument) {
+                    self.keys.push(argument.to_owned());
+                }
+            }
+            '+' | '-' => {
+                let allow = prefix == '+';
+                let unknown = "Unknown command or category name in ACL";
+                let rule = match argument.strip_prefix('@') {
+                    Some(category) => CATEGORIES
+                        .iter()
+                        .find(|known| known.eq_ignore_ascii_case(category))
+                        .map(|category| CommandRule::Category(category))
+                        .ok_or(unknown)?,
+                    None => spec(&argument.to_lowercase())
+                        .map(|spec| CommandRule::Command(spec.name))
+                        .ok_or(unknown)?,
+                };
+                self.allow_commands(allow, rule);
+            }
+            _ => return Err("Syntax error"),
+        }
+        Ok(())
+    }
+
+    fn add_password(&mut self, digest: String) {
+        self.nopass = false;
+        if !self.passwords.contains(&digest) {
+            self.passwords.push(digest);
+        }
+    }
+
+    fn remove_password(&mut self, digest: &str) -> Result<(), &'static str> {
+        let before = self.passwords.len();
+        self.passwords.retain(|password| password != digest);
+        if self.passwords.len() == before {
+            return Err("no such password");
+        }
+        Ok(())
+    }
+
+    fn allow_commands(&mut self, allow: bool, rule: CommandRule) {
+        // Every rule before is overridden by one that applies to all the commands.
+        if rule == CommandRule::Category("all") {
+            self.commands.clear();
+        }
+        self.commands.retain(|(_, existing)| *existing != rule);
+        self.commands.push((allow, rule));
+    }
+
+    fn allows_command(&self, spec: &CommandSpec) -> bool {
+        self.commands
+            .iter()
+            .rev()
+            .find(|(_, rule)| match rule {
+                CommandRule::Category(category) => {
+                    *category == "all" || spec.categories.contains(category)
+                }
+                CommandRule::Command(name) => *name == spec.name,
+            })
+            .is_some_and(|(allow, _)| *allow)
+    }
+
+    fn allows_all_keys(&self) -> bool {
+        self.keys.iter().any(|pattern| pattern == "*")
+    }
+
+    fn allows_key(&self, key: &[u8]) -> bool {
+        self.keys
+            .iter()
+            .any(|pattern| glob::matches(pattern.as_bytes(), key))
+    }
+
+    fn describe(&self) -> String {
+        let mut rules = vec![if self.enabled { "on" } else { "off" }.to_owned()];
+        if self.nopass {
+            rules.push("nopass".to_owned());
+        }
+        rules.extend(self.passwords.iter().map(|digest| format!("#{digest}")));
+        rules.extend(self.keys.iter().map(|pattern| format!("~{pattern}")));
+        // Nothing is allowed until a rule says otherwise.
+        if !matches!(
+            self.commands.first(),
+            Some((_, CommandRule::Category("all")))
+        ) {
+            rules.push("-@all".to_owned());
+        }
+        rules.extend(self.commands.iter().map(|(allow, rule)| {
+            let sign = if *allow { '+' } else { '-' };
+            match rule {
+                CommandRule::Category(category) => format!("{sign}@{category}"),
+                CommandRule::Command(name) => format!("{sign}{name}"),
+            }
+        }));
+        rules.join(" ")
+    }
+}
+
+/// The keys the command, given as the frames the client sent, acts on. They decide which node
+/// of a cluster serves the command, see [`Db::check_slot`].
+///
+/// [`Db::check_slot`]: crate::db::Db::check_slot
+pub fn command_keys(command: &Frame) -> Vec<Bytes> {
+    let Frame::Array(args) = command else {
+        return vec![];
+    };
+    let Some(spec) = args
+        .first()
+        .and_then(arg)
+        .and_then(|name| spec(&String::from_utf8_lossy(name).to_lowercase()))
+    else {
+        return vec![];
+    };
+    keys(spec.keys, args).cloned().collect()
+}
+
+/// The categories of the command, e.g. `string` and `write` for `SET`, or none if there is no
+/// such command.
+pub fn command_categories(name: &str) -> &'static [&'static str] {
+    spec(name).map_or(&[], |spec| spec.categories)
+}
+
+fn spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// The keys among the arguments of a command, the name of the command being the first one.
+fn keys(keys: Keys, args: &[Frame]) -> impl Iterator<Item = &Bytes> {
+    let step = if matches!(keys, Keys::Pairs) { 2 } else { 1 };
+    let (first, end) = match keys {
+        Keys::None => (0, 0),
+        Keys::Pairs => (1, args.len() as isize),
+        Keys::Range(first, last) if last < 0 => (first, args.len() as isize + last + 1),
+        Keys::Range(first, last) => (first, last + 1),
+        Keys::Migrate => match args.get(3).and_then(arg) {
+            Some(key) if key.is_empty() => {
+                let first = args
+                    .iter()
+                    .position(|frame| {
+                        arg(frame).is_some_and(|arg| arg.eq_ignore_ascii_case(b"keys"))
+                    })
+                    .map_or(args.len(), |keys| keys + 1);
+                (first, args.len() as isize)
+            }
+            _ => (3, 4),
+        },
+        Keys::Streams => {
+            let first = args
+                .iter()
+                .position(|frame| {
+                    arg(frame).is_some_and(|arg| arg.eq_ignore_ascii_case(b"streams"))
+                })
+                .map_or(args.len(), |streams| streams + 1);
+            (first, (first + (args.len() - first) / 2) as isize)
+        }
+        // A count that isn't a number fails when the command is parsed.
+        Keys::Counted(position) => {
+            let count = args
+                .get(position)
+                .and_then(arg)
+                .and_then(|count| std::str::from_utf8(count).ok()?.parse::<isize>().ok())
+                .unwrap_or(0);
+            (position + 1, position as isize + 1 + count.max(0))
+        }
+    };
+    let end = (end.max(0) as usize).min(args.len());
+    args[first.min(end)..end]
+        .iter()
+        .step_by(step)
+        .filter_map(arg)
+}
+
+fn arg(frame: &Frame) -> Option<&Bytes> {
+    match frame {
+        Frame::BulkString(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Passwords are only kept as their SHA-256 digest, like in redis.
+fn digest(password: &[u8]) -> String {
+    Sha256::digest(password)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::Acl;
+    use crate::frame::Frame;
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::BulkString(Bytes::from(arg.to_string())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_users_are_limited_to_their_commands_and_keys() {
+        let acl = Acl::default();
+        let rules = ["on", ">secret", "~app:*", "+@read", "-hgetall", "+set"];
+        acl.set_user("alice", &rules.map(String::from)).unwrap();
+        assert!(acl.authenticate("alice", b"secret"));
+        assert!(!acl.authenticate("alice", b"wrong"));
+        assert_eq!(
+            acl.list()[0],
+            format!(
+                "user alice on #{} ~app:* -@all +@read -hgetall +set",
+                super::digest(b"secret")
+            )
+        );
+
+        let alice = Some("alice");
+        assert!(acl.check(alice, &command(&["GET", "app:1"])).is_ok());
+        assert!(acl.check(alice, &command(&["set", "app:1", "x"])).is_ok());
+        let err = acl
+            .check(alice, &command(&["HGETALL", "app:1"]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NOPERM User alice has no permissions to run the 'hgetall' command"
+        );
+        assert!(acl.check(alice, &command(&["DEL", "app:1"])).is_err());
+        let err = acl
+            .check(alice, &command(&["EXISTS", "app:1", "other"]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "NOPERM No permissions to access a key");
+
+        assert!(acl.check(None, &command(&["AUTH", "x"])).is_ok());
+        assert!(acl.check(None, &command(&["GET", "x"])).is_err());
+    }
+}
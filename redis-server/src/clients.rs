@@ -0,0 +1,352 @@
+//! The clients connected to the server, which `CLIENT LIST` and `CLIENT KILL` act on, and the
+//! ones that asked with `MONITOR` to be sent every command the server executes.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use crate::frame::{Frame, Protocol};
+
+/// Every client connected to the server, along with what it last reported about itself.
+#[derive(Debug, Clone, Default)]
+pub struct Clients {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    // Ordered by id, which is also the order in which the clients connected.
+    clients: BTreeMap<u64, Client>,
+    monitors: HashMap<u64, UnboundedSender<Frame>>,
+    connections_received: u64,
+    commands_processed: u64,
+}
+
+#[derive(Debug)]
+struct Client {
+    info: ClientInfo,
+    connected: Instant,
+    last_interaction: Instant,
+    killed: Arc<Notify>,
+}
+
+/// What a connection reports about itself, see [`Connection::client_info`].
+///
+/// [`Connection::client_info`]: crate::connection::Connection::client_info
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: Option<SocketAddr>,
+    pub name: Option<Bytes>,
+    pub user: Option<String>,
+    pub db: usize,
+    pub protocol: Protocol,
+    pub channels: usize,
+    pub patterns: usize,
+    /// The number of commands queued, when in a transaction.
+    pub multi: Option<usize>,
+    pub blocked: bool,
+    pub monitor: bool,
+    /// Whether the client turned on `CLIENT TRACKING`.
+    pub tracking: bool,
+    pub replica: bool,
+    /// The name of the last command the client sent.
+    pub command: String,
+}
+
+/// The kinds of clients `CLIENT LIST` and `CLIENT KILL` can filter on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientKind {
+    Normal,
+    Master,
+    Replica,
+    Pubsub,
+}
+
+impl ClientKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "normal" => Some(ClientKind::Normal),
+            "master" => Some(ClientKind::Master),
+            "replica" | "slave" => Some(ClientKind::Replica),
+            "pubsub" => Some(ClientKind::Pubsub),
+            _ => None,
+        }
+    }
+}
+
+impl ClientInfo {
+    pub fn kind(&self) -> ClientKind {
+        if self.replica {
+            ClientKind::Replica
+        } else if self.channels + self.patterns > 0 {
+            ClientKind::Pubsub
+        } else {
+            ClientKind::Normal
+        }
+    }
+
+    /// The line describing the client in the reply to `CLIENT LIST`, in the same format
+    /// as redis.
+    fn describe(&self, age: Duration, idle: Duration) -> String {
+        let mut flags = String::new();
+        for (set, flag) in [
+            (self.replica, 'S'),
+            (self.monitor, 'O'),
+            (self.channels + self.patterns > 0, 'P'),
+            (self.multi.is_some(), 'x'),
+            (self.blocked, 'b'),
+            (self.tracking, 't'),
+        ] {
+            if set {
+                flags.push(flag);
+            }
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        let addr = self.addr.map(|addr| addr.to_string()).unwrap_or_default();
+        let name = self
+            .name
+            .as_ref()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .unwrap_or_default();
+        let multi = self.multi.map_or(-1, |queued| queued as i64);
+        let resp = match self.protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+        format!(
+            "id={} addr={addr} name={name} age={} idle={} flags={flags} db={} sub={} psub={} \
+             multi={multi} cmd={} user={} resp={resp}",
+            self.id,
+            age.as_secs(),
+            idle.as_secs(),
+            self.db,
+            self.channels,
+            self.patterns,
+            self.command,
+            self.user.as_deref().unwrap_or_default(),
+        )
+    }
+}
+
+impl Clients {
+    /// Adds a newly connected client. It is removed once the returned handle is dropped.
+    pub fn register(&self, info: ClientInfo) -> ClientHandle {
+        let mut registry = self.inner.lock().unwrap();
+        let id = info.id;
+        let killed = Arc::new(Notify::new());
+        let now = Instant::now();
+        registry.clients.insert(
+            id,
+            Client {
+                info,
+                connected: now,
+                last_interaction: now,
+                killed: killed.clone(),
+            },
+        );
+        registry.connections_received += 1;
+        ClientHandle {
+            id,
+            clients: self.clone(),
+            killed,
+        }
+    }
+
+    /// Replaces what the client reported about itself, after it sent a command or was unblocked.
+    pub fn update(&self, info: ClientInfo) {
+        let mut registry = self.inner.lock().unwrap();
+        if let Some(client) = registry.clients.get_mut(&info.id) {
+            client.info = info;
+            client.last_interaction = Instant::now();
+        }
+    }
+
+    /// Describes the clients `filter` picks, one per line.
+    pub fn list<F>(&self, filter: F) -> String
+    where
+        F: Fn(&ClientInfo) -> bool,
+    {
+        let registry = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let mut list = String::new();
+        for client in registry.clients.values() {
+            if filter(&client.info) {
+                let age = now - client.connected;
+                let idle = now - client.last_interaction;
+                let _ = writeln!(list, "{}", client.info.describe(age, idle));
+            }
+        }
+        list
+    }
+
+    /// Closes the connections of the clients `filter` picks, and returns how many there were.
+    /// A client is disconnected as soon as it is done with the command it is executing.
+    pub fn kill<F>(&self, filter: F) -> usize
+    where
+        F: Fn(&ClientInfo) -> bool,
+    {
+        let registry = self.inner.lock().unwrap();
+        let mut killed = 0;
+        for client in registry.clients.values() {
+            if filter(&client.info) {
+                client.killed.notify_one();
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    /// The number of connected clients, and how many of them are blocked.
+    pub fn connected(&self) -> (usize, usize) {
+        let registry = self.inner.lock().unwrap();
+        let blocked = registry
+            .clients
+            .values()
+            .filter(|client| client.info.blocked)
+            .count();
+        (registry.clients.len(), blocked)
+    }
+
+    /// The number of connections accepted and of commands executed since the server started.
+    pub fn stats(&self) -> (u64, u64) {
+        let registry = self.inner.lock().unwrap();
+        (registry.connections_received, registry.commands_processed)
+    }
+
+    /// Records that a command was executed in the database numbered `db`, sending it to the
+    /// clients running `MONITOR` unless it is `None`, which administrative commands are.
+    /// `addr` is the client's, and `None` for commands called by scripts.
+    pub fn executed(&self, db: usize, addr: Option<SocketAddr>, command: Option<&Frame>) {
+        let mut registry = self.inner.lock().unwrap();
+        registry.commands_processed += 1;
+        let Some(command) = command.filter(|_| !registry.monitors.is_empty()) else {
+            return;
+        };
+
+        let now = Utc::now();
+        let source = addr.map_or_else(|| "lua".to_owned(), |addr| addr.to_string());
+        let mut line = format!(
+            "{}.{:06} [{db} {source}]",
+            now.timestamp(),
+            now.timestamp_subsec_micros()
+        );
+        if let Frame::Array(args) = command {
+            for arg in args {
+                let arg = match arg {
+                    Frame::BulkString(arg) => &arg[..],
+                    Frame::SimpleString(arg) => arg.as_bytes(),
+                    _ => continue,
+                };
+                line.push(' ');
+                quote(&mut line, arg);
+            }
+        }
+        let line = Frame::SimpleString(line);
+        // Monitors are only removed by their `Monitor`, which holds the receiver.
+        for monitor in registry.monitors.values() {
+            let _ = monitor.send(line.clone());
+        }
+    }
+}
+
+/// Keeps a client registered for as long as its connection is open.
+#[derive(Debug)]
+pub struct ClientHandle {
+    id: u64,
+    clients: Clients,
+    killed: Arc<Notify>,
+}
+
+impl ClientHandle {
+    /// Waits until the client is killed with `CLIENT KILL`.
+    pub async fn killed(&self) {
+        self.killed.notified().await
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        self.clients.inner.lock().unwrap().clients.remove(&self.id);
+    }
+}
+
+/// The state of a connection that ran `MONITOR`.
+pub struct Monitor {
+    id: u64,
+    clients: Clients,
+    receiver: UnboundedReceiver<Frame>,
+}
+
+impl Monitor {
+    pub fn new(id: u64, clients: Clients) -> Self {
+        let (sender, receiver) = unbounded_channel();
+        clients.inner.lock().unwrap().monitors.insert(id, sender);
+        Self {
+            id,
+            clients,
+            receiver,
+        }
+    }
+
+    /// Waits for the next command executed by the server.
+    pub async fn receive(&mut self) -> Frame {
+        // The registry holds the sender until we are dropped.
+        self.receiver
+            .recv()
+            .await
+            .expect("the registry holds a sender")
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.clients.inner.lock().unwrap().monitors.remove(&self.id);
+    }
+}
+
+/// Appends the argument in double quotes, escaping it the way redis does.
+fn quote(line: &mut String, arg: &[u8]) {
+    line.push('"');
+    for &byte in arg {
+        match byte {
+            b'\\' => line.push_str("\\\\"),
+            b'"' => line.push_str("\\\""),
+            b'\n' => line.push_str("\\n"),
+            b'\r' => line.push_str("\\r"),
+            b'\t' => line.push_str("\\t"),
+            0x07 => line.push_str("\\a"),
+            0x08 => line.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => line.push(byte as char),
+            byte => {
+                let _ = write!(line, "\\x{byte:02x}");
+            }
+        }
+    }
+    line.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+
+    #[test]
+    fn test_arguments_are_quoted_like_redis() {
+        let mut line = String::new();
+        quote(&mut line, b"a \"b\"\n\x01\xff");
+        assert_eq!(line, r#""a \"b\"\n\x01\xff""#);
+    }
+}
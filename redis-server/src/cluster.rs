@@ -0,0 +1,166 @@
+//! The cluster bus, which nodes talk to each other on: every node pings the others a few times
+//! a second, telling them which slots it serves and about some of the nodes it knows of, so
+//! that every node ends up knowing all of them. See [`Cluster`] for what is done with that.
+//!
+//! Unlike redis, whose bus has a binary protocol of its own, messages are sent as RESP arrays,
+//! see [`Message::into_frame`].
+//!
+//! [`Cluster`]: crate::db::Cluster
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use log::{error, info, warn};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time::timeout,
+};
+
+use crate::{
+    connection::Connection,
+    db::{Db, Message, MessageKind, Peer},
+};
+
+/// How often every node is pinged.
+const PING_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Serves the nodes that connect to the bus, and pings the others, until the server shuts
+/// down. Returns right away unless in cluster mode.
+pub async fn run_bus(db: Arc<Db>, mut shutdown: watch::Receiver<bool>) {
+    if !db.is_cluster_enabled() {
+        return;
+    }
+    let config = db.config();
+    let listener = match TcpListener::bind((config.bind.as_str(), config.cluster_bus_port())).await
+    {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to listen on the cluster bus: {err}");
+            return;
+        }
+    };
+    info!(
+        "Cluster bus listening on port {}",
+        config.cluster_bus_port()
+    );
+
+    tokio::spawn({
+        let db = db.clone();
+        let shutdown = shutdown.clone();
+        ping_nodes(db, shutdown)
+    });
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_node(db.clone(), stream, shutdown.clone()));
+                }
+                Err(err) => warn!("Failed to accept a node on the cluster bus: {err}"),
+            },
+            _ = shutdown.changed() => return,
+        }
+    }
+}
+
+/// Answers the pings of a node that connected to us.
+async fn serve_node(db: Arc<Db>, stream: TcpStream, mut shutdown: watch::Receiver<bool>) {
+    let Ok(local) = stream.local_addr() else {
+        return;
+    };
+    let ip = local.ip().to_string();
+    let mut link = Connection::new(stream);
+    loop {
+        let frame = tokio::select! {
+            frame = link.read_frame() => frame,
+            _ = shutdown.changed() => return,
+        };
+        let Some(message) = frame.ok().flatten().and_then(Message::from_frame) else {
+            return;
+        };
+        let reply = db.with_cluster(|cluster| {
+            cluster.receive(message, None);
+            cluster.message(MessageKind::Pong, None, &ip)
+        });
+        let Some(reply) = reply else {
+            return;
+        };
+        link.write_frame(reply.into_frame());
+        if link.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Pings every node we know of, over a link kept open between pings, and sends a `MEET` to the
+/// ones we are shaking hands with.
+async fn ping_nodes(db: Arc<Db>, mut shutdown: watch::Receiver<bool>) {
+    let mut links: HashMap<String, Link> = HashMap::new();
+    let mut ticker = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.changed() => return,
+        }
+        let node_timeout = Duration::from_millis(db.config().cluster_node_timeout);
+        let Some(peers) = db.with_cluster(|cluster| {
+            cluster.expire_handshakes(node_timeout);
+            cluster.peers()
+        }) else {
+            return;
+        };
+        links.retain(|id, _| peers.iter().any(|peer| peer.id == *id));
+        for peer in peers {
+            // A node that doesn't answer in time is pinged over a new link next time.
+            let result = timeout(PING_INTERVAL * 4, ping(&db, &mut links, &peer)).await;
+            if let Err(err) = result.unwrap_or_else(|_| Err(anyhow!("timed out"))) {
+                if !peer.handshake {
+                    warn!(
+                        "Failed to ping node {} at {}:{}: {err}",
+                        peer.id, peer.ip, peer.bus_port
+                    );
+                }
+                links.remove(&peer.id);
+            }
+        }
+    }
+}
+
+/// The link with a node, along with the address it reaches us at.
+struct Link {
+    connection: Connection,
+    ip: String,
+}
+
+async fn ping(db: &Db, links: &mut HashMap<String, Link>, peer: &Peer) -> anyhow::Result<()> {
+    if !links.contains_key(&peer.id) {
+        let stream = TcpStream::connect((peer.ip.as_str(), peer.bus_port)).await?;
+        let ip = stream.local_addr()?.ip().to_string();
+        let connection = Connection::new(stream);
+        links.insert(peer.id.clone(), Link { connection, ip });
+    }
+    let link = links.get_mut(&peer.id).expect("the link was just opened");
+
+    let kind = if peer.handshake {
+        MessageKind::Meet
+    } else {
+        MessageKind::Ping
+    };
+    let message = db
+        .with_cluster(|cluster| cluster.message(kind, Some(&peer.id), &link.ip))
+        .ok_or_else(|| anyhow!("cluster mode is disabled"))?;
+    link.connection.write_frame(message.into_frame());
+    link.connection.flush().await?;
+    let reply = link
+        .connection
+        .read_frame()
+        .await?
+        .and_then(Message::from_frame)
+        .ok_or_else(|| anyhow!("the node closed the link or sent an invalid message"))?;
+    db.with_cluster(|cluster| cluster.receive(reply, Some(&peer.id)));
+    // The handshake is over, and the node is pinged under its real id from now on.
+    if peer.handshake {
+        links.remove(&peer.id);
+    }
+    Ok(())
+}
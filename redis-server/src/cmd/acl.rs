@@ -0,0 +1,99 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// Management of the users clients authenticate as, see [`crate::acl::Acl`].
+pub enum Acl {
+    /// Creates the user, or changes an existing one, by applying the rules in order.
+    Setuser {
+        username: String,
+        rules: Vec<String>,
+    },
+    /// Removes the users, disconnecting the clients authenticated as one of them.
+    Deluser(Vec<String>),
+    /// Describes every user with the rules that would recreate it.
+    List,
+    /// The user the current connection is authenticated as.
+    Whoami,
+}
+
+impl Acl {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'acl' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'acl|{subcommand}' command");
+
+        let acl = match subcommand.as_str() {
+            "setuser" => {
+                let username = parser
+                    .next_string()?
+                    .ok_or_else(wrong_number_of_arguments)?;
+                let mut rules = vec![];
+                while let Some(rule) = parser.next_string()? {
+                    rules.push(rule);
+                }
+                return Ok(Acl::Setuser { username, rules });
+            }
+            "deluser" => {
+                let mut usernames = vec![];
+                while let Some(username) = parser.next_string()? {
+                    usernames.push(username);
+                }
+                if usernames.is_empty() {
+                    return Err(wrong_number_of_arguments());
+                }
+                return Ok(Acl::Deluser(usernames));
+            }
+            "list" => Acl::List,
+            "whoami" => Acl::Whoami,
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try ACL HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(acl)
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let frame = match self {
+            Acl::Setuser { username, rules } => {
+                db.acl()
+                    .set_user(&username, &rules)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                Frame::SimpleString("OK".to_owned())
+            }
+            Acl::Deluser(usernames) => {
+                let deleted = db
+                    .acl()
+                    .delete_users(&usernames)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                db.clients().kill(|client| {
+                    client
+                        .user
+                        .as_ref()
+                        .is_some_and(|user| usernames.contains(user))
+                });
+                Frame::Integer(deleted as i64)
+            }
+            Acl::List => Frame::Array(
+                db.acl()
+                    .list()
+                    .into_iter()
+                    .map(|user| Frame::BulkString(Bytes::from(user)))
+                    .collect(),
+            ),
+            Acl::Whoami => match conn.user() {
+                Some(user) => Frame::BulkString(Bytes::from(user.to_owned())),
+                None => Frame::NullBulkString,
+            },
+        };
+        Ok(frame)
+    }
+}
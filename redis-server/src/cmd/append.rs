@@ -0,0 +1,36 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::{setrange::check_string_length, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `APPEND key value`
+pub struct Append {
+    key: String,
+    value: Bytes,
+}
+
+impl Append {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'append' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self { key, value })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_string_data_mut(self.key, |string| {
+            check_string_length(string.len() + self.value.len())?;
+            string.extend_from_slice(&self.value);
+            Ok((string.len(), true))
+        })?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -0,0 +1,21 @@
+use std::io;
+
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `ASKING`
+///
+/// Lets the next command access a slot this node is importing, which is where an `ASK`
+/// redirection sends clients.
+pub struct Asking;
+
+impl Asking {
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        if !db.is_cluster_enabled() {
+            return Err(io::Error::other(
+                "ERR This instance has cluster support disabled",
+            ));
+        }
+        conn.set_asking();
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
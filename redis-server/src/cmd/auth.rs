@@ -0,0 +1,67 @@
+use std::io;
+
+use anyhow::bail;
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `AUTH [username] password`
+///
+/// Authenticates the connection as the user, the `default` one if no username is given.
+pub struct Auth {
+    username: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let Some(first) = parser.next_bytes()? else {
+            bail!("ERR wrong number of arguments for 'auth' command");
+        };
+        let auth = match parser.next_bytes()? {
+            None => Self {
+                username: None,
+                password: first,
+            },
+            Some(password) => Self {
+                username: Some(String::from_utf8_lossy(&first).into_owned()),
+                password,
+            },
+        };
+        if parser.next_bytes()?.is_some() {
+            bail!("ERR syntax error");
+        }
+        Ok(auth)
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let username =
+            match self.username {
+                Some(username) => username,
+                None if db.acl().default_user_needs_no_password() => return Err(io::Error::other(
+                    "ERR AUTH <password> called without any password configured for the default \
+                     user. Are you sure your configuration is correct?",
+                )),
+                None => "default".to_owned(),
+            };
+        authenticate(conn, db, username, &self.password)?;
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
+
+/// Authenticates the connection as the user, for `AUTH` and `HELLO ... AUTH`.
+pub(crate) fn authenticate(
+    conn: &mut Connection,
+    db: &Db,
+    username: String,
+    password: &[u8],
+) -> io::Result<()> {
+    if !db.acl().authenticate(&username, password) {
+        return Err(io::Error::other(
+            "WRONGPASS invalid username-password pair or user is disabled.",
+        ));
+    }
+    conn.set_user(Some(username));
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use std::io;
+
+use crate::{db::Db, frame::Frame};
+
+pub struct Bgrewriteaof;
+
+impl Bgrewriteaof {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        db.background_rewrite_aof()?;
+        Ok(Frame::SimpleString(
+            "Background append only file rewriting started".to_owned(),
+        ))
+    }
+}
@@ -0,0 +1,12 @@
+use std::io;
+
+use crate::{db::Db, frame::Frame};
+
+pub struct Bgsave;
+
+impl Bgsave {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        db.background_save()?;
+        Ok(Frame::SimpleString("Background saving started".to_owned()))
+    }
+}
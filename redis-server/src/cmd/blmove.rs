@@ -0,0 +1,70 @@
+use std::{io, time::Duration};
+
+use super::{parse_timeout, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    connection::Connection,
+    db::{BlockingPop, Db, ListEnd},
+    frame::Frame,
+};
+
+pub struct Blmove {
+    source: String,
+    destination: String,
+    from: ListEnd,
+    to: ListEnd,
+    timeout: Option<Duration>,
+}
+
+impl Blmove {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let source = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a source key but found None"))?;
+        let destination = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a destination key but found None"))?;
+        let from = Self::list_end(parser)?;
+        let to = Self::list_end(parser)?;
+        let timeout = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a timeout but found None"))?;
+        let timeout = parse_timeout(&timeout)?;
+        Ok(Self {
+            source,
+            destination,
+            from,
+            to,
+            timeout,
+        })
+    }
+
+    fn list_end(parser: &mut ParseFrames) -> anyhow::Result<ListEnd> {
+        let end = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected LEFT or RIGHT but found None"))?;
+        match end.to_lowercase().as_str() {
+            "left" => Ok(ListEnd::Left),
+            "right" => Ok(ListEnd::Right),
+            _ => Err(anyhow!("ERR syntax error")),
+        }
+    }
+
+    /// Returns `None` when the client is blocked, in which case the reply is parked on `conn`.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        let destination = Some((self.destination, self.to));
+        match db.pop_or_block(vec![self.source], self.from, destination)? {
+            BlockingPop::Ready(_, value) => Ok(Some(Frame::BulkString(value))),
+            BlockingPop::Blocked(blocked) => {
+                conn.park(Box::pin(async move {
+                    match blocked.wait(self.timeout).await {
+                        Ok(Some((_, value))) => Frame::BulkString(value),
+                        Ok(None) => Frame::Null,
+                        Err(err) => Frame::Error(err.to_string()),
+                    }
+                }));
+                Ok(None)
+            }
+        }
+    }
+}
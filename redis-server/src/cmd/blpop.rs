@@ -0,0 +1,52 @@
+use std::{io, time::Duration};
+
+use super::{parse_timeout, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    connection::Connection,
+    db::{BlockingPop, Db, ListEnd},
+    frame::Frame,
+};
+
+pub struct Blpop {
+    keys: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl Blpop {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let mut keys = vec![];
+        while let Some(key) = parser.next_string()? {
+            keys.push(key);
+        }
+        let timeout = keys
+            .pop()
+            .filter(|_| !keys.is_empty())
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'blpop' command"))?;
+        let timeout = parse_timeout(&timeout)?;
+        Ok(Self { keys, timeout })
+    }
+
+    /// Returns `None` when the client is blocked, in which case the reply is parked on `conn`.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        match db.pop_or_block(self.keys, ListEnd::Left, None)? {
+            BlockingPop::Ready(key, value) => Ok(Some(Frame::Array(vec![
+                Frame::BulkString(key.into()),
+                Frame::BulkString(value),
+            ]))),
+            BlockingPop::Blocked(blocked) => {
+                conn.park(Box::pin(async move {
+                    match blocked.wait(self.timeout).await {
+                        Ok(Some((key, value))) => Frame::Array(vec![
+                            Frame::BulkString(key.into()),
+                            Frame::BulkString(value),
+                        ]),
+                        Ok(None) => Frame::NullArray,
+                        Err(err) => Frame::Error(err.to_string()),
+                    }
+                }));
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{
+    clients::{ClientInfo, ClientKind},
+    connection::Connection,
+    db::Db,
+    frame::{Frame, Protocol},
+    tracking::TrackingOptions,
+};
+
+/// Introspection and management of the client connections.
+pub enum Client {
+    /// The id of the current connection.
+    Id,
+    /// The name of the current connection.
+    Getname,
+    /// Names the current connection, or removes its name when empty.
+    Setname(Bytes),
+    /// Describes every client, or only those of the given kind or with the given ids.
+    List {
+        kind: Option<ClientKind>,
+        ids: Vec<u64>,
+    },
+    /// Closes the connections of the clients matching every filter.
+    Kill(Kill),
+    /// Tells the client when keys it may have cached change, or stops with `None`.
+    Tracking(Option<TrackingOptions>),
+}
+
+pub struct Kill {
+    filters: Vec<Filter>,
+    // Whether the current connection is spared even if it matches.
+    skip_me: bool,
+    // Set for the old `CLIENT KILL addr` form, which replies OK or an error instead of the
+    // number of clients killed.
+    legacy: bool,
+}
+
+enum Filter {
+    Id(u64),
+    Addr(String),
+    Kind(ClientKind),
+    User(String),
+}
+
+impl Filter {
+    fn matches(&self, client: &ClientInfo) -> bool {
+        match self {
+            Filter::Id(id) => client.id == *id,
+            Filter::Addr(addr) => client
+                .addr
+                .is_some_and(|client| client.to_string() == *addr),
+            Filter::Kind(kind) => client.kind() == *kind,
+            Filter::User(user) => client.user.as_ref() == Some(user),
+        }
+    }
+}
+
+impl Client {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'client' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'client|{subcommand}' command");
+
+        let client = match subcommand.as_str() {
+            "id" => Client::Id,
+            "getname" => Client::Getname,
+            "setname" => {
+                let name = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+                if name.iter().any(|byte| !(b'!'..=b'~').contains(byte)) {
+                    bail!(
+                        "ERR Client names cannot contain spaces, newlines or special characters."
+                    );
+                }
+                Client::Setname(name)
+            }
+            "list" => {
+                let mut kind = None;
+                let mut ids = vec![];
+                while let Some(option) = parser.next_string()? {
+                    match option.to_lowercase().as_str() {
+                        "type" => kind = Some(parse_kind(parser)?),
+                        "id" => {
+                            while let Some(id) = parser.next_string()? {
+                                let id = id
+                                    .parse()
+                                    .ok()
+                                    .filter(|id| *id > 0)
+                                    .ok_or_else(|| anyhow!("ERR Invalid client ID"))?;
+                                ids.push(id);
+                            }
+                            if ids.is_empty() {
+                                bail!("ERR syntax error");
+                            }
+                        }
+                        _ => bail!("ERR syntax error"),
+                    }
+                }
+                return Ok(Client::List { kind, ids });
+            }
+            "kill" => return Ok(Client::Kill(Kill::parse(parser)?)),
+            "tracking" => {
+                let on = match parser
+                    .next_string()?
+                    .ok_or_else(wrong_number_of_arguments)?
+                    .to_lowercase()
+                    .as_str()
+                {
+                    "on" => true,
+                    "off" => false,
+                    _ => bail!("ERR syntax error"),
+                };
+                let mut options = TrackingOptions::default();
+                while let Some(option) = parser.next_string()? {
+                    match option.to_lowercase().as_str() {
+                        "bcast" => options.bcast = true,
+                        "prefix" => {
+                            let prefix = parser
+                                .next_bytes()?
+                                .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                            options.prefixes.push(prefix);
+                        }
+                        "noloop" => options.noloop = true,
+                        _ => bail!("ERR syntax error"),
+                    }
+                }
+                if !options.bcast && !options.prefixes.is_empty() {
+                    bail!("ERR PREFIX option requires BCAST mode to be enabled");
+                }
+                return Ok(Client::Tracking(on.then_some(options)));
+            }
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try CLIENT HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(client)
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let frame = match self {
+            Client::Id => Frame::Integer(conn.id() as i64),
+            Client::Getname => match conn.name() {
+                Some(name) => Frame::BulkString(name.clone()),
+                None => Frame::NullBulkString,
+            },
+            Client::Setname(name) => {
+                conn.set_name((!name.is_empty()).then_some(name));
+                Frame::SimpleString("OK".to_owned())
+            }
+            Client::List { kind, ids } => {
+                let list = db.clients().list(|client| {
+                    kind.is_none_or(|kind| client.kind() == kind)
+                        && (ids.is_empty() || ids.contains(&client.id))
+                });
+                Frame::Verbatim {
+                    encoding: "txt".to_owned(),
+                    text: Bytes::from(list),
+                }
+            }
+            Client::Kill(kill) => {
+                let id = conn.id();
+                let killed = db.clients().kill(|client| {
+                    !(kill.skip_me && client.id == id)
+                        && kill.filters.iter().all(|filter| filter.matches(client))
+                });
+                match (kill.legacy, killed) {
+                    (true, 0) => return Err(io::Error::other("ERR No such client")),
+                    (true, _) => Frame::SimpleString("OK".to_owned()),
+                    (false, killed) => Frame::Integer(killed as i64),
+                }
+            }
+            Client::Tracking(options) => {
+                // The invalidation messages are pushed, which RESP2 clients couldn't tell apart
+                // from replies.
+                if options.is_some() && conn.protocol() == Protocol::Resp2 {
+                    return Err(io::Error::other(
+                        "ERR Client side caching requires RESP3, switch with HELLO 3",
+                    ));
+                }
+                conn.set_tracking(db.tracking(), options)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                Frame::SimpleString("OK".to_owned())
+            }
+        };
+        Ok(frame)
+    }
+}
+
+impl Kill {
+    fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let first = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'client|kill' command"))?;
+        let Some(value) = parser.next_string()? else {
+            return Ok(Self {
+                filters: vec![Filter::Addr(first)],
+                skip_me: false,
+                legacy: true,
+            });
+        };
+
+        let mut kill = Self {
+            filters: vec![],
+            skip_me: true,
+            legacy: false,
+        };
+        let mut filter = Some((first, value));
+        while let Some((name, value)) = filter {
+            match name.to_lowercase().as_str() {
+                "id" => {
+                    let id = value
+                        .parse()
+                        .ok()
+                        .filter(|id| *id > 0)
+                        .ok_or_else(|| anyhow!("ERR client-id should be greater than 0"))?;
+                    kill.filters.push(Filter::Id(id));
+                }
+                "addr" => kill.filters.push(Filter::Addr(value)),
+                "user" => kill.filters.push(Filter::User(value)),
+                "type" => {
+                    let kind = ClientKind::parse(&value)
+                        .ok_or_else(|| anyhow!("ERR Unknown client type '{value}'"))?;
+                    kill.filters.push(Filter::Kind(kind));
+                }
+                "skipme" => {
+                    kill.skip_me = match value.to_lowercase().as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => bail!("ERR syntax error"),
+                    }
+                }
+                _ => bail!("ERR syntax error"),
+            }
+            filter = match parser.next_string()? {
+                Some(name) => Some((
+                    name,
+                    parser
+                        .next_string()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?,
+                )),
+                None => None,
+            };
+        }
+        Ok(kill)
+    }
+}
+
+fn parse_kind(parser: &mut ParseFrames) -> anyhow::Result<ClientKind> {
+    let kind = parser
+        .next_string()?
+        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+    ClientKind::parse(&kind).ok_or_else(|| anyhow!("ERR Unknown client type '{kind}'"))
+}
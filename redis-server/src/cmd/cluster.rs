@@ -0,0 +1,243 @@
+use std::{io, net::IpAddr, time::Duration};
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{
+    db::{key_slot, Db, SetSlot, SLOTS},
+    frame::Frame,
+};
+
+/// Inspection and configuration of the cluster, in cluster mode.
+pub enum Cluster {
+    /// Serves the slots from now on.
+    Addslots(Vec<u16>),
+    /// Stops serving the slots, or forgets who serves them.
+    Delslots(Vec<u16>),
+    /// How many keys this node has in the slot.
+    Countkeysinslot(u16),
+    /// Up to `count` of the keys this node has in the slot.
+    Getkeysinslot(u16, usize),
+    /// The state of the cluster as seen from this node.
+    Info,
+    /// The slot of the key.
+    Keyslot(Bytes),
+    /// Adds the node at that address to the cluster.
+    Meet {
+        ip: String,
+        port: u16,
+        bus_port: Option<u16>,
+    },
+    /// The id of this node.
+    Myid,
+    /// Every node, with its address, epoch and slots, the way the cluster config file has them.
+    Nodes,
+    /// Moves a slot between nodes, see [`SetSlot`].
+    Setslot(u16, SetSlot),
+    /// The ranges of slots with the node serving them.
+    Slots,
+}
+
+impl Cluster {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'cluster' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'cluster|{subcommand}' command");
+        let next = |parser: &mut ParseFrames| -> anyhow::Result<String> {
+            parser.next_string()?.ok_or_else(wrong_number_of_arguments)
+        };
+
+        let cluster = match subcommand.as_str() {
+            "addslots" | "delslots" => {
+                let mut slots = vec![parse_slot(&next(parser)?)?];
+                while let Some(slot) = parser.next_string()? {
+                    slots.push(parse_slot(&slot)?);
+                }
+                let mut sorted = slots.clone();
+                sorted.sort_unstable();
+                if let Some(pair) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+                    bail!("ERR Slot {} specified multiple times", pair[0]);
+                }
+                if subcommand == "addslots" {
+                    Cluster::Addslots(slots)
+                } else {
+                    Cluster::Delslots(slots)
+                }
+            }
+            "countkeysinslot" => Cluster::Countkeysinslot(parse_slot(&next(parser)?)?),
+            "getkeysinslot" => {
+                let slot = parse_slot(&next(parser)?)?;
+                let count = next(parser)?
+                    .parse()
+                    .map_err(|_| anyhow!("ERR Invalid number of keys"))?;
+                Cluster::Getkeysinslot(slot, count)
+            }
+            "info" => Cluster::Info,
+            "keyslot" => {
+                let key = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+                Cluster::Keyslot(key)
+            }
+            "meet" => {
+                let ip = next(parser)?;
+                let port = next(parser)?;
+                let bus_port = parser.next_string()?;
+                let invalid = || anyhow!("ERR Invalid node address specified: {ip}:{port}");
+                if ip.parse::<IpAddr>().is_err() {
+                    return Err(invalid());
+                }
+                Cluster::Meet {
+                    port: port.parse().map_err(|_| invalid())?,
+                    bus_port: bus_port
+                        .map(|bus_port| bus_port.parse().map_err(|_| invalid()))
+                        .transpose()?,
+                    ip,
+                }
+            }
+            "myid" => Cluster::Myid,
+            "nodes" => Cluster::Nodes,
+            "setslot" => {
+                let slot = parse_slot(&next(parser)?)?;
+                let state = match next(parser)?.to_lowercase().as_str() {
+                    "importing" => SetSlot::Importing(next(parser)?),
+                    "migrating" => SetSlot::Migrating(next(parser)?),
+                    "stable" => SetSlot::Stable,
+                    "node" => SetSlot::Node(next(parser)?),
+                    _ => bail!(
+                        "ERR Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP"
+                    ),
+                };
+                Cluster::Setslot(slot, state)
+            }
+            "slots" => Cluster::Slots,
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try CLUSTER HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(cluster)
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let ok = || Frame::SimpleString("OK".to_owned());
+        let text = |text: String| Frame::BulkString(Bytes::from(text));
+        let verbatim = |text: String| Frame::Verbatim {
+            encoding: "txt".to_owned(),
+            text: Bytes::from(text),
+        };
+        // The keys in a slot are looked up before the cluster state is locked, since checking
+        // where a command's keys are served locks them the other way around.
+        let keys_in_slot = match &self {
+            Cluster::Countkeysinslot(slot) | Cluster::Getkeysinslot(slot, _) => {
+                db.keys_in_slot(*slot)
+            }
+            Cluster::Setslot(slot, SetSlot::Node(_)) => db.keys_in_slot(*slot),
+            _ => vec![],
+        };
+        let timeout = Duration::from_millis(db.config().cluster_node_timeout);
+        let reply = db.with_cluster(|cluster| match self {
+            Cluster::Addslots(slots) => cluster.add_slots(&slots).map(|_| ok()),
+            Cluster::Delslots(slots) => cluster.delete_slots(&slots).map(|_| ok()),
+            Cluster::Countkeysinslot(_) => Ok(Frame::Integer(keys_in_slot.len() as i64)),
+            Cluster::Getkeysinslot(_, count) => Ok(Frame::Array(
+                keys_in_slot
+                    .into_iter()
+                    .take(count)
+                    .map(|key| Frame::BulkString(Bytes::from(key)))
+                    .collect(),
+            )),
+            Cluster::Info => {
+                let myself = cluster.myself();
+                let size = cluster
+                    .nodes()
+                    .filter(|node| !cluster.slot_ranges(&node.id).is_empty())
+                    .count();
+                let fields = [
+                    (
+                        "cluster_state",
+                        if cluster.is_ok() { "ok" } else { "fail" }.to_owned(),
+                    ),
+                    (
+                        "cluster_slots_assigned",
+                        cluster.assigned_slots().to_string(),
+                    ),
+                    ("cluster_slots_ok", cluster.assigned_slots().to_string()),
+                    ("cluster_slots_pfail", "0".to_owned()),
+                    ("cluster_slots_fail", "0".to_owned()),
+                    (
+                        "cluster_known_nodes",
+                        cluster
+                            .nodes()
+                            .filter(|node| !node.handshake)
+                            .count()
+                            .to_string(),
+                    ),
+                    ("cluster_size", size.to_string()),
+                    ("cluster_current_epoch", cluster.current_epoch().to_string()),
+                    ("cluster_my_epoch", myself.config_epoch.to_string()),
+                ];
+                Ok(verbatim(
+                    fields
+                        .iter()
+                        .map(|(name, value)| format!("{name}:{value}\r\n"))
+                        .collect(),
+                ))
+            }
+            Cluster::Keyslot(key) => Ok(Frame::Integer(key_slot(&key).into())),
+            Cluster::Meet { ip, port, bus_port } => {
+                let bus_port = bus_port.unwrap_or_else(|| port.wrapping_add(10000));
+                cluster.meet(&ip, port, bus_port);
+                Ok(ok())
+            }
+            Cluster::Myid => Ok(text(cluster.myself().id.clone())),
+            Cluster::Nodes => Ok(verbatim(cluster.describe_nodes(timeout, true))),
+            Cluster::Setslot(slot, state) => cluster
+                .set_slot(slot, state, !keys_in_slot.is_empty())
+                .map(|_| ok()),
+            Cluster::Slots => {
+                let mut ranges: Vec<_> = cluster
+                    .nodes()
+                    .flat_map(|node| {
+                        cluster
+                            .slot_ranges(&node.id)
+                            .into_iter()
+                            .map(move |range| (range, node))
+                    })
+                    .collect();
+                ranges.sort_by_key(|(range, _)| *range);
+                Ok(Frame::Array(
+                    ranges
+                        .into_iter()
+                        .map(|((first, last), node)| {
+                            Frame::Array(vec![
+                                Frame::Integer(first.into()),
+                                Frame::Integer(last.into()),
+                                Frame::Array(vec![
+                                    text(node.ip.clone()),
+                                    Frame::Integer(node.port.into()),
+                                    text(node.id.clone()),
+                                ]),
+                            ])
+                        })
+                        .collect(),
+                ))
+            }
+        });
+        match reply {
+            Some(reply) => reply.map_err(io::Error::other),
+            None => Err(io::Error::other(
+                "ERR This instance has cluster support disabled",
+            )),
+        }
+    }
+}
+
+fn parse_slot(slot: &str) -> anyhow::Result<u16> {
+    slot.parse()
+        .ok()
+        .filter(|slot| usize::from(*slot) < SLOTS)
+        .ok_or_else(|| anyhow!("ERR Invalid or out of range slot"))
+}
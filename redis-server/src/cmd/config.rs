@@ -0,0 +1,98 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::ParseFrames;
+
+/// Reading and changing the server's parameters at runtime.
+pub enum Config {
+    /// The parameters matching any of the glob-style patterns.
+    Get(Vec<String>),
+    /// Changes every parameter, or none of them if one of the values is invalid.
+    Set(Vec<(String, String)>),
+    /// Writes the current parameters to the config file the server was started with.
+    Rewrite,
+}
+
+impl Config {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'config' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'config|{subcommand}' command");
+
+        let config = match subcommand.as_str() {
+            "get" => {
+                let mut patterns = vec![];
+                while let Some(pattern) = parser.next_string()? {
+                    patterns.push(pattern);
+                }
+                if patterns.is_empty() {
+                    return Err(wrong_number_of_arguments());
+                }
+                return Ok(Config::Get(patterns));
+            }
+            "set" => {
+                let mut parameters = vec![];
+                while let Some(name) = parser.next_string()? {
+                    let value = parser
+                        .next_string()?
+                        .ok_or_else(wrong_number_of_arguments)?;
+                    parameters.push((name, value));
+                }
+                if parameters.is_empty() {
+                    return Err(wrong_number_of_arguments());
+                }
+                return Ok(Config::Set(parameters));
+            }
+            "rewrite" => Config::Rewrite,
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try CONFIG HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(config)
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = match self {
+            Config::Get(patterns) => Frame::Map(
+                db.config()
+                    .get(&patterns)
+                    .into_iter()
+                    .map(|(name, value)| {
+                        (
+                            Frame::BulkString(Bytes::from_static(name.as_bytes())),
+                            Frame::BulkString(value.into()),
+                        )
+                    })
+                    .collect(),
+            ),
+            Config::Set(parameters) => {
+                db.update_config(|config| {
+                    for (i, (name, value)) in parameters.iter().enumerate() {
+                        if parameters[..i]
+                            .iter()
+                            .any(|(other, _)| other.eq_ignore_ascii_case(name))
+                        {
+                            bail!("ERR CONFIG SET failed (possibly related to argument '{name}') - duplicate parameter");
+                        }
+                        config.set(name, value)?;
+                    }
+                    Ok(())
+                })?;
+                Frame::SimpleString("OK".to_owned())
+            }
+            Config::Rewrite => {
+                db.config().rewrite()?;
+                Frame::SimpleString("OK".to_owned())
+            }
+        };
+        Ok(frame)
+    }
+}
@@ -0,0 +1,16 @@
+use std::io;
+
+use crate::{db::Db, frame::Frame};
+
+/// `DBSIZE`
+///
+/// The number of keys in the selected database. Like in redis, keys that have expired but
+/// haven't been removed yet are counted.
+pub struct Dbsize;
+
+impl Dbsize {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_data(|data| data.len());
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -1,6 +1,6 @@
 use std::io;
 
-use crate::{connection::Connection, db::Db, frame::Frame};
+use crate::{db::Db, frame::Frame};
 
 use super::ParseFrames;
 
@@ -17,9 +17,9 @@ impl Decr {
         Ok(Self { key })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
-        let new_value = db.with_integer_data_mut(self.key.clone(), |val| val - 1)?;
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let new_value = db.with_integer_data_mut(self.key.clone(), |val| val.checked_sub(1))?;
 
-        conn.write_frame(Frame::Integer(new_value))
+        Ok(Frame::Integer(new_value))
     }
 }
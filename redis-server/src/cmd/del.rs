@@ -1,6 +1,6 @@
 use std::io;
 
-use crate::{connection::Connection, db::Db, frame::Frame};
+use crate::{db::Db, frame::Frame};
 
 use super::ParseFrames;
 
@@ -17,7 +17,7 @@ impl Del {
         Ok(Self { keys })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
         let delete_count = db.with_data_mut(|data| {
             self.keys.iter().fold(0, |acc, key| {
                 if data.remove(key).is_some() {
@@ -28,6 +28,6 @@ impl Del {
             })
         });
         let frame = Frame::Integer(delete_count);
-        conn.write_frame(frame)
+        Ok(frame)
     }
 }
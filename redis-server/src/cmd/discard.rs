@@ -0,0 +1,14 @@
+use std::io;
+
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+pub struct Discard;
+
+impl Discard {
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        conn.take_transaction()
+            .ok_or_else(|| io::Error::other("ERR DISCARD without MULTI"))?;
+        conn.watched_keys_mut().unwatch(db);
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
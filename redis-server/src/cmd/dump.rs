@@ -0,0 +1,30 @@
+use std::io;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// `DUMP key`
+///
+/// The value of the key serialized the way redis does, which `RESTORE` turns back into a key.
+pub struct Dump {
+    key: String,
+}
+
+impl Dump {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'dump' command"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        Ok(match db.dump(&self.key) {
+            Some((payload, _)) => Frame::BulkString(Bytes::from(payload)),
+            None => Frame::Null,
+        })
+    }
+}
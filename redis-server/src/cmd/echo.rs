@@ -1,10 +1,8 @@
-use std::io;
-
 use bytes::Bytes;
 
 use super::ParseFrames;
+use crate::cmd::anyhow;
 use crate::frame::Frame;
-use crate::{cmd::anyhow, connection::Connection};
 
 pub struct Echo {
     message: Bytes,
@@ -18,9 +16,7 @@ impl Echo {
         Ok(Self { message })
     }
 
-    pub fn execute(self, conn: &mut Connection) -> io::Result<()> {
-        let frame = Frame::new_bulk_string(self.message);
-
-        conn.write_frame(frame)
+    pub fn execute(self) -> Frame {
+        Frame::new_bulk_string(self.message)
     }
 }
@@ -0,0 +1,66 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use crate::{connection::Connection, db::Db, frame::Frame, scripting};
+
+use super::ParseFrames;
+
+enum Script {
+    Source(Bytes),
+    Sha(String),
+}
+
+/// `EVAL` and `EVALSHA`.
+pub struct Eval {
+    script: Script,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+}
+
+impl Eval {
+    pub fn parse(parser: &mut ParseFrames, by_sha: bool) -> anyhow::Result<Self> {
+        let command = if by_sha { "evalsha" } else { "eval" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{command}' command");
+
+        let script = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let script = if by_sha {
+            Script::Sha(String::from_utf8_lossy(&script).into_owned())
+        } else {
+            Script::Source(script)
+        };
+        let numkeys = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+
+        let mut args = vec![];
+        while let Some(arg) = parser.next_bytes()? {
+            args.push(arg);
+        }
+        if numkeys < 0 {
+            bail!("ERR Number of keys can't be negative");
+        }
+        if numkeys as usize > args.len() {
+            bail!("ERR Number of keys can't be greater than number of args");
+        }
+        let keys = args.drain(..numkeys as usize).collect();
+        Ok(Self { script, keys, args })
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let script = match self.script {
+            Script::Source(script) => {
+                // Like redis, scripts sent with `EVAL` can then be called with `EVALSHA`.
+                db.scripts().load(script.clone());
+                script
+            }
+            Script::Sha(sha) => db
+                .scripts()
+                .get(&sha)
+                .ok_or_else(|| io::Error::other("NOSCRIPT No matching script. Please use EVAL."))?,
+        };
+        db.log_transaction(|| scripting::run(&script, self.keys, self.args, conn, db))
+    }
+}
@@ -0,0 +1,71 @@
+use std::io;
+
+use super::Propagation;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+pub struct Exec;
+
+impl Exec {
+    /// Executes the queued commands without any other command running in between, and replies
+    /// with their replies. Nothing is executed if one of the watched keys was modified.
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let transaction = conn
+            .take_transaction()
+            .ok_or_else(|| io::Error::other("ERR EXEC without MULTI"))?;
+        let watched_keys = conn.watched_keys_mut();
+        let modified = watched_keys.is_modified();
+        watched_keys.unwatch(db);
+
+        if transaction.failed {
+            return Err(io::Error::other(
+                "EXECABORT Transaction discarded because of previous errors.",
+            ));
+        }
+        if modified {
+            return Ok(Frame::NullArray);
+        }
+
+        let replies = db.log_transaction(|| {
+            transaction
+                .commands
+                .into_iter()
+                .map(|(command, frame)| {
+                    // The transaction can `SELECT` another database halfway through.
+                    let db = &db.select(conn.db_index());
+                    if let Err(err) = command.make_room(db) {
+                        return Frame::Error(err.to_string());
+                    }
+                    let timeout_reply = command.timeout_reply();
+                    let monitored = frame
+                        .as_ref()
+                        .filter(|_| command.is_monitored())
+                        .map(|frame| command.redact(frame).into_owned());
+                    let propagation = command.propagation();
+                    let execute = || match &frame {
+                        Some(frame) => command.execute_and_notify(conn, db, frame),
+                        None => command.execute(conn, db),
+                    };
+                    let reply = match (&frame, propagation) {
+                        (Some(frame), Propagation::Verbatim) => {
+                            db.log_verbatim(frame.clone(), execute)
+                        }
+                        _ => execute(),
+                    };
+                    db.clients()
+                        .executed(db.index(), conn.addr(), monitored.as_ref());
+                    match reply {
+                        Ok(Some(reply)) => reply,
+                        // Blocking commands don't block within a transaction, they time out
+                        // right away instead.
+                        Ok(None) => {
+                            conn.take_parked();
+                            timeout_reply
+                        }
+                        Err(err) => Frame::Error(err.to_string()),
+                    }
+                })
+                .collect()
+        });
+        Ok(Frame::Array(replies))
+    }
+}
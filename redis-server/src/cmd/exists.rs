@@ -1,6 +1,6 @@
 use std::io;
 
-use crate::{connection::Connection, db::Db, frame::Frame};
+use crate::{db::Db, frame::Frame};
 
 use super::ParseFrames;
 
@@ -17,7 +17,7 @@ impl Exists {
         Ok(Self { keys })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
         let count = db.with_data(|data| {
             data.iter().fold(0, |acc, (key, _)| {
                 if self.keys.contains(key) {
@@ -28,6 +28,6 @@ impl Exists {
             })
         });
         let frame = Frame::Integer(count);
-        conn.write_frame(frame)
+        Ok(frame)
     }
 }
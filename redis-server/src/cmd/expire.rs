@@ -0,0 +1,100 @@
+use std::io;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{Db, ExpireCondition},
+    frame::Frame,
+};
+
+/// How the time given to an `EXPIRE` variant is interpreted.
+#[derive(Debug, Clone, Copy)]
+pub enum Expiry {
+    /// `EXPIRE`: seconds from now.
+    Seconds,
+    /// `PEXPIRE`: milliseconds from now.
+    Milliseconds,
+    /// `EXPIREAT`: a unix timestamp in seconds.
+    UnixSeconds,
+    /// `PEXPIREAT`: a unix timestamp in milliseconds.
+    UnixMilliseconds,
+}
+
+impl Expiry {
+    fn command_name(self) -> &'static str {
+        match self {
+            Expiry::Seconds => "expire",
+            Expiry::Milliseconds => "pexpire",
+            Expiry::UnixSeconds => "expireat",
+            Expiry::UnixMilliseconds => "pexpireat",
+        }
+    }
+
+    /// Returns `None` if the time is out of range.
+    fn deadline(self, time: i64) -> Option<DateTime<Utc>> {
+        match self {
+            Expiry::Seconds => Utc::now().checked_add_signed(TimeDelta::try_seconds(time)?),
+            Expiry::Milliseconds => {
+                Utc::now().checked_add_signed(TimeDelta::try_milliseconds(time)?)
+            }
+            Expiry::UnixSeconds => DateTime::from_timestamp(time, 0),
+            Expiry::UnixMilliseconds => DateTime::from_timestamp_millis(time),
+        }
+    }
+}
+
+pub struct Expire {
+    key: String,
+    time: i64,
+    expiry: Expiry,
+    condition: ExpireCondition,
+}
+
+impl Expire {
+    pub fn parse(parser: &mut ParseFrames, expiry: Expiry) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let time = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a time but found None"))?;
+
+        let mut condition = ExpireCondition::Always;
+        while let Some(option) = parser.next_string()? {
+            let option = match option.to_lowercase().as_str() {
+                "nx" => ExpireCondition::Nx,
+                "xx" => ExpireCondition::Xx,
+                "gt" => ExpireCondition::Gt,
+                "lt" => ExpireCondition::Lt,
+                option => return Err(anyhow!("ERR Unsupported option {option}")),
+            };
+            if !matches!(condition, ExpireCondition::Always) {
+                return Err(anyhow!(
+                    "ERR NX and XX, GT or LT options at the same time are not compatible"
+                ));
+            }
+            condition = option;
+        }
+
+        Ok(Self {
+            key,
+            time,
+            expiry,
+            condition,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let Some(at) = self.expiry.deadline(self.time) else {
+            return Err(io::Error::other(format!(
+                "ERR invalid expire time in '{}' command",
+                self.expiry.command_name()
+            )));
+        };
+
+        let updated = db.expire(&self.key, at, self.condition);
+        Ok(Frame::Integer(updated as i64))
+    }
+}
@@ -0,0 +1,33 @@
+use std::io;
+
+use anyhow::anyhow;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// `FLUSHDB [ASYNC | SYNC]` and `FLUSHALL [ASYNC | SYNC]`
+///
+/// Removes every key of the selected database, or of all of them with `FLUSHALL`. Keys are
+/// always removed right away, since dropping them is cheap.
+pub struct Flush {
+    all: bool,
+}
+
+impl Flush {
+    pub fn parse(parser: &mut ParseFrames, all: bool) -> anyhow::Result<Self> {
+        if let Some(mode) = parser.next_string()? {
+            if !matches!(mode.to_lowercase().as_str(), "async" | "sync") {
+                return Err(anyhow!("ERR syntax error"));
+            }
+        }
+        if parser.next_string()?.is_some() {
+            return Err(anyhow!("ERR syntax error"));
+        }
+        Ok(Self { all })
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        db.flush(self.all);
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
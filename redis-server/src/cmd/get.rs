@@ -1,7 +1,11 @@
 use std::io;
 
 use super::ParseFrames;
-use crate::{cmd::anyhow, connection::Connection, db::Db, frame::Frame};
+use crate::{
+    cmd::anyhow,
+    db::{Db, WRONG_TYPE},
+    frame::Frame,
+};
 pub struct Get {
     key: String,
 }
@@ -14,12 +18,14 @@ impl Get {
         Ok(Self { key })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
-        let frame = db.with_data(|data| {
-            data.get(&self.key)
-                .map(|value| Frame::BulkString(value.clone()))
-                .unwrap_or(Frame::Null)
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_data(|data| match data.get(&self.key) {
+            Some(value) => match value.as_string() {
+                Some(value) => Frame::BulkString(value),
+                None => Frame::Error(WRONG_TYPE.to_owned()),
+            },
+            None => Frame::Null,
         });
-        conn.write_frame(frame)
+        Ok(frame)
     }
 }
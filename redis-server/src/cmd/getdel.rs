@@ -0,0 +1,33 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{wrong_type, Db},
+    frame::Frame,
+};
+
+/// `GETDEL key`
+pub struct Getdel {
+    key: String,
+}
+
+impl Getdel {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'getdel' command"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        db.with_data_mut(|data| {
+            let Some(value) = data.get(&self.key) else {
+                return Ok(Frame::Null);
+            };
+            let value = value.as_string().ok_or_else(wrong_type)?;
+            data.remove(&self.key);
+            Ok(Frame::BulkString(value))
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::{io, time::Duration};
+
+use chrono::{TimeDelta, Utc};
+
+use super::{set::parse_expiry, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`
+pub struct Getex {
+    key: String,
+    change: Option<TtlChange>,
+}
+
+enum TtlChange {
+    Expire(Duration),
+    Persist,
+}
+
+impl Getex {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'getex' command"))?;
+
+        let mut change = None;
+        while let Some(option) = parser.next_string()? {
+            let option = option.to_lowercase();
+            match option.as_str() {
+                _ if change.is_some() => return Err(anyhow!("ERR syntax error")),
+                "ex" | "px" | "exat" | "pxat" => {
+                    let time = parser
+                        .next_integer()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    change = Some(TtlChange::Expire(parse_expiry(&option, time, "getex")?));
+                }
+                "persist" => change = Some(TtlChange::Persist),
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+        Ok(Self { key, change })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let (expire, persist) = match self.change {
+            Some(TtlChange::Expire(duration)) => {
+                let at = TimeDelta::from_std(duration)
+                    .ok()
+                    .and_then(|duration| Utc::now().checked_add_signed(duration))
+                    .ok_or_else(|| {
+                        io::Error::other("ERR invalid expire time in 'getex' command")
+                    })?;
+                (Some(at), false)
+            }
+            Some(TtlChange::Persist) => (None, true),
+            None => (None, false),
+        };
+        let value = db.getex(&self.key, expire, persist)?;
+        Ok(value.map_or(Frame::Null, Frame::BulkString))
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{wrong_type, Db},
+    frame::Frame,
+};
+
+/// `GETRANGE key start end`
+pub struct Getrange {
+    key: String,
+    start: i64,
+    end: i64,
+}
+
+impl Getrange {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'getrange' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let start = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let end = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        Ok(Self { key, start, end })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let string = db.with_data(|data| match data.get(&self.key) {
+            Some(value) => value.as_string().ok_or_else(wrong_type),
+            None => Ok(Bytes::new()),
+        })?;
+        let range = substring(self.start, self.end, string.len());
+        Ok(Frame::BulkString(
+            range.map_or_else(Bytes::new, |(start, end)| string.slice(start..=end)),
+        ))
+    }
+}
+
+/// Unlike [`super::resolve_range`], an `end` before the start of the string is clamped to its
+/// first byte, as redis does for strings.
+fn substring(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    if (start < 0 && end < 0 && start > end) || len == 0 {
+        return None;
+    }
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, 3, Some((0, 3)))]
+    #[case(-3, -1, Some((2, 4)))]
+    #[case(0, -100, Some((0, 0)))]
+    #[case(10, 100, None)]
+    #[case(-1, -5, None)]
+    #[case(3, 1, None)]
+    fn test_substring(
+        #[case] start: i64,
+        #[case] end: i64,
+        #[case] expected: Option<(usize, usize)>,
+    ) {
+        assert_eq!(super::substring(start, end, 5), expected);
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hdel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl Hdel {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut fields = vec![];
+        while let Some(field) = parser.next_bytes()? {
+            fields.push(field);
+        }
+        if fields.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'hdel' command"));
+        }
+        Ok(Self { key, fields })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let removed = db.with_hash_data_mut(self.key, |hash| {
+            let removed = self
+                .fields
+                .iter()
+                .filter(|field| hash.remove(*field).is_some())
+                .count();
+            (removed, removed > 0)
+        })?;
+        Ok(Frame::Integer(removed as i64))
+    }
+}
@@ -0,0 +1,108 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use super::{auth::authenticate, ParseFrames};
+use crate::{
+    connection::Connection,
+    db::Db,
+    frame::{Frame, Protocol},
+};
+
+/// The version of redis whose behaviour is implemented, as reported to clients.
+pub(crate) const VERSION: &str = "7.2.0";
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+///
+/// Switches the connection to the given protocol version, and replies with details about
+/// the server in that version.
+pub struct Hello {
+    protocol: Option<Protocol>,
+    auth: Option<(String, Bytes)>,
+    name: Option<Bytes>,
+}
+
+impl Hello {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let protocol = match parser.next_integer() {
+            Ok(None) => None,
+            Ok(Some(2)) => Some(Protocol::Resp2),
+            Ok(Some(3)) => Some(Protocol::Resp3),
+            Ok(Some(_)) => bail!("NOPROTO unsupported protocol version"),
+            Err(_) => bail!("ERR Protocol version is not an integer or out of range"),
+        };
+
+        let mut hello = Self {
+            protocol,
+            auth: None,
+            name: None,
+        };
+        while let Some(option) = parser.next_string()? {
+            let syntax_error = || anyhow!("ERR Syntax error in HELLO option '{option}'");
+            match option.to_lowercase().as_str() {
+                "auth" => {
+                    let username = parser.next_string()?.ok_or_else(syntax_error)?;
+                    let password = parser.next_bytes()?.ok_or_else(syntax_error)?;
+                    hello.auth = Some((username, password));
+                }
+                "setname" => {
+                    hello.name = Some(parser.next_bytes()?.ok_or_else(syntax_error)?);
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(hello)
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        match self.auth {
+            Some((username, password)) => authenticate(conn, db, username, &password)?,
+            None if conn.user().is_none() => {
+                return Err(io::Error::other(
+                    "NOAUTH HELLO must be called with the client already authenticated, \
+                     otherwise the HELLO <proto> AUTH <user> <pass> option can be used to \
+                     authenticate the client and select the RESP protocol version at the same \
+                     time",
+                ))
+            }
+            None => {}
+        }
+        if let Some(name) = self.name {
+            if name.iter().any(|&byte| !(b'!'..=b'~').contains(&byte)) {
+                return Err(io::Error::other(
+                    "ERR Client names cannot contain spaces, newlines or special characters.",
+                ));
+            }
+            conn.set_name((!name.is_empty()).then_some(name));
+        }
+        if let Some(protocol) = self.protocol {
+            conn.set_protocol(protocol);
+        }
+
+        let proto = match conn.protocol() {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+        let field = |name: &'static str| Frame::BulkString(Bytes::from_static(name.as_bytes()));
+        let mode = if db.is_cluster_enabled() {
+            "cluster"
+        } else {
+            "standalone"
+        };
+        let role = if db.with_replication(|replication| replication.master().is_some()) {
+            "replica"
+        } else {
+            "master"
+        };
+        Ok(Frame::Map(vec![
+            (field("server"), field("redis")),
+            (field("version"), field(VERSION)),
+            (field("proto"), Frame::Integer(proto)),
+            (field("id"), Frame::Integer(conn.id() as i64)),
+            (field("mode"), field(mode)),
+            (field("role"), field(role)),
+            (field("modules"), Frame::Array(vec![])),
+        ]))
+    }
+}
@@ -0,0 +1,30 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hexists {
+    key: String,
+    field: Bytes,
+}
+
+impl Hexists {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let field = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a field but found None"))?;
+        Ok(Self { key, field })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let exists = db.with_hash_data(&self.key, |hash| {
+            hash.is_some_and(|hash| hash.contains_key(&self.field))
+        })?;
+        Ok(Frame::Integer(exists as i64))
+    }
+}
@@ -0,0 +1,32 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hget {
+    key: String,
+    field: Bytes,
+}
+
+impl Hget {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let field = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a field but found None"))?;
+        Ok(Self { key, field })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_hash_data(&self.key, |hash| {
+            hash.and_then(|hash| hash.get(&self.field).cloned())
+                .map(Frame::BulkString)
+                .unwrap_or(Frame::Null)
+        })?;
+        Ok(frame)
+    }
+}
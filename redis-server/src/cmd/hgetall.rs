@@ -0,0 +1,32 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hgetall {
+    key: String,
+}
+
+impl Hgetall {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let pairs = db.with_hash_data(&self.key, |hash| {
+            hash.into_iter()
+                .flatten()
+                .map(|(field, value)| {
+                    (
+                        Frame::BulkString(field.clone()),
+                        Frame::BulkString(value.clone()),
+                    )
+                })
+                .collect()
+        })?;
+        Ok(Frame::Map(pairs))
+    }
+}
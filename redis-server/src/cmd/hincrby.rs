@@ -0,0 +1,57 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{parse_integer, Db},
+    frame::Frame,
+};
+
+pub struct Hincrby {
+    key: String,
+    field: Bytes,
+    increment: i64,
+}
+
+impl Hincrby {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let field = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a field but found None"))?;
+        let increment = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected an increment but found None"))?;
+        Ok(Self {
+            key,
+            field,
+            increment,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_hash_data_mut(self.key, |hash| {
+            let current = match hash.get(&self.field) {
+                Some(value) => match parse_integer(value) {
+                    Some(value) => value,
+                    None => {
+                        let err = Frame::Error("ERR hash value is not an integer".to_owned());
+                        return (err, false);
+                    }
+                },
+                None => 0,
+            };
+            let Some(new_value) = current.checked_add(self.increment) else {
+                let err = Frame::Error("ERR increment or decrement would overflow".to_owned());
+                return (err, false);
+            };
+            hash.insert(self.field, Bytes::from(new_value.to_string()));
+            (Frame::Integer(new_value), true)
+        })?;
+        Ok(frame)
+    }
+}
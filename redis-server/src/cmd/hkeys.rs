@@ -0,0 +1,28 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hkeys {
+    key: String,
+}
+
+impl Hkeys {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let fields = db.with_hash_data(&self.key, |hash| {
+            hash.into_iter()
+                .flat_map(|hash| hash.keys())
+                .cloned()
+                .map(Frame::BulkString)
+                .collect()
+        })?;
+        Ok(Frame::Array(fields))
+    }
+}
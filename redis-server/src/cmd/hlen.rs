@@ -0,0 +1,22 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hlen {
+    key: String,
+}
+
+impl Hlen {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_hash_data(&self.key, |hash| hash.map(|hash| hash.len()).unwrap_or(0))?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -0,0 +1,41 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hmget {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl Hmget {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut fields = vec![];
+        while let Some(field) = parser.next_bytes()? {
+            fields.push(field);
+        }
+        if fields.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'hmget' command"));
+        }
+        Ok(Self { key, fields })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let values = db.with_hash_data(&self.key, |hash| {
+            self.fields
+                .iter()
+                .map(|field| {
+                    hash.and_then(|hash| hash.get(field).cloned())
+                        .map(Frame::BulkString)
+                        .unwrap_or(Frame::Null)
+                })
+                .collect()
+        })?;
+        Ok(Frame::Array(values))
+    }
+}
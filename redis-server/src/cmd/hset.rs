@@ -0,0 +1,44 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hset {
+    key: String,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl Hset {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut fields = vec![];
+        while let Some(field) = parser.next_bytes()? {
+            let value = parser
+                .next_bytes()?
+                .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'hset' command"))?;
+            fields.push((field, value));
+        }
+        if fields.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'hset' command"));
+        }
+        Ok(Self { key, fields })
+    }
+
+    /// Replies with the number of fields that were added, not counting updated fields.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        // Like in redis, the hash counts as changed even if the fields already had these values.
+        let added = db.with_hash_data_mut(self.key, |hash| {
+            let added = self
+                .fields
+                .into_iter()
+                .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+                .count();
+            (added, true)
+        })?;
+        Ok(Frame::Integer(added as i64))
+    }
+}
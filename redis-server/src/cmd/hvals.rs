@@ -0,0 +1,28 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Hvals {
+    key: String,
+}
+
+impl Hvals {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let values = db.with_hash_data(&self.key, |hash| {
+            hash.into_iter()
+                .flat_map(|hash| hash.values())
+                .cloned()
+                .map(Frame::BulkString)
+                .collect()
+        })?;
+        Ok(Frame::Array(values))
+    }
+}
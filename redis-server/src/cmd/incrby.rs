@@ -0,0 +1,41 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `INCRBY key increment`, or `DECRBY key decrement` when `decrement` is set.
+pub struct Incrby {
+    key: String,
+    increment: i64,
+}
+
+impl Incrby {
+    pub fn parse(parser: &mut ParseFrames, decrement: bool) -> anyhow::Result<Self> {
+        let name = if decrement { "decrby" } else { "incrby" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{name}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let increment = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        let increment = if decrement {
+            increment
+                .checked_neg()
+                .ok_or_else(|| anyhow!("ERR decrement would overflow"))?
+        } else {
+            increment
+        };
+        Ok(Self { key, increment })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let new_value =
+            db.with_integer_data_mut(self.key, |value| value.checked_add(self.increment))?;
+        Ok(Frame::Integer(new_value))
+    }
+}
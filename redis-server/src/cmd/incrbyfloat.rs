@@ -0,0 +1,69 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `INCRBYFLOAT key increment`
+pub struct Incrbyfloat {
+    key: String,
+    increment: f64,
+}
+
+impl Incrbyfloat {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'incrbyfloat' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let increment = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        let increment =
+            parse_float(&increment).ok_or_else(|| anyhow!("ERR value is not a valid float"))?;
+        Ok(Self { key, increment })
+    }
+
+    /// Like in redis, the new value is appended to the AOF and replicated as a
+    /// `SET key value KEEPTTL`, so that replaying the increment can't round it differently.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let key = Bytes::from(self.key.clone());
+        let new_value = db.with_string_data_mut(self.key, |string| {
+            let value = if string.is_empty() {
+                0.0
+            } else {
+                parse_float(string)
+                    .ok_or_else(|| io::Error::other("ERR value is not a valid float"))?
+            };
+            let value = value + self.increment;
+            if !value.is_finite() {
+                return Err(io::Error::other(
+                    "ERR increment would produce NaN or Infinity",
+                ));
+            }
+            let formatted = value.to_string();
+            string.clear();
+            string.extend_from_slice(formatted.as_bytes());
+            Ok((Bytes::from(formatted), true))
+        })?;
+        db.log_command(Frame::new_command([
+            Bytes::from_static(b"SET"),
+            key,
+            new_value.clone(),
+            Bytes::from_static(b"KEEPTTL"),
+        ]));
+        Ok(Frame::BulkString(new_value))
+    }
+}
+
+/// Parses a float the way redis does, which doesn't allow spaces or NaN.
+fn parse_float(value: &[u8]) -> Option<f64> {
+    std::str::from_utf8(value)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|value| !value.is_nan())
+}
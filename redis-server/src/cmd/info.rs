@@ -0,0 +1,158 @@
+use std::{fmt::Write, io};
+
+use bytes::Bytes;
+
+use super::{hello::VERSION, ParseFrames};
+use crate::{db::Db, frame::Frame};
+
+const SECTIONS: [&str; 8] = [
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "cluster",
+    "keyspace",
+];
+
+/// `INFO [section [section ...]]`
+///
+/// Statistics about the server, grouped in sections. Every section is included when none is
+/// given, or with `all`, `default` or `everything`.
+pub struct Info {
+    sections: Vec<String>,
+}
+
+impl Info {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let mut sections = vec![];
+        while let Some(section) = parser.next_string()? {
+            let section = section.to_lowercase();
+            match section.as_str() {
+                "all" | "default" | "everything" => sections.extend(SECTIONS.map(String::from)),
+                _ => sections.push(section),
+            }
+        }
+        if sections.is_empty() {
+            sections.extend(SECTIONS.map(String::from));
+        }
+        Ok(Self { sections })
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let mut text = String::new();
+        for section in SECTIONS {
+            if !self.sections.iter().any(|name| name == section) {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push_str("\r\n");
+            }
+            match section {
+                "server" => {
+                    text.push_str("# Server\r\n");
+                    field(&mut text, "redis_version", VERSION);
+                    let mode = if db.is_cluster_enabled() {
+                        "cluster"
+                    } else {
+                        "standalone"
+                    };
+                    field(&mut text, "redis_mode", mode);
+                    field(&mut text, "process_id", std::process::id());
+                    let config = db.config();
+                    field(&mut text, "tcp_port", config.port);
+                    let uptime = db.uptime().as_secs();
+                    field(&mut text, "uptime_in_seconds", uptime);
+                    field(&mut text, "uptime_in_days", uptime / (24 * 60 * 60));
+                    let file = config.file.map(|file| file.display().to_string());
+                    field(&mut text, "config_file", file.unwrap_or_default());
+                }
+                "clients" => {
+                    let (connected, blocked) = db.clients().connected();
+                    text.push_str("# Clients\r\n");
+                    field(&mut text, "connected_clients", connected);
+                    field(&mut text, "blocked_clients", blocked);
+                }
+                "memory" => {
+                    let config = db.config();
+                    let used_memory = db.used_memory() as u64;
+                    text.push_str("# Memory\r\n");
+                    field(&mut text, "used_memory", used_memory);
+                    field(&mut text, "used_memory_human", human(used_memory));
+                    field(&mut text, "maxmemory", config.maxmemory);
+                    field(&mut text, "maxmemory_human", human(config.maxmemory));
+                    field(
+                        &mut text,
+                        "maxmemory_policy",
+                        config.maxmemory_policy.name(),
+                    );
+                }
+                "persistence" => {
+                    text.push_str("# Persistence\r\n");
+                    for (name, value) in db.persistence_info() {
+                        field(&mut text, &name, value);
+                    }
+                }
+                "stats" => {
+                    let (connections, commands) = db.clients().stats();
+                    text.push_str("# Stats\r\n");
+                    field(&mut text, "total_connections_received", connections);
+                    field(&mut text, "total_commands_processed", commands);
+                    field(&mut text, "evicted_keys", db.evicted_keys());
+                }
+                "replication" => {
+                    text.push_str("# Replication\r\n");
+                    for (name, value) in db.with_replication(|replication| replication.info()) {
+                        field(&mut text, &name, value);
+                    }
+                }
+                "cluster" => {
+                    text.push_str("# Cluster\r\n");
+                    field(
+                        &mut text,
+                        "cluster_enabled",
+                        u8::from(db.is_cluster_enabled()),
+                    );
+                }
+                "keyspace" => {
+                    text.push_str("# Keyspace\r\n");
+                    for (index, (keys, expires)) in db.keyspace_sizes().into_iter().enumerate() {
+                        // Like in redis, empty databases are left out.
+                        if keys > 0 {
+                            field(
+                                &mut text,
+                                &format!("db{index}"),
+                                format!("keys={keys},expires={expires}"),
+                            );
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(Frame::Verbatim {
+            encoding: "txt".to_owned(),
+            text: Bytes::from(text),
+        })
+    }
+}
+
+fn field(text: &mut String, name: &str, value: impl std::fmt::Display) {
+    let _ = write!(text, "{name}:{value}\r\n");
+}
+
+/// Formats a number of bytes the way redis does, e.g. `1.50K`.
+fn human(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.2}{}", UNITS[unit])
+}
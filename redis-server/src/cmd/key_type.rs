@@ -0,0 +1,26 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Type {
+    key: String,
+}
+
+impl Type {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let type_name = db.with_data(|data| {
+            data.get(&self.key)
+                .map(|value| value.type_name())
+                .unwrap_or("none")
+        });
+        Ok(Frame::SimpleString(type_name.to_owned()))
+    }
+}
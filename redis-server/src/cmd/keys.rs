@@ -0,0 +1,34 @@
+use std::io;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame, glob};
+
+/// `KEYS pattern`
+///
+/// Every key that matches the glob-style pattern. Like in redis, this goes through the whole
+/// keyspace at once, which `SCAN` avoids.
+pub struct Keys {
+    pattern: String,
+}
+
+impl Keys {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let pattern = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'keys' command"))?;
+        Ok(Self { pattern })
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let keys = db.with_data(|data| {
+            data.iter()
+                .filter(|(key, _)| glob::matches(self.pattern.as_bytes(), key.as_bytes()))
+                .map(|(key, _)| Frame::BulkString(Bytes::from(key.clone())))
+                .collect()
+        });
+        Ok(Frame::Array(keys))
+    }
+}
@@ -0,0 +1,11 @@
+use std::io;
+
+use crate::{db::Db, frame::Frame};
+
+pub struct Lastsave;
+
+impl Lastsave {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        Ok(Frame::Integer(db.last_save().timestamp()))
+    }
+}
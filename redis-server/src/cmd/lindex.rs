@@ -0,0 +1,32 @@
+use std::io;
+
+use super::{resolve_index, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Lindex {
+    key: String,
+    index: i64,
+}
+
+impl Lindex {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let index = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected an index but found None"))?;
+        Ok(Self { key, index })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_list_data(&self.key, |list| {
+            list.and_then(|list| {
+                resolve_index(self.index, list.len()).and_then(|index| list.get(index).cloned())
+            })
+            .map(Frame::BulkString)
+            .unwrap_or(Frame::Null)
+        })?;
+        Ok(frame)
+    }
+}
@@ -0,0 +1,66 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+enum Position {
+    Before,
+    After,
+}
+
+pub struct Linsert {
+    key: String,
+    position: Position,
+    pivot: Bytes,
+    element: Bytes,
+}
+
+impl Linsert {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let position = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected BEFORE or AFTER but found None"))?;
+        let position = match position.to_lowercase().as_str() {
+            "before" => Position::Before,
+            "after" => Position::After,
+            _ => return Err(anyhow!("ERR syntax error")),
+        };
+        let pivot = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a pivot but found None"))?;
+        let element = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected an element but found None"))?;
+        Ok(Self {
+            key,
+            position,
+            pivot,
+            element,
+        })
+    }
+
+    /// Replies with the new length of the list, -1 if the pivot wasn't found,
+    /// or 0 if the key does not exist.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_list_data_mut(self.key, |list| {
+            if list.is_empty() {
+                return (0, false);
+            }
+            let Some(index) = list.iter().position(|value| *value == self.pivot) else {
+                return (-1, false);
+            };
+            let index = match self.position {
+                Position::Before => index,
+                Position::After => index + 1,
+            };
+            list.insert(index, self.element);
+            (list.len() as i64, true)
+        })?;
+        Ok(Frame::Integer(len))
+    }
+}
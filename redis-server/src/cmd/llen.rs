@@ -0,0 +1,22 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Llen {
+    key: String,
+}
+
+impl Llen {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_list_data(&self.key, |list| list.map(|list| list.len()).unwrap_or(0))?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -1,8 +1,11 @@
-use std::{collections::VecDeque, io};
+use std::io;
 
 use bytes::Bytes;
 
-use crate::{connection::Connection, db::Db, frame::Frame};
+use crate::{
+    db::{Db, ListEnd},
+    frame::Frame,
+};
 
 use super::ParseFrames;
 
@@ -25,16 +28,9 @@ impl Lpush {
         Ok(Self { key, values })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
-        let list = db.with_list_data_mut(self.key, |list| {
-            let mut list = VecDeque::from(list);
-
-            for value in self.values {
-                list.push_front(value);
-            }
-            list.into_iter().collect()
-        })?;
-        let frame = Frame::Integer(list.len() as i64);
-        conn.write_frame(frame)
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.push(self.key, self.values, ListEnd::Left)?;
+        let frame = Frame::Integer(len as i64);
+        Ok(frame)
     }
 }
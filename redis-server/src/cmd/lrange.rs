@@ -0,0 +1,42 @@
+use std::io;
+
+use super::{resolve_range, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Lrange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl Lrange {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let start = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a start index but found None"))?;
+        let stop = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a stop index but found None"))?;
+        Ok(Self { key, start, stop })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let values = db.with_list_data(&self.key, |list| {
+            let Some(list) = list else {
+                return vec![];
+            };
+            match resolve_range(self.start, self.stop, list.len()) {
+                Some((start, stop)) => list
+                    .range(start..=stop)
+                    .cloned()
+                    .map(Frame::BulkString)
+                    .collect(),
+                None => vec![],
+            }
+        })?;
+        Ok(Frame::Array(values))
+    }
+}
@@ -0,0 +1,64 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Lrem {
+    key: String,
+    count: i64,
+    element: Bytes,
+}
+
+impl Lrem {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let count = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a count but found None"))?;
+        let element = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected an element but found None"))?;
+        Ok(Self {
+            key,
+            count,
+            element,
+        })
+    }
+
+    /// A positive count removes matches from head to tail, a negative count from tail to head,
+    /// and zero removes every match.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let removed = db.with_list_data_mut(self.key, |list| {
+            let limit = match self.count {
+                0 => usize::MAX,
+                count => count.unsigned_abs() as usize,
+            };
+            let mut removed = 0;
+            if self.count < 0 {
+                let mut index = list.len();
+                while index > 0 && removed < limit {
+                    index -= 1;
+                    if list[index] == self.element {
+                        list.remove(index);
+                        removed += 1;
+                    }
+                }
+            } else {
+                list.retain(|value| {
+                    if removed < limit && *value == self.element {
+                        removed += 1;
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            (removed, removed > 0)
+        })?;
+        Ok(Frame::Integer(removed as i64))
+    }
+}
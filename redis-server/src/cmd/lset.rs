@@ -0,0 +1,42 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::{resolve_index, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Lset {
+    key: String,
+    index: i64,
+    value: Bytes,
+}
+
+impl Lset {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let index = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected an index but found None"))?;
+        let value = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a value but found None"))?;
+        Ok(Self { key, index, value })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        db.with_list_data_mut(self.key, |list| {
+            if list.is_empty() {
+                return (Err(io::Error::other("ERR no such key")), false);
+            }
+            match resolve_index(self.index, list.len()) {
+                Some(index) => {
+                    list[index] = self.value;
+                    (Ok(Frame::SimpleString("OK".to_owned())), true)
+                }
+                None => (Err(io::Error::other("ERR index out of range")), false),
+            }
+        })?
+    }
+}
@@ -0,0 +1,40 @@
+use std::io;
+
+use super::{resolve_range, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Ltrim {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl Ltrim {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let start = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a start index but found None"))?;
+        let stop = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("Expected a stop index but found None"))?;
+        Ok(Self { key, start, stop })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        db.with_list_data_mut(self.key, |list| {
+            let len = list.len();
+            match resolve_range(self.start, self.stop, len) {
+                Some((start, stop)) => {
+                    list.truncate(stop + 1);
+                    list.drain(..start);
+                }
+                None => list.clear(),
+            }
+            ((), list.len() < len)
+        })?;
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
@@ -0,0 +1,37 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `MGET key [key ...]`
+pub struct Mget {
+    keys: Vec<String>,
+}
+
+impl Mget {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let mut keys = vec![];
+        while let Some(key) = parser.next_string()? {
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'mget' command"));
+        }
+        Ok(Self { keys })
+    }
+
+    /// Keys that do not exist, or that hold something else than a string, are nil.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let values = db.with_data(|data| {
+            self.keys
+                .iter()
+                .map(|key| {
+                    data.get(key)
+                        .and_then(|value| value.as_string())
+                        .map_or(Frame::Null, Frame::BulkString)
+                })
+                .collect()
+        });
+        Ok(Frame::Array(values))
+    }
+}
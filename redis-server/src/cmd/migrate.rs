@@ -0,0 +1,217 @@
+use std::{io, time::Duration};
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use chrono::Utc;
+use tokio::{net::TcpStream, time::timeout};
+
+use super::ParseFrames;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+/// [AUTH password | AUTH2 username password] [KEYS key [key ...]]`
+///
+/// Moves the keys to another server with `RESTORE`, or with `RESTORE-ASKING` in cluster mode,
+/// deleting them here once it has them unless `COPY` is set. The keys are serialized when the
+/// command runs, and the client waits for the other server to reply without holding up the
+/// others.
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<String>,
+    db_index: usize,
+    timeout: Duration,
+    copy: bool,
+    replace: bool,
+    auth: Option<(Option<String>, String)>,
+}
+
+impl Migrate {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'migrate' command");
+        let host = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let port = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let port = u16::try_from(port).map_err(|_| anyhow!("ERR Invalid port"))?;
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let db_index = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let db_index =
+            usize::try_from(db_index).map_err(|_| anyhow!("ERR DB index is out of range"))?;
+        let timeout = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        // Like redis, a timeout that isn't positive means a second.
+        let timeout = Duration::from_millis(u64::try_from(timeout).unwrap_or(0).max(1000));
+
+        let mut migrate = Self {
+            host,
+            port,
+            keys: vec![],
+            db_index,
+            timeout,
+            copy: false,
+            replace: false,
+            auth: None,
+        };
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "copy" => migrate.copy = true,
+                "replace" => migrate.replace = true,
+                "auth" => {
+                    let password = parser
+                        .next_string()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    migrate.auth = Some((None, password));
+                }
+                "auth2" => {
+                    let syntax_error = || anyhow!("ERR syntax error");
+                    let username = parser.next_string()?.ok_or_else(syntax_error)?;
+                    let password = parser.next_string()?.ok_or_else(syntax_error)?;
+                    migrate.auth = Some((Some(username), password));
+                }
+                "keys" => {
+                    if !key.is_empty() {
+                        bail!("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string");
+                    }
+                    while let Some(key) = parser.next_string()? {
+                        migrate.keys.push(key);
+                    }
+                }
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        if !key.is_empty() {
+            migrate.keys.push(key);
+        }
+        Ok(migrate)
+    }
+
+    /// Returns `None` while the keys are sent, in which case the reply is parked on `conn`.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        let command: &'static [u8] = if db.is_cluster_enabled() {
+            b"RESTORE-ASKING"
+        } else {
+            b"RESTORE"
+        };
+        let now = Utc::now();
+        let restores: Vec<_> = self
+            .keys
+            .iter()
+            .filter_map(|key| {
+                let (payload, deadline) = db.dump(key)?;
+                // A key that is about to expire still has a time to live once restored.
+                let ttl = deadline.map_or(0, |at| (at - now).num_milliseconds().max(1));
+                let mut args = vec![
+                    Bytes::from_static(command),
+                    Bytes::from(key.clone()),
+                    Bytes::from(ttl.to_string()),
+                    Bytes::from(payload),
+                ];
+                if self.replace {
+                    args.push(Bytes::from_static(b"REPLACE"));
+                }
+                Some((key.clone(), Frame::new_command(args)))
+            })
+            .collect();
+        if restores.is_empty() {
+            return Ok(Some(Frame::SimpleString("NOKEY".to_owned())));
+        }
+
+        let db = db.clone();
+        conn.park(Box::pin(async move {
+            let (restored, result) = self.send(restores).await;
+            if !self.copy && !restored.is_empty() {
+                delete(&db, restored);
+            }
+            match result {
+                Ok(()) => Frame::SimpleString("OK".to_owned()),
+                Err(err) => Frame::Error(err.to_string()),
+            }
+        }));
+        Ok(None)
+    }
+
+    /// Sends the keys to the target, and returns the ones it restored, which are all of them
+    /// unless something went wrong.
+    async fn send(&self, restores: Vec<(String, Frame)>) -> (Vec<String>, anyhow::Result<()>) {
+        let mut restored = vec![];
+        let address = (self.host.as_str(), self.port);
+        let mut target = match timeout(self.timeout, TcpStream::connect(address)).await {
+            Ok(Ok(stream)) => Connection::new(stream),
+            _ => {
+                let err = anyhow!("IOERR error or timeout connecting to the client");
+                return (restored, Err(err));
+            }
+        };
+
+        let mut setup = vec![];
+        if let Some((username, password)) = &self.auth {
+            let mut args = vec![Bytes::from_static(b"AUTH")];
+            args.extend(username.clone().map(Bytes::from));
+            args.push(Bytes::from(password.clone()));
+            setup.push(Frame::new_command(args));
+        }
+        if self.db_index != 0 {
+            setup.push(Frame::new_command([
+                Bytes::from_static(b"SELECT"),
+                Bytes::from(self.db_index.to_string()),
+            ]));
+        }
+        for frame in setup {
+            if let Err(err) = self.request(&mut target, frame).await {
+                return (restored, Err(err));
+            }
+        }
+        for (key, frame) in restores {
+            if let Err(err) = self.request(&mut target, frame).await {
+                return (restored, Err(err));
+            }
+            restored.push(key);
+        }
+        (restored, Ok(()))
+    }
+
+    async fn request(&self, target: &mut Connection, frame: Frame) -> anyhow::Result<()> {
+        target.write_frame(frame);
+        let reply = timeout(self.timeout, async {
+            target.flush().await?;
+            target.read_frame().await
+        })
+        .await;
+        match reply {
+            Ok(Ok(Some(Frame::Error(err)))) => {
+                bail!("ERR Target instance replied with error: {err}")
+            }
+            Ok(Ok(Some(_))) => Ok(()),
+            _ => bail!("IOERR error or timeout reading to target instance"),
+        }
+    }
+}
+
+/// Deletes the keys that were moved, as a `DEL` in the AOF and the replication stream.
+fn delete(db: &Db, keys: Vec<String>) {
+    let mut args = vec![Bytes::from_static(b"DEL")];
+    args.extend(keys.iter().cloned().map(Bytes::from));
+    let command = Frame::new_command(args);
+    db.run_command(false, || {
+        db.log_write(Some(command.clone()), || {
+            db.notify_writes(&command, None, || {
+                db.with_data_mut(|data| {
+                    for key in &keys {
+                        data.remove(key);
+                    }
+                });
+                Ok(())
+            })
+        })
+    })
+    .expect("deleting keys can't fail");
+}
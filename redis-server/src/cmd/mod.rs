@@ -1,16 +1,117 @@
+pub mod acl;
+pub mod append;
+pub mod asking;
+pub mod auth;
+pub mod bgrewriteaof;
+pub mod bgsave;
+pub mod blmove;
+pub mod blpop;
+pub mod brpop;
+pub mod client;
+pub mod cluster;
+pub mod config;
+pub mod dbsize;
 pub mod decr;
 pub mod del;
+pub mod discard;
+pub mod dump;
 mod echo;
+pub mod eval;
+pub mod exec;
 pub mod exists;
+pub mod expire;
+pub mod flush;
 pub mod get;
+pub mod getdel;
+pub mod getex;
+pub mod getrange;
+pub mod hdel;
+pub mod hello;
+pub mod hexists;
+pub mod hget;
+pub mod hgetall;
+pub mod hincrby;
+pub mod hkeys;
+pub mod hlen;
+pub mod hmget;
+pub mod hset;
+pub mod hvals;
 pub mod incr;
+pub mod incrby;
+pub mod incrbyfloat;
+pub mod info;
+pub mod key_type;
+pub mod keys;
+pub mod lastsave;
+pub mod lindex;
+pub mod linsert;
+pub mod llen;
+pub mod lpop;
 pub mod lpush;
+pub mod lrange;
+pub mod lrem;
+pub mod lset;
+pub mod ltrim;
+pub mod mget;
+pub mod migrate;
+pub mod monitor;
+pub mod mset;
+pub mod multi;
+pub mod persist;
 mod ping;
+pub mod psync;
+pub mod publish;
+pub mod pubsub;
+pub mod randomkey;
+pub mod rename;
+pub mod replconf;
+pub mod replicaof;
+pub mod restore;
+pub mod role;
+pub mod rpop;
 pub mod rpush;
+pub mod sadd;
 pub mod save;
+pub mod scan;
+pub mod scard;
+pub mod script;
+pub mod select;
 pub mod set;
+pub mod setop;
+pub mod setrange;
+pub mod sintercard;
+pub mod sismember;
+pub mod slowlog;
+pub mod smembers;
+pub mod smove;
+pub mod spop;
+pub mod srandmember;
+pub mod srem;
+pub mod strlen;
+pub mod subscribe;
+pub mod ttl;
+pub mod unsubscribe;
+pub mod unwatch;
+pub mod watch;
+pub mod xack;
+pub mod xadd;
+pub mod xclaim;
+pub mod xdel;
+pub mod xgroup;
+pub mod xlen;
+pub mod xpending;
+pub mod xrange;
+pub mod xread;
+pub mod xtrim;
+pub mod zadd;
+pub mod zcard;
+pub mod zincrby;
+pub mod zrange;
+pub mod zrank;
+pub mod zrem;
+pub mod zscore;
 
-use std::io;
+use std::{borrow::Cow, io, time::Duration};
 
 use bytes::Bytes;
 use log::warn;
@@ -20,11 +121,136 @@ use anyhow::{anyhow, bail, Context};
 
 use crate::{connection::Connection, db::Db, frame::Frame};
 
+pub use self::{multi::Transaction, watch::WatchedKeys};
+
+/// The error clients of a read-only replica get for write commands.
+pub const READ_ONLY: &str = "READONLY You can't write against a read only replica.";
+
 use self::{
-    decr::Decr, del::Del, echo::Echo, exists::Exists, get::Get, incr::Incr, lpush::Lpush,
-    rpush::Rpush, save::Save, set::Set,
+    acl::Acl,
+    append::Append,
+    asking::Asking,
+    auth::Auth,
+    bgrewriteaof::Bgrewriteaof,
+    bgsave::Bgsave,
+    blmove::Blmove,
+    blpop::Blpop,
+    brpop::Brpop,
+    client::Client,
+    cluster::Cluster,
+    config::Config,
+    dbsize::Dbsize,
+    decr::Decr,
+    del::Del,
+    discard::Discard,
+    dump::Dump,
+    echo::Echo,
+    eval::Eval,
+    exec::Exec,
+    exists::Exists,
+    expire::{Expire, Expiry},
+    flush::Flush,
+    get::Get,
+    getdel::Getdel,
+    getex::Getex,
+    getrange::Getrange,
+    hdel::Hdel,
+    hello::Hello,
+    hexists::Hexists,
+    hget::Hget,
+    hgetall::Hgetall,
+    hincrby::Hincrby,
+    hkeys::Hkeys,
+    hlen::Hlen,
+    hmget::Hmget,
+    hset::Hset,
+    hvals::Hvals,
+    incr::Incr,
+    incrby::Incrby,
+    incrbyfloat::Incrbyfloat,
+    info::Info,
+    key_type::Type,
+    keys::Keys,
+    lastsave::Lastsave,
+    lindex::Lindex,
+    linsert::Linsert,
+    llen::Llen,
+    lpop::Lpop,
+    lpush::Lpush,
+    lrange::Lrange,
+    lrem::Lrem,
+    lset::Lset,
+    ltrim::Ltrim,
+    mget::Mget,
+    migrate::Migrate,
+    monitor::Monitor,
+    mset::Mset,
+    multi::Multi,
+    persist::Persist,
+    psync::Psync,
+    publish::Publish,
+    pubsub::Pubsub,
+    randomkey::Randomkey,
+    rename::Rename,
+    replconf::Replconf,
+    replicaof::Replicaof,
+    restore::Restore,
+    role::Role,
+    rpop::Rpop,
+    rpush::Rpush,
+    sadd::Sadd,
+    save::Save,
+    scan::Scan,
+    scard::Scard,
+    script::Script,
+    select::Select,
+    set::Set,
+    setop::{Operation, Setop},
+    setrange::Setrange,
+    sintercard::Sintercard,
+    sismember::Sismember,
+    slowlog::Slowlog,
+    smembers::Smembers,
+    smove::Smove,
+    spop::Spop,
+    srandmember::Srandmember,
+    srem::Srem,
+    strlen::Strlen,
+    subscribe::Subscribe,
+    ttl::Ttl,
+    unsubscribe::Unsubscribe,
+    unwatch::Unwatch,
+    watch::Watch,
+    xack::Xack,
+    xadd::Xadd,
+    xclaim::Xclaim,
+    xdel::Xdel,
+    xgroup::Xgroup,
+    xlen::Xlen,
+    xpending::Xpending,
+    xrange::Xrange,
+    xread::Xread,
+    xtrim::Xtrim,
+    zadd::Zadd,
+    zcard::Zcard,
+    zincrby::Zincrby,
+    zrange::Zrange,
+    zrank::Zrank,
+    zrem::Zrem,
+    zscore::Zscore,
 };
 
+/// How a command is appended to the AOF.
+pub enum Propagation {
+    /// The command doesn't change the keyspace.
+    None,
+    /// The command is appended the way the client sent it.
+    Verbatim,
+    /// The `Db` appends what the command did instead, e.g. the `LPOP` that a `BLPOP` amounted
+    /// to, or the absolute deadline set by `EXPIRE`, so that replaying it gives the same result.
+    Effects,
+}
+
 pub enum Command {
     Ping(Ping),
     Echo(Echo),
@@ -34,13 +260,122 @@ pub enum Command {
     Del(Del),
     Incr(Incr),
     Decr(Decr),
+    Mget(Mget),
+    Mset(Mset),
+    Getdel(Getdel),
+    Getex(Getex),
+    Append(Append),
+    Strlen(Strlen),
+    Getrange(Getrange),
+    Setrange(Setrange),
+    Incrby(Incrby),
+    Incrbyfloat(Incrbyfloat),
     Lpush(Lpush),
     Rpush(Rpush),
+    Lrange(Lrange),
+    Lpop(Lpop),
+    Rpop(Rpop),
+    Llen(Llen),
+    Lindex(Lindex),
+    Lset(Lset),
+    Ltrim(Ltrim),
+    Lrem(Lrem),
+    Linsert(Linsert),
+    Blpop(Blpop),
+    Brpop(Brpop),
+    Blmove(Blmove),
+    Hset(Hset),
+    Hget(Hget),
+    Hmget(Hmget),
+    Hdel(Hdel),
+    Hgetall(Hgetall),
+    Hincrby(Hincrby),
+    Hexists(Hexists),
+    Hlen(Hlen),
+    Hkeys(Hkeys),
+    Hvals(Hvals),
+    Zadd(Zadd),
+    Zrange(Zrange),
+    Zrank(Zrank),
+    Zscore(Zscore),
+    Xadd(Xadd),
+    Xlen(Xlen),
+    Xrange(Xrange),
+    Xdel(Xdel),
+    Xtrim(Xtrim),
+    Xread(Xread),
+    Xgroup(Xgroup),
+    Xack(Xack),
+    Xpending(Xpending),
+    Xclaim(Xclaim),
+    Zincrby(Zincrby),
+    Zrem(Zrem),
+    Zcard(Zcard),
     Save(Save),
-    Unknown,
+    Bgsave(Bgsave),
+    Bgrewriteaof(Bgrewriteaof),
+    Lastsave(Lastsave),
+    Type(Type),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Publish(Publish),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    Script(Script),
+    Pubsub(Pubsub),
+    Hello(Hello),
+    Config(Config),
+    Info(Info),
+    Replicaof(Replicaof),
+    Role(Role),
+    Replconf(Replconf),
+    Psync(Psync),
+    Select(Select),
+    Keys(Keys),
+    Scan(Scan),
+    Rename(Rename),
+    Dbsize(Dbsize),
+    Flush(Flush),
+    Randomkey(Randomkey),
+    Client(Client),
+    Monitor(Monitor),
+    Slowlog(Slowlog),
+    Auth(Auth),
+    Acl(Acl),
+    Sadd(Sadd),
+    Srem(Srem),
+    Smembers(Smembers),
+    Sismember(Sismember),
+    Scard(Scard),
+    Setop(Setop),
+    Sintercard(Sintercard),
+    Spop(Spop),
+    Srandmember(Srandmember),
+    Smove(Smove),
+    Asking(Asking),
+    Cluster(Cluster),
+    Dump(Dump),
+    Migrate(Migrate),
+    Restore(Restore),
 }
 
 impl Command {
+    /// Parses a command sent by a client authenticated as `user`, or that hasn't authenticated
+    /// yet if `None`. Fails if the user isn't allowed to run it on its keys, see [`Acl::check`].
+    ///
+    /// [`Acl::check`]: crate::acl::Acl::check
+    pub fn from_client_frame(frame: Frame, user: Option<&str>, db: &Db) -> anyhow::Result<Self> {
+        db.acl().check(user, &frame)?;
+        Self::from_frame(frame)
+    }
+
     pub fn from_frame(frame: Frame) -> anyhow::Result<Self> {
         let mut parser = ParseFrames::new(match frame {
             Frame::Array(frames) => frames,
@@ -60,40 +395,586 @@ impl Command {
             "ping" => Ok(Command::Ping(Ping::parse(&mut parser)?)),
             "echo" => Ok(Command::Echo(Echo::parse(&mut parser)?)),
             "set" => Ok(Command::Set(Set::parse(&mut parser)?)),
+            "setnx" => Ok(Command::Set(Set::parse_setnx(&mut parser)?)),
+            "getset" => Ok(Command::Set(Set::parse_getset(&mut parser)?)),
+            "setex" => Ok(Command::Set(Set::parse_setex(&mut parser, "setex", "ex")?)),
+            "psetex" => Ok(Command::Set(Set::parse_setex(&mut parser, "psetex", "px")?)),
             "get" => Ok(Command::Get(Get::parse(&mut parser)?)),
             "exists" => Ok(Command::Exists(Exists::parse(&mut parser)?)),
             "del" => Ok(Command::Del(Del::parse(&mut parser)?)),
             "incr" => Ok(Command::Incr(Incr::parse(&mut parser)?)),
             "decr" => Ok(Command::Decr(Decr::parse(&mut parser)?)),
+            "mget" => Ok(Command::Mget(Mget::parse(&mut parser)?)),
+            "mset" => Ok(Command::Mset(Mset::parse(&mut parser, false)?)),
+            "msetnx" => Ok(Command::Mset(Mset::parse(&mut parser, true)?)),
+            "getdel" => Ok(Command::Getdel(Getdel::parse(&mut parser)?)),
+            "getex" => Ok(Command::Getex(Getex::parse(&mut parser)?)),
+            "append" => Ok(Command::Append(Append::parse(&mut parser)?)),
+            "strlen" => Ok(Command::Strlen(Strlen::parse(&mut parser)?)),
+            "getrange" => Ok(Command::Getrange(Getrange::parse(&mut parser)?)),
+            "setrange" => Ok(Command::Setrange(Setrange::parse(&mut parser)?)),
+            "incrby" => Ok(Command::Incrby(Incrby::parse(&mut parser, false)?)),
+            "decrby" => Ok(Command::Incrby(Incrby::parse(&mut parser, true)?)),
+            "incrbyfloat" => Ok(Command::Incrbyfloat(Incrbyfloat::parse(&mut parser)?)),
             "lpush" => Ok(Command::Lpush(Lpush::parse(&mut parser)?)),
             "rpush" => Ok(Command::Rpush(Rpush::parse(&mut parser)?)),
+            "lrange" => Ok(Command::Lrange(Lrange::parse(&mut parser)?)),
+            "lpop" => Ok(Command::Lpop(Lpop::parse(&mut parser)?)),
+            "rpop" => Ok(Command::Rpop(Rpop::parse(&mut parser)?)),
+            "llen" => Ok(Command::Llen(Llen::parse(&mut parser)?)),
+            "lindex" => Ok(Command::Lindex(Lindex::parse(&mut parser)?)),
+            "lset" => Ok(Command::Lset(Lset::parse(&mut parser)?)),
+            "ltrim" => Ok(Command::Ltrim(Ltrim::parse(&mut parser)?)),
+            "lrem" => Ok(Command::Lrem(Lrem::parse(&mut parser)?)),
+            "linsert" => Ok(Command::Linsert(Linsert::parse(&mut parser)?)),
+            "blpop" => Ok(Command::Blpop(Blpop::parse(&mut parser)?)),
+            "brpop" => Ok(Command::Brpop(Brpop::parse(&mut parser)?)),
+            "blmove" => Ok(Command::Blmove(Blmove::parse(&mut parser)?)),
+            "hset" => Ok(Command::Hset(Hset::parse(&mut parser)?)),
+            "hget" => Ok(Command::Hget(Hget::parse(&mut parser)?)),
+            "hmget" => Ok(Command::Hmget(Hmget::parse(&mut parser)?)),
+            "hdel" => Ok(Command::Hdel(Hdel::parse(&mut parser)?)),
+            "hgetall" => Ok(Command::Hgetall(Hgetall::parse(&mut parser)?)),
+            "hincrby" => Ok(Command::Hincrby(Hincrby::parse(&mut parser)?)),
+            "hexists" => Ok(Command::Hexists(Hexists::parse(&mut parser)?)),
+            "hlen" => Ok(Command::Hlen(Hlen::parse(&mut parser)?)),
+            "hkeys" => Ok(Command::Hkeys(Hkeys::parse(&mut parser)?)),
+            "hvals" => Ok(Command::Hvals(Hvals::parse(&mut parser)?)),
+            "zadd" => Ok(Command::Zadd(Zadd::parse(&mut parser)?)),
+            "zrange" => Ok(Command::Zrange(Zrange::parse(&mut parser)?)),
+            "zrangebyscore" => Ok(Command::Zrange(Zrange::parse_by_score(&mut parser)?)),
+            "zrank" => Ok(Command::Zrank(Zrank::parse(&mut parser)?)),
+            "zscore" => Ok(Command::Zscore(Zscore::parse(&mut parser)?)),
+            "xadd" => Ok(Command::Xadd(Xadd::parse(&mut parser)?)),
+            "xlen" => Ok(Command::Xlen(Xlen::parse(&mut parser)?)),
+            "xrange" => Ok(Command::Xrange(Xrange::parse(&mut parser, false)?)),
+            "xrevrange" => Ok(Command::Xrange(Xrange::parse(&mut parser, true)?)),
+            "xdel" => Ok(Command::Xdel(Xdel::parse(&mut parser)?)),
+            "xtrim" => Ok(Command::Xtrim(Xtrim::parse(&mut parser)?)),
+            "xread" => Ok(Command::Xread(Xread::parse(&mut parser, false)?)),
+            "xreadgroup" => Ok(Command::Xread(Xread::parse(&mut parser, true)?)),
+            "xgroup" => Ok(Command::Xgroup(Xgroup::parse(&mut parser)?)),
+            "xack" => Ok(Command::Xack(Xack::parse(&mut parser)?)),
+            "xpending" => Ok(Command::Xpending(Xpending::parse(&mut parser)?)),
+            "xclaim" => Ok(Command::Xclaim(Xclaim::parse(&mut parser)?)),
+            "zincrby" => Ok(Command::Zincrby(Zincrby::parse(&mut parser)?)),
+            "zrem" => Ok(Command::Zrem(Zrem::parse(&mut parser)?)),
+            "zcard" => Ok(Command::Zcard(Zcard::parse(&mut parser)?)),
             "save" => Ok(Command::Save(Save)),
+            "bgsave" => Ok(Command::Bgsave(Bgsave)),
+            "bgrewriteaof" => Ok(Command::Bgrewriteaof(Bgrewriteaof)),
+            "lastsave" => Ok(Command::Lastsave(Lastsave)),
+            "type" => Ok(Command::Type(Type::parse(&mut parser)?)),
+            "expire" => Ok(Command::Expire(Expire::parse(
+                &mut parser,
+                Expiry::Seconds,
+            )?)),
+            "pexpire" => Ok(Command::Expire(Expire::parse(
+                &mut parser,
+                Expiry::Milliseconds,
+            )?)),
+            "expireat" => Ok(Command::Expire(Expire::parse(
+                &mut parser,
+                Expiry::UnixSeconds,
+            )?)),
+            "pexpireat" => Ok(Command::Expire(Expire::parse(
+                &mut parser,
+                Expiry::UnixMilliseconds,
+            )?)),
+            "ttl" => Ok(Command::Ttl(Ttl::parse(&mut parser, false)?)),
+            "pttl" => Ok(Command::Ttl(Ttl::parse(&mut parser, true)?)),
+            "persist" => Ok(Command::Persist(Persist::parse(&mut parser)?)),
+            "subscribe" => Ok(Command::Subscribe(Subscribe::parse(&mut parser, false)?)),
+            "psubscribe" => Ok(Command::Subscribe(Subscribe::parse(&mut parser, true)?)),
+            "unsubscribe" => Ok(Command::Unsubscribe(Unsubscribe::parse(
+                &mut parser,
+                false,
+            )?)),
+            "punsubscribe" => Ok(Command::Unsubscribe(Unsubscribe::parse(&mut parser, true)?)),
+            "publish" => Ok(Command::Publish(Publish::parse(&mut parser)?)),
+            "multi" => Ok(Command::Multi(Multi)),
+            "exec" => Ok(Command::Exec(Exec)),
+            "discard" => Ok(Command::Discard(Discard)),
+            "watch" => Ok(Command::Watch(Watch::parse(&mut parser)?)),
+            "unwatch" => Ok(Command::Unwatch(Unwatch)),
+            "eval" => Ok(Command::Eval(Eval::parse(&mut parser, false)?)),
+            "evalsha" => Ok(Command::Eval(Eval::parse(&mut parser, true)?)),
+            "script" => Ok(Command::Script(Script::parse(&mut parser)?)),
+            "pubsub" => Ok(Command::Pubsub(Pubsub::parse(&mut parser)?)),
+            "hello" => Ok(Command::Hello(Hello::parse(&mut parser)?)),
+            "config" => Ok(Command::Config(Config::parse(&mut parser)?)),
+            "info" => Ok(Command::Info(Info::parse(&mut parser)?)),
+            "replicaof" | "slaveof" => Ok(Command::Replicaof(Replicaof::parse(&mut parser)?)),
+            "role" => Ok(Command::Role(Role)),
+            "replconf" => Ok(Command::Replconf(Replconf::parse(&mut parser)?)),
+            "psync" => Ok(Command::Psync(Psync::parse(&mut parser)?)),
+            "select" => Ok(Command::Select(Select::parse(&mut parser)?)),
+            "keys" => Ok(Command::Keys(Keys::parse(&mut parser)?)),
+            "scan" => Ok(Command::Scan(Scan::parse(&mut parser)?)),
+            "rename" => Ok(Command::Rename(Rename::parse(&mut parser, false)?)),
+            "renamenx" => Ok(Command::Rename(Rename::parse(&mut parser, true)?)),
+            "dbsize" => Ok(Command::Dbsize(Dbsize)),
+            "flushdb" => Ok(Command::Flush(Flush::parse(&mut parser, false)?)),
+            "flushall" => Ok(Command::Flush(Flush::parse(&mut parser, true)?)),
+            "randomkey" => Ok(Command::Randomkey(Randomkey)),
+            "client" => Ok(Command::Client(Client::parse(&mut parser)?)),
+            "monitor" => Ok(Command::Monitor(Monitor)),
+            "slowlog" => Ok(Command::Slowlog(Slowlog::parse(&mut parser)?)),
+            "auth" => Ok(Command::Auth(Auth::parse(&mut parser)?)),
+            "acl" => Ok(Command::Acl(Acl::parse(&mut parser)?)),
+            "sadd" => Ok(Command::Sadd(Sadd::parse(&mut parser)?)),
+            "srem" => Ok(Command::Srem(Srem::parse(&mut parser)?)),
+            "smembers" => Ok(Command::Smembers(Smembers::parse(&mut parser)?)),
+            "sismember" => Ok(Command::Sismember(Sismember::parse(&mut parser, false)?)),
+            "smismember" => Ok(Command::Sismember(Sismember::parse(&mut parser, true)?)),
+            "scard" => Ok(Command::Scard(Scard::parse(&mut parser)?)),
+            "sinter" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Inter,
+                false,
+            )?)),
+            "sinterstore" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Inter,
+                true,
+            )?)),
+            "sunion" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Union,
+                false,
+            )?)),
+            "sunionstore" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Union,
+                true,
+            )?)),
+            "sdiff" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Diff,
+                false,
+            )?)),
+            "sdiffstore" => Ok(Command::Setop(Setop::parse(
+                &mut parser,
+                Operation::Diff,
+                true,
+            )?)),
+            "sintercard" => Ok(Command::Sintercard(Sintercard::parse(&mut parser)?)),
+            "spop" => Ok(Command::Spop(Spop::parse(&mut parser)?)),
+            "srandmember" => Ok(Command::Srandmember(Srandmember::parse(&mut parser)?)),
+            "smove" => Ok(Command::Smove(Smove::parse(&mut parser)?)),
+            "asking" => Ok(Command::Asking(Asking)),
+            "cluster" => Ok(Command::Cluster(Cluster::parse(&mut parser)?)),
+            "dump" => Ok(Command::Dump(Dump::parse(&mut parser)?)),
+            "migrate" => Ok(Command::Migrate(Migrate::parse(&mut parser)?)),
+            "restore" => Ok(Command::Restore(Restore::parse(&mut parser, false)?)),
+            "restore-asking" => Ok(Command::Restore(Restore::parse(&mut parser, true)?)),
             command => {
                 warn!("command: {command}");
-                Ok(Command::Unknown)
+                bail!("ERR unknown command")
             }
         }
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+    pub fn propagation(&self) -> Propagation {
+        match self {
+            Command::Set(_)
+            | Command::Del(_)
+            | Command::Incr(_)
+            | Command::Decr(_)
+            | Command::Lpush(_)
+            | Command::Rpush(_)
+            | Command::Lpop(_)
+            | Command::Rpop(_)
+            | Command::Lset(_)
+            | Command::Ltrim(_)
+            | Command::Lrem(_)
+            | Command::Linsert(_)
+            | Command::Hset(_)
+            | Command::Hdel(_)
+            | Command::Hincrby(_)
+            | Command::Zadd(_)
+            | Command::Zincrby(_)
+            | Command::Zrem(_)
+            | Command::Rename(_)
+            | Command::Flush(_)
+            | Command::Sadd(_)
+            | Command::Srem(_)
+            | Command::Smove(_)
+            | Command::Mset(_)
+            | Command::Getdel(_)
+            | Command::Append(_)
+            | Command::Setrange(_)
+            | Command::Incrby(_)
+            | Command::Xdel(_)
+            | Command::Xtrim(_)
+            | Command::Xgroup(_)
+            | Command::Xack(_) => Propagation::Verbatim,
+            Command::Setop(setop) if setop.is_store() => Propagation::Verbatim,
+            Command::Blpop(_)
+            | Command::Brpop(_)
+            | Command::Blmove(_)
+            | Command::Expire(_)
+            | Command::Exec(_)
+            | Command::Eval(_)
+            | Command::Spop(_)
+            | Command::Persist(_)
+            | Command::Getex(_)
+            | Command::Incrbyfloat(_)
+            | Command::Xadd(_)
+            | Command::Xclaim(_)
+            | Command::Restore(_)
+            | Command::Migrate(_) => Propagation::Effects,
+            Command::Xread(xread) if xread.is_group() => Propagation::Effects,
+            _ => Propagation::None,
+        }
+    }
+
+    /// Whether the command is served by a node importing the slot of its keys even if the
+    /// client didn't send `ASKING` first, see [`Db::check_slot`].
+    pub fn is_asking(&self) -> bool {
+        matches!(self, Command::Restore(restore) if restore.is_asking())
+    }
+
+    /// What a blocking command replies once it times out, which it does right away within a
+    /// transaction or a script.
+    pub fn timeout_reply(&self) -> Frame {
         match self {
-            Command::Ping(ping) => ping.execute(conn),
-            Command::Echo(echo) => echo.execute(conn),
-            Command::Set(set) => set.execute(conn, db),
-            Command::Get(get) => get.execute(conn, db),
-            Command::Exists(exists) => exists.execute(conn, db),
-            Command::Del(del) => del.execute(conn, db),
-            Command::Incr(incr) => incr.execute(conn, db),
-            Command::Decr(decr) => decr.execute(conn, db),
-            Command::Lpush(lpush) => lpush.execute(conn, db),
-            Command::Rpush(rpush) => rpush.execute(conn, db),
-            Command::Save(save) => save.execute(conn, db),
-            Command::Unknown => {
-                let frame = Frame::Error("ERR unknown command".to_string());
-                conn.write_frame(frame)
+            Command::Blmove(_) => Frame::Null,
+            _ => Frame::NullArray,
+        }
+    }
+
+    /// `SCRIPT KILL` is executed while a script is running, since it is meant to stop it.
+    pub fn is_allowed_while_busy(&self) -> bool {
+        matches!(self, Command::Script(Script::Kill))
+    }
+
+    /// Transactions and scripts are executed on their own, see [`Db::run_command`]. So are
+    /// `CONFIG`, which can turn the AOF on and off, and `REPLICAOF`, which changes the config.
+    pub fn is_exclusive(&self) -> bool {
+        matches!(
+            self,
+            Command::Exec(_) | Command::Eval(_) | Command::Config(_) | Command::Replicaof(_)
+        )
+    }
+
+    /// Whether the command can change the keyspace, which read-only replicas don't let
+    /// clients do. Transactions and scripts are checked command by command.
+    pub fn is_write(&self) -> bool {
+        !matches!(self.propagation(), Propagation::None)
+            && !matches!(self, Command::Exec(_) | Command::Eval(_))
+    }
+
+    /// Evicts keys if needed before running a command that can use more memory, see
+    /// [`Db::make_room`]. Fails when the command would exceed `maxmemory`.
+    pub fn make_room(&self, db: &Db) -> io::Result<()> {
+        let may_use_memory = matches!(
+            self,
+            Command::Set(_)
+                | Command::Incr(_)
+                | Command::Decr(_)
+                | Command::Lpush(_)
+                | Command::Rpush(_)
+                | Command::Lset(_)
+                | Command::Linsert(_)
+                | Command::Blmove(_)
+                | Command::Hset(_)
+                | Command::Hincrby(_)
+                | Command::Zadd(_)
+                | Command::Zincrby(_)
+                | Command::Sadd(_)
+                | Command::Setop(_)
+                | Command::Smove(_)
+                | Command::Mset(_)
+                | Command::Append(_)
+                | Command::Setrange(_)
+                | Command::Incrby(_)
+                | Command::Incrbyfloat(_)
+                | Command::Xadd(_)
+                | Command::Xgroup(_)
+                | Command::Xclaim(_)
+                | Command::Restore(_)
+        ) || matches!(self, Command::Xread(xread) if xread.is_group());
+        if may_use_memory {
+            db.make_room()?;
+        }
+        Ok(())
+    }
+
+    /// Whether a script can call the command with `redis.call`.
+    pub fn is_allowed_in_script(&self) -> bool {
+        !matches!(
+            self,
+            Command::Multi(_)
+                | Command::Exec(_)
+                | Command::Discard(_)
+                | Command::Watch(_)
+                | Command::Unwatch(_)
+                | Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::Bgrewriteaof(_)
+                | Command::Eval(_)
+                | Command::Script(_)
+                | Command::Hello(_)
+                | Command::Config(_)
+                | Command::Replicaof(_)
+                | Command::Replconf(_)
+                | Command::Psync(_)
+                | Command::Client(_)
+                | Command::Monitor(_)
+                | Command::Auth(_)
+                | Command::Acl(_)
+                | Command::Asking(_)
+                | Command::Cluster(_)
+                | Command::Migrate(_)
+        )
+    }
+
+    /// Whether the clients running `MONITOR` are sent the command. Like in redis,
+    /// administrative commands aren't.
+    pub fn is_monitored(&self) -> bool {
+        !matches!(
+            self,
+            Command::Save(_)
+                | Command::Bgsave(_)
+                | Command::Bgrewriteaof(_)
+                | Command::Config(_)
+                | Command::Replicaof(_)
+                | Command::Replconf(_)
+                | Command::Psync(_)
+                | Command::Monitor(_)
+                | Command::Slowlog(_)
+        )
+    }
+
+    /// The command as it is shown by `MONITOR` and `SLOWLOG`, given what the client sent.
+    /// Like in redis, passwords are replaced with `(redacted)`.
+    pub fn redact<'a>(&self, frame: &'a Frame) -> Cow<'a, Frame> {
+        let Frame::Array(args) = frame else {
+            return Cow::Borrowed(frame);
+        };
+        let is = |index: usize, name: &str| matches!(args.get(index), Some(Frame::BulkString(arg)) if arg.eq_ignore_ascii_case(name.as_bytes()));
+        let redacted: Vec<usize> = match self {
+            Command::Auth(_) => (1..args.len()).collect(),
+            Command::Acl(Acl::Setuser { .. }) => (3..args.len()).collect(),
+            Command::Hello(_) => (2..args.len())
+                .filter(|&index| is(index, "auth"))
+                .flat_map(|index| [index + 1, index + 2])
+                .collect(),
+            Command::Config(_) if is(1, "set") => (2..args.len())
+                .step_by(2)
+                .filter(|&index| is(index, "requirepass"))
+                .map(|index| index + 1)
+                .collect(),
+            _ => vec![],
+        };
+        if redacted.is_empty() {
+            return Cow::Borrowed(frame);
+        }
+        Cow::Owned(Frame::Array(
+            args.iter()
+                .enumerate()
+                .map(|(index, arg)| {
+                    if redacted.contains(&index) {
+                        Frame::BulkString(Bytes::from_static(b"(redacted)"))
+                    } else {
+                        arg.clone()
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    /// Queues the command if the client started a transaction with `MULTI`, replying `QUEUED`.
+    /// Otherwise the command is given back, to be executed right away.
+    ///
+    /// `frame` is what the client sent, which is appended to the AOF if the command is
+    /// propagated as is.
+    pub fn queue(self, conn: &mut Connection, frame: Option<&Frame>) -> Option<Self> {
+        let Some(transaction) = conn.transaction_mut() else {
+            return Some(self);
+        };
+        match self {
+            // These manage the transaction itself.
+            Command::Multi(_) | Command::Exec(_) | Command::Discard(_) | Command::Watch(_) => {
+                Some(self)
+            }
+            // Pub/sub confirmations can't be part of the reply to `EXEC`, nor can the commands
+            // sent to a monitor. Rewriting the AOF, turning it on and off, or changing what is
+            // replicated, needs the lock that a transaction holds while it runs. A transaction
+            // can't wait for `MIGRATE`'s target to reply either.
+            Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::Bgrewriteaof(_)
+            | Command::Config(_)
+            | Command::Replicaof(_)
+            | Command::Psync(_)
+            | Command::Monitor(_)
+            | Command::Migrate(_) => {
+                transaction.fail();
+                conn.send_error("ERR Command not allowed inside a transaction");
+                None
+            }
+            command => {
+                transaction.queue(command, frame);
+                conn.write_frame(Frame::SimpleString("QUEUED".to_owned()));
+                None
             }
         }
     }
+
+    /// Whether the command can be executed by a client that has subscribed to channels.
+    fn is_allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Ping(_)
+        )
+    }
+
+    /// Executes the command and returns its reply. There is no reply when the client is blocked
+    /// (see [`Connection::park`]), nor when subscribing, since the confirmations are pushed.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        if conn.in_subscribed_mode() && !self.is_allowed_when_subscribed() {
+            return Err(io::Error::other(
+                "ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT allowed in this context",
+            ));
+        }
+        let reply = match self {
+            Command::Ping(ping) => Ok(ping.execute(conn)),
+            Command::Echo(echo) => Ok(echo.execute()),
+            Command::Set(set) => set.execute(db),
+            Command::Get(get) => get.execute(db),
+            Command::Exists(exists) => exists.execute(db),
+            Command::Del(del) => del.execute(db),
+            Command::Incr(incr) => incr.execute(db),
+            Command::Decr(decr) => decr.execute(db),
+            Command::Mget(mget) => mget.execute(db),
+            Command::Mset(mset) => mset.execute(db),
+            Command::Getdel(getdel) => getdel.execute(db),
+            Command::Getex(getex) => getex.execute(db),
+            Command::Append(append) => append.execute(db),
+            Command::Strlen(strlen) => strlen.execute(db),
+            Command::Getrange(getrange) => getrange.execute(db),
+            Command::Setrange(setrange) => setrange.execute(db),
+            Command::Incrby(incrby) => incrby.execute(db),
+            Command::Incrbyfloat(incrbyfloat) => incrbyfloat.execute(db),
+            Command::Lpush(lpush) => lpush.execute(db),
+            Command::Rpush(rpush) => rpush.execute(db),
+            Command::Lrange(lrange) => lrange.execute(db),
+            Command::Lpop(lpop) => lpop.execute(db),
+            Command::Rpop(rpop) => rpop.execute(db),
+            Command::Llen(llen) => llen.execute(db),
+            Command::Lindex(lindex) => lindex.execute(db),
+            Command::Lset(lset) => lset.execute(db),
+            Command::Ltrim(ltrim) => ltrim.execute(db),
+            Command::Lrem(lrem) => lrem.execute(db),
+            Command::Linsert(linsert) => linsert.execute(db),
+            Command::Blpop(blpop) => return blpop.execute(conn, db),
+            Command::Brpop(brpop) => return brpop.execute(conn, db),
+            Command::Blmove(blmove) => return blmove.execute(conn, db),
+            Command::Hset(hset) => hset.execute(db),
+            Command::Hget(hget) => hget.execute(db),
+            Command::Hmget(hmget) => hmget.execute(db),
+            Command::Hdel(hdel) => hdel.execute(db),
+            Command::Hgetall(hgetall) => hgetall.execute(db),
+            Command::Hincrby(hincrby) => hincrby.execute(db),
+            Command::Hexists(hexists) => hexists.execute(db),
+            Command::Hlen(hlen) => hlen.execute(db),
+            Command::Hkeys(hkeys) => hkeys.execute(db),
+            Command::Hvals(hvals) => hvals.execute(db),
+            Command::Zadd(zadd) => zadd.execute(db),
+            Command::Zrange(zrange) => zrange.execute(db),
+            Command::Zrank(zrank) => zrank.execute(db),
+            Command::Zscore(zscore) => zscore.execute(db),
+            Command::Xadd(xadd) => xadd.execute(db),
+            Command::Xlen(xlen) => xlen.execute(db),
+            Command::Xrange(xrange) => xrange.execute(db),
+            Command::Xdel(xdel) => xdel.execute(db),
+            Command::Xtrim(xtrim) => xtrim.execute(db),
+            Command::Xread(xread) => return xread.execute(conn, db),
+            Command::Xgroup(xgroup) => xgroup.execute(db),
+            Command::Xack(xack) => xack.execute(db),
+            Command::Xpending(xpending) => xpending.execute(db),
+            Command::Xclaim(xclaim) => xclaim.execute(db),
+            Command::Zincrby(zincrby) => zincrby.execute(db),
+            Command::Zrem(zrem) => zrem.execute(db),
+            Command::Zcard(zcard) => zcard.execute(db),
+            Command::Save(save) => save.execute(db),
+            Command::Bgsave(bgsave) => bgsave.execute(db),
+            Command::Bgrewriteaof(bgrewriteaof) => bgrewriteaof.execute(db),
+            Command::Lastsave(lastsave) => lastsave.execute(db),
+            Command::Type(key_type) => key_type.execute(db),
+            Command::Expire(expire) => expire.execute(db),
+            Command::Ttl(ttl) => ttl.execute(db),
+            Command::Persist(persist) => persist.execute(db),
+            Command::Subscribe(subscribe) => return subscribe.execute(conn, db).map(|_| None),
+            Command::Unsubscribe(unsubscribe) => {
+                return unsubscribe.execute(conn, db).map(|_| None)
+            }
+            Command::Publish(publish) => publish.execute(db),
+            Command::Multi(multi) => multi.execute(conn),
+            Command::Exec(exec) => exec.execute(conn, db),
+            Command::Discard(discard) => discard.execute(conn, db),
+            Command::Watch(watch) => watch.execute(conn, db),
+            Command::Unwatch(unwatch) => unwatch.execute(conn, db),
+            Command::Eval(eval) => eval.execute(conn, db),
+            Command::Script(script) => script.execute(db),
+            Command::Pubsub(pubsub) => pubsub.execute(db),
+            Command::Hello(hello) => hello.execute(conn, db),
+            Command::Config(config) => config.execute(db),
+            Command::Info(info) => info.execute(db),
+            Command::Replicaof(replicaof) => replicaof.execute(db),
+            Command::Role(role) => role.execute(db),
+            Command::Replconf(replconf) => return replconf.execute(conn, db),
+            Command::Psync(psync) => psync.execute(conn, db),
+            Command::Select(select) => select.execute(conn, db),
+            Command::Keys(keys) => keys.execute(db),
+            Command::Scan(scan) => scan.execute(db),
+            Command::Rename(rename) => rename.execute(db),
+            Command::Dbsize(dbsize) => dbsize.execute(db),
+            Command::Flush(flush) => flush.execute(db),
+            Command::Randomkey(randomkey) => randomkey.execute(db),
+            Command::Client(client) => client.execute(conn, db),
+            Command::Monitor(monitor) => monitor.execute(conn, db),
+            Command::Slowlog(slowlog) => slowlog.execute(db),
+            Command::Auth(auth) => auth.execute(conn, db),
+            Command::Acl(acl) => acl.execute(conn, db),
+            Command::Sadd(sadd) => sadd.execute(db),
+            Command::Srem(srem) => srem.execute(db),
+            Command::Smembers(smembers) => smembers.execute(db),
+            Command::Sismember(sismember) => sismember.execute(db),
+            Command::Scard(scard) => scard.execute(db),
+            Command::Setop(setop) => setop.execute(db),
+            Command::Sintercard(sintercard) => sintercard.execute(db),
+            Command::Spop(spop) => spop.execute(db),
+            Command::Srandmember(srandmember) => srandmember.execute(db),
+            Command::Smove(smove) => smove.execute(db),
+            Command::Asking(asking) => asking.execute(conn, db),
+            Command::Cluster(cluster) => cluster.execute(db),
+            Command::Dump(dump) => dump.execute(db),
+            Command::Migrate(migrate) => return migrate.execute(conn, db),
+            Command::Restore(restore) => restore.execute(db),
+        };
+        reply.map(Some)
+    }
+
+    /// Executes the command the client sent as `frame`, telling about the keys it changes if it
+    /// is a write command, see [`Db::notify_writes`]. Otherwise the keys it reads are
+    /// remembered if the client is tracking them, before it runs, so that a change made in the
+    /// meantime isn't missed.
+    pub fn execute_and_notify(
+        self,
+        conn: &mut Connection,
+        db: &Db,
+        frame: &Frame,
+    ) -> io::Result<Option<Frame>> {
+        if !self.is_write() {
+            conn.track_reads(frame);
+            return self.execute(conn, db);
+        }
+        let client = conn.id();
+        db.notify_writes(frame, Some(client), || self.execute(conn, db))
+    }
 }
 
 pub struct ParseFrames {
@@ -120,6 +1001,16 @@ impl ParseFrames {
         }
     }
 
+    fn next_integer(&mut self) -> anyhow::Result<Option<i64>> {
+        self.next_string()?
+            .map(|value| {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| anyhow!("ERR value is not an integer or out of range"))
+            })
+            .transpose()
+    }
+
     fn next_bytes(&mut self) -> anyhow::Result<Option<Bytes>> {
         match self.items.next() {
             Some(Frame::SimpleString(s)) => Ok(Some(s.into())),
@@ -131,3 +1022,782 @@ impl ParseFrames {
         }
     }
 }
+
+/// Parses a sorted set score. Infinite scores are valid, but NaN is not.
+pub(crate) fn parse_score(score: &str) -> anyhow::Result<f64> {
+    score
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| anyhow!("ERR value is not a valid float"))
+}
+
+/// Parses the timeout of blocking commands, given in seconds. Zero means blocking forever.
+pub(crate) fn parse_timeout(timeout: &str) -> anyhow::Result<Option<Duration>> {
+    let timeout = timeout
+        .parse::<f64>()
+        .ok()
+        .filter(|timeout| timeout.is_finite())
+        .ok_or_else(|| anyhow!("ERR timeout is not a float or out of range"))?;
+    if timeout < 0.0 {
+        bail!("ERR timeout is negative");
+    }
+    if timeout == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(timeout)
+        .map(Some)
+        .map_err(|_| anyhow!("ERR timeout is out of range"))
+}
+
+/// Converts a redis style inclusive `start`/`stop` range, where negative indexes count from the
+/// end, into positions that are valid for a collection of `len` items.
+/// Returns `None` if the range does not contain any item.
+pub(crate) fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+/// Converts a redis style index, where negative indexes count from the end, into a position
+/// within a collection of `len` items.
+pub(crate) fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let len = len as i64;
+    let index = if index < 0 { len + index } else { index };
+    (0..len).contains(&index).then_some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use rstest::rstest;
+
+    use super::Command;
+    use crate::{
+        connection::Connection,
+        db::{Db, WRONG_TYPE},
+        frame::{Frame, Protocol},
+        pubsub::{Subscriber, Target},
+    };
+
+    fn connect() -> Connection {
+        let (stream, _) = tokio::io::duplex(1024);
+        Connection::new(stream)
+    }
+
+    /// Runs the command the way the server does, replying with errors as frames.
+    fn run(conn: &mut Connection, db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::new_command(args.iter().map(|arg| Bytes::from(arg.to_string())));
+        let command = match Command::from_frame(frame.clone()) {
+            Ok(command) => command,
+            Err(err) => return Frame::Error(err.to_string()),
+        };
+        let Some(command) = command.queue(conn, Some(&frame)) else {
+            return Frame::SimpleString("QUEUED".to_owned());
+        };
+        match command.execute_and_notify(conn, db, &frame) {
+            Ok(reply) => reply.unwrap_or(Frame::Null),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    #[rstest]
+    #[case(&["SETEX", "key", "10", "value"], Ok(10_000))]
+    #[case(&["PSETEX", "key", "1500", "value"], Ok(1500))]
+    #[case(&["SETEX", "key", "0", "value"], Err("ERR invalid expire time in 'setex' command"))]
+    #[case(&["PSETEX", "key", "-1", "value"], Err("ERR invalid expire time in 'psetex' command"))]
+    #[case(&["SETEX", "key", "10"], Err("ERR wrong number of arguments for 'setex' command"))]
+    #[tokio::test]
+    async fn test_setex(#[case] args: &[&str], #[case] expected: Result<u64, &str>) {
+        let db = Db::default();
+        let reply = run(&mut connect(), &db, args);
+        match expected {
+            Ok(ttl) => {
+                assert_eq!(reply, Frame::SimpleString("OK".to_owned()));
+                let left = db.ttl("key").flatten().unwrap().as_millis() as u64;
+                assert!(left <= ttl && left > ttl - 100, "{left}ms left");
+            }
+            Err(err) => {
+                assert_eq!(reply, Frame::Error(err.to_owned()));
+                assert_eq!(db.ttl("key"), None);
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(&["SET", "key", "value", "PX", "9223372036854775807"], "set")]
+    #[case(&["SET", "key", "value", "EX", "9223372036854775"], "set")]
+    #[case(&["SET", "key", "value", "EXAT", "9223372036854775"], "set")]
+    #[case(&["SETEX", "key", "9223372036854775", "value"], "setex")]
+    #[case(&["GETEX", "key", "PXAT", "9223372036854775807"], "getex")]
+    #[tokio::test]
+    async fn test_out_of_range_expiry(#[case] args: &[&str], #[case] command: &str) {
+        let db = Db::default();
+        let mut conn = connect();
+        run(&mut conn, &db, &["SET", "key", "old"]);
+        assert_eq!(
+            run(&mut conn, &db, args),
+            Frame::Error(format!("ERR invalid expire time in '{command}' command"))
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GET", "key"]),
+            Frame::BulkString(Bytes::from("old"))
+        );
+        assert_eq!(db.ttl("key"), Some(None));
+    }
+
+    #[tokio::test]
+    async fn test_string_commands() {
+        let db = Db::default();
+        let mut conn = connect();
+        let bulk = |value: &str| Frame::BulkString(Bytes::from(value.to_owned()));
+
+        assert_eq!(
+            run(&mut conn, &db, &["INCRBYFLOAT", "float", "10.5"]),
+            bulk("10.5")
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["INCRBYFLOAT", "float", "-0.25"]),
+            bulk("10.25")
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["INCRBYFLOAT", "float", "abc"]),
+            Frame::Error("ERR value is not a valid float".to_owned())
+        );
+        run(&mut conn, &db, &["SET", "word", "abc"]);
+        assert_eq!(
+            run(&mut conn, &db, &["INCRBYFLOAT", "word", "1"]),
+            Frame::Error("ERR value is not a valid float".to_owned())
+        );
+
+        assert_eq!(
+            run(&mut conn, &db, &["APPEND", "text", "Hello"]),
+            Frame::Integer(5)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["APPEND", "text", " World"]),
+            Frame::Integer(11)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SETRANGE", "text", "6", "Redis"]),
+            Frame::Integer(11)
+        );
+        assert_eq!(run(&mut conn, &db, &["GET", "text"]), bulk("Hello Redis"));
+        assert_eq!(
+            run(&mut conn, &db, &["GETRANGE", "text", "-5", "-1"]),
+            bulk("Redis")
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GETRANGE", "text", "0", "100"]),
+            bulk("Hello Redis")
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GETRANGE", "text", "20", "30"]),
+            bulk("")
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GETRANGE", "missing", "0", "-1"]),
+            bulk("")
+        );
+
+        // Writing past the end pads the string with zero bytes.
+        assert_eq!(
+            run(&mut conn, &db, &["SETRANGE", "padded", "3", "x"]),
+            Frame::Integer(4)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GET", "padded"]),
+            Frame::BulkString(Bytes::from_static(b"\0\0\0x"))
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SETRANGE", "empty", "3", ""]),
+            Frame::Integer(0)
+        );
+        assert_eq!(run(&mut conn, &db, &["EXISTS", "empty"]), Frame::Integer(0));
+        assert_eq!(
+            run(&mut conn, &db, &["SETRANGE", "text", "-1", "x"]),
+            Frame::Error("ERR offset is out of range".to_owned())
+        );
+
+        assert_eq!(
+            run(&mut conn, &db, &["MSETNX", "a", "1", "b", "2"]),
+            Frame::Integer(1)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["MSETNX", "b", "3", "c", "4"]),
+            Frame::Integer(0)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["MGET", "a", "b", "c"]),
+            Frame::Array(vec![bulk("1"), bulk("2"), Frame::Null])
+        );
+
+        run(&mut conn, &db, &["HSET", "hash", "a", "1"]);
+        for args in [
+            &["INCRBYFLOAT", "hash", "1"][..],
+            &["APPEND", "hash", "a"],
+            &["SETRANGE", "hash", "0", "a"],
+            &["GETRANGE", "hash", "0", "-1"],
+            &["GETEX", "hash"],
+        ] {
+            assert_eq!(
+                run(&mut conn, &db, args),
+                Frame::Error(WRONG_TYPE.to_owned())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_getex() {
+        let db = Db::default();
+        let mut conn = connect();
+        let value = Frame::BulkString(Bytes::from("value"));
+
+        assert_eq!(run(&mut conn, &db, &["GETEX", "key"]), Frame::Null);
+        run(&mut conn, &db, &["SET", "key", "value"]);
+        assert_eq!(run(&mut conn, &db, &["GETEX", "key"]), value);
+        assert_eq!(db.ttl("key"), Some(None));
+
+        assert_eq!(run(&mut conn, &db, &["GETEX", "key", "EX", "10"]), value);
+        let left = db.ttl("key").flatten().unwrap();
+        assert!(left > Duration::from_secs(9), "{left:?} left");
+        assert_eq!(run(&mut conn, &db, &["GETEX", "key", "PERSIST"]), value);
+        assert_eq!(db.ttl("key"), Some(None));
+
+        assert_eq!(
+            run(&mut conn, &db, &["GETEX", "key", "EX", "10", "PERSIST"]),
+            Frame::Error("ERR syntax error".to_owned())
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["GETEX", "key", "EX", "0"]),
+            Frame::Error("ERR invalid expire time in 'getex' command".to_owned())
+        );
+
+        // A deadline in the past deletes the key once its value is read.
+        assert_eq!(run(&mut conn, &db, &["GETEX", "key", "PXAT", "1"]), value);
+        assert_eq!(db.ttl("key"), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_commands() {
+        let db = Db::default();
+        let mut conn = connect();
+        let list = |values: &[&str]| {
+            Frame::Array(
+                values
+                    .iter()
+                    .map(|value| Frame::BulkString(Bytes::from(value.to_string())))
+                    .collect(),
+            )
+        };
+        let range = |conn: &mut Connection, start: &str, stop: &str| {
+            run(conn, &db, &["LRANGE", "list", start, stop])
+        };
+
+        run(&mut conn, &db, &["RPUSH", "list", "a", "b", "c", "b", "a"]);
+        assert_eq!(range(&mut conn, "1", "-2"), list(&["b", "c", "b"]));
+        assert_eq!(
+            range(&mut conn, "-100", "100"),
+            list(&["a", "b", "c", "b", "a"])
+        );
+        assert_eq!(range(&mut conn, "5", "10"), list(&[]));
+        assert_eq!(range(&mut conn, "3", "1"), list(&[]));
+        assert_eq!(
+            run(&mut conn, &db, &["LRANGE", "missing", "0", "-1"]),
+            list(&[])
+        );
+
+        // A negative count removes from the tail.
+        assert_eq!(
+            run(&mut conn, &db, &["LREM", "list", "-1", "b"]),
+            Frame::Integer(1)
+        );
+        assert_eq!(range(&mut conn, "0", "-1"), list(&["a", "b", "c", "a"]));
+        assert_eq!(
+            run(&mut conn, &db, &["LREM", "list", "0", "a"]),
+            Frame::Integer(2)
+        );
+        assert_eq!(range(&mut conn, "0", "-1"), list(&["b", "c"]));
+
+        assert_eq!(
+            run(&mut conn, &db, &["LINSERT", "list", "BEFORE", "c", "x"]),
+            Frame::Integer(3)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["LINSERT", "list", "AFTER", "c", "y"]),
+            Frame::Integer(4)
+        );
+        assert_eq!(
+            run(
+                &mut conn,
+                &db,
+                &["LINSERT", "list", "AFTER", "nopivot", "z"]
+            ),
+            Frame::Integer(-1)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["LINSERT", "missing", "AFTER", "a", "z"]),
+            Frame::Integer(0)
+        );
+        assert_eq!(range(&mut conn, "0", "-1"), list(&["b", "x", "c", "y"]));
+
+        assert_eq!(
+            run(&mut conn, &db, &["LSET", "list", "-1", "z"]),
+            Frame::SimpleString("OK".to_owned())
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["LSET", "list", "4", "z"]),
+            Frame::Error("ERR index out of range".to_owned())
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["LSET", "missing", "0", "z"]),
+            Frame::Error("ERR no such key".to_owned())
+        );
+
+        assert_eq!(
+            run(&mut conn, &db, &["LTRIM", "list", "1", "-2"]),
+            Frame::SimpleString("OK".to_owned())
+        );
+        assert_eq!(range(&mut conn, "0", "-1"), list(&["x", "c"]));
+        // A range that is empty removes the key.
+        run(&mut conn, &db, &["LTRIM", "list", "5", "10"]);
+        assert_eq!(run(&mut conn, &db, &["EXISTS", "list"]), Frame::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_set_commands() {
+        let db = Db::default();
+        let mut conn = connect();
+        // Sets are unordered, so their members are compared sorted.
+        let members = |frame: Frame| {
+            let Frame::Set(members) = frame else {
+                panic!("{frame:?} is not a set");
+            };
+            let mut members: Vec<_> = members
+                .into_iter()
+                .map(|member| match member {
+                    Frame::BulkString(member) => String::from_utf8(member.to_vec()).unwrap(),
+                    other => panic!("{other:?} is not a member"),
+                })
+                .collect();
+            members.sort();
+            members
+        };
+
+        run(&mut conn, &db, &["SADD", "a", "1", "2", "3", "4"]);
+        run(&mut conn, &db, &["SADD", "b", "3", "4", "5"]);
+        assert_eq!(
+            members(run(&mut conn, &db, &["SINTER", "a", "b"])),
+            ["3", "4"]
+        );
+        assert_eq!(
+            members(run(&mut conn, &db, &["SUNION", "a", "b", "missing"])),
+            ["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(
+            members(run(&mut conn, &db, &["SDIFF", "a", "b"])),
+            ["1", "2"]
+        );
+        assert_eq!(
+            members(run(&mut conn, &db, &["SINTER", "a", "missing"])),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(
+            run(&mut conn, &db, &["SINTERSTORE", "dest", "a", "b"]),
+            Frame::Integer(2)
+        );
+        assert_eq!(
+            members(run(&mut conn, &db, &["SMEMBERS", "dest"])),
+            ["3", "4"]
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SUNIONSTORE", "dest", "a", "b"]),
+            Frame::Integer(5)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SDIFFSTORE", "dest", "a", "b"]),
+            Frame::Integer(2)
+        );
+        assert_eq!(
+            members(run(&mut conn, &db, &["SMEMBERS", "dest"])),
+            ["1", "2"]
+        );
+        // An empty result removes the destination.
+        assert_eq!(
+            run(&mut conn, &db, &["SDIFFSTORE", "dest", "b", "a", "b"]),
+            Frame::Integer(0)
+        );
+        assert_eq!(run(&mut conn, &db, &["EXISTS", "dest"]), Frame::Integer(0));
+
+        assert_eq!(
+            run(&mut conn, &db, &["SMOVE", "a", "b", "1"]),
+            Frame::Integer(1)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SMOVE", "a", "b", "1"]),
+            Frame::Integer(0)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SMOVE", "a", "new", "2"]),
+            Frame::Integer(1)
+        );
+        assert_eq!(members(run(&mut conn, &db, &["SMEMBERS", "a"])), ["3", "4"]);
+        assert_eq!(members(run(&mut conn, &db, &["SMEMBERS", "new"])), ["2"]);
+
+        let popped = members(run(&mut conn, &db, &["SPOP", "b", "3"]));
+        assert_eq!(popped.len(), 3);
+        let left = members(run(&mut conn, &db, &["SMEMBERS", "b"]));
+        let mut all: Vec<_> = popped.into_iter().chain(left).collect();
+        all.sort();
+        assert_eq!(all, ["1", "3", "4", "5"]);
+        assert_eq!(members(run(&mut conn, &db, &["SPOP", "b", "10"])).len(), 1);
+        assert_eq!(run(&mut conn, &db, &["EXISTS", "b"]), Frame::Integer(0));
+
+        let Frame::Array(repeated) = run(&mut conn, &db, &["SRANDMEMBER", "a", "-5"]) else {
+            panic!("SRANDMEMBER with a count replies with an array");
+        };
+        assert_eq!(repeated.len(), 5);
+        assert_eq!(
+            run(&mut conn, &db, &["SRANDMEMBER", "a", "-100000000"]),
+            Frame::Error("ERR value is out of range".to_owned())
+        );
+
+        run(&mut conn, &db, &["SET", "string", "value"]);
+        for args in [
+            &["SINTER", "a", "string"][..],
+            &["SUNIONSTORE", "dest", "string"],
+            &["SMOVE", "string", "a", "3"],
+            &["SMOVE", "a", "string", "3"],
+            &["SPOP", "string", "1"],
+        ] {
+            assert_eq!(
+                run(&mut conn, &db, args),
+                Frame::Error(WRONG_TYPE.to_owned())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hello() {
+        let db = Db::default();
+        db.acl().set_requirepass("secret");
+        let mut conn = connect();
+        let proto = |reply: Frame| {
+            let Frame::Map(fields) = reply else {
+                panic!("{reply:?} is not a map");
+            };
+            fields
+                .into_iter()
+                .find(|(field, _)| *field == Frame::BulkString(Bytes::from("proto")))
+                .map(|(_, proto)| proto)
+        };
+
+        let Frame::Error(noauth) = run(&mut conn, &db, &["HELLO", "3"]) else {
+            panic!("HELLO ran without authenticating");
+        };
+        assert!(noauth.starts_with("NOAUTH "));
+        assert_eq!(
+            run(
+                &mut conn,
+                &db,
+                &["HELLO", "3", "AUTH", "default", "wrong", "SETNAME", "name"]
+            ),
+            Frame::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_owned()
+            )
+        );
+        assert_eq!(conn.user(), None);
+        assert_eq!(conn.protocol(), Protocol::Resp2);
+        assert_eq!(conn.name(), None);
+
+        let reply = run(
+            &mut conn,
+            &db,
+            &["HELLO", "3", "AUTH", "default", "secret", "SETNAME", "name"],
+        );
+        assert_eq!(proto(reply), Some(Frame::Integer(3)));
+        assert_eq!(conn.user(), Some("default"));
+        assert_eq!(conn.protocol(), Protocol::Resp3);
+        assert_eq!(conn.name(), Some(&Bytes::from("name")));
+
+        assert_eq!(
+            run(&mut conn, &db, &["HELLO", "2", "SETNAME", "bad name"]),
+            Frame::Error(
+                "ERR Client names cannot contain spaces, newlines or special characters."
+                    .to_owned()
+            )
+        );
+        assert_eq!(conn.protocol(), Protocol::Resp3);
+        assert_eq!(
+            run(&mut conn, &db, &["HELLO", "4"]),
+            Frame::Error("NOPROTO unsupported protocol version".to_owned())
+        );
+        assert_eq!(
+            proto(run(&mut conn, &db, &["HELLO", "2"])),
+            Some(Frame::Integer(2))
+        );
+        assert_eq!(conn.protocol(), Protocol::Resp2);
+        // Without a version, HELLO replies in the current one.
+        assert_eq!(
+            proto(run(&mut conn, &db, &["HELLO"])),
+            Some(Frame::Integer(2))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_commands() {
+        let db = Db::default();
+        let mut conn = connect();
+        let bulk = |value: &str| Frame::BulkString(Bytes::from(value.to_owned()));
+
+        assert_eq!(
+            run(&mut conn, &db, &["HSET", "hash", "a", "1", "b", "2"]),
+            Frame::Integer(2)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["HSET", "hash", "a", "3"]),
+            Frame::Integer(0)
+        );
+        assert_eq!(run(&mut conn, &db, &["HGET", "hash", "a"]), bulk("3"));
+        assert_eq!(
+            run(&mut conn, &db, &["HGET", "hash", "missing"]),
+            Frame::Null
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["HINCRBY", "hash", "b", "-5"]),
+            Frame::Integer(-3)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["HINCRBY", "hash", "c", "4"]),
+            Frame::Integer(4)
+        );
+        assert_eq!(
+            run(
+                &mut conn,
+                &db,
+                &["HINCRBY", "hash", "a", "9223372036854775807"]
+            ),
+            Frame::Error("ERR increment or decrement would overflow".to_owned())
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["HDEL", "hash", "b", "c", "missing"]),
+            Frame::Integer(2)
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["HGETALL", "hash"]),
+            Frame::Map(vec![(bulk("a"), bulk("3"))])
+        );
+
+        // The key goes away along with its last field.
+        assert_eq!(
+            run(&mut conn, &db, &["HDEL", "hash", "a"]),
+            Frame::Integer(1)
+        );
+        assert_eq!(run(&mut conn, &db, &["EXISTS", "hash"]), Frame::Integer(0));
+        assert_eq!(
+            run(&mut conn, &db, &["HGETALL", "hash"]),
+            Frame::Map(vec![])
+        );
+
+        run(&mut conn, &db, &["SET", "string", "value"]);
+        for args in [
+            &["HSET", "string", "a", "1"][..],
+            &["HGET", "string", "a"],
+            &["HDEL", "string", "a"],
+            &["HGETALL", "string"],
+            &["HINCRBY", "string", "a", "1"],
+        ] {
+            assert_eq!(
+                run(&mut conn, &db, args),
+                Frame::Error(WRONG_TYPE.to_owned())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_that_change_nothing_leave_watched_keys_alone() {
+        let db = Db::default();
+        let (mut watcher, mut other) = (connect(), connect());
+        run(&mut other, &db, &["RPUSH", "list", "a"]);
+
+        run(&mut watcher, &db, &["WATCH", "list", "missing"]);
+        assert_eq!(run(&mut other, &db, &["LPOP", "missing"]), Frame::Null);
+        assert_eq!(
+            run(&mut other, &db, &["LREM", "list", "0", "nomatch"]),
+            Frame::Integer(0)
+        );
+        run(&mut watcher, &db, &["MULTI"]);
+        run(&mut watcher, &db, &["LLEN", "list"]);
+        assert_eq!(
+            run(&mut watcher, &db, &["EXEC"]),
+            Frame::Array(vec![Frame::Integer(1)])
+        );
+
+        run(&mut watcher, &db, &["WATCH", "list"]);
+        run(&mut other, &db, &["LREM", "list", "0", "a"]);
+        run(&mut watcher, &db, &["MULTI"]);
+        run(&mut watcher, &db, &["LLEN", "list"]);
+        assert_eq!(run(&mut watcher, &db, &["EXEC"]), Frame::NullArray);
+    }
+
+    #[tokio::test]
+    async fn test_writes_that_change_nothing_are_not_notified() {
+        let db = Db::default();
+        let mut conn = connect();
+        run(
+            &mut conn,
+            &db,
+            &["CONFIG", "SET", "notify-keyspace-events", "KEA"],
+        );
+        let mut subscriber = Subscriber::new(db.pubsub().clone());
+        subscriber.subscribe(Target::Pattern(Bytes::from("__keyspace@0__:*")));
+        run(&mut conn, &db, &["RPUSH", "list", "a"]);
+        run(&mut conn, &db, &["HSET", "hash", "field", "value"]);
+        run(&mut conn, &db, &["SET", "string", "value"]);
+
+        for args in [
+            &["LPOP", "missing"][..],
+            &["HDEL", "hash", "missing"],
+            &["LREM", "list", "0", "nomatch"],
+            &["SETRANGE", "string", "0", ""],
+            &["RENAME", "hash", "hash"],
+        ] {
+            run(&mut conn, &db, args);
+        }
+        run(&mut conn, &db, &["DEL", "list"]);
+
+        let event = |key: &str, event: &str| {
+            Frame::Push(
+                [
+                    "pmessage",
+                    "__keyspace@0__:*",
+                    &format!("__keyspace@0__:{key}"),
+                    event,
+                ]
+                .into_iter()
+                .map(|arg| Frame::BulkString(Bytes::from(arg.to_owned())))
+                .collect(),
+            )
+        };
+        assert_eq!(subscriber.receive().await, Some(event("list", "rpush")));
+        assert_eq!(subscriber.receive().await, Some(event("hash", "hset")));
+        assert_eq!(subscriber.receive().await, Some(event("string", "set")));
+        assert_eq!(subscriber.receive().await, Some(event("list", "del")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_script_kill() {
+        let db = Db::default();
+        let mut conn = connect();
+        conn.set_user(db.acl().initial_user());
+        // A limit too large to be a deadline is no limit at all.
+        run(
+            &mut conn,
+            &db,
+            &["CONFIG", "SET", "lua-time-limit", &u64::MAX.to_string()],
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["EVAL", "return 1", "0"]),
+            Frame::Integer(1)
+        );
+
+        run(&mut conn, &db, &["CONFIG", "SET", "lua-time-limit", "10"]);
+        let eval = |script: &'static str| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                let mut conn = connect();
+                conn.set_user(db.acl().initial_user());
+                run(&mut conn, &db, &["EVAL", script, "0"])
+            })
+        };
+        // Other clients wait for the script, until it has run for too long.
+        let wait_busy = || async {
+            while let Ok(()) = db.run_client_command(false, || ()).await {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let busy = db.run_client_command(false, || ()).await.unwrap_err();
+            assert!(busy.to_string().starts_with("BUSY "));
+        };
+
+        let script = eval("while true do end");
+        wait_busy().await;
+        assert_eq!(
+            run(&mut conn, &db, &["SCRIPT", "KILL"]),
+            Frame::SimpleString("OK".to_owned())
+        );
+        assert_eq!(
+            script.join().unwrap(),
+            Frame::Error("ERR Script killed by user with SCRIPT KILL...".to_owned())
+        );
+        assert_eq!(
+            run(&mut conn, &db, &["SCRIPT", "KILL"]),
+            Frame::Error("NOTBUSY No scripts in execution right now.".to_owned())
+        );
+
+        let script = eval("while redis.call('INCR', 'counter') < 100000 do end");
+        wait_busy().await;
+        let Frame::Error(unkillable) = run(&mut conn, &db, &["SCRIPT", "KILL"]) else {
+            panic!("a script that wrote was killed");
+        };
+        assert!(unkillable.starts_with("UNKILLABLE "));
+        assert_eq!(script.join().unwrap(), Frame::Null);
+    }
+
+    #[rstest]
+    #[case(0, -1, 3, Some((0, 2)))]
+    #[case(0, 10, 3, Some((0, 2)))]
+    #[case(-2, -1, 3, Some((1, 2)))]
+    #[case(-10, 1, 3, Some((0, 1)))]
+    #[case(2, 1, 3, None)]
+    #[case(5, 10, 3, None)]
+    #[case(0, -4, 3, None)]
+    #[case(0, -1, 0, None)]
+    #[case(i64::MIN, i64::MAX, 3, Some((0, 2)))]
+    #[case(i64::MAX, i64::MAX, 3, None)]
+    fn test_resolve_range(
+        #[case] start: i64,
+        #[case] stop: i64,
+        #[case] len: usize,
+        #[case] expected: Option<(usize, usize)>,
+    ) {
+        assert_eq!(super::resolve_range(start, stop, len), expected);
+    }
+
+    #[rstest]
+    #[case("0", Ok(None))]
+    #[case("1.5", Ok(Some(Duration::from_millis(1500))))]
+    #[case("-1", Err("ERR timeout is negative"))]
+    #[case("inf", Err("ERR timeout is not a float or out of range"))]
+    #[case("1e30", Err("ERR timeout is out of range"))]
+    fn test_parse_timeout(#[case] timeout: &str, #[case] expected: Result<Option<Duration>, &str>) {
+        let result = super::parse_timeout(timeout).map_err(|err| err.to_string());
+        assert_eq!(result, expected.map_err(str::to_owned));
+    }
+
+    #[rstest]
+    #[case(0, 3, Some(0))]
+    #[case(-1, 3, Some(2))]
+    #[case(3, 3, None)]
+    #[case(-4, 3, None)]
+    #[case(i64::MIN, 3, None)]
+    #[case(0, 0, None)]
+    fn test_resolve_index(#[case] index: i64, #[case] len: usize, #[case] expected: Option<usize>) {
+        assert_eq!(super::resolve_index(index, len), expected);
+    }
+}
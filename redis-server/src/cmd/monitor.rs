@@ -0,0 +1,16 @@
+use std::io;
+
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `MONITOR`
+///
+/// Streams every command the server executes to the client, along with when it was executed,
+/// the database it acted on and who sent it.
+pub struct Monitor;
+
+impl Monitor {
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        conn.start_monitor(db.clients());
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
@@ -0,0 +1,40 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `MSET key value [key value ...]`, or `MSETNX` when `nx` is set, which sets none of the keys
+/// if any of them exists.
+pub struct Mset {
+    pairs: Vec<(String, Bytes)>,
+    nx: bool,
+}
+
+impl Mset {
+    pub fn parse(parser: &mut ParseFrames, nx: bool) -> anyhow::Result<Self> {
+        let name = if nx { "msetnx" } else { "mset" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{name}' command");
+        let mut pairs = vec![];
+        while let Some(key) = parser.next_string()? {
+            let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+            pairs.push((key, value));
+        }
+        if pairs.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self { pairs, nx })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let written = db.set_many(self.pairs, self.nx);
+        let frame = if self.nx {
+            Frame::Integer(written as i64)
+        } else {
+            Frame::SimpleString("OK".to_owned())
+        };
+        Ok(frame)
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+use crate::{connection::Connection, frame::Frame};
+
+use super::Command;
+
+/// The commands queued since `MULTI`, which `EXEC` then executes atomically.
+#[derive(Default)]
+pub struct Transaction {
+    // Each command along with the frame the client sent, which is appended to the AOF when the
+    // command is propagated as is.
+    pub(super) commands: Vec<(Command, Option<Frame>)>,
+    // Set when a command could not be queued, in which case `EXEC` discards the transaction.
+    pub(super) failed: bool,
+}
+
+impl Transaction {
+    pub fn queue(&mut self, command: Command, frame: Option<&Frame>) {
+        self.commands.push((command, frame.cloned()));
+    }
+
+    /// The number of commands queued.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Makes `EXEC` fail, e.g. because one of the commands could not be parsed.
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+}
+
+pub struct Multi;
+
+impl Multi {
+    pub fn execute(&self, conn: &mut Connection) -> io::Result<Frame> {
+        if !conn.begin_transaction() {
+            return Err(io::Error::other("ERR MULTI calls can not be nested"));
+        }
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
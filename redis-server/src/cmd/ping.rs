@@ -1,4 +1,4 @@
-use std::io;
+use bytes::Bytes;
 
 use super::ParseFrames;
 use crate::{connection::Connection, frame::Frame};
@@ -13,12 +13,19 @@ impl Ping {
         Ok(Self { optional_message })
     }
 
-    pub fn execute(self, conn: &mut Connection) -> io::Result<()> {
-        let frame = if let Some(message) = self.optional_message {
+    /// Subscribed clients get an array instead, so that they can tell the reply apart from messages.
+    pub fn execute(self, conn: &Connection) -> Frame {
+        if conn.in_subscribed_mode() {
+            let message = self.optional_message.unwrap_or_default();
+            return Frame::Array(vec![
+                Frame::BulkString(Bytes::from_static(b"pong")),
+                Frame::BulkString(message.into()),
+            ]);
+        }
+        if let Some(message) = self.optional_message {
             Frame::SimpleString(message)
         } else {
             Frame::SimpleString("PONG".to_string())
-        };
-        conn.write_frame(frame)
+        }
     }
 }
@@ -0,0 +1,51 @@
+use std::io;
+
+use anyhow::anyhow;
+
+use super::ParseFrames;
+use crate::{
+    connection::Connection,
+    db::{Db, Replica},
+    frame::Frame,
+};
+
+/// `PSYNC replid offset`
+///
+/// Sent by a replica to be fed the replication stream, carrying on from `offset` of the
+/// stream `replid` if it can, or from a snapshot of the keyspace otherwise. `PSYNC ? -1`
+/// asks for a snapshot right away.
+pub struct Psync {
+    replid: String,
+    offset: Option<u64>,
+}
+
+impl Psync {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'psync' command");
+        let replid = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let offset = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        Ok(Self {
+            replid,
+            offset: u64::try_from(offset).ok(),
+        })
+    }
+
+    /// The connection is only used to send the replication stream from then on, see
+    /// [`Connection::start_replica_feed`].
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        let ip = conn
+            .addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        let port = conn.replica_port().unwrap_or_default();
+        let (replica, receiver) = Replica::new(conn.id(), ip, port);
+        let (reply, feed) = db.add_replica(replica, receiver, &self.replid, self.offset);
+        conn.start_replica_feed(feed);
+        Ok(reply)
+    }
+}
@@ -0,0 +1,30 @@
+use std::io;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::ParseFrames;
+
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let channel = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'publish' command"))?;
+        let message = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'publish' command"))?;
+        Ok(Self { channel, message })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let receivers = db.pubsub().publish(self.channel, self.message);
+        Ok(Frame::Integer(receivers as i64))
+    }
+}
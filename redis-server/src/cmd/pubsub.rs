@@ -0,0 +1,72 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::ParseFrames;
+
+/// Introspection of the pub/sub channels.
+pub enum Pubsub {
+    /// The channels with at least one subscriber, optionally only the ones matching a pattern.
+    Channels(Option<Bytes>),
+    /// The number of subscribers of each channel.
+    Numsub(Vec<Bytes>),
+    /// The number of patterns clients are subscribed to.
+    Numpat,
+}
+
+impl Pubsub {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'pubsub' command"))?;
+        let pubsub = match subcommand.to_lowercase().as_str() {
+            "channels" => Pubsub::Channels(parser.next_bytes()?),
+            "numsub" => {
+                let mut channels = vec![];
+                while let Some(channel) = parser.next_bytes()? {
+                    channels.push(channel);
+                }
+                return Ok(Pubsub::Numsub(channels));
+            }
+            "numpat" => Pubsub::Numpat,
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try PUBSUB HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            bail!(
+                "ERR wrong number of arguments for 'pubsub|{}' command",
+                subcommand.to_lowercase()
+            );
+        }
+        Ok(pubsub)
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let pubsub = db.pubsub();
+        let frame = match self {
+            Pubsub::Channels(pattern) => Frame::Array(
+                pubsub
+                    .channels(pattern.as_deref())
+                    .into_iter()
+                    .map(Frame::BulkString)
+                    .collect(),
+            ),
+            Pubsub::Numsub(channels) => Frame::Map(
+                channels
+                    .into_iter()
+                    .map(|channel| {
+                        let subscribers = pubsub.subscribers(channel.clone());
+                        (
+                            Frame::BulkString(channel),
+                            Frame::Integer(subscribers as i64),
+                        )
+                    })
+                    .collect(),
+            ),
+            Pubsub::Numpat => Frame::Integer(pubsub.patterns() as i64),
+        };
+        Ok(frame)
+    }
+}
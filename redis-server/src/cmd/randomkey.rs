@@ -0,0 +1,20 @@
+use std::io;
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+/// `RANDOMKEY`
+///
+/// A key of the selected database picked at random, or nil if it is empty.
+pub struct Randomkey;
+
+impl Randomkey {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let key = db.with_data(|data| data.random_key().cloned());
+        Ok(match key {
+            Some(key) => Frame::BulkString(Bytes::from(key)),
+            None => Frame::Null,
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+
+use anyhow::anyhow;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// `RENAME key newkey` and `RENAMENX key newkey`
+///
+/// Renames the key, keeping its time to live. `RENAMENX` only does so if `newkey` doesn't
+/// exist, replying whether it did.
+pub struct Rename {
+    key: String,
+    new_key: String,
+    nx: bool,
+}
+
+impl Rename {
+    pub fn parse(parser: &mut ParseFrames, nx: bool) -> anyhow::Result<Self> {
+        let command = if nx { "renamenx" } else { "rename" };
+        let mut next = || {
+            parser
+                .next_string()?
+                .ok_or_else(|| anyhow!("ERR wrong number of arguments for '{command}' command"))
+        };
+        let key = next()?;
+        let new_key = next()?;
+        Ok(Self { key, new_key, nx })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let renamed = db.rename(&self.key, self.new_key, self.nx)?;
+        if self.nx {
+            Ok(Frame::Integer(renamed as i64))
+        } else {
+            Ok(Frame::SimpleString("OK".to_owned()))
+        }
+    }
+}
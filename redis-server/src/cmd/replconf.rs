@@ -0,0 +1,68 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+
+use super::ParseFrames;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `REPLCONF option value`
+///
+/// Sent by replicas to tell their master about themselves before `PSYNC`, and afterwards to
+/// acknowledge how much of the replication stream they have processed.
+pub enum Replconf {
+    ListeningPort(u16),
+    /// Capabilities such as `psync2`, which are all supported.
+    Capa,
+    Ack(u64),
+    /// Sent by a master to its replicas through the replication stream, which is handled by
+    /// the replica's link with its master.
+    GetAck,
+}
+
+impl Replconf {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let option = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'replconf' command"))?
+            .to_lowercase();
+        let value = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        let replconf = match option.as_str() {
+            "listening-port" => Replconf::ListeningPort(
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?,
+            ),
+            "capa" => {
+                while parser.next_bytes()?.is_some() {}
+                Replconf::Capa
+            }
+            "ack" => Replconf::Ack(
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?,
+            ),
+            "getack" => Replconf::GetAck,
+            _ => bail!("ERR Unrecognized REPLCONF option: {option}"),
+        };
+        Ok(replconf)
+    }
+
+    /// Acknowledgements don't get a reply.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        let ok = Some(Frame::SimpleString("OK".to_owned()));
+        match self {
+            Replconf::ListeningPort(port) => {
+                conn.set_replica_port(port);
+                Ok(ok)
+            }
+            Replconf::Capa => Ok(ok),
+            Replconf::Ack(offset) => {
+                db.with_replication(|replication| replication.ack(conn.id(), offset));
+                Ok(None)
+            }
+            Replconf::GetAck => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// `REPLICAOF host port` or `REPLICAOF NO ONE`
+///
+/// Makes the server a replica of another one, or promotes it back to a master.
+pub struct Replicaof {
+    master: Option<(String, u16)>,
+}
+
+impl Replicaof {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'replicaof' command");
+        let host = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let port = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(Self { master: None });
+        }
+        let Ok(port) = port.parse() else {
+            bail!("ERR Invalid master port");
+        };
+        Ok(Self {
+            master: Some((host, port)),
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let replica = self.master.is_some();
+        if !db.replicaof(self.master) && replica {
+            return Ok(Frame::SimpleString(
+                "OK Already connected to specified master".to_owned(),
+            ));
+        }
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
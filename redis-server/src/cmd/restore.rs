@@ -0,0 +1,78 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// `RESTORE key ttl serialized-value [REPLACE] [ABSTTL]`
+///
+/// Creates the key from a value serialized with `DUMP`, with a time to live of `ttl`
+/// milliseconds unless it is 0, or expiring at the Unix time `ttl` in milliseconds with
+/// `ABSTTL`. `RESTORE-ASKING` is the same, but is served by a node importing the key's slot
+/// as if the client had sent `ASKING` first, which is what `MIGRATE` sends in cluster mode.
+pub struct Restore {
+    key: String,
+    ttl: i64,
+    payload: Bytes,
+    replace: bool,
+    absttl: bool,
+    asking: bool,
+}
+
+impl Restore {
+    pub fn parse(parser: &mut ParseFrames, asking: bool) -> anyhow::Result<Self> {
+        let name = if asking { "restore-asking" } else { "restore" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{name}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let ttl = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let payload = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let (mut replace, mut absttl) = (false, false);
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "replace" => replace = true,
+                "absttl" => absttl = true,
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        if ttl < 0 {
+            bail!("ERR Invalid TTL value, must be >= 0");
+        }
+        Ok(Self {
+            key,
+            ttl,
+            payload,
+            replace,
+            absttl,
+            asking,
+        })
+    }
+
+    /// Whether the command is `RESTORE-ASKING`.
+    pub fn is_asking(&self) -> bool {
+        self.asking
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let expire_at = match (self.ttl, self.absttl) {
+            (0, _) => None,
+            (ttl, true) => {
+                Some(DateTime::from_timestamp_millis(ttl).unwrap_or(DateTime::<Utc>::MAX_UTC))
+            }
+            (ttl, false) => Some(
+                Utc::now()
+                    .checked_add_signed(chrono::Duration::milliseconds(ttl))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            ),
+        };
+        db.restore(self.key, self.payload, expire_at, self.replace)?;
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
@@ -0,0 +1,55 @@
+use std::io;
+
+use bytes::Bytes;
+
+use crate::{
+    db::{Db, LinkState},
+    frame::Frame,
+};
+
+/// `ROLE`
+///
+/// Whether the server is a master, with the replicas it feeds, or a replica, with the state
+/// of its link with the master.
+pub struct Role;
+
+impl Role {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let text = |text: &str| Frame::BulkString(Bytes::from(text.to_owned()));
+        let frame = db.with_replication(|replication| match replication.master() {
+            Some(master) => {
+                let state = match master.state {
+                    LinkState::Connect => "connect",
+                    LinkState::Connecting => "connecting",
+                    LinkState::Sync => "sync",
+                    LinkState::Connected => "connected",
+                };
+                Frame::Array(vec![
+                    text("slave"),
+                    text(&master.host),
+                    Frame::Integer(master.port.into()),
+                    text(state),
+                    Frame::Integer(replication.offset() as i64),
+                ])
+            }
+            None => Frame::Array(vec![
+                text("master"),
+                Frame::Integer(replication.offset() as i64),
+                Frame::Array(
+                    replication
+                        .replicas()
+                        .iter()
+                        .map(|replica| {
+                            Frame::Array(vec![
+                                text(&replica.ip),
+                                text(&replica.port.to_string()),
+                                text(&replica.ack_offset.to_string()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ]),
+        });
+        Ok(frame)
+    }
+}
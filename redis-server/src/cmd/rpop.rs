@@ -0,0 +1,42 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Rpop {
+    key: String,
+    count: Option<i64>,
+}
+
+impl Rpop {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let count = parser.next_integer()?;
+        if count.is_some_and(|count| count < 0) {
+            return Err(anyhow!("ERR value is out of range, must be positive"));
+        }
+        Ok(Self { key, count })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_list_data_mut(self.key, |list| match self.count {
+            None if list.is_empty() => (Frame::Null, false),
+            Some(_) if list.is_empty() => (Frame::NullArray, false),
+            Some(count) => {
+                let count = (count as usize).min(list.len());
+                let values = (0..count)
+                    .filter_map(|_| list.pop_back())
+                    .map(Frame::BulkString)
+                    .collect();
+                (Frame::Array(values), count > 0)
+            }
+            None => {
+                let value = list.pop_back().expect("the list is not empty");
+                (Frame::BulkString(value), true)
+            }
+        })?;
+        Ok(frame)
+    }
+}
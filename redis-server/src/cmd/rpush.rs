@@ -3,7 +3,11 @@ use std::io;
 use bytes::Bytes;
 
 use super::ParseFrames;
-use crate::{cmd::anyhow, connection::Connection, db::Db, frame::Frame};
+use crate::{
+    cmd::anyhow,
+    db::{Db, ListEnd},
+    frame::Frame,
+};
 
 pub struct Rpush {
     key: String,
@@ -23,13 +27,9 @@ impl Rpush {
         Ok(Self { key, values })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
-        let list = db.with_list_data_mut(self.key, |list| {
-            let mut list = list;
-            list.extend(self.values);
-            list
-        })?;
-        let frame = Frame::Integer(list.len() as i64);
-        conn.write_frame(frame)
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.push(self.key, self.values, ListEnd::Right)?;
+        let frame = Frame::Integer(len as i64);
+        Ok(frame)
     }
 }
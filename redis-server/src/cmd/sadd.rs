@@ -0,0 +1,40 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Sadd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl Sadd {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut members = vec![];
+        while let Some(member) = parser.next_bytes()? {
+            members.push(member);
+        }
+        if members.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'sadd' command"));
+        }
+        Ok(Self { key, members })
+    }
+
+    /// Replies with the number of members that were added, not counting those already in the set.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let added = db.with_set_data_mut(self.key, |set| {
+            let added = self
+                .members
+                .into_iter()
+                .filter(|member| set.insert(member.clone()))
+                .count();
+            (added, added > 0)
+        })?;
+        Ok(Frame::Integer(added as i64))
+    }
+}
@@ -1,12 +1,12 @@
 use std::io;
 
-use crate::{connection::Connection, db::Db};
+use crate::{db::Db, frame::Frame};
 
 pub struct Save;
 
 impl Save {
-    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
         db.save()?;
-        conn.write_frame(crate::frame::Frame::SimpleString("OK".to_owned()))
+        Ok(Frame::SimpleString("OK".to_owned()))
     }
 }
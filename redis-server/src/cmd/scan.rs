@@ -0,0 +1,84 @@
+use std::io;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame, glob};
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+///
+/// Goes through the keyspace a few keys at a time: each call replies with the cursor to pass
+/// to the next one, which is 0 once every key has been returned. Keys that exist for the
+/// whole iteration are returned at least once, however the keyspace changes in between.
+///
+/// `COUNT` is how many keys are looked at, before `MATCH` and `TYPE` filter them, so a call
+/// can return fewer keys, or none at all, before the iteration is over.
+pub struct Scan {
+    cursor: usize,
+    pattern: Option<String>,
+    count: usize,
+    key_type: Option<String>,
+}
+
+impl Scan {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let cursor = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'scan' command"))?
+            .parse()
+            .map_err(|_| anyhow!("ERR invalid cursor"))?;
+
+        let mut scan = Self {
+            cursor,
+            pattern: None,
+            count: 10,
+            key_type: None,
+        };
+        while let Some(option) = parser.next_string()? {
+            let value = parser
+                .next_string()?
+                .ok_or_else(|| anyhow!("ERR syntax error"))?;
+            match option.to_lowercase().as_str() {
+                "match" => scan.pattern = Some(value),
+                "count" => {
+                    scan.count = value
+                        .parse()
+                        .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?;
+                    if scan.count == 0 {
+                        return Err(anyhow!("ERR syntax error"));
+                    }
+                }
+                "type" => scan.key_type = Some(value.to_lowercase()),
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+        Ok(scan)
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let (cursor, keys) = db.with_data(|data| {
+            let (cursor, keys) = data.scan(self.cursor, self.count);
+            let keys = keys
+                .into_iter()
+                .filter(|key| {
+                    self.pattern
+                        .as_ref()
+                        .is_none_or(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes()))
+                })
+                .filter(|key| {
+                    self.key_type.as_ref().is_none_or(|key_type| {
+                        data.get(key)
+                            .is_some_and(|value| value.type_name() == key_type)
+                    })
+                })
+                .map(|key| Frame::BulkString(Bytes::from(key.clone())))
+                .collect();
+            (cursor, keys)
+        });
+        Ok(Frame::Array(vec![
+            Frame::BulkString(Bytes::from(cursor.to_string())),
+            Frame::Array(keys),
+        ]))
+    }
+}
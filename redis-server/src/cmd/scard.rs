@@ -0,0 +1,22 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Scard {
+    key: String,
+}
+
+impl Scard {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_set_data(&self.key, |set| set.map_or(0, |set| set.len()))?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -0,0 +1,79 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::ParseFrames;
+
+/// Management of the scripts cache.
+pub enum Script {
+    /// Caches a script without executing it, so that it can be called with `EVALSHA`.
+    Load(Bytes),
+    Exists(Vec<String>),
+    Flush,
+    /// Stops the running script, which is only allowed while it didn't write.
+    Kill,
+}
+
+impl Script {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'script' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'script|{subcommand}' command");
+
+        let script = match subcommand.as_str() {
+            "load" => Script::Load(parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?),
+            "exists" => {
+                let mut shas = vec![];
+                while let Some(sha) = parser.next_string()? {
+                    shas.push(sha);
+                }
+                if shas.is_empty() {
+                    return Err(wrong_number_of_arguments());
+                }
+                return Ok(Script::Exists(shas));
+            }
+            "flush" => {
+                // Scripts are always flushed right away.
+                if let Some(mode) = parser.next_string()? {
+                    if !matches!(mode.to_lowercase().as_str(), "async" | "sync") {
+                        bail!("ERR SCRIPT FLUSH only support SYNC|ASYNC option");
+                    }
+                }
+                Script::Flush
+            }
+            "kill" => Script::Kill,
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try SCRIPT HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(script)
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let scripts = db.scripts();
+        let frame = match self {
+            Script::Load(script) => Frame::BulkString(scripts.load(script).into()),
+            Script::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(scripts.contains(sha) as i64))
+                    .collect(),
+            ),
+            Script::Flush => {
+                scripts.flush();
+                Frame::SimpleString("OK".to_owned())
+            }
+            Script::Kill => {
+                scripts.kill()?;
+                Frame::SimpleString("OK".to_owned())
+            }
+        };
+        Ok(frame)
+    }
+}
@@ -0,0 +1,37 @@
+use std::io;
+
+use anyhow::anyhow;
+
+use super::ParseFrames;
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// `SELECT index`
+///
+/// Makes the client's commands act on the database numbered `index`.
+pub struct Select {
+    index: i64,
+}
+
+impl Select {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let index = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'select' command"))?;
+        Ok(Self { index })
+    }
+
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        // Like redis, a cluster only has the first database.
+        if db.is_cluster_enabled() && self.index != 0 {
+            return Err(io::Error::other(
+                "ERR SELECT is not allowed in cluster mode",
+            ));
+        }
+        let index = usize::try_from(self.index)
+            .ok()
+            .filter(|index| *index < db.databases())
+            .ok_or_else(|| io::Error::other("ERR DB index is out of range"))?;
+        conn.set_db_index(index);
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
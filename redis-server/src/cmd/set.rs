@@ -1,110 +1,177 @@
-use std::{
-    io,
-    time::{Duration, SystemTime},
-};
+use std::{io, time::Duration};
 
-use anyhow::Context;
 use bytes::Bytes;
+use chrono::{DateTime, TimeDelta, Utc};
 
 use super::ParseFrames;
-use crate::{cmd::anyhow, connection::Connection, db::Db, frame::Frame};
-
-#[derive(Default)]
-struct Options {
-    expiration: Option<Duration>,
-    return_existing_value: bool,
-}
+use crate::{
+    cmd::anyhow,
+    db::{Db, SetCondition, SetOptions},
+    frame::Frame,
+};
 
+/// `SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | KEEPTTL]`
+///
+/// `SETNX key value`, `GETSET key value`, `SETEX key seconds value` and
+/// `PSETEX key milliseconds value` are variants of it.
 pub struct Set {
     key: String,
     value: Bytes,
-    options: Options,
+    options: SetOptions,
+    // `SETNX` replies 1 or 0 instead of OK or nil.
+    integer_reply: bool,
 }
 
 impl Set {
     pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
-        let key = parser
-            .next_string()?
-            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
-        let value = parser
-            .next_bytes()?
-            .ok_or_else(|| anyhow!("Expected a string for value but found None"))?;
-
+        let (key, value) = Self::key_and_value(parser, "set")?;
         let options = Self::options(parser)?;
         Ok(Self {
             key,
             value,
             options,
+            integer_reply: false,
         })
     }
 
-    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
-        let old_value = db.set(self.key, self.value, self.options.expiration);
+    /// `SETNX key value`: sets the key only if it does not exist.
+    pub fn parse_setnx(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let (key, value) = Self::key_and_value(parser, "setnx")?;
+        Self::no_more_arguments(parser, "setnx")?;
+        Ok(Self {
+            key,
+            value,
+            options: SetOptions {
+                condition: SetCondition::Nx,
+                ..SetOptions::default()
+            },
+            integer_reply: true,
+        })
+    }
 
-        let frame_to_write = if self.options.return_existing_value {
-            old_value.map(Frame::BulkString).unwrap_or(Frame::Null)
-        } else {
+    /// `GETSET key value`: sets the key and replies with its previous value.
+    pub fn parse_getset(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let (key, value) = Self::key_and_value(parser, "getset")?;
+        Self::no_more_arguments(parser, "getset")?;
+        Ok(Self {
+            key,
+            value,
+            options: SetOptions {
+                get: true,
+                ..SetOptions::default()
+            },
+            integer_reply: false,
+        })
+    }
+
+    /// `SETEX key seconds value` or `PSETEX key milliseconds value`: sets the key along with
+    /// its time to live, `unit` being the `EX` or `PX` option of `SET` that `command` stands for.
+    pub fn parse_setex(
+        parser: &mut ParseFrames,
+        command: &str,
+        unit: &str,
+    ) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{command}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let time = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        Self::no_more_arguments(parser, command)?;
+        Ok(Self {
+            key,
+            value,
+            options: SetOptions {
+                expire: Some(parse_expiry(unit, time, command)?),
+                ..SetOptions::default()
+            },
+            integer_reply: false,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let get = self.options.get;
+        let (written, previous_value) = db.set(self.key, self.value, self.options)?;
+
+        let frame_to_write = if get {
+            previous_value.map_or(Frame::Null, Frame::BulkString)
+        } else if self.integer_reply {
+            Frame::Integer(written as i64)
+        } else if written {
             Frame::SimpleString("OK".to_owned())
+        } else {
+            Frame::Null
         };
 
-        conn.write_frame(frame_to_write)
+        Ok(frame_to_write)
     }
 
-    fn options(parser: &mut ParseFrames) -> anyhow::Result<Options> {
-        let mut options = Options::default();
+    fn key_and_value(parser: &mut ParseFrames, command: &str) -> anyhow::Result<(String, Bytes)> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{command}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        Ok((key, value))
+    }
+
+    fn no_more_arguments(parser: &mut ParseFrames, command: &str) -> anyhow::Result<()> {
+        if parser.next_bytes()?.is_some() {
+            return Err(anyhow!(
+                "ERR wrong number of arguments for '{command}' command"
+            ));
+        }
+        Ok(())
+    }
+
+    fn options(parser: &mut ParseFrames) -> anyhow::Result<SetOptions> {
+        let mut options = SetOptions::default();
         while let Some(option) = parser.next_string()? {
             let option = option.to_lowercase();
             match option.as_str() {
-                "ex" | "px" | "exat" | "pxat" => {
-                    let duration = parser
-                        .next_bytes()?
-                        .ok_or_else(|| anyhow!("Expected a value for duration but found None"))?;
-                    let duration = serde_json::from_slice(&duration)
-                        .context("Failed to parse time to a positive integer")?;
-
-                    match option.as_str() {
-                        "px" => {
-                            options.expiration = Some(Duration::from_millis(duration));
-                        }
-                        "ex" => {
-                            options.expiration = Some(Duration::from_secs(duration));
-                        }
-                        "exat" => {
-                            let now_in_secs = SystemTime::now()
-                                .duration_since(SystemTime::UNIX_EPOCH)
-                                .context("Failed to get the current time")?
-                                .as_secs();
-                            if duration > now_in_secs {
-                                options.expiration =
-                                    Some(Duration::from_secs(duration - now_in_secs));
-                            } else {
-                                return Err(anyhow!("The expiration time must be in the future"));
-                            }
-                        }
-                        "pxat" => {
-                            let duration = duration as u128;
-                            let now_in_millis = SystemTime::now()
-                                .duration_since(SystemTime::UNIX_EPOCH)
-                                .context("Failed to get the current time")?
-                                .as_millis();
-                            if duration > now_in_millis {
-                                options.expiration =
-                                    Some(Duration::from_millis((duration - now_in_millis) as u64));
-                            } else {
-                                return Err(anyhow!("The expiration time must be in the future"));
-                            }
-                        }
-                        _ => {}
-                    }
+                "nx" | "xx" if options.condition != SetCondition::Always => {
+                    return Err(anyhow!("ERR syntax error"))
                 }
-                "get" => {
-                    options.return_existing_value = true;
+                "nx" => options.condition = SetCondition::Nx,
+                "xx" => options.condition = SetCondition::Xx,
+                "get" => options.get = true,
+                "keepttl" if options.expire.is_none() => options.keep_ttl = true,
+                "ex" | "px" | "exat" | "pxat" if options.expire.is_none() && !options.keep_ttl => {
+                    let time = parser
+                        .next_integer()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    options.expire = Some(parse_expiry(&option, time, "set")?);
                 }
-
-                _ => {}
+                _ => return Err(anyhow!("ERR syntax error")),
             }
         }
 
         Ok(options)
     }
 }
+
+/// How long until a key expires, given the `EX`, `PX`, `EXAT` or `PXAT` option of `command`.
+/// A deadline in the past expires the key right away, one past what a timestamp can hold is
+/// rejected.
+pub(crate) fn parse_expiry(option: &str, time: i64, command: &str) -> anyhow::Result<Duration> {
+    let invalid = || anyhow!("ERR invalid expire time in '{command}' command");
+    let millis = match option {
+        "ex" | "exat" => time.checked_mul(1000),
+        _ => Some(time),
+    }
+    .filter(|millis| *millis > 0)
+    .ok_or_else(invalid)?;
+
+    let now = Utc::now();
+    let at = match option {
+        "exat" | "pxat" => DateTime::from_timestamp_millis(millis),
+        _ => TimeDelta::try_milliseconds(millis).and_then(|millis| now.checked_add_signed(millis)),
+    }
+    .ok_or_else(invalid)?;
+    Ok((at - now).to_std().unwrap_or(Duration::ZERO))
+}
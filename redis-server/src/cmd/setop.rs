@@ -0,0 +1,146 @@
+use std::{collections::HashSet, io};
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `SINTER`, `SUNION` and `SDIFF`, along with their `STORE` variants, which store the resulting
+/// set at a destination key instead of replying with it.
+pub struct Setop {
+    operation: Operation,
+    keys: Vec<String>,
+    destination: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Operation {
+    Inter,
+    Union,
+    /// The members of the first set that aren't in any of the others.
+    Diff,
+}
+
+impl Setop {
+    pub fn parse(
+        parser: &mut ParseFrames,
+        operation: Operation,
+        store: bool,
+    ) -> anyhow::Result<Self> {
+        let name = match operation {
+            Operation::Inter => "sinter",
+            Operation::Union => "sunion",
+            Operation::Diff => "sdiff",
+        };
+        let store_suffix = if store { "store" } else { "" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{name}{store_suffix}' command");
+
+        let destination = if store {
+            Some(
+                parser
+                    .next_string()?
+                    .ok_or_else(wrong_number_of_arguments)?,
+            )
+        } else {
+            None
+        };
+        let mut keys = vec![];
+        while let Some(key) = parser.next_string()? {
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self {
+            operation,
+            keys,
+            destination,
+        })
+    }
+
+    /// Whether the resulting set is stored, which makes this a write command.
+    pub fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let operation = self.operation;
+        if let Some(destination) = self.destination {
+            let len = db.store_set(destination, &self.keys, |sets| combine(operation, sets))?;
+            return Ok(Frame::Integer(len as i64));
+        }
+        let members = db.with_sets_data(&self.keys, |sets| {
+            combine(operation, sets)
+                .into_iter()
+                .map(Frame::BulkString)
+                .collect()
+        })?;
+        Ok(Frame::Set(members))
+    }
+}
+
+/// Computes the operation on the sets, where keys that do not exist are empty sets.
+pub(crate) fn combine(operation: Operation, sets: Vec<Option<&HashSet<Bytes>>>) -> HashSet<Bytes> {
+    let mut sets = sets.into_iter();
+    match operation {
+        Operation::Inter => {
+            let Some(mut sets) = sets.collect::<Option<Vec<_>>>() else {
+                return HashSet::new();
+            };
+            // Only the members of the smallest set need to be looked up in the others.
+            sets.sort_by_key(|set| set.len());
+            let Some((smallest, others)) = sets.split_first() else {
+                return HashSet::new();
+            };
+            smallest
+                .iter()
+                .filter(|member| others.iter().all(|set| set.contains(*member)))
+                .cloned()
+                .collect()
+        }
+        Operation::Union => sets.flatten().flatten().cloned().collect(),
+        Operation::Diff => {
+            let Some(first) = sets.next().flatten() else {
+                return HashSet::new();
+            };
+            let others: Vec<_> = sets.flatten().collect();
+            first
+                .iter()
+                .filter(|member| !others.iter().any(|set| set.contains(*member)))
+                .cloned()
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+    use rstest::rstest;
+
+    use super::{combine, Operation};
+
+    fn set(members: &[&'static str]) -> HashSet<Bytes> {
+        members.iter().map(|member| Bytes::from(*member)).collect()
+    }
+
+    #[rstest]
+    #[case(Operation::Inter, &["c"])]
+    #[case(Operation::Union, &["a", "b", "c", "d", "e"])]
+    #[case(Operation::Diff, &["a", "b"])]
+    fn test_combine(#[case] operation: Operation, #[case] expected: &[&'static str]) {
+        let (first, second) = (set(&["a", "b", "c"]), set(&["c", "d", "e"]));
+        let sets = vec![Some(&first), Some(&second)];
+        assert_eq!(combine(operation, sets), set(expected));
+        // A key that does not exist is an empty set.
+        let missing = vec![Some(&first), None];
+        let expected = match operation {
+            Operation::Inter => set(&[]),
+            _ => first.clone(),
+        };
+        assert_eq!(combine(operation, missing), expected);
+    }
+}
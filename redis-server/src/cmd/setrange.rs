@@ -0,0 +1,63 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// The largest string a command can build, redis' default `proto-max-bulk-len`.
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+/// `SETRANGE key offset value`
+pub struct Setrange {
+    key: String,
+    offset: usize,
+    value: Bytes,
+}
+
+impl Setrange {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'setrange' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let offset = parser
+            .next_integer()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        let offset = usize::try_from(offset).map_err(|_| anyhow!("ERR offset is out of range"))?;
+        Ok(Self { key, offset, value })
+    }
+
+    /// Overwrites the string from `offset` on, padding it with zero bytes if it is shorter.
+    /// An empty value leaves the string, or the lack of it, untouched.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_string_data_mut(self.key, |string| {
+            if self.value.is_empty() {
+                return Ok((string.len(), false));
+            }
+            let end = self.offset.saturating_add(self.value.len());
+            check_string_length(end)?;
+            if string.len() < end {
+                string.resize(end, 0);
+            }
+            string[self.offset..end].copy_from_slice(&self.value);
+            Ok((string.len(), true))
+        })?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+/// Fails if a command would make a string longer than redis allows.
+pub(crate) fn check_string_length(len: usize) -> io::Result<()> {
+    if len > MAX_STRING_LENGTH {
+        return Err(io::Error::other(
+            "ERR string exceeds maximum allowed size (proto-max-bulk-len)",
+        ));
+    }
+    Ok(())
+}
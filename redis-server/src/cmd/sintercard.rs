@@ -0,0 +1,57 @@
+use std::io;
+
+use super::{
+    setop::{combine, Operation},
+    ParseFrames,
+};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`
+///
+/// The size of the intersection of the sets, counting no further than `limit` if it isn't 0.
+pub struct Sintercard {
+    keys: Vec<String>,
+    limit: usize,
+}
+
+impl Sintercard {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let numkeys = parser
+            .next_integer()
+            .ok()
+            .flatten()
+            .filter(|numkeys| *numkeys > 0)
+            .ok_or_else(|| anyhow!("ERR numkeys should be greater than 0"))?;
+        let mut keys = vec![];
+        for _ in 0..numkeys {
+            let key = parser.next_string()?.ok_or_else(|| {
+                anyhow!("ERR Number of keys can't be greater than number of args")
+            })?;
+            keys.push(key);
+        }
+
+        let mut limit = 0;
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "limit" => {
+                    limit = parser
+                        .next_integer()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?
+                        .try_into()
+                        .map_err(|_| anyhow!("ERR LIMIT can't be negative"))?;
+                }
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+        Ok(Self { keys, limit })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_sets_data(&self.keys, |sets| combine(Operation::Inter, sets).len())?;
+        let len = match self.limit {
+            0 => len,
+            limit => len.min(limit),
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
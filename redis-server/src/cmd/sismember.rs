@@ -0,0 +1,50 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `SISMEMBER key member`, or `SMISMEMBER key member [member ...]` when `multiple` is set,
+/// which replies with an array telling for each member whether it is in the set.
+pub struct Sismember {
+    key: String,
+    members: Vec<Bytes>,
+    multiple: bool,
+}
+
+impl Sismember {
+    pub fn parse(parser: &mut ParseFrames, multiple: bool) -> anyhow::Result<Self> {
+        let name = if multiple { "smismember" } else { "sismember" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{name}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let mut members = vec![];
+        while let Some(member) = parser.next_bytes()? {
+            members.push(member);
+        }
+        if members.is_empty() || (!multiple && members.len() > 1) {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self {
+            key,
+            members,
+            multiple,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let found: Vec<_> = db.with_set_data(&self.key, |set| {
+            self.members
+                .iter()
+                .map(|member| Frame::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+                .collect()
+        })?;
+        if self.multiple {
+            return Ok(Frame::Array(found));
+        }
+        Ok(found.into_iter().next().expect("there is one member"))
+    }
+}
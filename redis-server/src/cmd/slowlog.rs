@@ -0,0 +1,72 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{db::Db, frame::Frame};
+
+/// The commands that took longer than `slowlog-log-slower-than` microseconds to execute.
+pub enum Slowlog {
+    /// The most recent entries, 10 by default, or all of them.
+    Get(Option<usize>),
+    /// The number of entries.
+    Len,
+    /// Removes every entry.
+    Reset,
+}
+
+impl Slowlog {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'slowlog' command"))?
+            .to_lowercase();
+        let slowlog =
+            match subcommand.as_str() {
+                "get" => match parser.next_integer()? {
+                    None => Slowlog::Get(Some(10)),
+                    Some(-1) => Slowlog::Get(None),
+                    Some(count) => Slowlog::Get(Some(usize::try_from(count).map_err(|_| {
+                        anyhow!("ERR count should be greater than or equal to -1")
+                    })?)),
+                },
+                "len" => Slowlog::Len,
+                "reset" => Slowlog::Reset,
+                _ => bail!("ERR unknown subcommand '{subcommand}'. Try SLOWLOG HELP."),
+            };
+        if parser.next_bytes()?.is_some() {
+            bail!("ERR wrong number of arguments for 'slowlog|{subcommand}' command");
+        }
+        Ok(slowlog)
+    }
+
+    pub fn execute(&self, db: &Db) -> io::Result<Frame> {
+        let slowlog = db.slowlog();
+        let frame = match self {
+            Slowlog::Get(count) => Frame::Array(
+                slowlog
+                    .get(*count)
+                    .into_iter()
+                    .map(|entry| {
+                        let addr = entry.addr.map(|addr| addr.to_string()).unwrap_or_default();
+                        Frame::Array(vec![
+                            Frame::Integer(entry.id as i64),
+                            Frame::Integer(entry.timestamp),
+                            Frame::Integer(entry.duration.as_micros() as i64),
+                            Frame::Array(entry.args.into_iter().map(Frame::BulkString).collect()),
+                            Frame::BulkString(Bytes::from(addr)),
+                            Frame::BulkString(entry.name.unwrap_or_default()),
+                        ])
+                    })
+                    .collect(),
+            ),
+            Slowlog::Len => Frame::Integer(slowlog.len() as i64),
+            Slowlog::Reset => {
+                slowlog.reset();
+                Frame::SimpleString("OK".to_owned())
+            }
+        };
+        Ok(frame)
+    }
+}
@@ -0,0 +1,28 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Smembers {
+    key: String,
+}
+
+impl Smembers {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let members = db.with_set_data(&self.key, |set| {
+            set.into_iter()
+                .flatten()
+                .cloned()
+                .map(Frame::BulkString)
+                .collect()
+        })?;
+        Ok(Frame::Set(members))
+    }
+}
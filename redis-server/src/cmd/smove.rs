@@ -0,0 +1,41 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `SMOVE source destination member`
+pub struct Smove {
+    source: String,
+    destination: String,
+    member: Bytes,
+}
+
+impl Smove {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'smove' command");
+        let source = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let destination = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let member = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        if parser.next_bytes()?.is_some() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self {
+            source,
+            destination,
+            member,
+        })
+    }
+
+    /// Replies with 1 if the member was moved, and 0 if it wasn't in the source set.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let moved = db.move_set_member(&self.source, self.destination, self.member)?;
+        Ok(Frame::Integer(moved as i64))
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `SPOP key [count]`
+///
+/// Removes members picked at random, replying with one of them, or with a set of up to `count`
+/// of them when it is given.
+pub struct Spop {
+    key: String,
+    count: Option<i64>,
+}
+
+impl Spop {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let count = parser.next_integer()?;
+        if count.is_some_and(|count| count < 0) {
+            return Err(anyhow!("ERR value is out of range, must be positive"));
+        }
+        if parser.next_bytes()?.is_some() {
+            return Err(anyhow!("ERR syntax error"));
+        }
+        Ok(Self { key, count })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let popped = db.pop_set(&self.key, self.count.unwrap_or(1) as usize)?;
+        let frame = match self.count {
+            Some(_) => Frame::Set(popped.into_iter().map(Frame::BulkString).collect()),
+            None => popped
+                .into_iter()
+                .next()
+                .map(Frame::BulkString)
+                .unwrap_or(Frame::Null),
+        };
+        Ok(frame)
+    }
+}
@@ -0,0 +1,68 @@
+use std::io;
+
+use rand::seq::{IteratorRandom, SliceRandom};
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// The most members a negative count can ask for, since each is picked while the keyspace is
+/// locked.
+const MAX_REPEATED_MEMBERS: u64 = 16 * 1024 * 1024;
+
+/// `SRANDMEMBER key [count]`
+///
+/// Replies with a member picked at random. With a positive `count`, replies with up to that
+/// many distinct members, and with a negative one, with exactly `-count` members which can
+/// repeat.
+pub struct Srandmember {
+    key: String,
+    count: Option<i64>,
+}
+
+impl Srandmember {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let count = parser.next_integer()?;
+        if count.is_some_and(|count| count < 0 && count.unsigned_abs() > MAX_REPEATED_MEMBERS) {
+            return Err(anyhow!("ERR value is out of range"));
+        }
+        if parser.next_bytes()?.is_some() {
+            return Err(anyhow!("ERR syntax error"));
+        }
+        Ok(Self { key, count })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let mut rng = rand::thread_rng();
+        let frame = db.with_set_data(&self.key, |set| {
+            let Some(count) = self.count else {
+                return set
+                    .and_then(|set| set.iter().choose(&mut rng))
+                    .cloned()
+                    .map(Frame::BulkString)
+                    .unwrap_or(Frame::Null);
+            };
+            let Some(set) = set else {
+                return Frame::Array(vec![]);
+            };
+            let members = if count >= 0 {
+                set.iter().choose_multiple(&mut rng, count as usize)
+            } else {
+                let members: Vec<_> = set.iter().collect();
+                (0..count.unsigned_abs())
+                    .map_while(|_| members.choose(&mut rng).copied())
+                    .collect()
+            };
+            Frame::Array(
+                members
+                    .into_iter()
+                    .cloned()
+                    .map(Frame::BulkString)
+                    .collect(),
+            )
+        })?;
+        Ok(frame)
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Srem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl Srem {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut members = vec![];
+        while let Some(member) = parser.next_bytes()? {
+            members.push(member);
+        }
+        if members.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'srem' command"));
+        }
+        Ok(Self { key, members })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let removed = db.with_set_data_mut(self.key, |set| {
+            let removed = self
+                .members
+                .iter()
+                .filter(|member| set.remove(*member))
+                .count();
+            (removed, removed > 0)
+        })?;
+        Ok(Frame::Integer(removed as i64))
+    }
+}
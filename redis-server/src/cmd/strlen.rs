@@ -0,0 +1,33 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{wrong_type, Db},
+    frame::Frame,
+};
+
+/// `STRLEN key`
+pub struct Strlen {
+    key: String,
+}
+
+impl Strlen {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'strlen' command"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_data(|data| match data.get(&self.key) {
+            Some(value) => value
+                .as_string()
+                .map(|string| string.len())
+                .ok_or_else(wrong_type),
+            None => Ok(0),
+        })?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+use crate::{connection::Connection, db::Db, pubsub::Target};
+use anyhow::anyhow;
+
+use super::ParseFrames;
+
+/// `SUBSCRIBE` and `PSUBSCRIBE`. Once subscribed, the connection only accepts the commands
+/// that manage its subscriptions, and `PING`.
+pub struct Subscribe {
+    targets: Vec<Target>,
+}
+
+impl Subscribe {
+    pub fn parse(parser: &mut ParseFrames, patterns: bool) -> anyhow::Result<Self> {
+        let target = if patterns {
+            Target::Pattern
+        } else {
+            Target::Channel
+        };
+        let mut targets = vec![];
+        while let Some(name) = parser.next_bytes()? {
+            targets.push(target(name));
+        }
+        if targets.is_empty() {
+            let command = if patterns { "psubscribe" } else { "subscribe" };
+            return Err(anyhow!(
+                "ERR wrong number of arguments for '{command}' command"
+            ));
+        }
+        Ok(Self { targets })
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+        let subscriber = conn.subscribe(db.pubsub());
+        let confirmations: Vec<_> = self
+            .targets
+            .into_iter()
+            .map(|target| subscriber.subscribe(target))
+            .collect();
+        for confirmation in confirmations {
+            conn.write_frame(confirmation);
+        }
+        Ok(())
+    }
+}
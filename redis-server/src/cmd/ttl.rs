@@ -0,0 +1,30 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `TTL` and `PTTL`, which only differ in the unit of the reply.
+pub struct Ttl {
+    key: String,
+    in_millis: bool,
+}
+
+impl Ttl {
+    pub fn parse(parser: &mut ParseFrames, in_millis: bool) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key, in_millis })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let ttl = match db.ttl(&self.key) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(ttl)) if self.in_millis => ttl.as_millis() as i64,
+            // Like redis, the remaining seconds are rounded to the closest integer.
+            Some(Some(ttl)) => ((ttl.as_millis() + 500) / 1000) as i64,
+        };
+        Ok(Frame::Integer(ttl))
+    }
+}
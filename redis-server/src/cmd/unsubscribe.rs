@@ -0,0 +1,46 @@
+use std::io;
+
+use bytes::Bytes;
+
+use crate::{connection::Connection, db::Db, pubsub::Target};
+
+use super::ParseFrames;
+
+/// `UNSUBSCRIBE` and `PUNSUBSCRIBE`. Without arguments, every channel, or pattern, is
+/// unsubscribed from.
+pub struct Unsubscribe {
+    names: Vec<Bytes>,
+    patterns: bool,
+}
+
+impl Unsubscribe {
+    pub fn parse(parser: &mut ParseFrames, patterns: bool) -> anyhow::Result<Self> {
+        let mut names = vec![];
+        while let Some(name) = parser.next_bytes()? {
+            names.push(name);
+        }
+        Ok(Self { names, patterns })
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<()> {
+        // Unsubscribing is confirmed even if the client isn't subscribed to anything.
+        let subscriber = conn.subscribe(db.pubsub());
+        let mut confirmations = vec![];
+        if self.names.is_empty() {
+            confirmations = subscriber.unsubscribe_all(self.patterns);
+        }
+        for name in self.names {
+            let target = if self.patterns {
+                Target::Pattern(name)
+            } else {
+                Target::Channel(name)
+            };
+            confirmations.push(subscriber.unsubscribe(&target));
+        }
+        for confirmation in confirmations {
+            conn.write_frame(confirmation);
+        }
+        conn.leave_subscribed_mode_if_done();
+        Ok(())
+    }
+}
@@ -0,0 +1,12 @@
+use std::io;
+
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+pub struct Unwatch;
+
+impl Unwatch {
+    pub fn execute(&self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        conn.watched_keys_mut().unwatch(db);
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
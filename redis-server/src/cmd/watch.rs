@@ -0,0 +1,68 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+use super::ParseFrames;
+
+/// The keys a client is watching, and whether any of them was modified since.
+#[derive(Default)]
+pub struct WatchedKeys {
+    // Along with the database they are in.
+    keys: Vec<(usize, String)>,
+    modified: Arc<AtomicBool>,
+}
+
+impl WatchedKeys {
+    pub fn is_modified(&self) -> bool {
+        self.modified.load(Ordering::Relaxed)
+    }
+
+    pub fn unwatch(&mut self, db: &Db) {
+        for (index, key) in std::mem::take(&mut self.keys) {
+            db.select(index)
+                .with_data_mut(|data| data.unwatch(&key, &self.modified));
+        }
+        self.modified = Arc::default();
+    }
+}
+
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+impl Watch {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let mut keys = vec![];
+        while let Some(key) = parser.next_string()? {
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'watch' command"));
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+        if conn.transaction_mut().is_some() {
+            return Err(io::Error::other("ERR WATCH inside MULTI is not allowed"));
+        }
+        let watched_keys = conn.watched_keys_mut();
+        db.with_data_mut(|data| {
+            for key in &self.keys {
+                data.watch(key.clone(), &watched_keys.modified);
+            }
+        });
+        watched_keys
+            .keys
+            .extend(self.keys.into_iter().map(|key| (db.index(), key)));
+        Ok(Frame::SimpleString("OK".to_owned()))
+    }
+}
@@ -0,0 +1,48 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::{xrange::parse_id, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    db::{Db, StreamId},
+    frame::Frame,
+};
+
+/// `XACK key group id [id ...]`
+///
+/// Removes the entries from the pending entries of the group.
+pub struct Xack {
+    key: String,
+    group: Bytes,
+    ids: Vec<StreamId>,
+}
+
+impl Xack {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xack' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let group = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let mut ids = vec![];
+        while let Some(id) = parser.next_bytes()? {
+            ids.push(parse_id(&id)?);
+        }
+        if ids.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self { key, group, ids })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let acknowledged = db.with_stream_data_mut(self.key, false, |stream| {
+            let acknowledged = stream
+                .and_then(|stream| stream.group_mut(&self.group))
+                .map_or(0, |group| group.ack(&self.ids));
+            (acknowledged, acknowledged > 0)
+        })?;
+        Ok(Frame::Integer(acknowledged as i64))
+    }
+}
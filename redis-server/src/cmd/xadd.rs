@@ -0,0 +1,71 @@
+use std::io;
+
+use super::{xrange::parse_id, xtrim::TrimOptions, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    db::{Db, Fields, NewId, Trim},
+    frame::Frame,
+};
+
+/// `XADD key [NOMKSTREAM] [<MAXLEN | MINID> [= | ~] threshold [LIMIT count]] <* | id> field
+/// value [field value ...]`
+pub struct Xadd {
+    key: String,
+    id: NewId,
+    fields: Fields,
+    // Unset by `NOMKSTREAM`.
+    create: bool,
+    trim: Option<(Trim, Option<usize>)>,
+}
+
+impl Xadd {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xadd' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+
+        let mut create = true;
+        let mut trim = TrimOptions::default();
+        let id = loop {
+            let argument = parser
+                .next_string()?
+                .ok_or_else(wrong_number_of_arguments)?;
+            if argument.eq_ignore_ascii_case("nomkstream") {
+                create = false;
+            } else if !trim.parse(&argument, parser)? {
+                break argument;
+            }
+        };
+        let id = match id.split_once('-') {
+            _ if id == "*" => NewId::Auto,
+            Some((ms, "*")) => NewId::Sequence(ms.parse().map_err(|_| {
+                anyhow!("ERR Invalid stream ID specified as stream command argument")
+            })?),
+            _ => NewId::Explicit(parse_id(id.as_bytes())?),
+        };
+
+        let mut fields = vec![];
+        while let Some(field) = parser.next_bytes()? {
+            let value = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+            fields.push((field, value));
+        }
+        if fields.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+
+        Ok(Self {
+            key,
+            id,
+            fields,
+            create,
+            trim: trim.finish()?,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let id = db.add_to_stream(self.key, self.id, self.fields, self.create, self.trim)?;
+        Ok(id.map_or(Frame::Null, |id| Frame::BulkString(id.to_bytes())))
+    }
+}
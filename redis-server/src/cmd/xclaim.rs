@@ -0,0 +1,134 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+use super::{
+    xrange::{entries_frame, parse_id},
+    ParseFrames,
+};
+use crate::{
+    cmd::{anyhow, bail},
+    db::{ClaimOptions, Db, StreamId},
+    frame::Frame,
+};
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]`
+///
+/// Gives the entries that other consumers of the group left pending for at least
+/// `min-idle-time` to `consumer`.
+pub struct Xclaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: Duration,
+    ids: Vec<StreamId>,
+    // How long ago the entries count as delivered, from `IDLE`.
+    idle: Option<Duration>,
+    options: ClaimOptions,
+}
+
+impl Xclaim {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xclaim' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let group = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let consumer = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let min_idle = parser
+            .next_integer()
+            .map_err(|_| anyhow!("ERR Invalid min-idle-time argument for XCLAIM"))?
+            .ok_or_else(wrong_number_of_arguments)?;
+
+        // The IDs go on until the first option.
+        let mut ids = vec![];
+        let mut option = None;
+        while let Some(argument) = parser.next_bytes()? {
+            match StreamId::parse(&argument, 0) {
+                Some(id) => ids.push(id),
+                None => {
+                    option = Some(argument);
+                    break;
+                }
+            }
+        }
+        if ids.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+
+        let mut idle = None;
+        let mut options = ClaimOptions::default();
+        while let Some(argument) = option {
+            let mut next_integer = |name: &str| {
+                parser
+                    .next_integer()
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| anyhow!("ERR Invalid {name} option argument for XCLAIM"))
+            };
+            match String::from_utf8_lossy(&argument).to_lowercase().as_str() {
+                "idle" => idle = Some(Duration::from_millis(next_integer("IDLE")?.max(0) as u64)),
+                "time" => {
+                    let time = DateTime::from_timestamp_millis(next_integer("TIME")?)
+                        .ok_or_else(|| anyhow!("ERR Invalid TIME option argument for XCLAIM"))?;
+                    options.delivered_at = Some(time);
+                }
+                "retrycount" => {
+                    options.deliveries = Some(next_integer("RETRYCOUNT")?.max(0) as u64);
+                }
+                "force" => options.force = true,
+                "justid" => options.just_id = true,
+                "lastid" => {
+                    let id = parser
+                        .next_bytes()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    options.last_id = Some(parse_id(&id)?);
+                }
+                _ => bail!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&argument)
+                ),
+            }
+            option = parser.next_bytes()?;
+        }
+
+        Ok(Self {
+            key,
+            group,
+            consumer,
+            min_idle: Duration::from_millis(min_idle.max(0) as u64),
+            ids,
+            idle,
+            options,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let mut options = self.options;
+        if let Some(idle) = self.idle {
+            options.delivered_at = chrono::Duration::from_std(idle)
+                .ok()
+                .and_then(|idle| Utc::now().checked_sub_signed(idle));
+        }
+        let just_id = options.just_id;
+        let claimed = db.claim(
+            &self.key,
+            &self.group,
+            &self.consumer,
+            self.min_idle,
+            &self.ids,
+            options,
+        )?;
+        if just_id {
+            let ids = claimed
+                .into_iter()
+                .map(|(id, _)| Frame::BulkString(id.to_bytes()))
+                .collect();
+            return Ok(Frame::Array(ids));
+        }
+        Ok(entries_frame(claimed))
+    }
+}
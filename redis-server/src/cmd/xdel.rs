@@ -0,0 +1,40 @@
+use std::io;
+
+use super::{xrange::parse_id, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    db::{Db, StreamId},
+    frame::Frame,
+};
+
+/// `XDEL key id [id ...]`
+pub struct Xdel {
+    key: String,
+    ids: Vec<StreamId>,
+}
+
+impl Xdel {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xdel' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let mut ids = vec![];
+        while let Some(id) = parser.next_bytes()? {
+            ids.push(parse_id(&id)?);
+        }
+        if ids.is_empty() {
+            return Err(wrong_number_of_arguments());
+        }
+        Ok(Self { key, ids })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let deleted = db.with_stream_data_mut(self.key, false, |stream| {
+            let deleted = stream.map_or(0, |stream| stream.delete(&self.ids));
+            (deleted, deleted > 0)
+        })?;
+        Ok(Frame::Integer(deleted as i64))
+    }
+}
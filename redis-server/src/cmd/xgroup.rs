@@ -0,0 +1,185 @@
+use std::io;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use chrono::Utc;
+
+use super::{xrange::parse_id, ParseFrames};
+use crate::{
+    db::{no_group, Db, Stream, StreamId},
+    frame::Frame,
+};
+
+/// Management of the consumer groups of a stream.
+pub enum Xgroup {
+    /// Creates a group that delivers the entries after the given ID, or only new entries for
+    /// `$`. `MKSTREAM` creates the stream if needed.
+    Create {
+        key: String,
+        group: Bytes,
+        id: Option<StreamId>,
+        create_stream: bool,
+    },
+    /// Moves the last entry delivered to the group, to the last entry of the stream for `$`.
+    Setid {
+        key: String,
+        group: Bytes,
+        id: Option<StreamId>,
+    },
+    Destroy {
+        key: String,
+        group: Bytes,
+    },
+    Createconsumer {
+        key: String,
+        group: Bytes,
+        consumer: Bytes,
+    },
+    /// Deletes the consumer along with its pending entries.
+    Delconsumer {
+        key: String,
+        group: Bytes,
+        consumer: Bytes,
+    },
+}
+
+impl Xgroup {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let subcommand = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'xgroup' command"))?
+            .to_lowercase();
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xgroup|{subcommand}' command");
+        let mut next_bytes = || -> anyhow::Result<Bytes> {
+            parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)
+        };
+        let key = String::from_utf8(next_bytes()?.to_vec())?;
+        let group = next_bytes()?;
+
+        let xgroup = match subcommand.as_str() {
+            "create" => {
+                let id = parse_last_id(&next_bytes()?)?;
+                let mut create_stream = false;
+                while let Some(option) = parser.next_string()? {
+                    match option.to_lowercase().as_str() {
+                        "mkstream" => create_stream = true,
+                        _ => bail!("ERR syntax error"),
+                    }
+                }
+                return Ok(Xgroup::Create {
+                    key,
+                    group,
+                    id,
+                    create_stream,
+                });
+            }
+            "setid" => Xgroup::Setid {
+                key,
+                group,
+                id: parse_last_id(&next_bytes()?)?,
+            },
+            "destroy" => Xgroup::Destroy { key, group },
+            "createconsumer" => Xgroup::Createconsumer {
+                key,
+                group,
+                consumer: next_bytes()?,
+            },
+            "delconsumer" => Xgroup::Delconsumer {
+                key,
+                group,
+                consumer: next_bytes()?,
+            },
+            _ => bail!("ERR unknown subcommand '{subcommand}'. Try XGROUP HELP."),
+        };
+        if parser.next_bytes()?.is_some() {
+            bail!("ERR wrong number of arguments for 'xgroup|{subcommand}' command");
+        }
+        Ok(xgroup)
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        match self {
+            Xgroup::Create {
+                key,
+                group,
+                id,
+                create_stream,
+            } => with_stream(db, key, create_stream, |stream, _| {
+                let id = id.unwrap_or(stream.last_id());
+                if !stream.create_group(group, id) {
+                    return Err(io::Error::other(
+                        "BUSYGROUP Consumer Group name already exists",
+                    ));
+                }
+                Ok((Frame::SimpleString("OK".to_owned()), true))
+            }),
+            Xgroup::Setid { key, group, id } => with_stream(db, key, false, |stream, key| {
+                let id = id.unwrap_or(stream.last_id());
+                let group = stream.group_mut(&group).ok_or_else(|| {
+                    let group = String::from_utf8_lossy(&group);
+                    io::Error::other(format!(
+                        "NOGROUP No such consumer group '{group}' for key name '{key}'"
+                    ))
+                })?;
+                group.set_last_delivered(id);
+                Ok((Frame::SimpleString("OK".to_owned()), true))
+            }),
+            Xgroup::Destroy { key, group } => with_stream(db, key, false, |stream, _| {
+                let destroyed = stream.destroy_group(&group);
+                Ok((Frame::Integer(destroyed as i64), destroyed))
+            }),
+            Xgroup::Createconsumer {
+                key,
+                group,
+                consumer,
+            } => with_stream(db, key, false, |stream, key| {
+                let group_state = stream
+                    .group_mut(&group)
+                    .ok_or_else(|| no_group(key, &group))?;
+                let created = group_state.create_consumer(consumer, Utc::now());
+                Ok((Frame::Integer(created as i64), created))
+            }),
+            Xgroup::Delconsumer {
+                key,
+                group,
+                consumer,
+            } => with_stream(db, key, false, |stream, key| {
+                let group_state = stream
+                    .group_mut(&group)
+                    .ok_or_else(|| no_group(key, &group))?;
+                let pending = group_state.delete_consumer(&consumer);
+                Ok((
+                    Frame::Integer(pending.unwrap_or(0) as i64),
+                    pending.is_some(),
+                ))
+            }),
+        }
+    }
+}
+
+/// Runs the closure against the stream, which has to exist unless `create` is set. The closure
+/// returns its reply along with whether it changed the stream.
+fn with_stream<F>(db: &Db, key: String, create: bool, f: F) -> io::Result<Frame>
+where
+    F: FnOnce(&mut Stream, &str) -> io::Result<(Frame, bool)>,
+{
+    let name = key.clone();
+    db.with_stream_data_mut(key, create, |stream| match stream {
+        Some(stream) => match f(stream, &name) {
+            Ok((frame, changed)) => (Ok(frame), changed),
+            Err(err) => (Err(err), false),
+        },
+        None => (Err(io::Error::other(
+            "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+        )), false),
+    })?
+}
+
+/// Parses the last delivered ID of a group, `None` standing for the last ID of the stream.
+fn parse_last_id(id: &[u8]) -> anyhow::Result<Option<StreamId>> {
+    match id {
+        b"$" => Ok(None),
+        id => parse_id(id).map(Some),
+    }
+}
@@ -0,0 +1,24 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+/// `XLEN key`
+pub struct Xlen {
+    key: String,
+}
+
+impl Xlen {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'xlen' command"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len =
+            db.with_stream_data(&self.key, |stream| stream.map_or(0, |stream| stream.len()))?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
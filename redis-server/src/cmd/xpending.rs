@@ -0,0 +1,152 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use chrono::Utc;
+
+use super::{xrange::parse_bound, ParseFrames};
+use crate::{
+    cmd::{anyhow, bail},
+    db::{no_group, Db, StreamId},
+    frame::Frame,
+};
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+///
+/// Without a range, sums up the pending entries of the group: how many there are, the
+/// smallest and greatest IDs, and how many each consumer has.
+pub struct Xpending {
+    key: String,
+    group: Bytes,
+    range: Option<Range>,
+}
+
+struct Range {
+    min_idle: Duration,
+    start: StreamId,
+    end: StreamId,
+    count: usize,
+    consumer: Option<Bytes>,
+}
+
+impl Xpending {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for 'xpending' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let group = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let Some(mut start) = parser.next_bytes()? else {
+            return Ok(Self {
+                key,
+                group,
+                range: None,
+            });
+        };
+
+        let mut min_idle = Duration::ZERO;
+        if start.eq_ignore_ascii_case(b"idle") {
+            let millis = parser
+                .next_integer()?
+                .ok_or_else(|| anyhow!("ERR syntax error"))?;
+            min_idle = Duration::from_millis(millis.max(0) as u64);
+            start = parser
+                .next_bytes()?
+                .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        }
+        let end = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        let count = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        let consumer = parser.next_bytes()?;
+        if parser.next_bytes()?.is_some() {
+            bail!("ERR syntax error");
+        }
+
+        Ok(Self {
+            key,
+            group,
+            range: Some(Range {
+                min_idle,
+                start: parse_bound(&start, false)?,
+                end: parse_bound(&end, true)?,
+                count: count.max(0) as usize,
+                consumer,
+            }),
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let now = Utc::now();
+        db.with_stream_data(&self.key, |stream| {
+            let group = stream
+                .and_then(|stream| stream.group(&self.group))
+                .ok_or_else(|| no_group(&self.key, &self.group))?;
+            let pending = group.pending();
+
+            let Some(range) = self.range else {
+                let (Some((first, _)), Some((last, _))) =
+                    (pending.first_key_value(), pending.last_key_value())
+                else {
+                    return Ok(Frame::Array(vec![
+                        Frame::Integer(0),
+                        Frame::Null,
+                        Frame::Null,
+                        Frame::NullArray,
+                    ]));
+                };
+                let consumers = group
+                    .consumers()
+                    .keys()
+                    .filter_map(|consumer| {
+                        let count = pending
+                            .values()
+                            .filter(|pending| pending.consumer == consumer)
+                            .count();
+                        (count > 0).then(|| {
+                            Frame::Array(vec![
+                                Frame::BulkString(consumer.clone()),
+                                Frame::BulkString(count.to_string().into()),
+                            ])
+                        })
+                    })
+                    .collect();
+                return Ok(Frame::Array(vec![
+                    Frame::Integer(pending.len() as i64),
+                    Frame::BulkString(first.to_bytes()),
+                    Frame::BulkString(last.to_bytes()),
+                    Frame::Array(consumers),
+                ]));
+            };
+
+            let entries = (range.start <= range.end)
+                .then(|| pending.range(range.start..=range.end))
+                .into_iter()
+                .flatten()
+                .filter(|(_, pending)| {
+                    range
+                        .consumer
+                        .as_ref()
+                        .is_none_or(|consumer| pending.consumer == consumer)
+                })
+                .map(|(id, pending)| {
+                    let idle = (now - pending.delivered_at).num_milliseconds().max(0);
+                    (id, pending, idle)
+                })
+                .filter(|(_, _, idle)| *idle as u128 >= range.min_idle.as_millis())
+                .take(range.count)
+                .map(|(id, pending, idle)| {
+                    Frame::Array(vec![
+                        Frame::BulkString(id.to_bytes()),
+                        Frame::BulkString(pending.consumer.clone()),
+                        Frame::Integer(idle),
+                        Frame::Integer(pending.deliveries as i64),
+                    ])
+                })
+                .collect();
+            Ok(Frame::Array(entries))
+        })?
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{
+    cmd::anyhow,
+    db::{Db, Entries, Fields, StreamId},
+    frame::Frame,
+};
+
+/// `XRANGE key start end [COUNT count]`
+///
+/// `XREVRANGE key end start [COUNT count]` is the same range, from the newest entry.
+pub struct Xrange {
+    key: String,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    rev: bool,
+}
+
+impl Xrange {
+    pub fn parse(parser: &mut ParseFrames, rev: bool) -> anyhow::Result<Self> {
+        let command = if rev { "xrevrange" } else { "xrange" };
+        let wrong_number_of_arguments =
+            || anyhow!("ERR wrong number of arguments for '{command}' command");
+        let key = parser
+            .next_string()?
+            .ok_or_else(wrong_number_of_arguments)?;
+        let first = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let second = parser.next_bytes()?.ok_or_else(wrong_number_of_arguments)?;
+        let (start, end) = if rev {
+            (second, first)
+        } else {
+            (first, second)
+        };
+
+        let mut count = None;
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "count" => {
+                    let value = parser
+                        .next_integer()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    count = Some(value.max(0) as usize);
+                }
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+
+        Ok(Self {
+            key,
+            start: parse_bound(&start, false)?,
+            end: parse_bound(&end, true)?,
+            count,
+            rev,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let count = self.count.unwrap_or(usize::MAX);
+        let entries = db.with_stream_data(&self.key, |stream| {
+            let Some(stream) = stream else {
+                return vec![];
+            };
+            let entry = |(id, fields): (&StreamId, &Fields)| (*id, Some(fields.clone()));
+            let range = stream.range(self.start, self.end);
+            if self.rev {
+                range.rev().take(count).map(entry).collect()
+            } else {
+                range.take(count).map(entry).collect()
+            }
+        })?;
+        Ok(entries_frame(entries))
+    }
+}
+
+/// Parses an ID given in full, or as a number of milliseconds alone, in which case it is the
+/// first ID of that millisecond.
+pub(crate) fn parse_id(id: &[u8]) -> anyhow::Result<StreamId> {
+    StreamId::parse(id, 0).ok_or_else(invalid_id)
+}
+
+/// Parses an end of a range: `-` and `+` for the smallest and greatest IDs, and a `(` prefix
+/// to exclude the ID. The milliseconds given alone cover the whole millisecond.
+pub(crate) fn parse_bound(bound: &[u8], end: bool) -> anyhow::Result<StreamId> {
+    match bound {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let default_seq = if end { u64::MAX } else { 0 };
+    let Some(bound) = bound.strip_prefix(b"(") else {
+        return StreamId::parse(bound, default_seq).ok_or_else(invalid_id);
+    };
+    let id = StreamId::parse(bound, default_seq).ok_or_else(invalid_id)?;
+    let excluded = if end { id.prev() } else { id.next() };
+    excluded.ok_or_else(|| {
+        let which = if end { "end" } else { "start" };
+        anyhow!("ERR invalid {which} ID for the interval")
+    })
+}
+
+/// Entries as `[id, [field, value, ...]]` pairs, with a nil in place of the fields of
+/// entries that were deleted.
+pub(crate) fn entries_frame(entries: Entries) -> Frame {
+    let entries = entries
+        .into_iter()
+        .map(|(id, fields)| {
+            let fields = match fields {
+                Some(fields) => Frame::Array(
+                    fields
+                        .into_iter()
+                        .flat_map(|(field, value)| [field, value])
+                        .map(Frame::BulkString)
+                        .collect(),
+                ),
+                None => Frame::Null,
+            };
+            Frame::Array(vec![Frame::BulkString(id.to_bytes()), fields])
+        })
+        .collect();
+    Frame::Array(entries)
+}
+
+fn invalid_id() -> anyhow::Error {
+    anyhow!("ERR Invalid stream ID specified as stream command argument")
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{parse_bound, StreamId};
+
+    #[rstest]
+    #[case(b"-", false, Some(StreamId::MIN))]
+    #[case(b"+", true, Some(StreamId::MAX))]
+    #[case(b"5", false, Some(StreamId { ms: 5, seq: 0 }))]
+    #[case(b"5", true, Some(StreamId { ms: 5, seq: u64::MAX }))]
+    #[case(b"(5-1", false, Some(StreamId { ms: 5, seq: 2 }))]
+    #[case(b"(5-0", true, Some(StreamId { ms: 4, seq: u64::MAX }))]
+    #[case(b"(0-0", true, None)]
+    #[case(b"5-x", false, None)]
+    fn test_parse_bound(#[case] bound: &[u8], #[case] end: bool, #[case] id: Option<StreamId>) {
+        assert_eq!(parse_bound(bound, end).ok(), id);
+    }
+}
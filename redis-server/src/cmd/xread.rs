@@ -0,0 +1,159 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+
+use super::{xrange::entries_frame, xrange::parse_id, ParseFrames};
+use crate::{
+    cmd::{anyhow, bail},
+    connection::Connection,
+    db::{BlockingRead, Db, Entries, GroupReader, ReadFrom},
+    frame::{Frame, Protocol},
+};
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`
+///
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+/// [key ...] id [id ...]` reads for a consumer of a group instead.
+pub struct Xread {
+    streams: Vec<(String, ReadFrom)>,
+    group: Option<GroupReader>,
+    count: Option<usize>,
+    // How long to wait for entries if there are none, `Some(None)` meaning forever.
+    block: Option<Option<Duration>>,
+}
+
+impl Xread {
+    pub fn parse(parser: &mut ParseFrames, group: bool) -> anyhow::Result<Self> {
+        let command = if group { "xreadgroup" } else { "xread" };
+        let mut reader = None;
+        if group {
+            let option = parser.next_string()?.unwrap_or_default();
+            if !option.eq_ignore_ascii_case("group") {
+                bail!("ERR Missing GROUP option for XREADGROUP");
+            }
+            let (Some(group), Some(consumer)) = (parser.next_bytes()?, parser.next_bytes()?) else {
+                bail!("ERR wrong number of arguments for 'xreadgroup' command");
+            };
+            reader = Some(GroupReader {
+                group,
+                consumer,
+                no_ack: false,
+            });
+        }
+
+        let mut count = None;
+        let mut block = None;
+        loop {
+            let option = parser
+                .next_string()?
+                .ok_or_else(|| anyhow!("ERR wrong number of arguments for '{command}' command"))?;
+            match option.to_lowercase().as_str() {
+                "count" => {
+                    let value = parser
+                        .next_integer()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    // Like in redis, a count that isn't positive means no count.
+                    count = usize::try_from(value).ok().filter(|count| *count > 0);
+                }
+                "block" => {
+                    let millis =
+                        parser.next_integer().ok().flatten().ok_or_else(|| {
+                            anyhow!("ERR timeout is not an integer or out of range")
+                        })?;
+                    let millis =
+                        u64::try_from(millis).map_err(|_| anyhow!("ERR timeout is negative"))?;
+                    block = Some((millis > 0).then(|| Duration::from_millis(millis)));
+                }
+                "noack" if group => {
+                    if let Some(reader) = &mut reader {
+                        reader.no_ack = true;
+                    }
+                }
+                "streams" => break,
+                _ => bail!("ERR syntax error"),
+            }
+        }
+
+        let mut arguments = vec![];
+        while let Some(argument) = parser.next_bytes()? {
+            arguments.push(argument);
+        }
+        if arguments.is_empty() || arguments.len() % 2 != 0 {
+            bail!(
+                "ERR Unbalanced '{command}' list of streams: for each stream key an ID or '$' must be specified."
+            );
+        }
+        let ids = arguments.split_off(arguments.len() / 2);
+        let streams = arguments
+            .into_iter()
+            .zip(ids)
+            .map(|(key, id)| {
+                let key = String::from_utf8(key.to_vec())?;
+                Ok((key, parse_from(&id, group)?))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            streams,
+            group: reader,
+            count,
+            block,
+        })
+    }
+
+    /// Whether this is an `XREADGROUP`, which changes the groups it reads for.
+    pub fn is_group(&self) -> bool {
+        self.group.is_some()
+    }
+
+    /// Returns `None` when the client is blocked, in which case the reply is parked on `conn`.
+    pub fn execute(self, conn: &mut Connection, db: &Db) -> io::Result<Option<Frame>> {
+        let protocol = conn.protocol();
+        let read = db.read_streams(self.streams, self.group, self.count, self.block.is_some())?;
+        match read {
+            BlockingRead::Ready(streams) if streams.is_empty() => Ok(Some(Frame::NullArray)),
+            BlockingRead::Ready(streams) => Ok(Some(streams_frame(streams, protocol))),
+            BlockingRead::Blocked(blocked) => {
+                let timeout = self.block.flatten();
+                conn.park(Box::pin(async move {
+                    match blocked.wait(timeout).await {
+                        Ok(Some(stream)) => streams_frame(vec![stream], protocol),
+                        Ok(None) => Frame::NullArray,
+                        Err(err) => Frame::Error(err.to_string()),
+                    }
+                }));
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn parse_from(id: &Bytes, group: bool) -> anyhow::Result<ReadFrom> {
+    match &id[..] {
+        b"$" if group => bail!(
+            "ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+        ),
+        b"$" => Ok(ReadFrom::Last),
+        b">" if group => Ok(ReadFrom::New),
+        b">" => bail!(
+            "ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option."
+        ),
+        id => Ok(ReadFrom::After(parse_id(id)?)),
+    }
+}
+
+/// The entries read from each stream, keyed by the name of the stream. RESP2 has no maps, so
+/// each stream is a pair there, as in redis.
+fn streams_frame(streams: Vec<(String, Entries)>, protocol: Protocol) -> Frame {
+    let streams = streams
+        .into_iter()
+        .map(|(key, entries)| (Frame::BulkString(key.into()), entries_frame(entries)));
+    match protocol {
+        Protocol::Resp3 => Frame::Map(streams.collect()),
+        Protocol::Resp2 => Frame::Array(
+            streams
+                .map(|(key, entries)| Frame::Array(vec![key, entries]))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+
+use super::{xrange::parse_id, ParseFrames};
+use crate::{
+    cmd::anyhow,
+    db::{Db, Trim},
+    frame::Frame,
+};
+
+/// `XTRIM key <MAXLEN | MINID> [= | ~] threshold [LIMIT count]`
+///
+/// Approximate trimming lets redis keep a few more entries than asked for; we always trim
+/// exactly, which is also what replaying the command gives.
+pub struct Xtrim {
+    key: String,
+    trim: Trim,
+    limit: Option<usize>,
+}
+
+impl Xtrim {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("ERR wrong number of arguments for 'xtrim' command"))?;
+        let mut options = TrimOptions::default();
+        while let Some(option) = parser.next_string()? {
+            if !options.parse(&option, parser)? {
+                return Err(anyhow!("ERR syntax error"));
+            }
+        }
+        let (trim, limit) = options
+            .finish()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        Ok(Self { key, trim, limit })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let evicted = db.with_stream_data_mut(self.key, false, |stream| {
+            let evicted = stream.map_or(0, |stream| stream.trim(self.trim, self.limit));
+            (evicted, evicted > 0)
+        })?;
+        Ok(Frame::Integer(evicted as i64))
+    }
+}
+
+/// The `<MAXLEN | MINID> [= | ~] threshold [LIMIT count]` options, which `XADD` takes too.
+#[derive(Default)]
+pub(crate) struct TrimOptions {
+    trim: Option<Trim>,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl TrimOptions {
+    /// Parses `option` along with its arguments. Returns false if it isn't one of these options.
+    pub(crate) fn parse(&mut self, option: &str, parser: &mut ParseFrames) -> anyhow::Result<bool> {
+        let option = option.to_lowercase();
+        match option.as_str() {
+            "maxlen" | "minid" => {
+                let mut threshold = parser
+                    .next_bytes()?
+                    .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                if let b"=" | b"~" = &threshold[..] {
+                    self.approximate = &threshold[..] == b"~";
+                    threshold = parser
+                        .next_bytes()?
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                }
+                self.trim = Some(if option == "maxlen" {
+                    let max_len = std::str::from_utf8(&threshold)
+                        .ok()
+                        .and_then(|max_len| max_len.parse::<i64>().ok())
+                        .ok_or_else(|| anyhow!("ERR value is not an integer or out of range"))?;
+                    let max_len = usize::try_from(max_len)
+                        .map_err(|_| anyhow!("ERR The MAXLEN argument must be >= 0."))?;
+                    Trim::MaxLen(max_len)
+                } else {
+                    Trim::MinId(parse_id(&threshold)?)
+                });
+            }
+            "limit" => {
+                let limit = parser
+                    .next_integer()?
+                    .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                let limit = usize::try_from(limit)
+                    .map_err(|_| anyhow!("ERR The LIMIT argument must be >= 0."))?;
+                self.limit = Some(limit);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The trimming to do, if any, along with the most entries to evict.
+    pub(crate) fn finish(self) -> anyhow::Result<Option<(Trim, Option<usize>)>> {
+        if self.limit.is_some() && !self.approximate {
+            return Err(anyhow!(
+                "ERR syntax error, LIMIT cannot be used without the special ~ option"
+            ));
+        }
+        // Like in redis, a limit of 0 means no limit.
+        let limit = self.limit.filter(|limit| *limit > 0);
+        Ok(self.trim.map(|trim| (trim, limit)))
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::{parse_score, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+#[derive(Default)]
+struct Options {
+    // Only add new members.
+    nx: bool,
+    // Only update existing members.
+    xx: bool,
+    // Only update a member if the new score is greater than the current one.
+    gt: bool,
+    // Only update a member if the new score is less than the current one.
+    lt: bool,
+    // Count changed members in the reply, not only the added ones.
+    ch: bool,
+    // Behave like `ZINCRBY`.
+    incr: bool,
+}
+
+pub struct Zadd {
+    key: String,
+    options: Options,
+    members: Vec<(f64, Bytes)>,
+}
+
+impl Zadd {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+
+        let mut args = vec![];
+        while let Some(arg) = parser.next_string()? {
+            args.push(arg);
+        }
+        let mut args = args.into_iter().peekable();
+
+        let mut options = Options::default();
+        while let Some(option) = args.peek() {
+            match option.to_lowercase().as_str() {
+                "nx" => options.nx = true,
+                "xx" => options.xx = true,
+                "gt" => options.gt = true,
+                "lt" => options.lt = true,
+                "ch" => options.ch = true,
+                "incr" => options.incr = true,
+                _ => break,
+            }
+            args.next();
+        }
+
+        if options.nx && options.xx {
+            return Err(anyhow!(
+                "ERR XX and NX options at the same time are not compatible"
+            ));
+        }
+        if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+            return Err(anyhow!(
+                "ERR GT, LT, and/or NX options at the same time are not compatible"
+            ));
+        }
+
+        let mut members = vec![];
+        while let Some(score) = args.next() {
+            let member = args.next().ok_or_else(|| anyhow!("ERR syntax error"))?;
+            members.push((parse_score(&score)?, Bytes::from(member)));
+        }
+        if members.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'zadd' command"));
+        }
+        if options.incr && members.len() > 1 {
+            return Err(anyhow!(
+                "ERR INCR option supports a single increment-element pair"
+            ));
+        }
+
+        Ok(Self {
+            key,
+            options,
+            members,
+        })
+    }
+
+    /// Replies with the number of added members, or with the new score when `INCR` is used.
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let options = self.options;
+        let frame = db.with_sorted_set_data_mut(self.key, |sorted_set| {
+            let mut added = 0;
+            let mut changed = 0;
+            let mut incremented = None;
+
+            for (score, member) in self.members {
+                let current = sorted_set.score(&member);
+                let score = match current {
+                    Some(current) if options.incr => current + score,
+                    _ => score,
+                };
+                if score.is_nan() {
+                    let err = Frame::Error("ERR resulting score is not a number (NaN)".to_owned());
+                    return (err, added + changed > 0);
+                }
+
+                let skip = match current {
+                    None => options.xx,
+                    Some(current) => {
+                        options.nx
+                            || (options.gt && score <= current)
+                            || (options.lt && score >= current)
+                    }
+                };
+                if skip {
+                    continue;
+                }
+
+                match sorted_set.insert(member, score) {
+                    None => added += 1,
+                    Some(previous) if previous != score => changed += 1,
+                    Some(_) => {}
+                }
+                incremented = Some(score);
+            }
+
+            let frame = if options.incr {
+                incremented.map(Frame::Double).unwrap_or(Frame::Null)
+            } else if options.ch {
+                Frame::Integer(added + changed)
+            } else {
+                Frame::Integer(added)
+            };
+            (frame, added + changed > 0)
+        })?;
+        Ok(frame)
+    }
+}
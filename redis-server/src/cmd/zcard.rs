@@ -0,0 +1,24 @@
+use std::io;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Zcard {
+    key: String,
+}
+
+impl Zcard {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        Ok(Self { key })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let len = db.with_sorted_set_data(&self.key, |sorted_set| {
+            sorted_set.map(|sorted_set| sorted_set.len()).unwrap_or(0)
+        })?;
+        Ok(Frame::Integer(len as i64))
+    }
+}
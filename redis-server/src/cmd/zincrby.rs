@@ -0,0 +1,44 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::{parse_score, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Zincrby {
+    key: String,
+    increment: f64,
+    member: Bytes,
+}
+
+impl Zincrby {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let increment = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected an increment but found None"))?;
+        let member = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a member but found None"))?;
+        Ok(Self {
+            key,
+            increment: parse_score(&increment)?,
+            member,
+        })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_sorted_set_data_mut(self.key, |sorted_set| {
+            let score = sorted_set.score(&self.member).unwrap_or(0.0) + self.increment;
+            if score.is_nan() {
+                let err = Frame::Error("ERR resulting score is not a number (NaN)".to_owned());
+                return (err, false);
+            }
+            sorted_set.insert(self.member, score);
+            (Frame::Double(score), true)
+        })?;
+        Ok(frame)
+    }
+}
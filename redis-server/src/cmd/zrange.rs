@@ -0,0 +1,207 @@
+use std::{io, ops::Bound};
+
+use bytes::Bytes;
+
+use super::{parse_score, resolve_range, ParseFrames};
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+enum Range {
+    Rank(i64, i64),
+    Score(Bound<f64>, Bound<f64>),
+    Lex(Bound<Bytes>, Bound<Bytes>),
+}
+
+pub struct Zrange {
+    key: String,
+    range: Range,
+    rev: bool,
+    // The offset and count given with `LIMIT`.
+    limit: Option<(usize, Option<usize>)>,
+    with_scores: bool,
+}
+
+impl Zrange {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let start = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a start but found None"))?;
+        let stop = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a stop but found None"))?;
+
+        let mut by_score = false;
+        let mut by_lex = false;
+        let mut rev = false;
+        let mut limit = None;
+        let mut with_scores = false;
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "byscore" => by_score = true,
+                "bylex" => by_lex = true,
+                "rev" => rev = true,
+                "limit" => limit = Some(Self::limit(parser)?),
+                "withscores" => with_scores = true,
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+
+        if by_score && by_lex {
+            return Err(anyhow!("ERR syntax error"));
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            return Err(anyhow!(
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+            ));
+        }
+        if with_scores && by_lex {
+            return Err(anyhow!(
+                "ERR syntax error, WITHSCORES not supported in combination with BYLEX"
+            ));
+        }
+
+        // With `REV`, the range is given from the highest to the lowest member.
+        let (min, max) = if rev && (by_score || by_lex) {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+        let range = if by_score {
+            Range::Score(score_bound(&min)?, score_bound(&max)?)
+        } else if by_lex {
+            Range::Lex(lex_bound(&min)?, lex_bound(&max)?)
+        } else {
+            let start = min
+                .parse()
+                .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?;
+            let stop = max
+                .parse()
+                .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?;
+            Range::Rank(start, stop)
+        };
+
+        Ok(Self {
+            key,
+            range,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+
+    /// `ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]`
+    pub fn parse_by_score(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let min = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a min but found None"))?;
+        let max = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a max but found None"))?;
+
+        let mut limit = None;
+        let mut with_scores = false;
+        while let Some(option) = parser.next_string()? {
+            match option.to_lowercase().as_str() {
+                "limit" => limit = Some(Self::limit(parser)?),
+                "withscores" => with_scores = true,
+                _ => return Err(anyhow!("ERR syntax error")),
+            }
+        }
+
+        Ok(Self {
+            key,
+            range: Range::Score(score_bound(&min)?, score_bound(&max)?),
+            rev: false,
+            limit,
+            with_scores,
+        })
+    }
+
+    fn limit(parser: &mut ParseFrames) -> anyhow::Result<(usize, Option<usize>)> {
+        let offset = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        let count = parser
+            .next_integer()?
+            .ok_or_else(|| anyhow!("ERR syntax error"))?;
+        // A negative offset returns nothing, while a negative count returns everything.
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        let count = usize::try_from(count).ok();
+        Ok((offset, count))
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let members = db.with_sorted_set_data(&self.key, |sorted_set| {
+            let Some(sorted_set) = sorted_set else {
+                return vec![];
+            };
+
+            let mut members = match &self.range {
+                Range::Rank(start, stop) => {
+                    let Some((start, stop)) = resolve_range(*start, *stop, sorted_set.len()) else {
+                        return vec![];
+                    };
+                    // Ranks are counted from the highest score with `REV`.
+                    let members: Vec<_> = if self.rev {
+                        sorted_set.iter().rev().collect()
+                    } else {
+                        sorted_set.iter().collect()
+                    };
+                    return members[start..=stop]
+                        .iter()
+                        .map(|(member, score)| ((*member).clone(), *score))
+                        .collect();
+                }
+                Range::Score(min, max) => sorted_set.range_by_score(*min, *max),
+                Range::Lex(min, max) => sorted_set.range_by_lex(min.as_ref(), max.as_ref()),
+            };
+
+            if self.rev {
+                members.reverse();
+            }
+            let (offset, count) = self.limit.unwrap_or((0, None));
+            members
+                .into_iter()
+                .skip(offset)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(member, score)| (member.clone(), score))
+                .collect()
+        })?;
+
+        let frames = members
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let score = self.with_scores.then_some(Frame::Double(score));
+                std::iter::once(Frame::BulkString(member)).chain(score)
+            })
+            .collect();
+        Ok(Frame::Array(frames))
+    }
+}
+
+/// Parses a score bound such as `1.5`, `(1.5` for an exclusive bound, `-inf` or `+inf`.
+fn score_bound(bound: &str) -> anyhow::Result<Bound<f64>> {
+    let error = || anyhow!("ERR min or max is not a float");
+    match bound.strip_prefix('(') {
+        Some(score) => parse_score(score).map(Bound::Excluded).map_err(|_| error()),
+        None => parse_score(bound).map(Bound::Included).map_err(|_| error()),
+    }
+}
+
+/// Parses a lexicographical bound: `[member` is inclusive, `(member` exclusive,
+/// while `-` and `+` are the lowest and highest possible members.
+fn lex_bound(bound: &str) -> anyhow::Result<Bound<Bytes>> {
+    match bound {
+        "-" | "+" => Ok(Bound::Unbounded),
+        bound => match bound.split_at_checked(1) {
+            Some(("[", member)) => Ok(Bound::Included(Bytes::from(member.to_owned()))),
+            Some(("(", member)) => Ok(Bound::Excluded(Bytes::from(member.to_owned()))),
+            _ => Err(anyhow!("ERR min or max not valid string range item")),
+        },
+    }
+}
@@ -0,0 +1,33 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Zrank {
+    key: String,
+    member: Bytes,
+}
+
+impl Zrank {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let member = parser
+            .next_bytes()?
+            .ok_or_else(|| anyhow!("Expected a member but found None"))?;
+        Ok(Self { key, member })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let frame = db.with_sorted_set_data(&self.key, |sorted_set| {
+            sorted_set
+                .and_then(|sorted_set| sorted_set.rank(&self.member))
+                .map(|rank| Frame::Integer(rank as i64))
+                .unwrap_or(Frame::Null)
+        })?;
+        Ok(frame)
+    }
+}
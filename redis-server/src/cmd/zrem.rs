@@ -0,0 +1,39 @@
+use std::io;
+
+use bytes::Bytes;
+
+use super::ParseFrames;
+use crate::{cmd::anyhow, db::Db, frame::Frame};
+
+pub struct Zrem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl Zrem {
+    pub fn parse(parser: &mut ParseFrames) -> anyhow::Result<Self> {
+        let key = parser
+            .next_string()?
+            .ok_or_else(|| anyhow!("Expected a string for key but found None"))?;
+        let mut members = vec![];
+        while let Some(member) = parser.next_bytes()? {
+            members.push(member);
+        }
+        if members.is_empty() {
+            return Err(anyhow!("ERR wrong number of arguments for 'zrem' command"));
+        }
+        Ok(Self { key, members })
+    }
+
+    pub fn execute(self, db: &Db) -> io::Result<Frame> {
+        let removed = db.with_sorted_set_data_mut(self.key, |sorted_set| {
+            let removed = self
+                .members
+                .iter()
+                .filter(|member| sorted_set.remove(member).is_some())
+                .count();
+            (removed, removed > 0)
+        })?;
+        Ok(Frame::Integer(removed as i64))
+    }
+}
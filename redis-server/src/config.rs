@@ -0,0 +1,959 @@
+use std::{collections::HashSet, fmt, fs, io, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::glob;
+
+/// Settings that can be given in a `redis.conf` style file, on the command line, or changed
+/// at runtime with `CONFIG SET`, the same way as with redis:
+/// `redis-server /etc/redis.conf --dir /var/lib/redis --dbfilename dump.rdb`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The config file the server was started with, which `CONFIG REWRITE` updates.
+    pub file: Option<PathBuf>,
+    /// The address the server listens on.
+    pub bind: String,
+    pub port: u16,
+    /// How many numbered databases clients can `SELECT`.
+    pub databases: usize,
+    /// The directory snapshots and the AOF are written to and loaded from.
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// Whether every write command is appended to the AOF.
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: AppendFsync,
+    /// A snapshot is saved in the background as soon as any of these is met.
+    pub save: Vec<SaveRule>,
+    /// The most memory the keyspace can use, in bytes. 0 means there is no limit.
+    pub maxmemory: u64,
+    /// Which keys are evicted to stay within `maxmemory`.
+    pub maxmemory_policy: MaxmemoryPolicy,
+    /// How many keys are sampled to pick the one to evict.
+    pub maxmemory_samples: usize,
+    /// The master this server replicates, set on startup or with `REPLICAOF`.
+    pub replicaof: Option<(String, u16)>,
+    /// Whether clients of a replica are refused write commands.
+    pub replica_read_only: bool,
+    /// How many bytes of the replication stream are kept, so that a replica that was
+    /// disconnected for a short while can catch up without a full sync.
+    pub repl_backlog_size: u64,
+    /// Commands that take longer than this many microseconds are added to the slow log.
+    /// A negative value disables the slow log.
+    pub slowlog_log_slower_than: i64,
+    /// How many entries the slow log keeps.
+    pub slowlog_max_len: usize,
+    /// How many milliseconds a script runs before other clients are replied `BUSY` and it can
+    /// be stopped with `SCRIPT KILL`. 0 lets scripts run for as long as they need.
+    pub lua_time_limit: u64,
+    /// Which changes to keys are published to the pub/sub channels of keyspace notifications.
+    pub notify_keyspace_events: KeyspaceEvents,
+    /// How much can be queued for a client subscribed to channels before it is disconnected.
+    pub client_output_buffer_limit_pubsub: OutputBufferLimit,
+    /// The password of the `default` user. Empty means clients don't need to `AUTH`.
+    pub requirepass: String,
+    /// Whether the server is a node of a cluster, serving only the keys of its hash slots.
+    pub cluster_enabled: bool,
+    /// Where the node saves what it knows of the cluster, relative to `dir`. Unlike the
+    /// other files, it is written by the node itself and isn't meant to be edited.
+    pub cluster_config_file: String,
+    /// How many milliseconds a node can go without answering pings before it is flagged as
+    /// failing, and how long a handshake with a new node can take.
+    pub cluster_node_timeout: u64,
+    /// The port of the cluster bus, which nodes talk to each other on. 0 means the client
+    /// port plus 10000, like with redis.
+    pub cluster_port: u16,
+}
+
+/// How often the AOF is flushed to disk. The commands are always handed to the OS
+/// before the client gets a reply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AppendFsync {
+    /// After every write command, so nothing is lost.
+    Always,
+    /// Once per second from a background thread, so at most a second of writes is lost.
+    EverySec,
+    /// Whenever the OS decides to.
+    No,
+}
+
+/// What happens to writes once `maxmemory` is reached. Keys are picked among a few random
+/// ones, so the least recently used key evicted is only approximately the least recent one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxmemoryPolicy {
+    /// Writes that could use more memory fail instead.
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    /// Only keys with a time to live are evicted, the least recently used first.
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    /// Only keys with a time to live are evicted, the ones expiring first first.
+    VolatileTtl,
+}
+
+impl MaxmemoryPolicy {
+    const NAMES: [(&'static str, MaxmemoryPolicy); 8] = [
+        ("noeviction", MaxmemoryPolicy::NoEviction),
+        ("allkeys-lru", MaxmemoryPolicy::AllKeysLru),
+        ("allkeys-lfu", MaxmemoryPolicy::AllKeysLfu),
+        ("allkeys-random", MaxmemoryPolicy::AllKeysRandom),
+        ("volatile-lru", MaxmemoryPolicy::VolatileLru),
+        ("volatile-lfu", MaxmemoryPolicy::VolatileLfu),
+        ("volatile-random", MaxmemoryPolicy::VolatileRandom),
+        ("volatile-ttl", MaxmemoryPolicy::VolatileTtl),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, policy)| *policy == self)
+            .map(|(name, _)| *name)
+            .expect("every policy has a name")
+    }
+
+    /// Whether only keys with a time to live can be evicted.
+    pub fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            MaxmemoryPolicy::VolatileLru
+                | MaxmemoryPolicy::VolatileLfu
+                | MaxmemoryPolicy::VolatileRandom
+                | MaxmemoryPolicy::VolatileTtl
+        )
+    }
+}
+
+/// Which keyspace notifications are published, given like with redis as a string of
+/// characters: `K` and `E` for the `__keyspace@<db>__:<key>` and `__keyevent@<db>__:<event>`
+/// channels, followed by the classes of events, e.g. `g` for generic commands like `DEL`, `$`
+/// for string commands, `x` for expired keys, or `A` for all of them. Nothing is published
+/// unless both a kind of channel and a class are given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyspaceEvents {
+    keyspace: bool,
+    keyevent: bool,
+    // One bit per character of `CLASSES`.
+    classes: u16,
+}
+
+impl KeyspaceEvents {
+    /// Generic, string, list, set, hash, sorted set, expired, evicted and stream events.
+    const CLASSES: &'static str = "g$lshzxet";
+    const ALL: u16 = (1 << Self::CLASSES.len()) - 1;
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut events = Self::default();
+        for flag in value.chars() {
+            match flag {
+                'K' => events.keyspace = true,
+                'E' => events.keyevent = true,
+                'A' => events.classes = Self::ALL,
+                class => events.classes |= 1 << Self::CLASSES.find(class)?,
+            }
+        }
+        Some(events)
+    }
+
+    /// Whether events are published to the channel named after the key.
+    pub fn keyspace(self) -> bool {
+        self.keyspace
+    }
+
+    /// Whether events are published to the channel named after the event.
+    pub fn keyevent(self) -> bool {
+        self.keyevent
+    }
+
+    /// Whether the events of the class, one of the characters above, are published.
+    pub fn is_enabled(self, class: char) -> bool {
+        !self.is_empty()
+            && Self::CLASSES
+                .find(class)
+                .is_some_and(|bit| self.classes & 1 << bit != 0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        !(self.keyspace || self.keyevent) || self.classes == 0
+    }
+}
+
+impl fmt::Display for KeyspaceEvents {
+    /// The flags the way `CONFIG GET` shows them, e.g. `AKE`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.classes == Self::ALL {
+            f.write_str("A")?;
+        } else {
+            for (bit, class) in Self::CLASSES.chars().enumerate() {
+                if self.classes & 1 << bit != 0 {
+                    write!(f, "{class}")?;
+                }
+            }
+        }
+        if self.keyspace {
+            f.write_str("K")?;
+        }
+        if self.keyevent {
+            f.write_str("E")?;
+        }
+        Ok(())
+    }
+}
+
+/// Save a snapshot once `changes` keys were changed within `seconds` of the last one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// A client is disconnected once more than `hard` bytes are queued for it, or more than
+/// `soft` bytes for `soft_seconds` in a row. A limit of 0 is no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OutputBufferLimit {
+    pub hard: u64,
+    pub soft: u64,
+    pub soft_seconds: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file: None,
+            bind: "127.0.0.1".to_owned(),
+            port: 6379,
+            databases: 16,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_owned(),
+            appendonly: false,
+            appendfilename: "appendonly.aof".to_owned(),
+            appendfsync: AppendFsync::EverySec,
+            save: vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            maxmemory: 0,
+            maxmemory_policy: MaxmemoryPolicy::NoEviction,
+            maxmemory_samples: 5,
+            replicaof: None,
+            replica_read_only: true,
+            repl_backlog_size: 1024 * 1024,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            lua_time_limit: 5000,
+            notify_keyspace_events: KeyspaceEvents::default(),
+            client_output_buffer_limit_pubsub: OutputBufferLimit {
+                hard: 32 * 1024 * 1024,
+                soft: 8 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+            requirepass: String::new(),
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_owned(),
+            cluster_node_timeout: 15000,
+            cluster_port: 0,
+        }
+    }
+}
+
+struct Parameter {
+    name: &'static str,
+    /// Whether `CONFIG SET` can change it. The others only take effect on startup.
+    mutable: bool,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> anyhow::Result<()>,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "bind",
+        mutable: false,
+        get: |config| config.bind.clone(),
+        set: |config, value| {
+            config.bind = value.to_owned();
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "port",
+        mutable: false,
+        get: |config| config.port.to_string(),
+        set: |config, value| {
+            config.port = value
+                .parse()
+                .context("argument couldn't be parsed into a port")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "databases",
+        mutable: false,
+        get: |config| config.databases.to_string(),
+        set: |config, value| {
+            config.databases = value
+                .parse()
+                .ok()
+                .filter(|databases| *databases >= 1)
+                .ok_or_else(|| anyhow!("argument must be a positive number of databases"))?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dir",
+        mutable: true,
+        get: |config| config.dir.display().to_string(),
+        set: |config, value| {
+            if !fs::metadata(value).is_ok_and(|metadata| metadata.is_dir()) {
+                bail!("No such directory");
+            }
+            config.dir = PathBuf::from(value);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dbfilename",
+        mutable: true,
+        get: |config| config.dbfilename.clone(),
+        set: |config, value| {
+            config.dbfilename = file_name(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendonly",
+        mutable: true,
+        get: |config| yes_or_no(config.appendonly),
+        set: |config, value| {
+            config.appendonly = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendfilename",
+        mutable: false,
+        get: |config| config.appendfilename.clone(),
+        set: |config, value| {
+            config.appendfilename = file_name(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendfsync",
+        mutable: true,
+        get: |config| {
+            match config.appendfsync {
+                AppendFsync::Always => "always",
+                AppendFsync::EverySec => "everysec",
+                AppendFsync::No => "no",
+            }
+            .to_owned()
+        },
+        set: |config, value| {
+            config.appendfsync = match value.to_lowercase().as_str() {
+                "always" => AppendFsync::Always,
+                "everysec" => AppendFsync::EverySec,
+                "no" => AppendFsync::No,
+                _ => bail!("Invalid appendfsync policy '{value}'"),
+            };
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "save",
+        mutable: true,
+        get: |config| {
+            config
+                .save
+                .iter()
+                .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: |config, value| {
+            let numbers = value
+                .split_whitespace()
+                .map(|number| number.parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| anyhow!("Invalid save parameters"))?;
+            if numbers.len() % 2 != 0 {
+                bail!("Invalid save parameters");
+            }
+            config.save = numbers
+                .chunks(2)
+                .map(|rule| SaveRule {
+                    seconds: rule[0],
+                    changes: rule[1],
+                })
+                .collect();
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory",
+        mutable: true,
+        get: |config| config.maxmemory.to_string(),
+        set: |config, value| {
+            config.maxmemory = parse_memory(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory-policy",
+        mutable: true,
+        get: |config| config.maxmemory_policy.name().to_owned(),
+        set: |config, value| {
+            config.maxmemory_policy = MaxmemoryPolicy::NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(value))
+                .map(|(_, policy)| *policy)
+                .ok_or_else(|| anyhow!("argument(s) must be one of the following: noeviction, allkeys-lru, allkeys-lfu, allkeys-random, volatile-lru, volatile-lfu, volatile-random, volatile-ttl"))?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory-samples",
+        mutable: true,
+        get: |config| config.maxmemory_samples.to_string(),
+        set: |config, value| {
+            config.maxmemory_samples = value
+                .parse()
+                .ok()
+                .filter(|samples| (1..=64).contains(samples))
+                .ok_or_else(|| anyhow!("argument must be between 1 and 64 inclusive"))?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "replicaof",
+        mutable: false,
+        get: |config| match &config.replicaof {
+            Some((host, port)) => format!("{host} {port}"),
+            None => String::new(),
+        },
+        set: |config, value| {
+            config.replicaof = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => None,
+                [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => {
+                    None
+                }
+                [host, port] => Some((
+                    host.to_owned(),
+                    port.parse().context("Invalid master port")?,
+                )),
+                _ => bail!("Expected the master's host and port"),
+            };
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "replica-read-only",
+        mutable: true,
+        get: |config| yes_or_no(config.replica_read_only),
+        set: |config, value| {
+            config.replica_read_only = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "repl-backlog-size",
+        mutable: true,
+        get: |config| config.repl_backlog_size.to_string(),
+        set: |config, value| {
+            config.repl_backlog_size = parse_memory(value)?.max(16 * 1024);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "slowlog-log-slower-than",
+        mutable: true,
+        get: |config| config.slowlog_log_slower_than.to_string(),
+        set: |config, value| {
+            config.slowlog_log_slower_than = value
+                .parse()
+                .context("argument couldn't be parsed into an integer")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "slowlog-max-len",
+        mutable: true,
+        get: |config| config.slowlog_max_len.to_string(),
+        set: |config, value| {
+            config.slowlog_max_len = value
+                .parse()
+                .context("argument couldn't be parsed into an integer")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "lua-time-limit",
+        mutable: true,
+        get: |config| config.lua_time_limit.to_string(),
+        set: |config, value| {
+            config.lua_time_limit = value
+                .parse()
+                .context("argument couldn't be parsed into an integer")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "notify-keyspace-events",
+        mutable: true,
+        get: |config| config.notify_keyspace_events.to_string(),
+        set: |config, value| {
+            config.notify_keyspace_events = KeyspaceEvents::parse(value)
+                .ok_or_else(|| anyhow!("Invalid event class character. Use 'Ag$lshzxetKE'."))?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "client-output-buffer-limit",
+        mutable: true,
+        get: |config| {
+            let limit = config.client_output_buffer_limit_pubsub;
+            format!(
+                "pubsub {} {} {}",
+                limit.hard, limit.soft, limit.soft_seconds
+            )
+        },
+        // Given like with redis as `<class> <hard> <soft> <soft seconds>`, possibly repeated,
+        // though only the limits of the pubsub class are enforced.
+        set: |config, value| {
+            let words: Vec<_> = value.split_whitespace().collect();
+            if words.len() % 4 != 0 {
+                bail!("Wrong number of arguments in buffer limit configuration.");
+            }
+            for limit in words.chunks(4) {
+                if !limit[0].eq_ignore_ascii_case("pubsub") {
+                    bail!("Invalid client class specified in buffer limit configuration, only pubsub is supported.");
+                }
+                config.client_output_buffer_limit_pubsub = OutputBufferLimit {
+                    hard: parse_memory(limit[1])?,
+                    soft: parse_memory(limit[2])?,
+                    soft_seconds: limit[3]
+                        .parse()
+                        .context("argument couldn't be parsed into an integer")?,
+                };
+            }
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "requirepass",
+        mutable: true,
+        get: |config| config.requirepass.clone(),
+        set: |config, value| {
+            config.requirepass = value.to_owned();
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "cluster-enabled",
+        mutable: false,
+        get: |config| yes_or_no(config.cluster_enabled),
+        set: |config, value| {
+            config.cluster_enabled = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "cluster-config-file",
+        mutable: false,
+        get: |config| config.cluster_config_file.clone(),
+        set: |config, value| {
+            config.cluster_config_file = file_name(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "cluster-node-timeout",
+        mutable: true,
+        get: |config| config.cluster_node_timeout.to_string(),
+        set: |config, value| {
+            config.cluster_node_timeout = value
+                .parse()
+                .ok()
+                .filter(|timeout| *timeout > 0)
+                .ok_or_else(|| anyhow!("argument must be a positive number of milliseconds"))?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "cluster-port",
+        mutable: false,
+        get: |config| config.cluster_port.to_string(),
+        set: |config, value| {
+            config.cluster_port = value
+                .parse()
+                .context("argument couldn't be parsed into a port")?;
+            Ok(())
+        },
+    },
+];
+
+impl Config {
+    /// Reads the config file, if the first argument is one, and then applies the options
+    /// given on the command line on top of it.
+    pub fn from_args(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut args = args.peekable();
+        let mut config = Self::default();
+        let mut directives = vec![];
+
+        if let Some(path) = args.next_if(|arg| !arg.starts_with("--")) {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read the config file '{path}'"))?;
+            for line in contents.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let args = split_args(line)?;
+                if let Some((name, values)) = args.split_first() {
+                    directives.push((name.to_lowercase(), values.to_vec()));
+                }
+            }
+            config.file = Some(PathBuf::from(path));
+        }
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                bail!("Unexpected argument '{arg}'");
+            };
+            let mut values = vec![];
+            while let Some(value) = args.next_if(|arg| !arg.starts_with("--")) {
+                values.push(value);
+            }
+            if values.is_empty() {
+                bail!("Expected a value for '{arg}' but found None");
+            }
+            directives.push((name.to_lowercase(), values));
+        }
+
+        // Like redis, every `save` directive adds a rule to the ones before it, and the first
+        // one replaces the default rules.
+        let mut save: Option<Vec<String>> = None;
+        for (name, values) in directives {
+            if name == "save" {
+                let rules = save.get_or_insert_with(Vec::new);
+                match values.as_slice() {
+                    [value] if value.is_empty() => rules.clear(),
+                    _ => rules.extend(values),
+                }
+                continue;
+            }
+            let parameter = parameter(&name).ok_or_else(|| anyhow!("Unknown option '{name}'"))?;
+            (parameter.set)(&mut config, &values.join(" "))
+                .with_context(|| format!("Invalid value for '{name}'"))?;
+        }
+        if let Some(rules) = save {
+            (parameter("save").expect("save is a parameter").set)(&mut config, &rules.join(" "))?;
+        }
+        Ok(config)
+    }
+
+    /// The parameters whose name matches one of the glob-style patterns, with their value.
+    pub fn get(&self, patterns: &[String]) -> Vec<(&'static str, String)> {
+        PARAMETERS
+            .iter()
+            .filter(|parameter| {
+                patterns.iter().any(|pattern| {
+                    glob::matches(pattern.to_lowercase().as_bytes(), parameter.name.as_bytes())
+                })
+            })
+            .map(|parameter| (parameter.name, (parameter.get)(self)))
+            .collect()
+    }
+
+    /// Changes a parameter the way `CONFIG SET` does, refusing the ones that can only be
+    /// given on startup.
+    pub fn set(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        let parameter = parameter(&name.to_lowercase()).ok_or_else(|| {
+            anyhow!("ERR Unknown option or number of arguments for CONFIG SET - '{name}'")
+        })?;
+        if !parameter.mutable {
+            bail!(
+                "ERR CONFIG SET failed (possibly related to argument '{name}') - can't set immutable config"
+            );
+        }
+        (parameter.set)(self, value).map_err(|err| {
+            anyhow!("ERR CONFIG SET failed (possibly related to argument '{name}') - {err}")
+        })
+    }
+
+    /// Writes the current parameters to the config file, the way `CONFIG REWRITE` does.
+    ///
+    /// The file's comments and layout are kept: the lines of the parameters are updated in
+    /// place, and the parameters that aren't in the file yet are only added if they differ
+    /// from their default.
+    pub fn rewrite(&self) -> io::Result<()> {
+        let path = self
+            .file
+            .as_ref()
+            .ok_or_else(|| io::Error::other("ERR The server is running without a config file"))?;
+        let failed = |err: io::Error| io::Error::other(format!("ERR Rewriting config file: {err}"));
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(failed(err)),
+        };
+
+        let mut lines = vec![];
+        let mut rewritten = HashSet::new();
+        for line in contents.lines() {
+            let name = match line.trim_start() {
+                directive if !directive.starts_with('#') => {
+                    directive.split_whitespace().next().map(str::to_lowercase)
+                }
+                _ => None,
+            };
+            match name.as_deref().and_then(parameter) {
+                // Repeated directives are merged into the first one.
+                Some(parameter) => {
+                    if rewritten.insert(parameter.name) {
+                        lines.extend(self.directives(parameter));
+                    }
+                }
+                None => lines.push(line.to_owned()),
+            }
+        }
+        let default = Self::default();
+        for parameter in PARAMETERS {
+            if !rewritten.contains(parameter.name)
+                && (parameter.get)(self) != (parameter.get)(&default)
+            {
+                lines.extend(self.directives(parameter));
+            }
+        }
+
+        // The file is replaced at once, so that a failure can't leave it halfway written.
+        let temp_path = path.with_file_name(format!("temp-{}.conf", std::process::id()));
+        fs::write(&temp_path, lines.join("\n") + "\n").map_err(failed)?;
+        fs::rename(&temp_path, path).map_err(failed)
+    }
+
+    /// Where the snapshot of the database is saved.
+    pub fn rdb_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    pub fn aof_path(&self) -> PathBuf {
+        self.dir.join(&self.appendfilename)
+    }
+
+    pub fn cluster_config_path(&self) -> PathBuf {
+        self.dir.join(&self.cluster_config_file)
+    }
+
+    pub fn cluster_bus_port(&self) -> u16 {
+        match self.cluster_port {
+            0 => self.port.wrapping_add(10000),
+            port => port,
+        }
+    }
+
+    /// The lines of the config file that set the parameter to its current value.
+    fn directives(&self, parameter: &Parameter) -> Vec<String> {
+        if parameter.name == "save" {
+            if self.save.is_empty() {
+                return vec![r#"save """#.to_owned()];
+            }
+            return self
+                .save
+                .iter()
+                .map(|rule| format!("save {} {}", rule.seconds, rule.changes))
+                .collect();
+        }
+        let value = (parameter.get)(self);
+        if value.is_empty() || value.contains(char::is_whitespace) || value.contains('"') {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            return vec![format!(r#"{} "{escaped}""#, parameter.name)];
+        }
+        vec![format!("{} {value}", parameter.name)]
+    }
+}
+
+fn parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|parameter| parameter.name == name)
+}
+
+/// Splits a line of the config file into its arguments, which can be quoted like in redis,
+/// e.g. `save ""` or `dir "/var/lib/my redis"`.
+fn split_args(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(args);
+        };
+        let mut arg = String::new();
+        match first {
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => arg.push('\n'),
+                        Some('t') => arg.push('\t'),
+                        Some(ch) => arg.push(ch),
+                        None => bail!("Unbalanced quotes in configuration line: {line}"),
+                    },
+                    Some(ch) => arg.push(ch),
+                    None => bail!("Unbalanced quotes in configuration line: {line}"),
+                }
+            },
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(ch) => arg.push(ch),
+                    None => bail!("Unbalanced quotes in configuration line: {line}"),
+                }
+            },
+            ch => {
+                arg.push(ch);
+                while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace()) {
+                    arg.push(ch);
+                }
+            }
+        }
+        args.push(arg);
+    }
+}
+
+fn parse_bool(value: &str) -> anyhow::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => bail!("argument must be 'yes' or 'no'"),
+    }
+}
+
+fn yes_or_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_owned()
+}
+
+/// Parses an amount of memory like redis does, e.g. `100mb`. `k`, `m` and `g` are powers
+/// of 1000, while `kb`, `mb` and `gb` are powers of 1024.
+fn parse_memory(value: &str) -> anyhow::Result<u64> {
+    let value = value.to_lowercase();
+    let digits = value.trim_end_matches(|ch: char| ch.is_ascii_alphabetic());
+    let unit = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => bail!("argument must be a memory value"),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(unit))
+        .ok_or_else(|| anyhow!("argument must be a memory value"))
+}
+
+/// Files are always created in `dir`, so their name can't be a path.
+fn file_name(value: &str) -> anyhow::Result<String> {
+    if value.is_empty() || value.contains('/') {
+        bail!("argument can't be a path, just a filename");
+    }
+    Ok(value.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{parse_memory, split_args, Config, KeyspaceEvents, SaveRule};
+
+    #[rstest]
+    #[case("0", 0)]
+    #[case("100", 100)]
+    #[case("1k", 1000)]
+    #[case("1kb", 1024)]
+    #[case("2MB", 2 * 1024 * 1024)]
+    #[case("1g", 1000 * 1000 * 1000)]
+    fn test_parse_memory(#[case] value: &str, #[case] expected: u64) {
+        assert_eq!(parse_memory(value).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("", "")]
+    #[case("KEA", "AKE")]
+    #[case("Ex$g", "g$xE")]
+    #[case("g$lshzxetK", "AK")]
+    fn test_keyspace_events_are_shown_like_redis(#[case] value: &str, #[case] expected: &str) {
+        let events = KeyspaceEvents::parse(value).unwrap();
+        assert_eq!(events.to_string(), expected);
+        assert_eq!(KeyspaceEvents::parse(expected), Some(events));
+        assert!(KeyspaceEvents::parse("Kq").is_none());
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"dir "/var/lib/my redis"  'a b' c"#).unwrap(),
+            ["dir", "/var/lib/my redis", "a b", "c"]
+        );
+        assert_eq!(split_args(r#"save """#).unwrap(), ["save", ""]);
+        assert!(split_args(r#"dir "/var"#).is_err());
+    }
+
+    #[test]
+    fn test_command_line_options_are_applied_on_top_of_the_file() {
+        let path = std::env::temp_dir().join(format!("redis-{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\nport 7000\nsave 900 1\nsave 300 10\nappendonly yes\n",
+        )
+        .unwrap();
+
+        let args = [path.display().to_string(), "--port".into(), "7001".into()];
+        let config = Config::from_args(args.into_iter()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.port, 7001);
+        assert!(config.appendonly);
+        assert_eq!(
+            config.save,
+            [
+                SaveRule {
+                    seconds: 900,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 10
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_keeps_the_rest_of_the_file() {
+        let path = std::env::temp_dir().join(format!("redis-rewrite-{}.conf", std::process::id()));
+        std::fs::write(&path, "# comment\nsave 900 1\n\nsave 300 10\n").unwrap();
+
+        let mut config = Config::from_args([path.display().to_string()].into_iter()).unwrap();
+        config.set("save", "").unwrap();
+        config.set("maxmemory", "1kb").unwrap();
+        config.rewrite().unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents, "# comment\nsave \"\"\n\nmaxmemory 1024\n");
+    }
+}
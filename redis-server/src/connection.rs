@@ -1,108 +1,595 @@
 use std::{
-    io::{self, Write},
-    net::TcpStream,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
-use anyhow::Context;
-use bytes::{BufMut, BytesMut};
+use anyhow::{bail, Context};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use log::warn;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use std::io::Read;
+use crate::{
+    acl,
+    clients::{ClientInfo, Clients, Monitor},
+    cmd::{Transaction, WatchedKeys},
+    db::ReplicaFeed,
+    frame::{Frame, Parser, Protocol},
+    pubsub::{PubSub, Subscriber},
+    tracking::{Tracker, Tracking, TrackingOptions},
+};
+
+/// A reply that only becomes available later on, e.g. for a client blocked on `BLPOP`.
+pub type PendingReply = Pin<Box<dyn Future<Output = Frame> + Send>>;
+
+/// What a connection reads commands from and writes replies to. Usually a `TcpStream`,
+/// but commands replayed from the AOF are executed against a connection that discards replies.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
 
-use crate::frame::Frame;
+/// Hands out the ids of the connections, which are unique for the lifetime of the server.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
 
 pub struct Connection {
-    stream: TcpStream,
+    id: u64,
+    stream: Box<dyn Stream>,
+    // The address of the client, when it is connected over TCP.
+    addr: Option<SocketAddr>,
+    // Bytes read from the stream that have not been parsed into a frame yet.
+    // A single read can contain part of a frame, or several pipelined frames.
+    buffer: BytesMut,
+    // Holds the frame whose start has been taken out of `buffer` but not its end.
+    parser: Parser,
+    // Replies that have not been written to the stream yet. Commands only ever append to it,
+    // so that executing them never waits on the client.
+    output: BytesMut,
+    protocol: Protocol,
+    // Set with `HELLO ... SETNAME`.
+    name: Option<Bytes>,
+    // The user the client authenticated as, see [`Acl`]. `None` until it does.
+    //
+    // [`Acl`]: crate::acl::Acl
+    user: Option<String>,
+    // The database picked with `SELECT`.
+    db_index: usize,
+    // Set with `ASKING`, for the next command only.
+    asking: bool,
+    parked: Option<PendingReply>,
+    subscriber: Option<Subscriber>,
+    // Set with `MONITOR`.
+    monitor: Option<Monitor>,
+    // Set with `CLIENT TRACKING ON`.
+    tracker: Option<Tracker>,
+    // Set between `MULTI` and `EXEC`.
+    transaction: Option<Transaction>,
+    watched_keys: WatchedKeys,
+    // Set with `REPLCONF listening-port` by a replica, before it sends `PSYNC`.
+    replica_port: Option<u16>,
+    replica_feed: Option<ReplicaFeed>,
 }
 
 impl Connection {
-    pub fn new(socket: TcpStream) -> Self {
-        Self { stream: socket }
+    pub fn new(stream: impl Stream + 'static) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            stream: Box::new(stream),
+            addr: None,
+            buffer: BytesMut::with_capacity(4 * 1024),
+            parser: Parser::default(),
+            output: BytesMut::with_capacity(4 * 1024),
+            protocol: Protocol::default(),
+            name: None,
+            user: None,
+            db_index: 0,
+            asking: false,
+            parked: None,
+            subscriber: None,
+            monitor: None,
+            tracker: None,
+            transaction: None,
+            watched_keys: WatchedKeys::default(),
+            replica_port: None,
+            replica_feed: None,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    pub fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = Some(addr);
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Replies are encoded for `protocol` from now on.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    pub fn name(&self) -> Option<&Bytes> {
+        self.name.as_ref()
+    }
+
+    pub fn set_name(&mut self, name: Option<Bytes>) {
+        self.name = name;
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// The database the client's commands act on, see [`Db::select`].
+    ///
+    /// [`Db::select`]: crate::db::Db::select
+    pub fn db_index(&self) -> usize {
+        self.db_index
+    }
+
+    pub fn set_db_index(&mut self, index: usize) {
+        self.db_index = index;
+    }
+
+    /// Lets the next command access a slot that is being imported, see [`Db::check_slot`].
+    ///
+    /// [`Db::check_slot`]: crate::db::Db::check_slot
+    pub fn set_asking(&mut self) {
+        self.asking = true;
+    }
+
+    /// Whether the client sent `ASKING` before the current command, which it only applies to.
+    pub fn take_asking(&mut self) -> bool {
+        std::mem::take(&mut self.asking)
+    }
+
+    /// Blocks the client until the reply is ready. No other command is read from the
+    /// connection in the meantime.
+    pub fn park(&mut self, reply: PendingReply) {
+        self.parked = Some(reply);
+    }
+
+    pub fn take_parked(&mut self) -> Option<PendingReply> {
+        self.parked.take()
+    }
+
+    pub fn replica_port(&self) -> Option<u16> {
+        self.replica_port
+    }
+
+    pub fn set_replica_port(&mut self, port: u16) {
+        self.replica_port = Some(port);
     }
 
-    pub fn read_bytes(&mut self) -> anyhow::Result<Option<BytesMut>> {
-        let mut data = [0_u8; 128];
+    /// Turns the connection into the link with a replica, which is only sent the replication
+    /// stream from now on.
+    pub fn start_replica_feed(&mut self, feed: ReplicaFeed) {
+        self.replica_feed = Some(feed);
+    }
 
-        let n = self
-            .stream
-            .read(&mut data)
-            .context("Failed to read buffer")?;
+    pub fn take_replica_feed(&mut self) -> Option<ReplicaFeed> {
+        self.replica_feed.take()
+    }
 
-        if n == 0 {
-            return Ok(None);
+    /// Starts queueing commands instead of executing them. Returns false if a transaction
+    /// was already started.
+    pub fn begin_transaction(&mut self) -> bool {
+        if self.transaction.is_some() {
+            return false;
         }
+        self.transaction = Some(Transaction::default());
+        true
+    }
 
-        Ok(Some(BytesMut::from(&data[..n])))
+    pub fn transaction_mut(&mut self) -> Option<&mut Transaction> {
+        self.transaction.as_mut()
     }
 
-    pub fn send_error(&mut self, err: &str) -> io::Result<()> {
-        let frame = Frame::Error(err.to_string());
-        self.write_frame(frame)
+    pub fn take_transaction(&mut self) -> Option<Transaction> {
+        self.transaction.take()
     }
 
-    pub fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
-        let response = match frame {
-            Frame::SimpleString(content) => {
-                let mut response = BytesMut::new();
-                response.put_u8(b'+');
-                response.extend(content.as_bytes());
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
-            }
-            Frame::Error(content) => {
-                let mut response = BytesMut::new();
-                response.put_u8(b'-');
-                response.extend(content.as_bytes());
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+    pub fn watched_keys_mut(&mut self) -> &mut WatchedKeys {
+        &mut self.watched_keys
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscriber.is_some()
+    }
+
+    /// Whether the client is restricted to the pub/sub commands. Only RESP2 clients are, since
+    /// RESP3 clients can tell replies apart from the messages pushed to them.
+    pub fn in_subscribed_mode(&self) -> bool {
+        self.is_subscribed() && self.protocol == Protocol::Resp2
+    }
+
+    /// Puts the connection in subscribed mode, where messages are pushed to the client
+    /// while it is waiting for the next command.
+    pub fn subscribe(&mut self, pubsub: &PubSub) -> &mut Subscriber {
+        self.subscriber
+            .get_or_insert_with(|| Subscriber::new(pubsub.clone()))
+    }
+
+    /// Leaves subscribed mode once the client is no longer subscribed to anything.
+    pub fn leave_subscribed_mode_if_done(&mut self) {
+        if self
+            .subscriber
+            .as_ref()
+            .is_some_and(|subscriber| subscriber.count() == 0)
+        {
+            self.subscriber = None;
+        }
+    }
+
+    /// Sends every command the server executes to the client from now on, while it is waiting
+    /// for the next command.
+    pub fn start_monitor(&mut self, clients: &Clients) {
+        if self.monitor.is_none() {
+            self.monitor = Some(Monitor::new(self.id, clients.clone()));
+        }
+    }
+
+    /// Tells the client when the keys it reads change from now on, or stops with `None`. The
+    /// mode can't be switched without turning tracking off first, like with redis.
+    pub fn set_tracking(
+        &mut self,
+        tracking: &Tracking,
+        options: Option<TrackingOptions>,
+    ) -> anyhow::Result<()> {
+        let Some(mut options) = options else {
+            self.tracker = None;
+            return Ok(());
+        };
+        if let Some(tracker) = self.tracker.take() {
+            if tracker.options().bcast != options.bcast {
+                self.tracker = Some(tracker);
+                bail!("ERR You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.");
             }
-            Frame::BulkString(bytes) => {
-                let mut response = BytesMut::new();
-                response.put_u8(b'$');
-                response.extend(bytes.len().to_string().as_bytes());
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response.extend(&bytes);
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+            let mut prefixes = tracker.options().prefixes.clone();
+            prefixes.retain(|prefix| !options.prefixes.contains(prefix));
+            options.prefixes.extend(prefixes);
+        }
+        self.tracker = Some(Tracker::new(self.id, tracking.clone(), options));
+        Ok(())
+    }
+
+    /// Remembers the keys the command reads, if the client is tracking them.
+    pub fn track_reads(&self, command: &Frame) {
+        if let Some(tracker) = &self.tracker {
+            tracker.track(acl::command_keys(command));
+        }
+    }
+
+    /// What `CLIENT LIST` shows about the client, whose last command was `command`.
+    pub fn client_info(&self, command: &str) -> ClientInfo {
+        let (subscriptions, patterns) = self.subscriber.as_ref().map_or((0, 0), |subscriber| {
+            (subscriber.count(), subscriber.patterns())
+        });
+        ClientInfo {
+            id: self.id,
+            addr: self.addr,
+            name: self.name.clone(),
+            user: self.user.clone(),
+            db: self.db_index,
+            protocol: self.protocol,
+            channels: subscriptions - patterns,
+            patterns,
+            multi: self
+                .transaction
+                .as_ref()
+                .map(|transaction| transaction.len()),
+            blocked: self.parked.is_some(),
+            monitor: self.monitor.is_some(),
+            tracking: self.tracker.is_some(),
+            replica: self.replica_feed.is_some(),
+            command: command.to_owned(),
+        }
+    }
+
+    /// Writes the buffered replies to the stream.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        write_all(&mut self.stream, &mut self.output).await
+    }
+
+    /// Reads the next frame from the stream.
+    ///
+    /// Frames that are already buffered are returned without touching the socket, which is how
+    /// pipelined commands get served. Otherwise we keep reading until a full frame is available,
+    /// sending the messages of the channels the client subscribed to in the meantime, and the
+    /// commands executed by the server if it is monitoring them.
+    /// Returns `None` once the client has closed the connection, or once it has to be
+    /// disconnected for not keeping up with the messages of its channels.
+    ///
+    /// This can be cancelled, e.g. on shutdown, without losing any data.
+    pub async fn read_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(Some(frame));
             }
-            Frame::Boolean(bool) => {
-                let mut response = BytesMut::new();
-                response.put_u8(b'#');
-                let val = if bool { b't' } else { b'f' };
-                response.put_u8(val);
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+
+            // Replies to the frames parsed so far are sent before we wait on the next read.
+            // A client that doesn't read them can't be sent its messages either, so it is
+            // disconnected once too many are queued.
+            tokio::select! {
+                flushed = write_all(&mut self.stream, &mut self.output) => {
+                    flushed.context("Failed to flush the stream")?;
+                }
+                _ = overflowed(&self.subscriber) => {
+                    self.log_overflow();
+                    return Ok(None);
+                }
             }
-            Frame::Integer(val) => {
-                let mut response = BytesMut::new();
-                response.put_u8(b':');
-                response.extend(val.to_string().as_bytes());
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+
+            let read = tokio::select! {
+                read = self.stream.read_buf(&mut self.buffer) => read,
+                message = pushed(&mut self.subscriber, &mut self.monitor, &mut self.tracker) => {
+                    let Some(message) = message else {
+                        self.log_overflow();
+                        return Ok(None);
+                    };
+                    encode(&message, self.protocol, &mut self.output);
+                    continue;
+                }
+            };
+            let n = read.context("Failed to read buffer")?;
+
+            if n == 0 {
+                if self.buffer.is_empty() && !self.parser.is_parsing() {
+                    return Ok(None);
+                }
+                bail!("Connection reset by peer");
             }
-            Frame::Double(val) => {
-                let mut response = BytesMut::new();
+        }
+    }
+
+    fn log_overflow(&self) {
+        warn!(
+            "Client id={} addr={} closed for going over the pubsub output buffer limits",
+            self.id,
+            self.addr.map(|addr| addr.to_string()).unwrap_or_default()
+        );
+    }
+
+    fn parse_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        self.parser.parse(&mut self.buffer)
+    }
+
+    pub fn send_error(&mut self, err: &str) {
+        let frame = Frame::Error(err.to_string());
+        self.write_frame(frame)
+    }
+
+    /// Buffers the frame, which is sent on the next [`Connection::flush`].
+    pub fn write_frame(&mut self, frame: Frame) {
+        encode(&frame, self.protocol, &mut self.output);
+    }
+
+    /// Buffers bytes that are already encoded, e.g. the replication stream.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+    }
+}
+
+/// Writes the buffered replies to the stream. Only what was written is removed from the
+/// buffer, so this can be cancelled.
+async fn write_all(stream: &mut Box<dyn Stream>, output: &mut BytesMut) -> io::Result<()> {
+    while output.has_remaining() {
+        stream.write_buf(output).await?;
+    }
+    stream.flush().await
+}
+
+/// Waits for the next message published to a channel the client subscribed to, the next
+/// command executed if it is monitoring them, or the next keys to invalidate if it is tracking
+/// them. Never returns if it is doing none of these, and returns `None` if the client went
+/// over the pubsub output buffer limits.
+async fn pushed(
+    subscriber: &mut Option<Subscriber>,
+    monitor: &mut Option<Monitor>,
+    tracker: &mut Option<Tracker>,
+) -> Option<Frame> {
+    tokio::select! {
+        Some(message) = async { Some(subscriber.as_mut()?.receive().await) } => message,
+        Some(command) = async { Some(monitor.as_mut()?.receive().await) } => Some(command),
+        Some(keys) = async { Some(tracker.as_mut()?.receive().await) } => Some(keys),
+        else => std::future::pending().await,
+    }
+}
+
+/// Waits until a subscribed client goes over the pubsub output buffer limits.
+async fn overflowed(subscriber: &Option<Subscriber>) {
+    match subscriber {
+        Some(subscriber) => subscriber.overflowed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Encodes the frame for a client speaking `protocol`, downgrading what RESP2 lacks.
+pub(crate) fn encode(frame: &Frame, protocol: Protocol, response: &mut BytesMut) {
+    let resp3 = protocol == Protocol::Resp3;
+    match frame {
+        Frame::SimpleString(content) => {
+            response.put_u8(b'+');
+            response.extend(content.as_bytes());
+            response.extend_from_slice(b"\r\n");
+        }
+        Frame::Error(content) => {
+            response.put_u8(b'-');
+            response.extend(content.as_bytes());
+            response.extend_from_slice(b"\r\n");
+        }
+        Frame::BulkString(bytes) => encode_blob(b'$', bytes, response),
+        Frame::Boolean(bool) if resp3 => {
+            let val = if *bool { b"#t\r\n" } else { b"#f\r\n" };
+            response.extend_from_slice(val);
+        }
+        // Just like redis, RESP2 clients get 1 and 0.
+        Frame::Boolean(bool) => encode(&Frame::Integer(*bool as i64), protocol, response),
+        Frame::Integer(val) => {
+            response.put_u8(b':');
+            response.extend(val.to_string().as_bytes());
+            response.extend_from_slice(b"\r\n");
+        }
+        Frame::Double(val) => {
+            let val = match val {
+                val if val.is_nan() => "nan".to_owned(),
+                val => val.to_string(),
+            };
+            if resp3 {
                 response.put_u8(b',');
-                response.extend(val.to_string().as_bytes());
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+                response.extend(val.as_bytes());
+                response.extend_from_slice(b"\r\n");
+            } else {
+                encode_blob(b'$', val.as_bytes(), response);
             }
-            Frame::Null => {
-                let mut response = BytesMut::with_capacity(3);
-                response.put_u8(b'_');
-                response.put_u8(b'\r');
-                response.put_u8(b'\n');
-                response
+        }
+        Frame::BigNumber(number) if resp3 => {
+            response.put_u8(b'(');
+            response.extend(number.as_bytes());
+            response.extend_from_slice(b"\r\n");
+        }
+        Frame::BigNumber(number) => encode_blob(b'$', number.as_bytes(), response),
+        Frame::Verbatim { encoding, text } if resp3 => {
+            let mut content = BytesMut::with_capacity(encoding.len() + 1 + text.len());
+            content.extend(encoding.as_bytes());
+            content.put_u8(b':');
+            content.extend(text);
+            encode_blob(b'=', &content, response);
+        }
+        Frame::Verbatim { text, .. } => encode_blob(b'$', text, response),
+        Frame::Array(frames) => encode_aggregate(b'*', frames, protocol, response),
+        Frame::Set(frames) => {
+            encode_aggregate(if resp3 { b'~' } else { b'*' }, frames, protocol, response)
+        }
+        Frame::Push(frames) => {
+            encode_aggregate(if resp3 { b'>' } else { b'*' }, frames, protocol, response)
+        }
+        Frame::Map(pairs) => {
+            // RESP2 clients get a flat array of alternating keys and values instead.
+            let (kind, len) = if resp3 {
+                (b'%', pairs.len())
+            } else {
+                (b'*', pairs.len() * 2)
+            };
+            response.put_u8(kind);
+            response.extend(len.to_string().as_bytes());
+            response.extend_from_slice(b"\r\n");
+            for (key, value) in pairs {
+                encode(key, protocol, response);
+                encode(value, protocol, response);
             }
-            _ => unimplemented!(),
-        };
+        }
+        // RESP3 has a single null, where RESP2 clients expect a null bulk string.
+        Frame::Null | Frame::NullBulkString | Frame::NullArray if resp3 => {
+            response.extend_from_slice(b"_\r\n")
+        }
+        Frame::Null | Frame::NullBulkString => response.extend_from_slice(b"$-1\r\n"),
+        Frame::NullArray => response.extend_from_slice(b"*-1\r\n"),
+    }
+}
+
+fn encode_blob(kind: u8, bytes: &[u8], response: &mut BytesMut) {
+    response.put_u8(kind);
+    response.extend(bytes.len().to_string().as_bytes());
+    response.extend_from_slice(b"\r\n");
+    response.extend(bytes);
+    response.extend_from_slice(b"\r\n");
+}
+
+fn encode_aggregate(kind: u8, frames: &[Frame], protocol: Protocol, response: &mut BytesMut) {
+    response.put_u8(kind);
+    response.extend(frames.len().to_string().as_bytes());
+    response.extend_from_slice(b"\r\n");
+    for frame in frames {
+        encode(frame, protocol, response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::{Bytes, BytesMut};
+    use rstest::rstest;
+    use tokio::io::AsyncWriteExt;
+
+    use super::{encode, Connection};
+    use crate::frame::{Frame, Protocol};
+
+    #[rstest]
+    #[case(Frame::Null, "$-1\r\n", "_\r\n")]
+    #[case(Frame::NullBulkString, "$-1\r\n", "_\r\n")]
+    #[case(Frame::NullArray, "*-1\r\n", "_\r\n")]
+    #[case(Frame::Boolean(true), ":1\r\n", "#t\r\n")]
+    #[case(Frame::Double(1.5), "$3\r\n1.5\r\n", ",1.5\r\n")]
+    #[case(Frame::Map(vec![(Frame::Integer(1), Frame::Null)]), "*2\r\n:1\r\n$-1\r\n", "%1\r\n:1\r\n_\r\n")]
+    #[case(Frame::Set(vec![Frame::Integer(1)]), "*1\r\n:1\r\n", "~1\r\n:1\r\n")]
+    #[case(Frame::Push(vec![Frame::Integer(1)]), "*1\r\n:1\r\n", ">1\r\n:1\r\n")]
+    #[case(
+        Frame::Verbatim { encoding: "txt".into(), text: Bytes::from("Some") },
+        "$4\r\nSome\r\n",
+        "=8\r\ntxt:Some\r\n"
+    )]
+    #[case(Frame::BigNumber("12345678901234567890".into()), "$20\r\n12345678901234567890\r\n", "(12345678901234567890\r\n")]
+    fn test_resp3_frames_are_downgraded_for_resp2(
+        #[case] frame: Frame,
+        #[case] resp2: &'static str,
+        #[case] resp3: &'static str,
+    ) {
+        for (protocol, expected) in [(Protocol::Resp2, resp2), (Protocol::Resp3, resp3)] {
+            let mut buffer = BytesMut::new();
+            encode(&frame, protocol, &mut buffer);
+            assert_eq!(buffer, expected.as_bytes());
+            // RESP3 clients get back the frames they can parse, except that nulls are all the same.
+            if protocol == Protocol::Resp3
+                && !matches!(frame, Frame::NullBulkString | Frame::NullArray)
+            {
+                assert_eq!(Frame::deserialize(&buffer).unwrap(), frame);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_splits_pipelined_frames_and_waits_for_partial_ones() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut conn = Connection::new(server);
+        let command =
+            |args: &[&str]| Frame::new_command(args.iter().map(|arg| Bytes::from(arg.to_string())));
+
+        client
+            .write_all(
+                b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\n*2\r\n$3\r\nGET\r\n$3\r\nk",
+            )
+            .await
+            .unwrap();
+        assert_eq!(conn.read_frame().await.unwrap(), Some(command(&["PING"])));
+        assert_eq!(
+            conn.read_frame().await.unwrap(),
+            Some(command(&["ECHO", "hi"]))
+        );
+        // The rest of the last frame isn't there yet. Giving up on the read loses nothing.
+        let read = tokio::time::timeout(Duration::from_millis(10), conn.read_frame()).await;
+        assert!(read.is_err());
 
-        self.stream.write_all(&response)
+        client.write_all(b"ey\r\n").await.unwrap();
+        assert_eq!(
+            conn.read_frame().await.unwrap(),
+            Some(command(&["GET", "key"]))
+        );
+        drop(client);
+        assert_eq!(conn.read_frame().await.unwrap(), None);
     }
 }
@@ -0,0 +1,226 @@
+//! The append only file: every command that changes the keyspace is appended to it, so that
+//! the keyspace can be rebuilt by replaying them on startup.
+//!
+//! Like redis, a rewritten AOF starts with an RDB snapshot of the keyspace followed by the
+//! commands that were executed since.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bytes::BytesMut;
+use log::warn;
+
+use super::{rdb, Keyspace};
+use crate::{
+    config::AppendFsync,
+    connection::encode,
+    frame::{Frame, Protocol},
+};
+
+#[derive(Debug)]
+pub struct Aof {
+    path: PathBuf,
+    file: File,
+    fsync: AppendFsync,
+    // Commands appended while a rewrite is in progress. They are added to the rewritten file
+    // once the snapshot has been written to it.
+    rewrite_buffer: Option<Vec<u8>>,
+}
+
+/// What was read from an existing AOF.
+pub struct AofContents {
+    pub preamble: Option<Vec<Keyspace>>,
+    pub commands: Vec<Frame>,
+}
+
+impl Aof {
+    pub fn open(path: PathBuf, fsync: AppendFsync) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            fsync,
+            rewrite_buffer: None,
+        })
+    }
+
+    pub fn fsync(&self) -> AppendFsync {
+        self.fsync
+    }
+
+    pub fn set_fsync(&mut self, fsync: AppendFsync) {
+        self.fsync = fsync;
+    }
+
+    /// Appends the commands with a single write, so that a crash can only leave the last
+    /// command incomplete.
+    pub fn append(&mut self, commands: &[Frame]) -> io::Result<()> {
+        let mut buffer = BytesMut::new();
+        for command in commands {
+            encode(command, Protocol::Resp2, &mut buffer);
+        }
+
+        self.file.write_all(&buffer)?;
+        if let Some(rewrite_buffer) = &mut self.rewrite_buffer {
+            rewrite_buffer.extend_from_slice(&buffer);
+        }
+        if self.fsync == AppendFsync::Always {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// A handle to the file that can be synced without holding on to the `Aof`.
+    pub fn file(&self) -> io::Result<File> {
+        self.file.try_clone()
+    }
+
+    /// Starts buffering the commands that are appended from now on, and returns the
+    /// temporary path the rewritten file should be written to.
+    pub fn start_rewrite(&mut self) -> io::Result<PathBuf> {
+        if self.rewrite_buffer.is_some() {
+            return Err(io::Error::other(
+                "ERR Background append only file rewriting already in progress",
+            ));
+        }
+        self.rewrite_buffer = Some(vec![]);
+        Ok(self.temp_path())
+    }
+
+    pub fn is_rewriting(&self) -> bool {
+        self.rewrite_buffer.is_some()
+    }
+
+    /// Completes the rewrite once the snapshot has been written to the temporary file, by
+    /// adding the buffered commands and replacing the current file with it.
+    pub fn finish_rewrite(&mut self, written: io::Result<()>) -> io::Result<()> {
+        let temp_path = self.temp_path();
+        // The AOF was disabled and enabled again since the rewrite started, so the snapshot
+        // is missing the commands in between.
+        let Some(buffered) = self.rewrite_buffer.take() else {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(io::Error::other("The AOF was reopened during the rewrite"));
+        };
+
+        let result = written.and_then(|_| {
+            let mut file = OpenOptions::new().append(true).open(&temp_path)?;
+            file.write_all(&buffered)?;
+            file.sync_data()?;
+            std::fs::rename(&temp_path, &self.path)?;
+            self.file = file;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.path
+            .with_file_name(format!("temp-rewriteaof-bg-{}.aof", std::process::id()))
+    }
+}
+
+/// Writes a snapshot of the databases as the base of a new AOF.
+pub fn write_base(path: &Path, databases: &[Keyspace]) -> io::Result<()> {
+    let file = File::create(path)?;
+    rdb::write(BufWriter::new(&file), databases)?;
+    file.sync_data()
+}
+
+/// Reads the AOF at `path`, or returns `None` if there is none.
+///
+/// If the server was killed halfway through appending a command, the incomplete command is
+/// removed from the file, just like redis does with `aof-load-truncated yes`.
+pub fn read(path: &Path, databases: usize) -> io::Result<Option<AofContents>> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut content = vec![];
+    file.read_to_end(&mut content)?;
+
+    let mut remaining = content.as_slice();
+    let preamble = if remaining.starts_with(b"REDIS") {
+        Some(rdb::read(&mut remaining, databases)?)
+    } else {
+        None
+    };
+
+    let mut commands = vec![];
+    loop {
+        if remaining.is_empty() {
+            break;
+        }
+        let parsed = Frame::parse(remaining).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Bad file format reading the append only file: {err}"),
+            )
+        })?;
+        match parsed {
+            Some((frame, consumed)) => {
+                commands.push(frame);
+                remaining = &remaining[consumed..];
+            }
+            None => {
+                let valid_len = content.len() - remaining.len();
+                warn!(
+                    "The AOF ends with an incomplete command, truncating it to {valid_len} bytes"
+                );
+                file.set_len(valid_len as u64)?;
+                break;
+            }
+        }
+    }
+
+    Ok(Some(AofContents { preamble, commands }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use bytes::{Bytes, BytesMut};
+
+    use super::{read, write_base, Aof};
+    use crate::{
+        config::AppendFsync,
+        connection::encode,
+        db::{Keyspace, Value},
+        frame::{Frame, Protocol},
+    };
+
+    fn set(key: &'static str, value: &'static str) -> Frame {
+        Frame::new_command([Bytes::from("SET"), Bytes::from(key), Bytes::from(value)])
+    }
+
+    #[test]
+    fn test_incomplete_commands_are_truncated() {
+        let path = std::env::temp_dir().join(format!("truncated-{}.aof", std::process::id()));
+        let mut keyspace = Keyspace::default();
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("value")));
+        write_base(&path, &[keyspace]).unwrap();
+
+        let mut aof = Aof::open(path.clone(), AppendFsync::Always).unwrap();
+        aof.append(&[set("a", "1")]).unwrap();
+        // What is left when the server is killed halfway through appending a command.
+        let mut partial = BytesMut::new();
+        encode(&set("b", "2"), Protocol::Resp2, &mut partial);
+        let len = std::fs::metadata(&path).unwrap().len();
+        aof.file.write_all(&partial[..partial.len() / 2]).unwrap();
+        drop(aof);
+
+        let contents = read(&path, 1).unwrap().unwrap();
+        assert_eq!(contents.preamble.unwrap()[0].iter().count(), 1);
+        assert_eq!(contents.commands, [set("a", "1")]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
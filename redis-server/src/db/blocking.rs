@@ -0,0 +1,101 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use super::stream::{Entries, GroupReader, StreamId};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// A client blocked by `BLPOP`, `BRPOP` or `BLMOVE` until a value is pushed to a list.
+#[derive(Debug)]
+pub(super) struct BlockedClient {
+    pub(super) from: ListEnd,
+    /// Set for `BLMOVE`, where the popped value is pushed to another list.
+    pub(super) destination: Option<(String, ListEnd)>,
+    pub(super) sender: oneshot::Sender<(String, Bytes)>,
+}
+
+/// A client blocked by `XREAD` or `XREADGROUP` until entries are added to one of the streams.
+#[derive(Debug)]
+pub(super) struct BlockedReader {
+    /// The entries after these IDs are read from each stream, unless reading for a group, in
+    /// which case the entries never delivered to the group are.
+    pub(super) after: HashMap<String, StreamId>,
+    pub(super) group: Option<GroupReader>,
+    pub(super) count: Option<usize>,
+    /// Fails if the group was destroyed in the meantime.
+    pub(super) sender: oneshot::Sender<io::Result<(String, Entries)>>,
+}
+
+/// Clients waiting for something to happen to one of the keys they are blocked on.
+#[derive(Debug)]
+pub(super) struct BlockedClients<T> {
+    next_id: u64,
+    // Each client, along with the keys it is blocked on.
+    clients: HashMap<u64, (Vec<String>, T)>,
+    // The ids of the clients blocked on each key, in the order they blocked.
+    queues: HashMap<String, VecDeque<u64>>,
+}
+
+impl<T> Default for BlockedClients<T> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            clients: HashMap::new(),
+            queues: HashMap::new(),
+        }
+    }
+}
+
+impl<T> BlockedClients<T> {
+    pub(super) fn block(&mut self, keys: Vec<String>, client: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        for key in &keys {
+            self.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        self.clients.insert(id, (keys, client));
+        id
+    }
+
+    /// Removes and returns the client that has been waiting on `key` the longest.
+    pub(super) fn next(&mut self, key: &str) -> Option<T> {
+        let id = *self.queues.get(key)?.front()?;
+        self.remove(id)
+    }
+
+    /// The ids of the clients blocked on `key`, oldest first.
+    pub(super) fn waiting_on(&self, key: &str) -> Vec<u64> {
+        self.queues
+            .get(key)
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn get(&self, id: u64) -> Option<&T> {
+        self.clients.get(&id).map(|(_, client)| client)
+    }
+
+    /// Unregisters the client from every key it is blocked on.
+    pub(super) fn remove(&mut self, id: u64) -> Option<T> {
+        let (keys, client) = self.clients.remove(&id)?;
+        for key in &keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|blocked| *blocked != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(client)
+    }
+}
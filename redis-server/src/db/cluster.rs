@@ -0,0 +1,886 @@
+//! The state of a cluster node: which node serves each of the 16384 hash slots, what we know
+//! of the other nodes, and the slots being migrated between nodes. The nodes keep each other
+//! up to date over the cluster bus, see [`crate::cluster`].
+//!
+//! Like redis, every node has a config epoch, and when two nodes claim the same slot the one
+//! with the greater epoch wins. A node bumps its epoch when it takes a slot over with
+//! `CLUSTER SETSLOT ... NODE`, so that its claim wins over that of the previous owner.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::{config::Config, frame::Frame};
+
+/// How many hash slots the keys are spread over.
+pub const SLOTS: usize = 16384;
+
+#[derive(Debug)]
+pub struct Cluster {
+    myself: String,
+    // The greatest epoch seen in the cluster.
+    current_epoch: u64,
+    // Every node we know of, including ourselves.
+    nodes: HashMap<String, Node>,
+    // The id of the node serving each slot.
+    slots: Vec<Option<String>>,
+    // Slots we serve that are being moved to another node, and slots being moved to us.
+    migrating: HashMap<u16, String>,
+    importing: HashMap<u16, String>,
+    path: PathBuf,
+    // Set whenever what is saved to the config file changes.
+    changed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub bus_port: u16,
+    pub config_epoch: u64,
+    /// Set until the node answers our `MEET`. Until then, its id is a random one of our own.
+    pub handshake: bool,
+    /// When we sent the ping the node hasn't answered yet, if any.
+    pub ping_sent: Option<DateTime<Utc>>,
+    pub pong_received: Option<DateTime<Utc>>,
+    // When we first heard of the node, so that handshakes that never complete are dropped.
+    added: Instant,
+}
+
+/// What nodes send each other over the bus: every message tells the receiver the slots the
+/// sender serves, along with a few of the nodes it knows of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub sender: String,
+    /// The address the sender is reached at, as seen from its end of the link.
+    pub ip: String,
+    pub port: u16,
+    pub bus_port: u16,
+    pub current_epoch: u64,
+    pub config_epoch: u64,
+    /// The slots the sender serves, as inclusive ranges.
+    pub slots: Vec<(u16, u16)>,
+    pub gossip: Vec<Gossip>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+    /// Asks the receiver to add the sender to the nodes it knows of.
+    Meet,
+    Ping,
+    /// The reply to both of the others.
+    Pong,
+}
+
+/// A node the sender of a message knows of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gossip {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub bus_port: u16,
+}
+
+/// A node the bus has to ping, see [`Cluster::peers`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub id: String,
+    pub ip: String,
+    pub bus_port: u16,
+    pub handshake: bool,
+}
+
+/// The argument of `CLUSTER SETSLOT`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetSlot {
+    /// The slot is being moved from that node to us.
+    Importing(String),
+    /// The slot is being moved from us to that node.
+    Migrating(String),
+    /// The migration is over, or was given up.
+    Stable,
+    /// The slot is served by that node from now on.
+    Node(String),
+}
+
+impl Cluster {
+    /// Loads the state saved in the config file, or starts out as a new node that only knows
+    /// of itself and serves no slots.
+    pub fn new(config: &Config) -> io::Result<Self> {
+        let path = config.cluster_config_path();
+        let mut cluster = match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents, path)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let myself = new_node_id();
+                Self {
+                    myself: myself.clone(),
+                    current_epoch: 0,
+                    nodes: HashMap::from([(
+                        myself.clone(),
+                        Node::new(myself, config.bind.clone(), 0, 0),
+                    )]),
+                    slots: vec![None; SLOTS],
+                    migrating: HashMap::new(),
+                    importing: HashMap::new(),
+                    path,
+                    changed: true,
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        // The ports may have changed since the file was saved.
+        let myself = cluster.myself_mut();
+        myself.port = config.port;
+        myself.bus_port = config.cluster_bus_port();
+        cluster.changed = true;
+        cluster.save()?;
+        Ok(cluster)
+    }
+
+    fn parse(contents: &str, path: PathBuf) -> io::Result<Self> {
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unrecoverable error: corrupted cluster config file {path:?}"),
+            )
+        };
+        let mut myself = None;
+        let mut current_epoch = 0;
+        let mut nodes = HashMap::new();
+        let mut slots = vec![None; SLOTS];
+        let mut migrating = HashMap::new();
+        let mut importing = HashMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields[0] == "vars" {
+                for pair in fields[1..].chunks(2) {
+                    if let [name, value] = pair {
+                        if *name == "currentEpoch" {
+                            current_epoch = value.parse().map_err(|_| corrupted())?;
+                        }
+                    }
+                }
+                continue;
+            }
+            let [id, address, flags, _master, _ping_sent, _pong_received, config_epoch, _link, ranges @ ..] =
+                &fields[..]
+            else {
+                return Err(corrupted());
+            };
+            let (ip, port, bus_port) = parse_address(address).ok_or_else(corrupted)?;
+            let mut node = Node::new(id.to_string(), ip, port, bus_port);
+            node.config_epoch = config_epoch.parse().map_err(|_| corrupted())?;
+            if flags.split(',').any(|flag| flag == "myself") {
+                myself = Some(id.to_string());
+            }
+            for range in ranges {
+                if let Some(range) = range.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+                    if let Some((slot, to)) = range.split_once("->-") {
+                        migrating.insert(slot.parse().map_err(|_| corrupted())?, to.to_owned());
+                    } else if let Some((slot, from)) = range.split_once("-<-") {
+                        importing.insert(slot.parse().map_err(|_| corrupted())?, from.to_owned());
+                    }
+                    continue;
+                }
+                let (first, last) = parse_range(range).ok_or_else(corrupted)?;
+                for slot in first..=last {
+                    slots[usize::from(slot)] = Some(id.to_string());
+                }
+            }
+            nodes.insert(id.to_string(), node);
+        }
+        let myself = myself.ok_or_else(corrupted)?;
+        Ok(Self {
+            myself,
+            current_epoch,
+            nodes,
+            slots,
+            migrating,
+            importing,
+            path,
+            changed: false,
+        })
+    }
+
+    /// Writes the state to the config file if it changed since it was last saved.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut contents = self.describe_nodes(Duration::MAX, false);
+        contents.push_str(&format!(
+            "vars currentEpoch {} lastVoteEpoch 0\n",
+            self.current_epoch
+        ));
+        // Written to a temporary file first, so that a crash can't leave a truncated file.
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, &self.path)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn myself(&self) -> &Node {
+        &self.nodes[&self.myself]
+    }
+
+    fn myself_mut(&mut self) -> &mut Node {
+        self.nodes
+            .get_mut(&self.myself)
+            .expect("we know of ourselves")
+    }
+
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Every node we know of, including ourselves and the nodes we are shaking hands with.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
+    pub fn node(&self, id: &str) -> Option<&Node> {
+        self.nodes.get(id).filter(|node| !node.handshake)
+    }
+
+    /// The node serving the slot, if any.
+    pub fn owner(&self, slot: u16) -> Option<&Node> {
+        self.slots[usize::from(slot)]
+            .as_ref()
+            .map(|id| &self.nodes[id])
+    }
+
+    pub fn is_mine(&self, slot: u16) -> bool {
+        self.slots[usize::from(slot)].as_deref() == Some(self.myself.as_str())
+    }
+
+    /// The node the slot is being moved to, if we are moving it.
+    pub fn migrating_to(&self, slot: u16) -> Option<&Node> {
+        self.migrating.get(&slot).and_then(|id| self.nodes.get(id))
+    }
+
+    /// The node the slot is being moved from, if it is being moved to us.
+    pub fn importing_from(&self, slot: u16) -> Option<&Node> {
+        self.importing.get(&slot).and_then(|id| self.nodes.get(id))
+    }
+
+    /// Whether every slot is served by some node, in which case the cluster is up.
+    pub fn is_ok(&self) -> bool {
+        self.slots.iter().all(Option::is_some)
+    }
+
+    /// How many slots are served by a node.
+    pub fn assigned_slots(&self) -> usize {
+        self.slots.iter().filter(|owner| owner.is_some()).count()
+    }
+
+    /// The slots the node serves, as inclusive ranges.
+    pub fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = vec![];
+        for (slot, owner) in self.slots.iter().enumerate() {
+            if owner.as_deref() != Some(id) {
+                continue;
+            }
+            let slot = slot as u16;
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == slot => *last = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    /// Serves the slots from now on, the way `CLUSTER ADDSLOTS` does. Nothing changes if any
+    /// of them is already served by a node.
+    pub fn add_slots(&mut self, slots: &[u16]) -> Result<(), String> {
+        if let Some(slot) = slots
+            .iter()
+            .find(|slot| self.slots[usize::from(**slot)].is_some())
+        {
+            return Err(format!("ERR Slot {slot} is already busy"));
+        }
+        for slot in slots {
+            self.slots[usize::from(*slot)] = Some(self.myself.clone());
+            self.importing.remove(slot);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Forgets who serves the slots, the way `CLUSTER DELSLOTS` does. Nothing changes if any
+    /// of them isn't served by a node.
+    pub fn delete_slots(&mut self, slots: &[u16]) -> Result<(), String> {
+        if let Some(slot) = slots
+            .iter()
+            .find(|slot| self.slots[usize::from(**slot)].is_none())
+        {
+            return Err(format!("ERR Slot {slot} is already unassigned"));
+        }
+        for slot in slots {
+            self.slots[usize::from(*slot)] = None;
+            self.migrating.remove(slot);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Changes the state of the slot, the way `CLUSTER SETSLOT` does. `has_keys` tells whether
+    /// we still have keys in the slot, which we can't give away.
+    pub fn set_slot(&mut self, slot: u16, state: SetSlot, has_keys: bool) -> Result<(), String> {
+        let known = |cluster: &Self, id: &str| {
+            cluster
+                .node(id)
+                .map(|node| node.id.clone())
+                .ok_or_else(|| format!("ERR I don't know about node {id}"))
+        };
+        match state {
+            SetSlot::Migrating(id) => {
+                if !self.is_mine(slot) {
+                    return Err(format!("ERR I'm not the owner of hash slot {slot}"));
+                }
+                let id = known(self, &id)?;
+                if id == self.myself {
+                    return Err("ERR Target node is myself".to_owned());
+                }
+                self.migrating.insert(slot, id);
+            }
+            SetSlot::Importing(id) => {
+                if self.is_mine(slot) {
+                    return Err(format!("ERR I'm already the owner of hash slot {slot}"));
+                }
+                let id = known(self, &id)?;
+                if id == self.myself {
+                    return Err("ERR Source node is myself".to_owned());
+                }
+                self.importing.insert(slot, id);
+            }
+            SetSlot::Stable => {
+                self.migrating.remove(&slot);
+                self.importing.remove(&slot);
+            }
+            SetSlot::Node(id) => {
+                let id = known(self, &id)?;
+                if self.is_mine(slot) && id != self.myself && has_keys {
+                    return Err(format!(
+                        "ERR Can't assign hashslot {slot} to a different node while I still hold keys for this hash slot."
+                    ));
+                }
+                self.migrating.remove(&slot);
+                // Taking over a slot that was being imported bumps our epoch, so that our claim
+                // wins over that of its previous owner.
+                if self.importing.remove(&slot).is_some() && id == self.myself {
+                    self.bump_epoch();
+                }
+                self.slots[usize::from(slot)] = Some(id);
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    fn bump_epoch(&mut self) {
+        self.current_epoch += 1;
+        let epoch = self.current_epoch;
+        self.myself_mut().config_epoch = epoch;
+    }
+
+    /// Starts a handshake with the node at that address, the way `CLUSTER MEET` does. It is
+    /// only added to the nodes we know of once it answers.
+    pub fn meet(&mut self, ip: &str, port: u16, bus_port: u16) {
+        let known = self
+            .nodes
+            .values()
+            .any(|node| node.ip == ip && node.port == port && node.bus_port == bus_port);
+        if !known {
+            let id = new_node_id();
+            let mut node = Node::new(id.clone(), ip.to_owned(), port, bus_port);
+            node.handshake = true;
+            self.nodes.insert(id, node);
+        }
+    }
+
+    /// The nodes the bus has to ping: all of them but ourselves.
+    pub fn peers(&self) -> Vec<Peer> {
+        self.nodes
+            .values()
+            .filter(|node| node.id != self.myself)
+            .map(|node| Peer {
+                id: node.id.clone(),
+                ip: node.ip.clone(),
+                bus_port: node.bus_port,
+                handshake: node.handshake,
+            })
+            .collect()
+    }
+
+    /// Drops the nodes we started a handshake with that didn't answer in time.
+    pub fn expire_handshakes(&mut self, timeout: Duration) {
+        self.nodes
+            .retain(|_, node| !node.handshake || node.added.elapsed() < timeout);
+    }
+
+    /// The message to send to the peer, which is told about a few random nodes. We learn the
+    /// address we are reached at from the link, since we may listen on every interface.
+    pub fn message(&mut self, kind: MessageKind, peer: Option<&str>, ip: &str) -> Message {
+        if self.myself().ip != ip {
+            self.myself_mut().ip = ip.to_owned();
+            self.changed = true;
+        }
+        if let Some(node) = peer.and_then(|id| self.nodes.get_mut(id)) {
+            if kind != MessageKind::Pong && node.ping_sent.is_none() {
+                node.ping_sent = Some(Utc::now());
+            }
+        }
+        let myself = self.myself();
+        let mut rng = rand::thread_rng();
+        let gossip = self
+            .nodes
+            .values()
+            .filter(|node| node.id != self.myself && !node.handshake)
+            .filter(|node| Some(node.id.as_str()) != peer)
+            .filter(|_| rng.gen_bool(0.5))
+            .map(|node| Gossip {
+                id: node.id.clone(),
+                ip: node.ip.clone(),
+                port: node.port,
+                bus_port: node.bus_port,
+            })
+            .collect();
+        Message {
+            kind,
+            sender: myself.id.clone(),
+            ip: myself.ip.clone(),
+            port: myself.port,
+            bus_port: myself.bus_port,
+            current_epoch: self.current_epoch,
+            config_epoch: myself.config_epoch,
+            slots: self.slot_ranges(&self.myself),
+            gossip,
+        }
+    }
+
+    /// Takes in what the message tells us. `peer` is the id we know the sender by when the
+    /// message is the reply to one of our own, which is a random one until the handshake
+    /// completes.
+    ///
+    /// Returns false if the message is from a node we don't know of and that didn't `MEET` us,
+    /// in which case it is ignored.
+    pub fn receive(&mut self, message: Message, peer: Option<&str>) -> bool {
+        if message.sender == self.myself {
+            // We met ourselves, e.g. through another of our addresses.
+            if let Some(peer) = peer.filter(|peer| *peer != self.myself) {
+                self.nodes.remove(peer);
+            }
+            return false;
+        }
+        if let Some(peer) = peer.filter(|peer| *peer != message.sender) {
+            // The handshake completed, so the node is known by its real id from now on.
+            if self.nodes.get(peer).is_some_and(|node| node.handshake) {
+                self.nodes.remove(peer);
+                self.add_node(&message);
+            }
+        }
+        if !self.nodes.contains_key(&message.sender) {
+            if message.kind != MessageKind::Meet {
+                return false;
+            }
+            self.add_node(&message);
+        }
+
+        let node = self
+            .nodes
+            .get_mut(&message.sender)
+            .expect("the sender was added");
+        if message.kind == MessageKind::Pong {
+            node.ping_sent = None;
+        }
+        node.pong_received = Some(Utc::now());
+        if (node.ip.as_str(), node.port, node.bus_port)
+            != (message.ip.as_str(), message.port, message.bus_port)
+            || node.config_epoch != message.config_epoch
+        {
+            node.ip = message.ip.clone();
+            node.port = message.port;
+            node.bus_port = message.bus_port;
+            node.config_epoch = message.config_epoch;
+            self.changed = true;
+        }
+        if message.current_epoch > self.current_epoch {
+            self.current_epoch = message.current_epoch;
+            self.changed = true;
+        }
+        self.resolve_epoch_collision(&message);
+        self.update_slots(&message);
+
+        for gossip in &message.gossip {
+            let known = self.nodes.contains_key(&gossip.id)
+                || self
+                    .nodes
+                    .values()
+                    .any(|node| node.handshake && node.ip == gossip.ip && node.port == gossip.port);
+            if !known && gossip.id != self.myself {
+                self.meet(&gossip.ip, gossip.port, gossip.bus_port);
+            }
+        }
+        true
+    }
+
+    fn add_node(&mut self, message: &Message) {
+        let mut node = Node::new(
+            message.sender.clone(),
+            message.ip.clone(),
+            message.port,
+            message.bus_port,
+        );
+        node.config_epoch = message.config_epoch;
+        self.nodes.insert(message.sender.clone(), node);
+        self.changed = true;
+    }
+
+    /// Like redis, the node with the smaller id bumps its epoch when two nodes share one, so
+    /// that every node ends up with an epoch of its own.
+    fn resolve_epoch_collision(&mut self, message: &Message) {
+        if message.config_epoch == self.myself().config_epoch && message.sender < self.myself {
+            self.bump_epoch();
+            self.changed = true;
+        }
+    }
+
+    /// Hands the slots the sender claims over to it, unless they are served by a node with a
+    /// greater epoch.
+    fn update_slots(&mut self, message: &Message) {
+        for &(first, last) in &message.slots {
+            for slot in first..=last {
+                let index = usize::from(slot);
+                if self.slots[index].as_deref() == Some(message.sender.as_str()) {
+                    continue;
+                }
+                // The node a slot is being imported from keeps claiming it until it is handed
+                // over to us.
+                if self.importing.contains_key(&slot) {
+                    continue;
+                }
+                let owner_epoch = self.slots[index]
+                    .as_ref()
+                    .map(|owner| self.nodes[owner].config_epoch);
+                if owner_epoch.is_none_or(|epoch| epoch < message.config_epoch) {
+                    self.slots[index] = Some(message.sender.clone());
+                    self.migrating.remove(&slot);
+                    self.changed = true;
+                }
+            }
+        }
+    }
+
+    /// Describes the nodes the way `CLUSTER NODES` does, a line each. The nodes that didn't
+    /// answer our pings within `timeout` are flagged as failing.
+    pub fn describe_nodes(&self, timeout: Duration, handshakes: bool) -> String {
+        let mut nodes: Vec<_> = self
+            .nodes
+            .values()
+            .filter(|node| handshakes || !node.handshake)
+            .collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut description = String::new();
+        for node in nodes {
+            let myself = node.id == self.myself;
+            let failing = node.ping_sent.is_some_and(|sent| {
+                (Utc::now() - sent)
+                    .to_std()
+                    .is_ok_and(|elapsed| elapsed > timeout)
+            });
+            let flags = match (myself, node.handshake, failing) {
+                (true, _, _) => "myself,master",
+                (_, true, _) => "handshake",
+                (_, _, true) => "master,fail?",
+                _ => "master",
+            };
+            let millis = |at: Option<DateTime<Utc>>| at.map_or(0, |at| at.timestamp_millis());
+            let connected = myself || (node.pong_received.is_some() && !failing);
+            description.push_str(&format!(
+                "{} {}:{}@{} {flags} - {} {} {} {}",
+                node.id,
+                node.ip,
+                node.port,
+                node.bus_port,
+                millis(node.ping_sent),
+                millis(node.pong_received),
+                node.config_epoch,
+                if connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                },
+            ));
+            for (first, last) in self.slot_ranges(&node.id) {
+                if first == last {
+                    description.push_str(&format!(" {first}"));
+                } else {
+                    description.push_str(&format!(" {first}-{last}"));
+                }
+            }
+            if myself {
+                let mut migrating: Vec<_> = self.migrating.iter().collect();
+                migrating.sort();
+                for (slot, to) in migrating {
+                    description.push_str(&format!(" [{slot}->-{to}]"));
+                }
+                let mut importing: Vec<_> = self.importing.iter().collect();
+                importing.sort();
+                for (slot, from) in importing {
+                    description.push_str(&format!(" [{slot}-<-{from}]"));
+                }
+            }
+            description.push('\n');
+        }
+        description
+    }
+}
+
+impl Node {
+    fn new(id: String, ip: String, port: u16, bus_port: u16) -> Self {
+        Self {
+            id,
+            ip,
+            port,
+            bus_port,
+            config_epoch: 0,
+            handshake: false,
+            ping_sent: None,
+            pong_received: None,
+            added: Instant::now(),
+        }
+    }
+}
+
+impl Message {
+    /// Messages are sent as arrays of bulk strings, like commands are: the kind of message,
+    /// the sender and its epochs, its slots as a comma-separated list of ranges, and then
+    /// four strings for each node it gossips about.
+    pub fn into_frame(self) -> Frame {
+        let kind: &'static [u8] = match self.kind {
+            MessageKind::Meet => b"MEET",
+            MessageKind::Ping => b"PING",
+            MessageKind::Pong => b"PONG",
+        };
+        let slots = self
+            .slots
+            .iter()
+            .map(|(first, last)| format!("{first}-{last}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut args = vec![
+            Bytes::from_static(kind),
+            Bytes::from(self.sender),
+            Bytes::from(self.ip),
+            Bytes::from(self.port.to_string()),
+            Bytes::from(self.bus_port.to_string()),
+            Bytes::from(self.current_epoch.to_string()),
+            Bytes::from(self.config_epoch.to_string()),
+            Bytes::from(slots),
+        ];
+        for gossip in self.gossip {
+            args.push(Bytes::from(gossip.id));
+            args.push(Bytes::from(gossip.ip));
+            args.push(Bytes::from(gossip.port.to_string()));
+            args.push(Bytes::from(gossip.bus_port.to_string()));
+        }
+        Frame::new_command(args)
+    }
+
+    /// Returns `None` if the frame isn't a message.
+    pub fn from_frame(frame: Frame) -> Option<Self> {
+        let Frame::Array(frames) = frame else {
+            return None;
+        };
+        let args = frames
+            .into_iter()
+            .map(|frame| match frame {
+                Frame::BulkString(bytes) => String::from_utf8(bytes.to_vec()).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let [kind, sender, ip, port, bus_port, current_epoch, config_epoch, slots, gossip @ ..] =
+            &args[..]
+        else {
+            return None;
+        };
+        let kind = match kind.as_str() {
+            "MEET" => MessageKind::Meet,
+            "PING" => MessageKind::Ping,
+            "PONG" => MessageKind::Pong,
+            _ => return None,
+        };
+        let slots = slots
+            .split(',')
+            .filter(|range| !range.is_empty())
+            .map(parse_range)
+            .collect::<Option<_>>()?;
+        if gossip.len() % 4 != 0 {
+            return None;
+        }
+        let gossip = gossip
+            .chunks(4)
+            .map(|node| {
+                Some(Gossip {
+                    id: node[0].clone(),
+                    ip: node[1].clone(),
+                    port: node[2].parse().ok()?,
+                    bus_port: node[3].parse().ok()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            kind,
+            sender: sender.clone(),
+            ip: ip.clone(),
+            port: port.parse().ok()?,
+            bus_port: bus_port.parse().ok()?,
+            current_epoch: current_epoch.parse().ok()?,
+            config_epoch: config_epoch.parse().ok()?,
+            slots,
+            gossip,
+        })
+    }
+}
+
+/// The hash slot of the key. Like redis, only the part of the key between the first `{` and
+/// the next `}` is hashed when it isn't empty, so that related keys can be put in the same
+/// slot, e.g. `{user:1000}:followers` and `{user:1000}:following`.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|byte| *byte == b'{')
+        .and_then(|open| {
+            let rest = &key[open + 1..];
+            let close = rest.iter().position(|byte| *byte == b'}')?;
+            Some(&rest[..close])
+        })
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or(key);
+    crc16(hashed) % SLOTS as u16
+}
+
+/// CRC16-CCITT (XMODEM), which is what redis hashes keys with.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn new_node_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).expect("the digit is below 16"))
+        .collect()
+}
+
+/// Parses `ip:port@bus_port`, the way addresses are shown by `CLUSTER NODES`.
+fn parse_address(address: &str) -> Option<(String, u16, u16)> {
+    let (address, bus_port) = address.split_once('@')?;
+    let (ip, port) = address.rsplit_once(':')?;
+    Some((ip.to_owned(), port.parse().ok()?, bus_port.parse().ok()?))
+}
+
+/// Parses a slot, or an inclusive range of slots such as `0-5460`.
+fn parse_range(range: &str) -> Option<(u16, u16)> {
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let (first, last): (u16, u16) = (first.parse().ok()?, last.parse().ok()?);
+    (first <= last && usize::from(last) < SLOTS).then_some((first, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{crc16, key_slot, Cluster, MessageKind, SetSlot};
+    use crate::config::Config;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[rstest]
+    #[case(b"foo", 12182)]
+    #[case(b"{user1000}.following", 3443)]
+    #[case(b"{user1000}.followers", 3443)]
+    #[case(b"foo{}{bar}", 8363)]
+    #[case(b"foo{{bar}}zap", 4015)]
+    #[case(b"foo{bar}{zap}", 5061)]
+    fn test_key_slot(#[case] key: &[u8], #[case] slot: u16) {
+        assert_eq!(key_slot(key), slot);
+    }
+
+    fn node(dir: &std::path::Path, port: u16) -> Cluster {
+        let config = Config {
+            dir: dir.to_owned(),
+            port,
+            cluster_enabled: true,
+            cluster_config_file: format!("nodes-{port}.conf"),
+            ..Config::default()
+        };
+        Cluster::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_gossip_and_config_file() {
+        let dir = std::env::temp_dir().join(format!("cluster-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut a = node(&dir, 7000);
+        let mut b = node(&dir, 7001);
+        a.add_slots(&[0, 1, 2]).unwrap();
+        b.add_slots(&[3]).unwrap();
+
+        // `a` meets `b`, which only gets to know `a` from its `MEET`.
+        a.meet("127.0.0.1", 7001, 17001);
+        let handshake = a.peers()[0].id.clone();
+        let meet = a.message(MessageKind::Meet, Some(&handshake), "127.0.0.1");
+        assert!(b.receive(meet, None));
+        let pong = b.message(MessageKind::Pong, None, "127.0.0.1");
+        assert!(a.receive(pong, Some(&handshake)));
+        assert_eq!(a.peers()[0].id, b.myself().id);
+        assert_eq!(a.owner(3).unwrap().id, b.myself().id);
+        assert_eq!(b.owner(0).unwrap().id, a.myself().id);
+        // One of them bumped its epoch since they shared one, which the other learns of.
+        let ping = a.message(MessageKind::Ping, Some(&b.myself().id), "127.0.0.1");
+        assert!(b.receive(ping, None));
+        assert_ne!(a.myself().config_epoch, b.myself().config_epoch);
+
+        // `a` hands slot 2 over to `b`, whose claim wins once it bumps its epoch.
+        let (a_id, b_id) = (a.myself().id.clone(), b.myself().id.clone());
+        a.set_slot(2, SetSlot::Migrating(b_id.clone()), true)
+            .unwrap();
+        b.set_slot(2, SetSlot::Importing(a_id.clone()), false)
+            .unwrap();
+        assert!(a.set_slot(2, SetSlot::Node(b_id.clone()), true).is_err());
+        b.set_slot(2, SetSlot::Node(b_id.clone()), false).unwrap();
+        let ping = b.message(MessageKind::Ping, Some(&a_id), "127.0.0.1");
+        assert!(a.receive(ping, Some(&b_id)));
+        assert_eq!(a.owner(2).unwrap().id, b_id);
+        assert!(a.migrating_to(2).is_none());
+
+        a.save().unwrap();
+        let reloaded = node(&dir, 7000);
+        assert_eq!(reloaded.myself().id, a_id);
+        assert_eq!(reloaded.current_epoch(), a.current_epoch());
+        assert_eq!(reloaded.slot_ranges(&a_id), vec![(0, 1)]);
+        assert_eq!(reloaded.slot_ranges(&b_id), vec![(2, 3)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,605 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        Arc, OnceLock, Weak,
+    },
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use rand::Rng;
+
+use super::Value;
+use crate::config::MaxmemoryPolicy;
+
+/// The bookkeeping of a key besides its name and value, e.g. its slot in the map.
+const ENTRY_OVERHEAD: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    when: Instant,
+    // Since Instant is an opaque type, we cannot serialize it directly and save it to disk.
+    // This is why we also keep the expiry time in DateTime<Utc> format.
+    at: DateTime<Utc>,
+}
+
+/// What happened to a key, as recorded for keyspace notifications and client side caching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyEvent {
+    /// The command being executed changed the key.
+    Changed,
+    /// The key was removed once its time to live was over.
+    Expired,
+    /// The key was removed to stay within `maxmemory`.
+    Evicted,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Value,
+    // Roughly how many bytes the key and its value take.
+    size: usize,
+    usage: Usage,
+}
+
+/// How recently and how often a key was accessed, which decides which keys are evicted.
+/// Reads update it too, while only holding a shared reference to the keyspace.
+#[derive(Debug)]
+struct Usage {
+    // Milliseconds since the clock started, see `now`.
+    last_access: AtomicU64,
+    // Like in redis, a logarithmic counter: the more often the key was accessed, the less
+    // likely it is to be incremented. It is decremented for every minute the key is idle.
+    frequency: AtomicU8,
+}
+
+/// The keys and their values, along with the time to live of the keys that have one.
+///
+/// Reads treat expired keys as missing even before the background task purges them,
+/// and every removal also clears the key's deadline so it can't expire a newer value.
+///
+/// The keys are kept in insertion order so that they can be sampled at random, when picking
+/// the ones to evict, and so that `SCAN` can walk through them while they change, see `scan`.
+#[derive(Debug, Clone, Default)]
+pub struct Keyspace {
+    values: IndexMap<String, Entry>,
+    // Keys ordered by when they expire, so that the background task can find the next one.
+    expiry: BTreeSet<(Instant, String)>,
+    // The current deadline of each key in `expiry`.
+    deadlines: IndexMap<String, Deadline>,
+    // The flags of the clients watching each key, set once the key is modified.
+    watchers: HashMap<String, Vec<Weak<AtomicBool>>>,
+    // What happened to keys since the events were last taken. `None` unless they are recorded,
+    // which they only are while someone is told about them.
+    events: Option<Vec<(KeyEvent, String)>>,
+    // How many times keys were modified, which tells when a snapshot is due.
+    changes: u64,
+    // The sum of the sizes of the entries.
+    used_memory: usize,
+    // Keys whose value was handed out to be modified in place, so their size has to be
+    // measured again before the memory usage is reported.
+    resized: HashSet<String>,
+}
+
+impl Keyspace {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        if self.is_expired(key, Instant::now()) {
+            return None;
+        }
+        let entry = self.values.get(key)?;
+        entry.usage.touch();
+        Some(&entry.value)
+    }
+
+    /// Unlike `get`, this doesn't count as an access to the key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        !self.is_expired(key, Instant::now()) && self.values.contains_key(key)
+    }
+
+    /// Like `get`, this doesn't count as a modification of the key: callers that change the
+    /// value call `touch` once they did.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.remove_if_expired(key);
+        if !self.values.contains_key(key) {
+            return None;
+        }
+        self.resized.insert(key.to_owned());
+        let entry = self.values.get_mut(key)?;
+        entry.usage.touch();
+        Some(&mut entry.value)
+    }
+
+    /// Like `get_mut`, inserting `default` first if the key does not exist. Meant for values
+    /// that are always changed afterwards, since an empty value is left behind otherwise.
+    pub fn get_or_insert_with<F>(&mut self, key: String, default: F) -> &mut Value
+    where
+        F: FnOnce() -> Value,
+    {
+        self.remove_if_expired(&key);
+        self.resized.insert(key.clone());
+        let entry = self.values.entry(key).or_insert_with(|| Entry {
+            value: default(),
+            size: 0,
+            usage: Usage::new(),
+        });
+        entry.usage.touch();
+        &mut entry.value
+    }
+
+    /// Replaces the value of the key. Its time to live, if it has one, is kept.
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        self.remove_if_expired(&key);
+        self.touch(&key);
+        let size = entry_size(&key, &value);
+        self.used_memory += size;
+        match self.values.get_mut(&key) {
+            Some(entry) => {
+                self.used_memory -= entry.size;
+                entry.size = size;
+                entry.usage.touch();
+                Some(std::mem::replace(&mut entry.value, value))
+            }
+            None => {
+                let entry = Entry {
+                    value,
+                    size,
+                    usage: Usage::new(),
+                };
+                self.values.insert(key, entry);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.remove_if_expired(key);
+        self.remove_as(key, KeyEvent::Changed)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        let now = Instant::now();
+        self.values
+            .iter()
+            .filter(move |(key, _)| !self.is_expired(key, now))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Returns the keys among the `count` slots that come before `cursor`, and the cursor to
+    /// carry on from, which is 0 once every slot has been visited. A cursor of 0 starts from the
+    /// last slot.
+    ///
+    /// Slots are visited from the last one to the first one. Removing a key moves the last key
+    /// to the slot it frees, so a key that wasn't visited yet never moves past the cursor: every
+    /// key that exists for the whole iteration is returned, and some can be returned twice.
+    pub fn scan(&self, cursor: usize, count: usize) -> (usize, Vec<&String>) {
+        let end = match cursor {
+            0 => self.values.len(),
+            cursor => cursor.min(self.values.len()),
+        };
+        let start = end.saturating_sub(count);
+        let now = Instant::now();
+        let keys = self.values.as_slice()[start..end]
+            .keys()
+            .rev()
+            .filter(|key| !self.is_expired(key, now))
+            .collect();
+        (start, keys)
+    }
+
+    /// A key picked at random, or `None` if there are no keys.
+    pub fn random_key(&self) -> Option<&String> {
+        let mut rng = rand::thread_rng();
+        let now = Instant::now();
+        // Expired keys that haven't been purged yet are skipped, picking again a few times
+        // before looking for a key that hasn't expired in order.
+        (0..10)
+            .filter_map(|_| {
+                let index = rng.gen_range(0..self.values.len().max(1));
+                Some(self.values.get_index(index)?.0)
+            })
+            .find(|key| !self.is_expired(key, now))
+            .or_else(|| self.iter().next().map(|(key, _)| key))
+    }
+
+    /// Removes every key, the way `FLUSHDB` does. The clients watching keys are told that they
+    /// were modified, but no event is recorded for each key.
+    pub(super) fn clear(&mut self) {
+        let watched: Vec<_> = self.watchers.keys().cloned().collect();
+        for key in watched {
+            self.touch(&key);
+        }
+        *self = Self {
+            changes: self.changes + self.values.len() as u64,
+            events: self.events.take().map(|_| vec![]),
+            ..Self::default()
+        };
+    }
+
+    /// The number of keys, including expired keys that haven't been purged yet.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The number of keys with a time to live.
+    pub fn expires(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    /// When the key expires, if it has a time to live.
+    pub fn deadline(&self, key: &str) -> Option<DateTime<Utc>> {
+        self.deadlines.get(key).map(|deadline| deadline.at)
+    }
+
+    /// How many times keys were modified since the keyspace was created. Only ever grows.
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Roughly how many bytes the keys and their values take.
+    pub fn used_memory(&mut self) -> usize {
+        for key in std::mem::take(&mut self.resized) {
+            if let Some(entry) = self.values.get_mut(&key) {
+                let size = entry_size(&key, &entry.value);
+                self.used_memory = self.used_memory - entry.size + size;
+                entry.size = size;
+            }
+        }
+        self.used_memory
+    }
+
+    /// Removes the key that `policy` picks among `samples` random ones, and returns it.
+    /// Returns `None` if there is no key the policy can evict.
+    pub fn evict(&mut self, policy: MaxmemoryPolicy, samples: usize) -> Option<String> {
+        let candidates = if policy.is_volatile() {
+            self.deadlines.len()
+        } else {
+            self.values.len()
+        };
+        if policy == MaxmemoryPolicy::NoEviction || candidates == 0 {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        let now = now();
+        // The key with the lowest score is evicted.
+        let score = |key: &str| {
+            let usage = &self.values.get(key)?.usage;
+            let last_access = usage.last_access.load(Ordering::Relaxed);
+            let score = match policy {
+                MaxmemoryPolicy::AllKeysLru | MaxmemoryPolicy::VolatileLru => (last_access, 0),
+                MaxmemoryPolicy::AllKeysLfu | MaxmemoryPolicy::VolatileLfu => {
+                    (usage.frequency(now) as u64, last_access)
+                }
+                MaxmemoryPolicy::VolatileTtl => (since_start(self.deadlines.get(key)?.when), 0),
+                _ => (0, 0),
+            };
+            Some(score)
+        };
+        let key = (0..samples)
+            .filter_map(|_| {
+                let index = rng.gen_range(0..candidates);
+                let key = if policy.is_volatile() {
+                    self.deadlines.get_index(index)?.0
+                } else {
+                    self.values.get_index(index)?.0
+                };
+                Some((score(key)?, key))
+            })
+            .min_by_key(|(score, _)| *score)
+            .map(|(_, key)| key.clone())?;
+
+        self.remove_as(&key, KeyEvent::Evicted);
+        Some(key)
+    }
+
+    /// Sets `modified` as soon as the key is modified, which is how `WATCH` is implemented.
+    pub fn watch(&mut self, key: String, modified: &Arc<AtomicBool>) {
+        let watchers = self.watchers.entry(key).or_default();
+        // Clients that disconnected without unwatching leave their flags behind.
+        watchers.retain(|watcher| watcher.strong_count() > 0);
+        watchers.push(Arc::downgrade(modified));
+    }
+
+    pub fn unwatch(&mut self, key: &str, modified: &Arc<AtomicBool>) {
+        if let Some(watchers) = self.watchers.get_mut(key) {
+            watchers.retain(|watcher| !std::ptr::eq(watcher.as_ptr(), Arc::as_ptr(modified)));
+            if watchers.is_empty() {
+                self.watchers.remove(key);
+            }
+        }
+    }
+
+    /// Sets when the key expires, replacing its previous deadline.
+    /// Returns the instant at which the background task has to remove it.
+    pub(super) fn expire_at(&mut self, key: &str, at: DateTime<Utc>) -> Instant {
+        self.clear_expiry(key);
+
+        let now = Instant::now();
+        let when = (at - Utc::now())
+            .to_std()
+            .map(|duration| now + duration)
+            .unwrap_or(now);
+        self.touch(key);
+        self.expiry.insert((when, key.to_owned()));
+        self.deadlines.insert(key.to_owned(), Deadline { when, at });
+        when
+    }
+
+    /// Removes the time to live of the key. Returns false if it didn't have one.
+    pub(super) fn clear_expiry(&mut self, key: &str) -> bool {
+        let cleared = self.discard_deadline(key);
+        if cleared {
+            self.touch(key);
+        }
+        cleared
+    }
+
+    /// Starts or stops recording what happens to keys, see [`Keyspace::take_events`].
+    pub(super) fn record_events(&mut self, record: bool) {
+        self.events = record.then(Vec::new);
+    }
+
+    /// What happened to keys since the events were last taken, in order. A key changed
+    /// several times by a command has an event for every change.
+    pub(super) fn take_events(&mut self) -> Vec<(KeyEvent, String)> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The instant at which the next key expires.
+    pub(super) fn next_expiry(&self) -> Option<Instant> {
+        self.expiry.first().map(|(when, _)| *when)
+    }
+
+    /// Removes every key that has expired by `now`, returning when the next key expires.
+    pub(super) fn purge_expired(&mut self, now: Instant) -> Option<Instant> {
+        while let Some((when, key)) = self.expiry.first().cloned() {
+            if when > now {
+                return Some(when);
+            }
+            self.remove_as(&key, KeyEvent::Expired);
+        }
+        None
+    }
+
+    fn is_expired(&self, key: &str, now: Instant) -> bool {
+        self.deadlines
+            .get(key)
+            .is_some_and(|deadline| deadline.when <= now)
+    }
+
+    /// Lets the clients watching the key know that it was modified, and records the change.
+    /// Values changed in place through `get_mut` are only modified once this is called.
+    pub(super) fn touch(&mut self, key: &str) {
+        self.touch_as(key, KeyEvent::Changed);
+    }
+
+    fn touch_as(&mut self, key: &str, event: KeyEvent) {
+        self.changes += 1;
+        if let Some(watchers) = self.watchers.remove(key) {
+            for modified in watchers.iter().filter_map(Weak::upgrade) {
+                modified.store(true, Ordering::Relaxed);
+            }
+        }
+        if let Some(events) = &mut self.events {
+            events.push((event, key.to_owned()));
+        }
+    }
+
+    fn remove_if_expired(&mut self, key: &str) {
+        if self.is_expired(key, Instant::now()) {
+            self.remove_as(key, KeyEvent::Expired);
+        }
+    }
+
+    /// Removes the key along with its time to live, recording `event` if it existed.
+    fn remove_as(&mut self, key: &str, event: KeyEvent) -> Option<Value> {
+        self.discard_deadline(key);
+        let removed = self.discard(key);
+        if removed.is_some() {
+            self.touch_as(key, event);
+        }
+        removed
+    }
+
+    fn discard_deadline(&mut self, key: &str) -> bool {
+        match self.deadlines.swap_remove(key) {
+            Some(deadline) => {
+                self.expiry.remove(&(deadline.when, key.to_owned()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the value, and its size from the memory usage.
+    fn discard(&mut self, key: &str) -> Option<Value> {
+        let entry = self.values.swap_remove(key)?;
+        self.used_memory -= entry.size;
+        self.resized.remove(key);
+        Some(entry.value)
+    }
+}
+
+impl Usage {
+    /// New keys start with a few accesses, so that they get a chance to be accessed again
+    /// before they are evicted.
+    const INITIAL_FREQUENCY: u8 = 5;
+    /// The higher it is, the more accesses it takes to increment the frequency.
+    const LOG_FACTOR: f64 = 10.0;
+
+    fn new() -> Self {
+        Self {
+            last_access: AtomicU64::new(now()),
+            frequency: AtomicU8::new(Self::INITIAL_FREQUENCY),
+        }
+    }
+
+    fn touch(&self) {
+        let now = now();
+        let mut frequency = self.frequency(now);
+        if frequency < u8::MAX {
+            let base = frequency.saturating_sub(Self::INITIAL_FREQUENCY) as f64;
+            if rand::random::<f64>() < 1.0 / (base * Self::LOG_FACTOR + 1.0) {
+                frequency += 1;
+            }
+        }
+        self.frequency.store(frequency, Ordering::Relaxed);
+        self.last_access.store(now, Ordering::Relaxed);
+    }
+
+    /// The access frequency, decremented for every minute since the last access.
+    fn frequency(&self, now: u64) -> u8 {
+        let idle_minutes = now.saturating_sub(self.last_access.load(Ordering::Relaxed)) / 60_000;
+        self.frequency
+            .load(Ordering::Relaxed)
+            .saturating_sub(idle_minutes.min(u8::MAX as u64) as u8)
+    }
+}
+
+impl Clone for Usage {
+    fn clone(&self) -> Self {
+        Self {
+            last_access: AtomicU64::new(self.last_access.load(Ordering::Relaxed)),
+            frequency: AtomicU8::new(self.frequency.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn entry_size(key: &str, value: &Value) -> usize {
+    ENTRY_OVERHEAD + key.len() + value.memory_usage()
+}
+
+/// The clock of the key usage, in milliseconds since it started.
+fn now() -> u64 {
+    since_start(Instant::now())
+}
+
+fn since_start(instant: Instant) -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let start = *START.get_or_init(Instant::now);
+    instant.saturating_duration_since(start).as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use bytes::Bytes;
+    use chrono::{Duration, Utc};
+
+    use super::{Keyspace, Value};
+    use crate::config::MaxmemoryPolicy;
+
+    #[test]
+    fn test_expired_keys_are_not_visible() {
+        let mut keyspace = Keyspace::default();
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("value")));
+        keyspace.expire_at("key", Utc::now() - Duration::seconds(1));
+
+        assert!(keyspace.get("key").is_none());
+        assert_eq!(keyspace.iter().count(), 0);
+        assert!(keyspace.get_mut("key").is_none());
+        assert!(keyspace.is_empty());
+    }
+
+    #[test]
+    fn test_watched_keys_are_flagged_once_modified() {
+        let mut keyspace = Keyspace::default();
+        let modified = Arc::new(AtomicBool::new(false));
+        keyspace.watch("key".to_owned(), &modified);
+        keyspace.watch("other".to_owned(), &modified);
+        keyspace.unwatch("other", &modified);
+
+        keyspace.insert("other".to_owned(), Value::String(Bytes::from("value")));
+        assert!(!modified.load(Ordering::Relaxed));
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("value")));
+        assert!(modified.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_removing_a_key_clears_its_deadline() {
+        let mut keyspace = Keyspace::default();
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("value")));
+        keyspace.expire_at("key", Utc::now() + Duration::seconds(10));
+        keyspace.expire_at("key", Utc::now() + Duration::seconds(20));
+        assert_eq!(
+            keyspace.expiry.len(),
+            1,
+            "the previous deadline is replaced"
+        );
+
+        keyspace.remove("key");
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("new")));
+        assert!(keyspace.deadline("key").is_none());
+        assert_eq!(keyspace.next_expiry(), None);
+    }
+
+    #[test]
+    fn test_scan_returns_keys_that_exist_throughout() {
+        let mut keyspace = Keyspace::default();
+        for key in 0..20 {
+            keyspace.insert(key.to_string(), Value::String(Bytes::from("value")));
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, keys) = keyspace.scan(cursor, 3);
+            seen.extend(keys.into_iter().cloned());
+            // Removing keys moves others around, and new keys come and go.
+            keyspace.remove(&(cursor % 7).to_string());
+            keyspace.insert(format!("new-{cursor}"), Value::String(Bytes::from("value")));
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        for key in 7..20 {
+            assert!(seen.contains(&key.to_string()), "{key} was not returned");
+        }
+    }
+
+    #[test]
+    fn test_memory_usage_follows_changes() {
+        let mut keyspace = Keyspace::default();
+        keyspace.insert("key".to_owned(), Value::String(Bytes::from("value")));
+        let used = keyspace.used_memory();
+        assert!(used > 0);
+
+        if let Some(Value::String(value)) = keyspace.get_mut("key") {
+            *value = Bytes::from("a much longer value");
+        }
+        assert_eq!(keyspace.used_memory(), used + 14);
+        keyspace.remove("key");
+        assert_eq!(keyspace.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_least_recently_used_key_is_evicted() {
+        let mut keyspace = Keyspace::default();
+        for key in ["a", "b", "c"] {
+            keyspace.insert(key.to_owned(), Value::String(Bytes::from("value")));
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        keyspace.get("a");
+
+        // With this many samples, every key is all but certain to be sampled.
+        assert_eq!(
+            keyspace.evict(MaxmemoryPolicy::AllKeysLru, 64).as_deref(),
+            Some("b")
+        );
+        assert_eq!(keyspace.evict(MaxmemoryPolicy::VolatileLru, 64), None);
+        assert_eq!(keyspace.evict(MaxmemoryPolicy::NoEviction, 64), None);
+        assert_eq!(keyspace.len(), 2);
+    }
+}
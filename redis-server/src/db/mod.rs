@@ -0,0 +1,2777 @@
+mod aof;
+mod blocking;
+mod cluster;
+mod keyspace;
+mod notify;
+mod rdb;
+mod replication;
+mod sorted_set;
+mod stream;
+mod value;
+
+pub use blocking::ListEnd;
+pub use cluster::{key_slot, Cluster, Gossip, Message, MessageKind, Node, Peer, SetSlot, SLOTS};
+pub use keyspace::{KeyEvent, Keyspace};
+pub use replication::{LinkState, Master, Replica, ReplicaFeed, Replication};
+pub use sorted_set::{Score, SortedSet};
+pub use stream::{
+    ClaimOptions, Consumer, ConsumerGroup, Entries, Fields, GroupReader, NewId, PendingEntry,
+    ReadFrom, Stream, StreamId, Trim,
+};
+pub use value::{Value, WRONG_TYPE};
+
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use rand::seq::IteratorRandom;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, Once, RwLock, TryLockError},
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot, Notify};
+
+use self::{
+    aof::Aof,
+    blocking::{BlockedClient, BlockedClients, BlockedReader},
+};
+use crate::{
+    acl::Acl,
+    clients::Clients,
+    config::{AppendFsync, Config, MaxmemoryPolicy},
+    frame::Frame,
+    pubsub::PubSub,
+    scripting::Scripts,
+    slowlog::SlowLog,
+    tracking::Tracking,
+};
+
+/// A handle to the numbered database a client selected, see [`Db::select`]. The other
+/// databases, and everything besides the keyspace, are shared by all the handles.
+#[derive(Debug, Clone)]
+pub struct Db {
+    inner: Arc<DbInner>,
+    index: usize,
+    // The background tasks are shut down once the last handle is dropped.
+    _tasks: Arc<BackgroundTasks>,
+}
+
+#[derive(Debug)]
+struct BackgroundTasks(Arc<DbInner>);
+
+impl Drop for BackgroundTasks {
+    fn drop(&mut self) {
+        self.0.shutdown_purge_task();
+    }
+}
+
+#[derive(Debug)]
+struct DbInner {
+    data: RwLock<Data>,
+    // Held for reading while a command runs, and for writing while a transaction runs, so
+    // that no other command sees a transaction halfway through.
+    commands: RwLock<()>,
+    background_task: Notify,
+    snapshots: Mutex<Snapshots>,
+    // Only set once the AOF has been replayed, so that replayed commands aren't appended again.
+    // `None` while the AOF is disabled.
+    //
+    // Also held while a write command runs, so that the AOF and the replication stream have
+    // the commands in the order they were executed.
+    aof: Mutex<Option<Aof>>,
+    // The thread syncing the AOF once per second is only started the first time it is enabled.
+    aof_fsync_thread: Once,
+    config: RwLock<Config>,
+    replication: Mutex<Replication>,
+    // `None` unless `cluster-enabled` is set.
+    cluster: Option<Mutex<Cluster>>,
+    // Held while write commands run when someone is told about the keys they change, so that
+    // the keys are told apart from those of other commands, see `Db::notify_writes`.
+    notifications: Mutex<()>,
+    pubsub: PubSub,
+    tracking: Tracking,
+    scripts: Scripts,
+    clients: Clients,
+    slowlog: SlowLog,
+    acl: Acl,
+    started: Instant,
+}
+
+#[derive(Debug)]
+struct Data {
+    // The numbered databases, which clients pick with `SELECT`.
+    databases: Vec<Database>,
+    // The changes made by the command being executed that have to be appended to the AOF and
+    // fed to replicas in place of the command itself. `None` when neither is needed.
+    propagated: Option<Vec<Frame>>,
+    // The database the commands propagated last apply to. A `SELECT` is propagated before
+    // commands for another one, or before the first one when this is `None`, e.g. in a new AOF.
+    propagated_db: Option<usize>,
+    // Set while the commands of a transaction or a script are executed.
+    in_transaction: bool,
+    // The number of keys evicted to stay within `maxmemory`.
+    evicted_keys: u64,
+    shutdown: bool,
+}
+
+#[derive(Debug, Default)]
+struct Database {
+    keyspace: Keyspace,
+    // Clients blocked on `BLPOP`, `BRPOP` and `BLMOVE` until a value is pushed to a list.
+    blocked: BlockedClients<BlockedClient>,
+    // Clients blocked on `XREAD` and `XREADGROUP` until entries are added to a stream.
+    blocked_readers: BlockedClients<BlockedReader>,
+}
+
+#[derive(Debug)]
+struct Snapshots {
+    last_save: DateTime<Utc>,
+    last_attempt: DateTime<Utc>,
+    // Whether the last attempt succeeded.
+    last_ok: bool,
+    // The number of changes the keyspace had been through when the last snapshot was taken.
+    saved_changes: u64,
+    // Only one snapshot is written at a time, whether by `SAVE` or `BGSAVE`.
+    in_progress: bool,
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new_with_data_mut(vec![], Config::default(), None)
+    }
+}
+
+impl Db {
+    /// Loads the snapshot found at the configured path, if there is one.
+    ///
+    /// Just like redis, the snapshot is ignored when there is an AOF to load instead,
+    /// since the AOF is more up to date.
+    ///
+    /// In cluster mode, what the node knows of the cluster is loaded from its config file.
+    pub fn new(config: &Config) -> io::Result<Self> {
+        let cluster = config
+            .cluster_enabled
+            .then(|| Cluster::new(config))
+            .transpose()?;
+        let path = config.rdb_path();
+        if config.appendonly && config.aof_path().exists() {
+            return Ok(Self::new_with_data_mut(vec![], config.clone(), cluster));
+        }
+
+        let keyspaces = match File::open(&path) {
+            Ok(file) => rdb::read(BufReader::new(file), config.databases)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+        Ok(Self::new_with_data_mut(keyspaces, config.clone(), cluster))
+    }
+
+    /// The databases missing from `keyspaces` start empty.
+    fn new_with_data_mut(
+        keyspaces: Vec<Keyspace>,
+        config: Config,
+        cluster: Option<Cluster>,
+    ) -> Self {
+        let mut databases: Vec<_> = keyspaces
+            .into_iter()
+            .map(|keyspace| Database {
+                keyspace,
+                ..Database::default()
+            })
+            .collect();
+        databases.resize_with(config.databases, Database::default);
+        let data = Data {
+            databases,
+            propagated: None,
+            propagated_db: None,
+            in_transaction: false,
+            evicted_keys: 0,
+            shutdown: false,
+        };
+        let saved_changes = data.changes();
+        let slowlog = SlowLog::default();
+        slowlog.configure(&config);
+        let pubsub = PubSub::default();
+        pubsub.configure(&config);
+        let acl = Acl::default();
+        acl.set_requirepass(&config.requirepass);
+        let db_inner = DbInner {
+            data: RwLock::new(data),
+            commands: RwLock::new(()),
+            background_task: Notify::new(),
+            // Just like redis, the last save is the startup time until a snapshot is written.
+            snapshots: Mutex::new(Snapshots {
+                last_save: Utc::now(),
+                last_attempt: Utc::now(),
+                last_ok: true,
+                saved_changes,
+                in_progress: false,
+            }),
+            aof: Mutex::new(None),
+            aof_fsync_thread: Once::new(),
+            replication: Mutex::new(Replication::new(
+                config.replicaof.clone(),
+                config.repl_backlog_size,
+            )),
+            config: RwLock::new(config),
+            cluster: cluster.map(Mutex::new),
+            notifications: Mutex::new(()),
+            pubsub,
+            tracking: Tracking::default(),
+            scripts: Scripts::default(),
+            clients: Clients::default(),
+            slowlog,
+            acl,
+            started: Instant::now(),
+        };
+        let inner = Arc::new(db_inner);
+        tokio::spawn(purge_expired_tasks(inner.clone()));
+        tokio::spawn(save_on_changes(inner.clone()));
+        tokio::spawn(ping_replicas(inner.clone()));
+        Self {
+            _tasks: Arc::new(BackgroundTasks(inner.clone())),
+            inner,
+            index: 0,
+        }
+    }
+
+    /// A handle to the database numbered `index`, which must be below [`Db::databases`].
+    pub fn select(&self, index: usize) -> Db {
+        Db {
+            index,
+            ..self.clone()
+        }
+    }
+
+    /// The number of the database the keyspace methods act on.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// How many databases there are.
+    pub fn databases(&self) -> usize {
+        self.inner.data.read().unwrap().databases.len()
+    }
+
+    /// The pub/sub channels, which are independent from the keyspace.
+    pub fn pubsub(&self) -> &PubSub {
+        &self.inner.pubsub
+    }
+
+    /// The clients tracking keys for client side caching.
+    pub fn tracking(&self) -> &Tracking {
+        &self.inner.tracking
+    }
+
+    /// The scripts cached for `EVALSHA`.
+    pub fn scripts(&self) -> &Scripts {
+        &self.inner.scripts
+    }
+
+    /// The connected clients.
+    pub fn clients(&self) -> &Clients {
+        &self.inner.clients
+    }
+
+    /// The commands that were slow to execute.
+    pub fn slowlog(&self) -> &SlowLog {
+        &self.inner.slowlog
+    }
+
+    /// The users clients authenticate as, and what they are allowed to do.
+    pub fn acl(&self) -> &Acl {
+        &self.inner.acl
+    }
+
+    /// How long the server has been running.
+    pub fn uptime(&self) -> Duration {
+        self.inner.started.elapsed()
+    }
+
+    /// Useful for read access. Access to data is under a shared access lock.
+    pub fn with_data<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Keyspace) -> T,
+    {
+        f(self.inner.data.read().unwrap().keyspace(self.index))
+    }
+
+    pub fn with_data_mut<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Keyspace) -> T,
+    {
+        f(self.inner.data.write().unwrap().keyspace_mut(self.index))
+    }
+
+    /// Replaces the integer stored at `key`, taken to be 0 if the key does not exist, with the
+    /// one the closure returns, and returns it. The closure returns `None` if the result would
+    /// overflow. The key keeps its time to live.
+    pub fn with_integer_data_mut<F>(&self, key: String, f: F) -> io::Result<i64>
+    where
+        F: FnOnce(i64) -> Option<i64>,
+    {
+        self.with_data_mut(|data| {
+            let value = match data.get(&key) {
+                Some(Value::Integer(value)) => *value,
+                Some(Value::String(value)) => parse_integer(value).ok_or_else(|| {
+                    io::Error::other("ERR value is not an integer or out of range")
+                })?,
+                Some(_) => return Err(wrong_type()),
+                None => 0,
+            };
+            let value = f(value)
+                .ok_or_else(|| io::Error::other("ERR increment or decrement would overflow"))?;
+            data.insert(key, Value::Integer(value));
+            Ok(value)
+        })
+    }
+
+    /// Gives the closure mutable access to the string stored at `key`, which is empty if the key
+    /// does not exist. The closure returns its result along with whether it changed the string,
+    /// and must not change it if it fails. The key keeps its time to live, and is only created
+    /// if the string isn't empty afterwards.
+    pub fn with_string_data_mut<T, F>(&self, key: String, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut BytesMut) -> io::Result<(T, bool)>,
+    {
+        self.with_data_mut(|data| {
+            let existed = match data.get(&key) {
+                Some(Value::String(_) | Value::Integer(_)) => true,
+                Some(_) => return Err(wrong_type()),
+                None => false,
+            };
+            let current = match data.get_mut(&key) {
+                // Taken out of the keyspace, so that it can be changed without being copied.
+                Some(Value::String(bytes)) => std::mem::take(bytes),
+                Some(Value::Integer(integer)) => Bytes::from(integer.to_string()),
+                _ => Bytes::new(),
+            };
+            let mut string = current
+                .try_into_mut()
+                .unwrap_or_else(|bytes| BytesMut::from(&bytes[..]));
+            let (result, changed) = match f(&mut string) {
+                Ok((result, changed)) => (Ok(result), changed),
+                Err(err) => (Err(err), false),
+            };
+            if changed {
+                if existed || !string.is_empty() {
+                    data.insert(key, Value::String(string.freeze()));
+                }
+            } else if let Some(Value::String(bytes)) = data.get_mut(&key) {
+                // Put back as it was, without counting as a modification.
+                *bytes = string.freeze();
+            }
+            result
+        })
+    }
+
+    /// Gives the closure read access to the list stored at `key`, or `None` if the key does not exist.
+    pub fn with_list_data<T, F>(&self, key: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&VecDeque<Bytes>>) -> T,
+    {
+        self.with_data(|data| match data.get(key) {
+            Some(Value::List(list)) => Ok(f(Some(list))),
+            Some(_) => Err(wrong_type()),
+            None => Ok(f(None)),
+        })
+    }
+
+    /// Gives the closure mutable access to the list stored at `key`, which is empty if the key
+    /// does not exist. The closure returns its result along with whether it changed the list:
+    /// the key is only created, or removed if the list is empty afterwards, when it did.
+    pub fn with_list_data_mut<T, F>(&self, key: String, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut VecDeque<Bytes>) -> (T, bool),
+    {
+        self.with_value_mut(
+            key,
+            || Value::List(VecDeque::new()),
+            |value| match value {
+                Value::List(list) => Ok(f(list)),
+                _ => Err(wrong_type()),
+            },
+        )
+    }
+
+    /// Pushes the values to the list stored at `key` and returns its new length.
+    /// Clients blocked on the list are then served, oldest first.
+    pub fn push(&self, key: String, values: Vec<Bytes>, to: ListEnd) -> io::Result<usize> {
+        let mut state = self.inner.data.write().unwrap();
+        let len = state.push(self.index, &key, values, to)?;
+        state.serve_blocked_clients(self.index, key);
+        Ok(len)
+    }
+
+    /// Pops a value from the first non-empty list among `keys`, optionally pushing it to
+    /// `destination`. If all the lists are empty, the caller is registered as a blocked client
+    /// and gets a [`BlockedPop`] to wait on.
+    pub fn pop_or_block(
+        &self,
+        keys: Vec<String>,
+        from: ListEnd,
+        destination: Option<(String, ListEnd)>,
+    ) -> io::Result<BlockingPop> {
+        let mut state = self.inner.data.write().unwrap();
+        let index = self.index;
+        let keyspace = state.keyspace(index);
+
+        for key in keys.iter().chain(destination.iter().map(|(key, _)| key)) {
+            if matches!(keyspace.get(key), Some(value) if !matches!(value, Value::List(_))) {
+                return Err(wrong_type());
+            }
+        }
+
+        if let Some(key) = keys
+            .iter()
+            .find(|key| matches!(keyspace.get(key), Some(Value::List(list)) if !list.is_empty()))
+        {
+            let value = state.pop(index, key, from).expect("the list is not empty");
+            state.propagate_pop(index, key, from);
+            if let Some((destination, to)) = destination {
+                state.push(index, &destination, vec![value.clone()], to)?;
+                state.propagate_push(index, &destination, &value, to);
+                state.serve_blocked_clients(index, destination);
+            }
+            return Ok(BlockingPop::Ready(key.clone(), value));
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let client = BlockedClient {
+            from,
+            destination,
+            sender,
+        };
+        let id = state.databases[index].blocked.block(keys, client);
+        Ok(BlockingPop::Blocked(BlockedPop {
+            id,
+            index,
+            receiver,
+            db: self.inner.clone(),
+        }))
+    }
+
+    /// Gives the closure read access to the hash stored at `key`, or `None` if the key does not exist.
+    pub fn with_hash_data<T, F>(&self, key: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&HashMap<Bytes, Bytes>>) -> T,
+    {
+        self.with_data(|data| match data.get(key) {
+            Some(Value::Hash(hash)) => Ok(f(Some(hash))),
+            Some(_) => Err(wrong_type()),
+            None => Ok(f(None)),
+        })
+    }
+
+    /// Gives the closure mutable access to the hash stored at `key`, which is empty if the key
+    /// does not exist. The closure returns its result along with whether it changed the hash:
+    /// the key is only created, or removed if the hash is empty afterwards, when it did.
+    pub fn with_hash_data_mut<T, F>(&self, key: String, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut HashMap<Bytes, Bytes>) -> (T, bool),
+    {
+        self.with_value_mut(
+            key,
+            || Value::Hash(HashMap::new()),
+            |value| match value {
+                Value::Hash(hash) => Ok(f(hash)),
+                _ => Err(wrong_type()),
+            },
+        )
+    }
+
+    /// Gives the closure read access to the sorted set stored at `key`, or `None` if the key does
+    /// not exist.
+    pub fn with_sorted_set_data<T, F>(&self, key: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&SortedSet>) -> T,
+    {
+        self.with_data(|data| match data.get(key) {
+            Some(Value::SortedSet(sorted_set)) => Ok(f(Some(sorted_set))),
+            Some(_) => Err(wrong_type()),
+            None => Ok(f(None)),
+        })
+    }
+
+    /// Gives the closure mutable access to the sorted set stored at `key`, which is empty if the key
+    /// does not exist. The closure returns its result along with whether it changed the sorted
+    /// set: the key is only created, or removed if the sorted set is empty afterwards, when it
+    /// did.
+    pub fn with_sorted_set_data_mut<T, F>(&self, key: String, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut SortedSet) -> (T, bool),
+    {
+        self.with_value_mut(
+            key,
+            || Value::SortedSet(SortedSet::default()),
+            |value| match value {
+                Value::SortedSet(sorted_set) => Ok(f(sorted_set)),
+                _ => Err(wrong_type()),
+            },
+        )
+    }
+
+    /// Gives the closure read access to the set stored at `key`, or `None` if the key does not exist.
+    pub fn with_set_data<T, F>(&self, key: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&HashSet<Bytes>>) -> T,
+    {
+        self.with_data(|data| Ok(f(sets(data, [key])?[0])))
+    }
+
+    /// Gives the closure read access to the sets stored at each of `keys`, `None` for the keys
+    /// that do not exist. Fails if any of the keys holds another kind of value.
+    pub fn with_sets_data<T, F>(&self, keys: &[String], f: F) -> io::Result<T>
+    where
+        F: FnOnce(Vec<Option<&HashSet<Bytes>>>) -> T,
+    {
+        self.with_data(|data| Ok(f(sets(data, keys.iter().map(String::as_str))?)))
+    }
+
+    /// Gives the closure mutable access to the set stored at `key`, which is empty if the key
+    /// does not exist. The closure returns its result along with whether it changed the set:
+    /// the key is only created, or removed if the set is empty afterwards, when it did.
+    pub fn with_set_data_mut<T, F>(&self, key: String, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut HashSet<Bytes>) -> (T, bool),
+    {
+        self.with_value_mut(
+            key,
+            || Value::Set(HashSet::new()),
+            |value| match value {
+                Value::Set(set) => Ok(f(set)),
+                _ => Err(wrong_type()),
+            },
+        )
+    }
+
+    /// Replaces the value of `destination`, and its time to live, with the set the closure
+    /// computes from the sets stored at `keys`. The key is removed instead if that set is empty.
+    /// Returns the size of the set.
+    pub fn store_set<F>(&self, destination: String, keys: &[String], f: F) -> io::Result<usize>
+    where
+        F: FnOnce(Vec<Option<&HashSet<Bytes>>>) -> HashSet<Bytes>,
+    {
+        self.with_data_mut(|data| {
+            let set = f(sets(data, keys.iter().map(String::as_str))?);
+            let len = set.len();
+            if set.is_empty() {
+                data.remove(&destination);
+            } else {
+                data.insert(destination.clone(), Value::Set(set));
+                data.clear_expiry(&destination);
+            }
+            Ok(len)
+        })
+    }
+
+    /// Moves `member` from the set stored at `source` to the one at `destination`, creating it
+    /// if needed. Returns false if `member` isn't in the source set.
+    pub fn move_set_member(
+        &self,
+        source: &str,
+        destination: String,
+        member: Bytes,
+    ) -> io::Result<bool> {
+        self.with_data_mut(|data| {
+            let sets = sets(data, [source, destination.as_str()])?;
+            if !sets[0].is_some_and(|set| set.contains(&member)) {
+                return Ok(false);
+            }
+            if source == destination {
+                return Ok(true);
+            }
+            if let Some(Value::Set(set)) = data.get_mut(source) {
+                set.remove(&member);
+                if set.is_empty() {
+                    data.remove(source);
+                } else {
+                    data.touch(source);
+                }
+            }
+            if let Value::Set(set) =
+                data.get_or_insert_with(destination.clone(), || Value::Set(HashSet::new()))
+            {
+                set.insert(member);
+            }
+            data.touch(&destination);
+            Ok(true)
+        })
+    }
+
+    /// Removes up to `count` members picked at random from the set stored at `key`, and returns
+    /// them. They are appended to the AOF as an `SREM`, so that replaying it removes the same
+    /// members.
+    pub fn pop_set(&self, key: &str, count: usize) -> io::Result<Vec<Bytes>> {
+        let mut state = self.inner.data.write().unwrap();
+        let keyspace = state.keyspace_mut(self.index);
+        if sets(keyspace, [key])?[0].is_none() || count == 0 {
+            return Ok(vec![]);
+        }
+        let Some(Value::Set(set)) = keyspace.get_mut(key) else {
+            unreachable!("the key holds a set");
+        };
+        let popped: Vec<Bytes> = set
+            .iter()
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .into_iter()
+            .cloned()
+            .collect();
+        for member in &popped {
+            set.remove(member);
+        }
+        if set.is_empty() {
+            keyspace.remove(key);
+        } else {
+            keyspace.touch(key);
+        }
+
+        let command = [Bytes::from_static(b"SREM"), Bytes::from(key.to_owned())]
+            .into_iter()
+            .chain(popped.iter().cloned())
+            .map(Frame::BulkString)
+            .collect();
+        state.propagate(self.index, Frame::Array(command));
+        Ok(popped)
+    }
+
+    /// Gives the closure read access to the stream stored at `key`, or `None` if the key does not
+    /// exist.
+    pub fn with_stream_data<T, F>(&self, key: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&Stream>) -> T,
+    {
+        self.with_data(|data| match data.get(key) {
+            Some(Value::Stream(stream)) => Ok(f(Some(stream))),
+            Some(_) => Err(wrong_type()),
+            None => Ok(f(None)),
+        })
+    }
+
+    /// Gives the closure mutable access to the stream stored at `key`, or `None` if the key does
+    /// not exist, unless `create` is set, in which case it gets an empty stream. The closure
+    /// returns its result along with whether it changed the stream, which is only created if it
+    /// did. Streams are kept even if they are empty afterwards.
+    pub fn with_stream_data_mut<T, F>(&self, key: String, create: bool, f: F) -> io::Result<T>
+    where
+        F: FnOnce(Option<&mut Stream>) -> (T, bool),
+    {
+        self.with_data_mut(|data| {
+            let (result, changed) = match data.get_mut(&key) {
+                Some(Value::Stream(stream)) => f(Some(stream)),
+                Some(_) => return Err(wrong_type()),
+                None if create => {
+                    let mut stream = Stream::default();
+                    let (result, changed) = f(Some(&mut stream));
+                    if changed {
+                        data.insert(key.clone(), Value::Stream(stream));
+                    }
+                    (result, false)
+                }
+                None => f(None),
+            };
+            if changed {
+                data.touch(&key);
+            }
+            Ok(result)
+        })
+    }
+
+    /// Appends an entry to the stream stored at `key` and returns its ID, then evicts the oldest
+    /// entries according to `trim`. Returns `None` if there is no stream and `create` isn't set.
+    ///
+    /// It is appended to the AOF with the ID it got, followed by an `XTRIM` down to the first
+    /// entry left if any were evicted. Clients blocked reading the stream are then served.
+    pub fn add_to_stream(
+        &self,
+        key: String,
+        id: NewId,
+        fields: Fields,
+        create: bool,
+        trim: Option<(Trim, Option<usize>)>,
+    ) -> io::Result<Option<StreamId>> {
+        let mut state = self.inner.data.write().unwrap();
+        let index = self.index;
+        let keyspace = state.keyspace_mut(index);
+        // A new stream is only inserted once the entry was added to it.
+        let mut created = match keyspace.get(&key) {
+            Some(Value::Stream(_)) => None,
+            Some(_) => return Err(wrong_type()),
+            None if !create => return Ok(None),
+            None => Some(Stream::default()),
+        };
+        let stream = match &mut created {
+            Some(stream) => stream,
+            None => match keyspace.get_mut(&key) {
+                Some(Value::Stream(stream)) => stream,
+                _ => unreachable!("the key holds a stream"),
+            },
+        };
+        let id = stream.add(id, fields.clone())?;
+        let trimmed = trim.is_some_and(|(trim, limit)| stream.trim(trim, limit) > 0);
+        let first_left = stream
+            .first_id()
+            .or(stream.last_id().next())
+            .unwrap_or(StreamId::MAX);
+        match created {
+            Some(stream) => {
+                keyspace.insert(key.clone(), Value::Stream(stream));
+            }
+            None => keyspace.touch(&key),
+        }
+
+        let command = [
+            Bytes::from_static(b"XADD"),
+            Bytes::from(key.clone()),
+            id.to_bytes(),
+        ]
+        .into_iter()
+        .chain(fields.into_iter().flat_map(|(field, value)| [field, value]));
+        state.propagate(index, Frame::new_command(command));
+        if trimmed {
+            let command = Frame::new_command([
+                Bytes::from_static(b"XTRIM"),
+                Bytes::from(key.clone()),
+                Bytes::from_static(b"MINID"),
+                first_left.to_bytes(),
+            ]);
+            state.propagate(index, command);
+        }
+        state.serve_blocked_readers(index, &key);
+        Ok(Some(id))
+    }
+
+    /// Reads the entries after the given position in each of the streams, or those of the
+    /// consumer group when `group` is set, see [`Stream::deliver`] and [`Stream::pending_for`].
+    /// Only the streams with entries are replied with, except for the history of a consumer.
+    ///
+    /// If there are none and `block` is set, the caller is registered as a blocked client and
+    /// gets a [`BlockedRead`] to wait on instead. It is then served by the first entries added
+    /// to one of the streams.
+    pub fn read_streams(
+        &self,
+        streams: Vec<(String, ReadFrom)>,
+        group: Option<GroupReader>,
+        count: Option<usize>,
+        block: bool,
+    ) -> io::Result<BlockingRead> {
+        let mut state = self.inner.data.write().unwrap();
+        let index = self.index;
+        let keyspace = state.keyspace(index);
+
+        // Every stream is checked before anything is delivered to the consumer.
+        let mut positions = Vec::with_capacity(streams.len());
+        for (key, from) in streams {
+            let stream = match keyspace.get(&key) {
+                Some(Value::Stream(stream)) => Some(stream),
+                Some(_) => return Err(wrong_type()),
+                None => None,
+            };
+            if let Some(group) = &group {
+                if stream
+                    .and_then(|stream| stream.group(&group.group))
+                    .is_none()
+                {
+                    let group = String::from_utf8_lossy(&group.group);
+                    return Err(io::Error::other(format!(
+                        "NOGROUP No such key '{key}' or consumer group '{group}' in XREADGROUP with GROUP option"
+                    )));
+                }
+            }
+            let from = match from {
+                ReadFrom::Last => ReadFrom::After(stream.map_or(StreamId::MIN, Stream::last_id)),
+                from => from,
+            };
+            positions.push((key, from));
+        }
+
+        let mut read = vec![];
+        for (key, from) in &positions {
+            let entries = state.read_stream(index, key, *from, group.as_ref(), count)?;
+            let history = group.is_some() && matches!(from, ReadFrom::After(_));
+            if !entries.is_empty() || history {
+                read.push((key.clone(), entries));
+            }
+        }
+        if !read.is_empty() || !block {
+            return Ok(BlockingRead::Ready(read));
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let after = positions
+            .iter()
+            .filter_map(|(key, from)| match from {
+                ReadFrom::After(id) => Some((key.clone(), *id)),
+                _ => None,
+            })
+            .collect();
+        let keys = positions.into_iter().map(|(key, _)| key).collect();
+        let reader = BlockedReader {
+            after,
+            group,
+            count,
+            sender,
+        };
+        let id = state.databases[index].blocked_readers.block(keys, reader);
+        Ok(BlockingRead::Blocked(BlockedRead {
+            id,
+            index,
+            receiver,
+            db: self.inner.clone(),
+        }))
+    }
+
+    /// Gives pending entries of a consumer group to `consumer`, see [`Stream::claim`], and
+    /// returns those claimed.
+    ///
+    /// Each claimed entry is appended to the AOF as an `XCLAIM` with its new delivery time and
+    /// counter, and the pending entries dropped because they were deleted as an `XACK`.
+    pub fn claim(
+        &self,
+        key: &str,
+        group: &Bytes,
+        consumer: &Bytes,
+        min_idle: Duration,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> io::Result<Entries> {
+        let mut state = self.inner.data.write().unwrap();
+        let index = self.index;
+        let stream = match state.keyspace_mut(index).get_mut(key) {
+            Some(Value::Stream(stream)) => stream,
+            Some(_) => return Err(wrong_type()),
+            None => return Err(no_group(key, group)),
+        };
+        let created = stream
+            .group(group)
+            .is_some_and(|group| !group.consumers().contains_key(consumer));
+        let (claimed, deleted) = stream
+            .claim(group, consumer, min_idle, ids, options, Utc::now())
+            .ok_or_else(|| no_group(key, group))?;
+
+        let group_state = stream.group(group).expect("the group exists");
+        let last_delivered = group_state.last_delivered();
+        let mut commands = vec![];
+        if created {
+            commands.push(xgroup_command(
+                b"CREATECONSUMER",
+                key,
+                group,
+                consumer.clone(),
+            ));
+        }
+        for (id, _) in &claimed {
+            let pending = &group_state.pending()[id];
+            commands.push(xclaim_command(key, group, *id, pending, last_delivered));
+        }
+        if !deleted.is_empty() {
+            let command = [
+                Bytes::from_static(b"XACK"),
+                Bytes::from(key.to_owned()),
+                group.clone(),
+            ]
+            .into_iter()
+            .chain(deleted.iter().map(|id| id.to_bytes()));
+            commands.push(Frame::new_command(command));
+        }
+        if options.last_id.is_some() && claimed.is_empty() {
+            commands.push(xgroup_command(
+                b"SETID",
+                key,
+                group,
+                last_delivered.to_bytes(),
+            ));
+        }
+        // Something changed in the group if and only if there is something to propagate.
+        if !commands.is_empty() {
+            state.keyspace_mut(index).touch(key);
+        }
+        for command in commands {
+            state.propagate(index, command);
+        }
+        Ok(claimed)
+    }
+
+    /// Runs the closure against the value stored at `key`, or `default` if the key does not
+    /// exist. Unless the closure tells it didn't change the value, the key is then marked as
+    /// modified, created, or removed if it holds an empty collection.
+    fn with_value_mut<T, D, F>(&self, key: String, default: D, f: F) -> io::Result<T>
+    where
+        D: FnOnce() -> Value,
+        F: FnOnce(&mut Value) -> io::Result<(T, bool)>,
+    {
+        self.with_data_mut(|data| {
+            let mut created = None;
+            let value = match data.get_mut(&key) {
+                Some(value) => value,
+                None => created.insert(default()),
+            };
+            let (result, changed) = f(value)?;
+            let empty = value.is_empty_collection();
+            match created {
+                _ if !changed => {}
+                Some(value) if !empty => {
+                    data.insert(key, value);
+                }
+                Some(_) => {}
+                None if empty => {
+                    data.remove(&key);
+                }
+                None => data.touch(&key),
+            }
+            Ok(result)
+        })
+    }
+
+    /// Sets the key to the string, unless the condition isn't met. Returns whether the key was
+    /// set, along with the string it held before if `options.get` is set.
+    /// Like in redis, any previous time to live is discarded unless `options.keep_ttl` is set.
+    pub fn set(
+        &self,
+        key: String,
+        value: Bytes,
+        options: SetOptions,
+    ) -> io::Result<(bool, Option<Bytes>)> {
+        let mut state = self.inner.data.write().unwrap();
+
+        let keyspace = state.keyspace_mut(self.index);
+        let previous_value = match keyspace.get(&key) {
+            Some(previous) if options.get => Some(previous.as_string().ok_or_else(wrong_type)?),
+            _ => None,
+        };
+        let allowed = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::Nx => !keyspace.contains_key(&key),
+            SetCondition::Xx => keyspace.contains_key(&key),
+        };
+        if !allowed {
+            return Ok((false, previous_value));
+        }
+        let at = options
+            .expire
+            .map(|duration| {
+                chrono::Duration::from_std(duration)
+                    .ok()
+                    .and_then(|duration| Utc::now().checked_add_signed(duration))
+                    .ok_or_else(|| io::Error::other("ERR invalid expire time in 'set' command"))
+            })
+            .transpose()?;
+        keyspace.insert(key.clone(), Value::String(value));
+        if !options.keep_ttl {
+            keyspace.clear_expiry(&key);
+        }
+
+        let when = at.map(|at| {
+            // The deadline is logged as is, so replaying the AOF later doesn't extend it.
+            state.propagate(self.index, pexpireat(&key, at));
+            state.keyspace_mut(self.index).expire_at(&key, at)
+        });
+        let notify = when.is_some() && state.keyspace(self.index).next_expiry() == when;
+
+        drop(state);
+
+        if notify {
+            self.inner.background_task.notify_one();
+        }
+
+        Ok((true, previous_value))
+    }
+
+    /// Sets every key to its string, discarding their time to live, the way `MSET` does. With
+    /// `nx`, nothing is set if any of the keys exists, and false is returned.
+    pub fn set_many(&self, pairs: Vec<(String, Bytes)>, nx: bool) -> bool {
+        self.with_data_mut(|data| {
+            if nx && pairs.iter().any(|(key, _)| data.contains_key(key)) {
+                return false;
+            }
+            for (key, value) in pairs {
+                data.insert(key.clone(), Value::String(value));
+                data.clear_expiry(&key);
+            }
+            true
+        })
+    }
+
+    /// Makes the key expire at `at`, or deletes it right away if that is in the past.
+    /// Returns false if the key does not exist or the condition is not met.
+    pub fn expire(&self, key: &str, at: DateTime<Utc>, condition: ExpireCondition) -> bool {
+        let mut state = self.inner.data.write().unwrap();
+        let (updated, notify) = state.expire(self.index, key, at, condition);
+        drop(state);
+
+        if notify {
+            self.inner.background_task.notify_one();
+        }
+        updated
+    }
+
+    /// Removes the time to live of the key. Returns false if the key does not exist
+    /// or does not have one, in which case nothing is appended to the AOF.
+    pub fn persist(&self, key: &str) -> bool {
+        self.inner.data.write().unwrap().persist(self.index, key)
+    }
+
+    /// Returns the string stored at `key`, making it expire at `expire` if given or
+    /// removing its time to live if `persist` is set, under a single write lock.
+    pub fn getex(
+        &self,
+        key: &str,
+        expire: Option<DateTime<Utc>>,
+        persist: bool,
+    ) -> io::Result<Option<Bytes>> {
+        let mut state = self.inner.data.write().unwrap();
+        let Some(value) = state.keyspace(self.index).get(key) else {
+            return Ok(None);
+        };
+        let value = value.as_string().ok_or_else(wrong_type)?;
+
+        let mut notify = false;
+        if let Some(at) = expire {
+            notify = state.expire(self.index, key, at, ExpireCondition::Always).1;
+        } else if persist {
+            state.persist(self.index, key);
+        }
+        drop(state);
+
+        if notify {
+            self.inner.background_task.notify_one();
+        }
+        Ok(Some(value))
+    }
+
+    /// Returns `None` if the key does not exist, and `Some(None)` if it exists
+    /// but does not have a time to live.
+    pub fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        self.with_data(|data| {
+            data.get(key)?;
+            let ttl = data
+                .deadline(key)
+                .map(|at| (at - Utc::now()).to_std().unwrap_or(Duration::ZERO));
+            Some(ttl)
+        })
+    }
+
+    /// Renames the key `from` to `to`, along with its time to live, replacing any value `to`
+    /// had unless `nx` is set. Returns false if `nx` kept it from being renamed.
+    pub fn rename(&self, from: &str, to: String, nx: bool) -> io::Result<bool> {
+        let mut state = self.inner.data.write().unwrap();
+        let keyspace = state.keyspace_mut(self.index);
+        if !keyspace.contains_key(from) {
+            return Err(io::Error::other("ERR no such key"));
+        }
+        // Like in redis, a key renamed to itself is left as is, which `RENAMENX` replies 0 to.
+        if from == to || (nx && keyspace.contains_key(&to)) {
+            return Ok(!nx);
+        }
+
+        let deadline = keyspace.deadline(from);
+        let value = keyspace.remove(from).expect("the key exists");
+        let is_list = matches!(value, Value::List(_));
+        keyspace.insert(to.clone(), value);
+        keyspace.clear_expiry(&to);
+        let notify = deadline.is_some_and(|at| {
+            let when = keyspace.expire_at(&to, at);
+            keyspace.next_expiry() == Some(when)
+        });
+        // Like a push, a list renamed to a key that clients are blocked on serves them.
+        if is_list {
+            state.serve_blocked_clients(self.index, to);
+        }
+        drop(state);
+
+        if notify {
+            self.inner.background_task.notify_one();
+        }
+        Ok(true)
+    }
+
+    /// Removes every key of the selected database, or of all of them with `all`. The clients
+    /// tracking keys are told to drop all of them.
+    pub fn flush(&self, all: bool) {
+        let mut state = self.inner.data.write().unwrap();
+        for (index, database) in state.databases.iter_mut().enumerate() {
+            if all || index == self.index {
+                database.keyspace.clear();
+            }
+        }
+        drop(state);
+        self.inner.tracking.invalidate_all();
+    }
+
+    /// Writes a snapshot of the databases, blocking until it is on disk.
+    pub fn save(&self) -> io::Result<()> {
+        let (path, keyspaces) = self.inner.start_snapshot()?;
+        let changes = total_changes(&keyspaces);
+        self.inner
+            .finish_snapshot(write_snapshot(&path, &keyspaces), changes)
+    }
+
+    /// Writes a snapshot of the databases from a background thread.
+    pub fn background_save(&self) -> io::Result<()> {
+        self.inner.background_save()
+    }
+
+    /// Makes sure everything is on disk before the server exits: the AOF is synced, and a
+    /// final snapshot is written.
+    pub fn shutdown(&self) -> io::Result<()> {
+        if let Some(aof) = self.inner.aof.lock().unwrap().as_ref() {
+            aof.file()?.sync_all()?;
+        }
+        self.save()
+    }
+
+    /// When the last snapshot was successfully written.
+    pub fn last_save(&self) -> DateTime<Utc> {
+        self.inner.snapshots.lock().unwrap().last_save
+    }
+
+    /// The fields of the persistence section of `INFO`.
+    pub fn persistence_info(&self) -> Vec<(String, String)> {
+        let aof = self.inner.aof.lock().unwrap();
+        let changes = self.inner.data.read().unwrap().changes();
+        let snapshots = self.inner.snapshots.lock().unwrap();
+        let status = |ok: bool| if ok { "ok" } else { "err" }.to_owned();
+        vec![
+            ("loading".to_owned(), "0".to_owned()),
+            (
+                "rdb_changes_since_last_save".to_owned(),
+                changes.saturating_sub(snapshots.saved_changes).to_string(),
+            ),
+            (
+                "rdb_bgsave_in_progress".to_owned(),
+                u8::from(snapshots.in_progress).to_string(),
+            ),
+            (
+                "rdb_last_save_time".to_owned(),
+                snapshots.last_save.timestamp().to_string(),
+            ),
+            (
+                "rdb_last_bgsave_status".to_owned(),
+                status(snapshots.last_ok),
+            ),
+            (
+                "aof_enabled".to_owned(),
+                u8::from(aof.is_some()).to_string(),
+            ),
+            (
+                "aof_rewrite_in_progress".to_owned(),
+                u8::from(aof.as_ref().is_some_and(Aof::is_rewriting)).to_string(),
+            ),
+        ]
+    }
+
+    /// The number of keys, and of keys with a time to live, of each database.
+    pub fn keyspace_sizes(&self) -> Vec<(usize, usize)> {
+        self.inner
+            .data
+            .read()
+            .unwrap()
+            .databases
+            .iter()
+            .map(|database| (database.keyspace.len(), database.keyspace.expires()))
+            .collect()
+    }
+
+    /// The current parameters, as set on startup or with `CONFIG SET`.
+    pub fn config(&self) -> Config {
+        self.inner.config.read().unwrap().clone()
+    }
+
+    /// Changes the parameters with `f`, and applies them. Nothing is changed if `f` fails.
+    ///
+    /// Turning on `appendonly` writes a new AOF from the current keyspace, since the one on
+    /// disk, if any, is out of date.
+    pub fn update_config<F>(&self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Config) -> anyhow::Result<()>,
+    {
+        let mut config = self.inner.config.write().unwrap();
+        let mut updated = config.clone();
+        f(&mut updated).map_err(|err| io::Error::other(err.to_string()))?;
+
+        match (config.appendonly, updated.appendonly) {
+            (false, true) => self.start_aof(&updated, true)?,
+            (true, false) => self.stop_aof(),
+            _ => {
+                if let Some(aof) = self.inner.aof.lock().unwrap().as_mut() {
+                    aof.set_fsync(updated.appendfsync);
+                }
+            }
+        }
+        self.inner
+            .replication
+            .lock()
+            .unwrap()
+            .set_backlog_size(updated.repl_backlog_size);
+        self.inner.slowlog.configure(&updated);
+        self.inner.pubsub.configure(&updated);
+        // Only a new `requirepass` replaces the passwords `ACL SETUSER` gave the default user.
+        if updated.requirepass != config.requirepass {
+            self.inner.acl.set_requirepass(&updated.requirepass);
+        }
+        *config = updated;
+        Ok(())
+    }
+
+    /// Loads the AOF's snapshot, if it starts with one, and returns the commands appended
+    /// after it so that the caller can replay them.
+    pub fn load_aof(&self) -> io::Result<Vec<Frame>> {
+        let config = self.config();
+        let Some(contents) = aof::read(&config.aof_path(), config.databases)? else {
+            return Ok(vec![]);
+        };
+        if let Some(keyspaces) = contents.preamble {
+            self.inner
+                .data
+                .write()
+                .unwrap()
+                .replace_keyspaces(keyspaces);
+        }
+        Ok(contents.commands)
+    }
+
+    /// Starts appending write commands to the AOF, once it has been replayed on startup.
+    /// If there is no AOF yet, it is created from the current keyspace.
+    pub fn enable_aof(&self) -> io::Result<()> {
+        let config = self.inner.config.read().unwrap();
+        self.start_aof(&config, false)
+    }
+
+    /// Opens the AOF, writing it from the current keyspace first if `rewrite` is set.
+    fn start_aof(&self, config: &Config, rewrite: bool) -> io::Result<()> {
+        let path = config.aof_path();
+        let mut aof = self.inner.aof.lock().unwrap();
+        if aof.is_some() {
+            return Err(io::Error::other("The AOF is already enabled"));
+        }
+        let mut state = self.inner.data.write().unwrap();
+        if rewrite || !path.exists() {
+            aof::write_base(&path, &state.keyspaces())?;
+        }
+        *aof = Some(Aof::open(path, config.appendfsync)?);
+        state.propagated = Some(vec![]);
+        state.propagated_db = None;
+        drop(state);
+        drop(aof);
+
+        self.inner.aof_fsync_thread.call_once(|| {
+            let inner = self.inner.clone();
+            std::thread::spawn(move || fsync_aof_every_second(inner));
+        });
+        Ok(())
+    }
+
+    fn stop_aof(&self) {
+        let mut aof = self.inner.aof.lock().unwrap();
+        *aof = None;
+        // The changes are still needed by the replicas.
+        if !self.inner.replication.lock().unwrap().is_active() {
+            self.inner.data.write().unwrap().propagated = None;
+        }
+    }
+
+    /// Runs a command that changes the keyspace and appends it to the AOF, followed by the
+    /// changes the `Db` recorded in its place, e.g. the absolute deadline of a `SET` with `EX`.
+    /// The same commands are fed to the replicas. A command that fails is left out.
+    ///
+    /// Write commands run one at a time while the AOF or replication is enabled, so that they
+    /// are logged in the same order as they were executed.
+    pub fn log_write<T, F>(&self, command: Option<Frame>, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let mut aof = self.inner.aof.lock().unwrap();
+        let state = self.inner.data.read().unwrap();
+        if state.propagated.is_none() {
+            drop(state);
+            drop(aof);
+            return f();
+        }
+        drop(state);
+        // It comes after the keys evicted to make room for it, which were removed before it ran.
+        let result = match command {
+            Some(command) => self.log_verbatim(command, f),
+            None => f(),
+        };
+
+        let propagated = self.inner.data.write().unwrap().propagated.replace(vec![]);
+        let commands = propagated.unwrap_or_default();
+        if let Some(aof) = aof.as_mut() {
+            if let Err(err) = aof.append(&commands) {
+                error!("Failed to append to the AOF: {err}");
+            }
+        }
+        // The writes a replica's clients make, if it isn't read-only, are its own.
+        let mut replication = self.inner.replication.lock().unwrap();
+        if replication.master().is_none() {
+            replication.feed(&commands);
+        }
+        result
+    }
+
+    /// Runs a command, concurrently with other commands unless it is `exclusive`.
+    ///
+    /// This has to be the outermost lock taken, since commands can take any of the other ones.
+    pub fn run_command<T, F>(&self, exclusive: bool, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        if exclusive {
+            let _commands = self.inner.commands.write().unwrap();
+            f()
+        } else {
+            let _commands = self.inner.commands.read().unwrap();
+            f()
+        }
+    }
+
+    /// Runs a command of a client like `run_command`, unless a script is running for longer
+    /// than `lua-time-limit`, in which case the client is replied `BUSY`. The client waits for
+    /// the script without holding up its thread, so that `SCRIPT KILL` can still be handled.
+    pub async fn run_client_command<T, F>(&self, exclusive: bool, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> T,
+    {
+        self.inner.scripts.wait().await?;
+        if exclusive {
+            // It may be a script running for long, meanwhile the tasks of the other clients are
+            // moved off this thread so that they can be replied.
+            return Ok(tokio::task::block_in_place(|| self.run_command(true, f)));
+        }
+        // The same goes for waiting on an exclusive command that started in the meantime.
+        let _commands = match self.inner.commands.try_read() {
+            Ok(commands) => commands,
+            Err(TryLockError::WouldBlock) => {
+                return Ok(tokio::task::block_in_place(|| self.run_command(false, f)));
+            }
+            Err(TryLockError::Poisoned(err)) => panic!("{err}"),
+        };
+        Ok(f())
+    }
+
+    /// Runs the commands of a transaction or a script. What they append to the AOF is wrapped
+    /// in `MULTI`/`EXEC`, so that replaying a partially written transaction has no effect.
+    /// A script called from a transaction is part of the transaction.
+    pub fn log_transaction<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let mut state = self.inner.data.write().unwrap();
+        if state.in_transaction {
+            drop(state);
+            return f();
+        }
+        state.in_transaction = true;
+        let start = state.propagated.as_ref().map(Vec::len);
+        drop(state);
+
+        let result = f();
+
+        let mut state = self.inner.data.write().unwrap();
+        state.in_transaction = false;
+        if let (Some(start), Some(propagated)) = (start, &mut state.propagated) {
+            if propagated.len() > start {
+                propagated.insert(start, Frame::new_command([Bytes::from_static(b"MULTI")]));
+                propagated.push(Frame::new_command([Bytes::from_static(b"EXEC")]));
+            }
+        }
+        result
+    }
+
+    /// Appends a command to the AOF as is, for the effects of a command.
+    pub fn log_command(&self, command: Frame) {
+        self.inner
+            .data
+            .write()
+            .unwrap()
+            .propagate(self.index, command);
+    }
+
+    /// Appends a command to the AOF as is before running it with `f`, so that it comes before
+    /// its effects, e.g. the deadline of a `SET` with `EX`. It is taken back out if it fails.
+    pub fn log_verbatim<T, F>(&self, command: Frame, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let mut state = self.inner.data.write().unwrap();
+        state.propagate(self.index, command);
+        let position = state
+            .propagated
+            .as_ref()
+            .map(|propagated| propagated.len() - 1);
+        drop(state);
+
+        let result = f();
+        if let (Err(_), Some(position)) = (&result, position) {
+            let mut state = self.inner.data.write().unwrap();
+            if let Some(propagated) = &mut state.propagated {
+                propagated.remove(position);
+            }
+        }
+        result
+    }
+
+    /// Evicts keys until the memory used is back under `maxmemory`, before running a command
+    /// that can use more memory. Fails if the policy doesn't allow evicting enough keys.
+    ///
+    /// The evicted keys are appended to the AOF as `DEL`s. Like redis, keys are evicted from
+    /// every database, starting from a random one.
+    pub fn make_room(&self) -> io::Result<()> {
+        let config = self.inner.config.read().unwrap();
+        let (maxmemory, policy, samples) = (
+            config.maxmemory,
+            config.maxmemory_policy,
+            config.maxmemory_samples,
+        );
+        drop(config);
+        if maxmemory == 0 {
+            return Ok(());
+        }
+
+        let notifications = self.inner.start_recording();
+        let result = self.evict(maxmemory, policy, samples);
+        if notifications.is_some() {
+            let events = self.inner.data.write().unwrap().take_events();
+            self.inner.notify(events, None, None);
+        }
+        result
+    }
+
+    fn evict(&self, maxmemory: u64, policy: MaxmemoryPolicy, samples: usize) -> io::Result<()> {
+        let mut data = self.inner.data.write().unwrap();
+        let databases = data.databases.len();
+        let mut index = rand::random::<usize>() % databases;
+        // The databases that were found to have no key the policy can evict.
+        let mut exhausted = 0;
+        while data.used_memory() as u64 > maxmemory {
+            if exhausted == databases {
+                return Err(io::Error::other(
+                    "OOM command not allowed when used memory > 'maxmemory'.",
+                ));
+            }
+            let Some(key) = data.keyspace_mut(index).evict(policy, samples) else {
+                exhausted += 1;
+                index = (index + 1) % databases;
+                continue;
+            };
+            exhausted = 0;
+            data.evicted_keys += 1;
+            data.propagate(
+                index,
+                Frame::new_command([Bytes::from_static(b"DEL"), Bytes::from(key)]),
+            );
+            index = (index + 1) % databases;
+        }
+        Ok(())
+    }
+
+    /// Roughly how many bytes the keys and their values take.
+    pub fn used_memory(&self) -> usize {
+        self.inner.data.write().unwrap().used_memory()
+    }
+
+    /// Gives the closure access to the state of replication.
+    pub fn with_replication<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Replication) -> T,
+    {
+        f(&mut self.inner.replication.lock().unwrap())
+    }
+
+    /// Starts replicating `master`, or stops replicating with `None`, the way `REPLICAOF` does.
+    /// Returns false if we were already replicating it.
+    pub fn replicaof(&self, master: Option<(String, u16)>) -> bool {
+        let mut config = self.inner.config.write().unwrap();
+        config.replicaof = master.clone();
+        self.inner.replication.lock().unwrap().set_master(master)
+    }
+
+    /// Whether clients are refused write commands, since we are a read-only replica.
+    pub fn is_read_only_replica(&self) -> bool {
+        self.inner.config.read().unwrap().replica_read_only
+            && self.inner.replication.lock().unwrap().master().is_some()
+    }
+
+    /// Registers a replica that sent `PSYNC replid offset`. It carries on from the backlog if
+    /// it can, otherwise it is sent a snapshot of the keyspace first.
+    ///
+    /// Returns the reply to `PSYNC` and what to send the replica from then on.
+    pub fn add_replica(
+        &self,
+        replica: Replica,
+        receiver: mpsc::UnboundedReceiver<Bytes>,
+        replid: &str,
+        offset: Option<u64>,
+    ) -> (Frame, ReplicaFeed) {
+        // No write runs between taking the snapshot and feeding the replica what follows it.
+        let _aof = self.inner.aof.lock().unwrap();
+        let mut replication = self.inner.replication.lock().unwrap();
+        let (reply, full_sync) = replication.add_replica(replica, replid, offset);
+        let mut data = self.inner.data.write().unwrap();
+        data.propagated.get_or_insert_with(Vec::new);
+        // The replica starts from the first database, whether it loads the snapshot or
+        // carries on where it left off.
+        data.propagated_db = None;
+        let snapshot = full_sync.then(|| data.keyspaces());
+        (reply, ReplicaFeed::new(snapshot, receiver))
+    }
+
+    /// Replaces the keyspace with the snapshot the master sent for a full sync, which is as of
+    /// `offset` of its stream `replid`. The AOF, if enabled, is rewritten from it.
+    pub fn sync_from_master(&self, replid: String, offset: u64, rdb: &[u8]) -> io::Result<()> {
+        let config = self.config();
+        let keyspaces = rdb::read(rdb, config.databases)?;
+        let mut aof = self.inner.aof.lock().unwrap();
+        let mut replication = self.inner.replication.lock().unwrap();
+        let mut data = self.inner.data.write().unwrap();
+        if aof.is_some() {
+            aof::write_base(&config.aof_path(), &keyspaces)?;
+            *aof = Some(Aof::open(config.aof_path(), config.appendfsync)?);
+        }
+        data.replace_keyspaces(keyspaces);
+        data.propagated = Some(vec![]);
+        data.propagated_db = None;
+        replication.start_from_master(replid, offset);
+        self.inner.tracking.invalidate_all();
+        Ok(())
+    }
+
+    /// Runs a command received from the master as `raw`, for the selected database, with `f`,
+    /// which returns the database selected afterwards. It is appended to the AOF if `log` is
+    /// set, and fed to our own replicas, as is: the master already sent its effects.
+    pub fn apply_from_master<F>(&self, command: &Frame, raw: Bytes, log: bool, f: F)
+    where
+        F: FnOnce() -> usize,
+    {
+        let mut aof = self.inner.aof.lock().unwrap();
+        let mut data = self.inner.data.write().unwrap();
+        let propagated = data.propagated.take();
+        // The AOF can have been rewritten since the master last sent a `SELECT`.
+        let select = data.propagated_db != Some(self.index);
+        drop(data);
+        let index = f();
+        let mut data = self.inner.data.write().unwrap();
+        data.propagated = propagated;
+        // The master's stream selects the same database as we do, so this also holds for
+        // the stream we feed if we are promoted.
+        data.propagated_db = Some(index);
+        drop(data);
+
+        if let (Some(aof), true) = (aof.as_mut(), log) {
+            let commands: Vec<_> = select
+                .then(|| select_command(self.index))
+                .into_iter()
+                .chain([command.clone()])
+                .collect();
+            if let Err(err) = aof.append(&commands) {
+                error!("Failed to append to the AOF: {err}");
+            }
+        }
+        self.inner.replication.lock().unwrap().feed_bytes(raw);
+    }
+
+    /// Runs a write command sent as `command`, then tells about the keys it changed the clients
+    /// subscribed to keyspace notifications, and the clients tracking the keys, except `client`
+    /// if it asked not to be told about its own changes.
+    ///
+    /// Nothing is recorded unless someone is told about it. Otherwise write commands run one
+    /// at a time, so that the keys they change aren't mixed up. A command that fails is taken
+    /// to have changed nothing, though it can still find that keys expired.
+    pub fn notify_writes<T, F>(&self, command: &Frame, client: Option<u64>, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let Some(_notifications) = self.inner.start_recording() else {
+            return f();
+        };
+        let result = f();
+        let mut events = self.inner.data.write().unwrap().take_events();
+        if result.is_err() {
+            events.retain(|(_, event, _)| *event != KeyEvent::Changed);
+        }
+        self.inner.notify(events, Some(command), client);
+        result
+    }
+
+    /// Gives the closure access to what the node knows of the cluster, which is saved to the
+    /// cluster config file whenever it changes. Returns `None` unless in cluster mode.
+    pub fn with_cluster<T, F>(&self, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Cluster) -> T,
+    {
+        let mut cluster = self.inner.cluster.as_ref()?.lock().unwrap();
+        let result = f(&mut cluster);
+        if let Err(err) = cluster.save() {
+            error!("Failed to save the cluster config file: {err}");
+        }
+        Some(result)
+    }
+
+    pub fn is_cluster_enabled(&self) -> bool {
+        self.inner.cluster.is_some()
+    }
+
+    /// Fails unless the keys of a command can be served by this node, in cluster mode, with
+    /// the error that redirects the client to the right node, e.g. `MOVED 3999 127.0.0.1:6381`.
+    ///
+    /// Like redis, the keys must all be in the same slot. While a slot is migrated, the keys
+    /// that are still here are served here, and the client is sent to the target node with
+    /// `ASK` for the others, where it has to send `ASKING` first for them to be served.
+    pub fn check_slot(&self, keys: &[Bytes], asking: bool) -> io::Result<()> {
+        let Some(cluster) = &self.inner.cluster else {
+            return Ok(());
+        };
+        let Some(slot) = keys.first().map(|key| key_slot(key)) else {
+            return Ok(());
+        };
+        if keys.iter().any(|key| key_slot(key) != slot) {
+            return Err(io::Error::other(
+                "CROSSSLOT Keys in request don't hash to the same slot",
+            ));
+        }
+
+        let cluster = cluster.lock().unwrap();
+        let address = |node: &Node| format!("{}:{}", node.ip, node.port);
+        if cluster.is_mine(slot) {
+            if let Some(target) = cluster.migrating_to(slot) {
+                let missing = self.with_data(|data| {
+                    keys.iter()
+                        .filter(|key| !data.contains_key(&String::from_utf8_lossy(key)))
+                        .count()
+                });
+                if missing == keys.len() {
+                    return Err(io::Error::other(format!("ASK {slot} {}", address(target))));
+                }
+                if missing > 0 {
+                    return Err(io::Error::other(
+                        "TRYAGAIN Multiple keys request during rehashing of slot",
+                    ));
+                }
+            }
+            return Ok(());
+        }
+        if asking && cluster.importing_from(slot).is_some() {
+            return Ok(());
+        }
+        match cluster.owner(slot) {
+            Some(owner) => Err(io::Error::other(format!("MOVED {slot} {}", address(owner)))),
+            None => Err(io::Error::other("CLUSTERDOWN Hash slot not served")),
+        }
+    }
+
+    /// The keys in the hash slot, which are found by going through the whole keyspace.
+    pub fn keys_in_slot(&self, slot: u16) -> Vec<String> {
+        self.with_data(|data| {
+            data.iter()
+                .map(|(key, _)| key)
+                .filter(|key| key_slot(key.as_bytes()) == slot)
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// The value of the key serialized the way `DUMP` does, along with its time to live.
+    /// Returns `None` if the key does not exist.
+    pub fn dump(&self, key: &str) -> Option<(Vec<u8>, Option<DateTime<Utc>>)> {
+        self.with_data(|data| Some((rdb::dump(data.get(key)?), data.deadline(key))))
+    }
+
+    /// Sets the key to a value serialized by `DUMP`, expiring at `expire_at` if set. Fails if
+    /// the key exists, unless `replace` is set, or if the payload is invalid.
+    ///
+    /// Like redis, a key restored with a deadline that is already past is deleted instead.
+    pub fn restore(
+        &self,
+        key: String,
+        payload: Bytes,
+        expire_at: Option<DateTime<Utc>>,
+        replace: bool,
+    ) -> io::Result<()> {
+        let value = rdb::restore(&payload).map_err(|err| io::Error::other(format!("ERR {err}")))?;
+        let mut state = self.inner.data.write().unwrap();
+        let keyspace = state.keyspace_mut(self.index);
+        if !replace && keyspace.contains_key(&key) {
+            return Err(io::Error::other("BUSYKEY Target key name already exists."));
+        }
+        if expire_at.is_some_and(|at| at <= Utc::now()) {
+            if keyspace.remove(&key).is_some() {
+                state.propagate(
+                    self.index,
+                    Frame::new_command([Bytes::from_static(b"DEL"), Bytes::from(key)]),
+                );
+            }
+            return Ok(());
+        }
+
+        let (is_list, is_stream) = (
+            matches!(value, Value::List(_)),
+            matches!(value, Value::Stream(_)),
+        );
+        keyspace.insert(key.clone(), value);
+        keyspace.clear_expiry(&key);
+        // The deadline is logged on its own, so replaying the AOF later doesn't extend it.
+        state.propagate(
+            self.index,
+            Frame::new_command([
+                Bytes::from_static(b"RESTORE"),
+                Bytes::from(key.clone()),
+                Bytes::from_static(b"0"),
+                payload,
+                Bytes::from_static(b"REPLACE"),
+            ]),
+        );
+        let notify = expire_at.is_some_and(|at| {
+            state.propagate(self.index, pexpireat(&key, at));
+            let keyspace = state.keyspace_mut(self.index);
+            let when = keyspace.expire_at(&key, at);
+            keyspace.next_expiry() == Some(when)
+        });
+        if is_list {
+            state.serve_blocked_clients(self.index, key);
+        } else if is_stream {
+            state.serve_blocked_readers(self.index, &key);
+        }
+        drop(state);
+
+        if notify {
+            self.inner.background_task.notify_one();
+        }
+        Ok(())
+    }
+
+    /// The number of keys evicted since the server started.
+    pub fn evicted_keys(&self) -> u64 {
+        self.inner.data.read().unwrap().evicted_keys
+    }
+
+    /// Rewrites the AOF from a snapshot of the keyspace in a background thread.
+    /// Commands executed in the meantime are added to the new file once it is written.
+    pub fn background_rewrite_aof(&self) -> io::Result<()> {
+        // Holding the AOF lock means no command runs between taking the snapshot and
+        // buffering the commands that follow it.
+        let mut aof = self.inner.aof.lock().unwrap();
+        let temp_path = aof
+            .as_mut()
+            .ok_or_else(|| {
+                io::Error::other("ERR AOF is disabled, turn it on with CONFIG SET appendonly yes")
+            })?
+            .start_rewrite()?;
+        let mut data = self.inner.data.write().unwrap();
+        // The commands that follow the snapshot start from the first database.
+        data.propagated_db = None;
+        let keyspaces = data.keyspaces();
+        drop(data);
+        drop(aof);
+
+        let inner = self.inner.clone();
+        std::thread::spawn(move || {
+            let written = aof::write_base(&temp_path, &keyspaces);
+            let result = match inner.aof.lock().unwrap().as_mut() {
+                Some(aof) => aof.finish_rewrite(written),
+                None => std::fs::remove_file(&temp_path),
+            };
+            if let Err(err) = result {
+                error!("Background AOF rewrite failed: {err}");
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Data {
+    fn keyspace(&self, index: usize) -> &Keyspace {
+        &self.databases[index].keyspace
+    }
+
+    fn keyspace_mut(&mut self, index: usize) -> &mut Keyspace {
+        &mut self.databases[index].keyspace
+    }
+
+    /// Copies of the keyspaces, e.g. for a snapshot. Values are mostly `Bytes`, so cloning
+    /// them doesn't copy their content.
+    fn keyspaces(&self) -> Vec<Keyspace> {
+        self.databases
+            .iter()
+            .map(|database| database.keyspace.clone())
+            .collect()
+    }
+
+    /// Replaces the keyspaces with those loaded from a snapshot. Blocked clients keep waiting.
+    fn replace_keyspaces(&mut self, keyspaces: Vec<Keyspace>) {
+        for (database, keyspace) in self.databases.iter_mut().zip(keyspaces) {
+            database.keyspace = keyspace;
+        }
+    }
+
+    fn changes(&self) -> u64 {
+        self.databases
+            .iter()
+            .map(|database| database.keyspace.changes())
+            .sum()
+    }
+
+    fn used_memory(&mut self) -> usize {
+        self.databases
+            .iter_mut()
+            .map(|database| database.keyspace.used_memory())
+            .sum()
+    }
+
+    fn record_events(&mut self) {
+        for database in &mut self.databases {
+            database.keyspace.record_events(true);
+        }
+    }
+
+    /// What happened to keys in every database since the events started to be recorded,
+    /// which they no longer are.
+    fn take_events(&mut self) -> Vec<(usize, KeyEvent, String)> {
+        let mut events = vec![];
+        for (index, database) in self.databases.iter_mut().enumerate() {
+            let keyspace = &mut database.keyspace;
+            events.extend(
+                keyspace
+                    .take_events()
+                    .into_iter()
+                    .map(|(event, key)| (index, event, key)),
+            );
+            keyspace.record_events(false);
+        }
+        events
+    }
+
+    /// Returns whether the key was given the deadline `at`, and whether that is now the
+    /// first deadline in the database, which the background task has to be told about.
+    fn expire(
+        &mut self,
+        index: usize,
+        key: &str,
+        at: DateTime<Utc>,
+        condition: ExpireCondition,
+    ) -> (bool, bool) {
+        if !self.keyspace(index).contains_key(key) {
+            return (false, false);
+        }
+
+        // A key without a time to live is treated as if it never expires.
+        let current = self.keyspace(index).deadline(key);
+        let allowed = match condition {
+            ExpireCondition::Always => true,
+            ExpireCondition::Nx => current.is_none(),
+            ExpireCondition::Xx => current.is_some(),
+            ExpireCondition::Gt => current.is_some_and(|current| at > current),
+            ExpireCondition::Lt => current.is_none_or(|current| at < current),
+        };
+        if !allowed {
+            return (false, false);
+        }
+
+        if at <= Utc::now() {
+            self.keyspace_mut(index).remove(key);
+            self.propagate(
+                index,
+                Frame::new_command([Bytes::from_static(b"DEL"), Bytes::from(key.to_owned())]),
+            );
+            return (true, false);
+        }
+
+        self.propagate(index, pexpireat(key, at));
+        let keyspace = self.keyspace_mut(index);
+        let when = keyspace.expire_at(key, at);
+        (true, keyspace.next_expiry() == Some(when))
+    }
+
+    fn persist(&mut self, index: usize, key: &str) -> bool {
+        let keyspace = self.keyspace_mut(index);
+        let persisted = keyspace.contains_key(key) && keyspace.clear_expiry(key);
+        if persisted {
+            self.propagate(
+                index,
+                Frame::new_command([Bytes::from_static(b"PERSIST"), Bytes::from(key.to_owned())]),
+            );
+        }
+        persisted
+    }
+
+    /// Records a change made to the database numbered `index`.
+    fn propagate(&mut self, index: usize, command: Frame) {
+        let Some(propagated) = &mut self.propagated else {
+            return;
+        };
+        if self.propagated_db != Some(index) {
+            propagated.push(select_command(index));
+            self.propagated_db = Some(index);
+        }
+        propagated.push(command);
+    }
+
+    fn propagate_pop(&mut self, index: usize, key: &str, from: ListEnd) {
+        let command = match from {
+            ListEnd::Left => "LPOP",
+            ListEnd::Right => "RPOP",
+        };
+        self.propagate(
+            index,
+            Frame::new_command([
+                Bytes::from_static(command.as_bytes()),
+                Bytes::from(key.to_owned()),
+            ]),
+        );
+    }
+
+    fn propagate_push(&mut self, index: usize, key: &str, value: &Bytes, to: ListEnd) {
+        let command = match to {
+            ListEnd::Left => "LPUSH",
+            ListEnd::Right => "RPUSH",
+        };
+        self.propagate(
+            index,
+            Frame::new_command([
+                Bytes::from_static(command.as_bytes()),
+                Bytes::from(key.to_owned()),
+                value.clone(),
+            ]),
+        );
+    }
+
+    fn push(
+        &mut self,
+        index: usize,
+        key: &str,
+        values: Vec<Bytes>,
+        to: ListEnd,
+    ) -> io::Result<usize> {
+        let keyspace = self.keyspace_mut(index);
+        let Value::List(list) =
+            keyspace.get_or_insert_with(key.to_owned(), || Value::List(VecDeque::new()))
+        else {
+            return Err(wrong_type());
+        };
+        for value in values {
+            match to {
+                ListEnd::Left => list.push_front(value),
+                ListEnd::Right => list.push_back(value),
+            }
+        }
+        let len = list.len();
+        keyspace.touch(key);
+        Ok(len)
+    }
+
+    fn pop(&mut self, index: usize, key: &str, from: ListEnd) -> Option<Bytes> {
+        let keyspace = self.keyspace_mut(index);
+        let Some(Value::List(list)) = keyspace.get_mut(key) else {
+            return None;
+        };
+        let value = match from {
+            ListEnd::Left => list.pop_front(),
+            ListEnd::Right => list.pop_back(),
+        };
+        if list.is_empty() {
+            keyspace.remove(key);
+        } else {
+            keyspace.touch(key);
+        }
+        value
+    }
+
+    /// Hands the values of the list at `key` to the clients blocked on it, oldest first.
+    /// Values moved by `BLMOVE` can in turn unblock clients waiting on the destination list.
+    fn serve_blocked_clients(&mut self, index: usize, key: String) {
+        let mut keys = vec![key];
+        while let Some(key) = keys.pop() {
+            while matches!(self.keyspace(index).get(&key), Some(Value::List(list)) if !list.is_empty())
+            {
+                let Some(client) = self.databases[index].blocked.next(&key) else {
+                    break;
+                };
+
+                if let Some((destination, _)) = &client.destination {
+                    // Dropping the client's sender lets it know the move failed.
+                    if matches!(self.keyspace(index).get(destination), Some(value) if !matches!(value, Value::List(_)))
+                    {
+                        continue;
+                    }
+                }
+
+                let value = self
+                    .pop(index, &key, client.from)
+                    .expect("the list is not empty");
+                self.propagate_pop(index, &key, client.from);
+                if let Some((destination, to)) = client.destination {
+                    let _ = self.push(index, &destination, vec![value.clone()], to);
+                    self.propagate_push(index, &destination, &value, to);
+                    keys.push(destination);
+                }
+                let _ = client.sender.send((key.clone(), value));
+            }
+        }
+    }
+
+    /// Reads the stream at `key` from the given position, for `group` if set. The consumer
+    /// reading is created if needed, and the entries delivered become pending, which is
+    /// appended to the AOF as `XCLAIM`s of the entries, see [`xclaim_command`].
+    fn read_stream(
+        &mut self,
+        index: usize,
+        key: &str,
+        from: ReadFrom,
+        group: Option<&GroupReader>,
+        count: Option<usize>,
+    ) -> io::Result<Entries> {
+        let Some(reader) = group else {
+            let ReadFrom::After(after) = from else {
+                unreachable!("only groups read new entries");
+            };
+            let entries = match self.keyspace(index).get(key) {
+                Some(Value::Stream(stream)) => stream
+                    .after(after)
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|(id, fields)| (*id, Some(fields.clone())))
+                    .collect(),
+                _ => vec![],
+            };
+            return Ok(entries);
+        };
+
+        let no_such_group = || no_group(key, &reader.group);
+        let Some(Value::Stream(stream)) = self.keyspace_mut(index).get_mut(key) else {
+            return Err(no_such_group());
+        };
+        let (group, consumer) = (&reader.group, &reader.consumer);
+        let created = stream
+            .group(group)
+            .is_some_and(|group| !group.consumers().contains_key(consumer));
+        let now = Utc::now();
+        let entries = match from {
+            ReadFrom::After(after) => stream
+                .pending_for(group, consumer, after, count, now)
+                .ok_or_else(no_such_group)?,
+            _ => stream
+                .deliver(group, consumer, count, reader.no_ack, now)
+                .ok_or_else(no_such_group)?
+                .into_iter()
+                .map(|(id, fields)| (id, Some(fields)))
+                .collect(),
+        };
+
+        let group_state = stream.group(group).expect("the group exists");
+        let last_delivered = group_state.last_delivered();
+        let mut commands = vec![];
+        if created {
+            commands.push(xgroup_command(
+                b"CREATECONSUMER",
+                key,
+                group,
+                consumer.clone(),
+            ));
+        }
+        if from == ReadFrom::New && !entries.is_empty() {
+            if reader.no_ack {
+                commands.push(xgroup_command(
+                    b"SETID",
+                    key,
+                    group,
+                    last_delivered.to_bytes(),
+                ));
+            } else {
+                for (id, _) in &entries {
+                    let pending = &group_state.pending()[id];
+                    commands.push(xclaim_command(key, group, *id, pending, last_delivered));
+                }
+            }
+        }
+        if !commands.is_empty() {
+            self.keyspace_mut(index).touch(key);
+        }
+        for command in commands {
+            self.propagate(index, command);
+        }
+        Ok(entries)
+    }
+
+    /// Hands the entries added to the stream at `key` to the clients blocked reading it,
+    /// oldest first. Those for which there is nothing to read, e.g. because other consumers of
+    /// their group were given the entries, keep waiting.
+    fn serve_blocked_readers(&mut self, index: usize, key: &str) {
+        for id in self.databases[index].blocked_readers.waiting_on(key) {
+            let reader = self.databases[index]
+                .blocked_readers
+                .get(id)
+                .expect("the client is blocked");
+            let from = match reader.after.get(key) {
+                Some(after) => ReadFrom::After(*after),
+                None => ReadFrom::New,
+            };
+            let (group, count) = (reader.group.clone(), reader.count);
+            let read = match self.read_stream(index, key, from, group.as_ref(), count) {
+                Ok(entries) if entries.is_empty() => continue,
+                read => read,
+            };
+            let reader = self.databases[index]
+                .blocked_readers
+                .remove(id)
+                .expect("the client is blocked");
+            let _ = reader
+                .sender
+                .send(read.map(|entries| (key.to_owned(), entries)));
+        }
+    }
+}
+
+pub enum BlockingPop {
+    Ready(String, Bytes),
+    Blocked(BlockedPop),
+}
+
+pub enum BlockingRead {
+    Ready(Vec<(String, Entries)>),
+    Blocked(BlockedRead),
+}
+
+/// A client blocked until entries are added to one of the streams it is reading.
+/// The client is unregistered from the wait-queue when this is dropped.
+pub struct BlockedRead {
+    id: u64,
+    // The database the client is blocked in.
+    index: usize,
+    receiver: oneshot::Receiver<io::Result<(String, Entries)>>,
+    db: Arc<DbInner>,
+}
+
+impl BlockedRead {
+    /// Waits for entries, returning the key of the stream they were read from.
+    /// Returns `None` if the timeout elapses first; no timeout means waiting forever.
+    pub async fn wait(
+        mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(String, Entries)>> {
+        let received = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut self.receiver).await.ok(),
+            None => Some((&mut self.receiver).await),
+        };
+
+        match received {
+            Some(Ok(read)) => read.map(Some),
+            Some(Err(_)) => Err(io::Error::other("ERR the blocked client was unregistered")),
+            None => {
+                // Entries could have been handed to us right as the timeout elapsed, see
+                // `BlockedPop::wait`.
+                self.unblock();
+                self.receiver.try_recv().ok().transpose()
+            }
+        }
+    }
+
+    fn unblock(&self) {
+        self.db.data.write().unwrap().databases[self.index]
+            .blocked_readers
+            .remove(self.id);
+    }
+}
+
+impl Drop for BlockedRead {
+    fn drop(&mut self) {
+        self.unblock();
+    }
+}
+
+/// A client blocked until a value is pushed to one of the lists it is waiting on.
+/// The client is unregistered from the wait-queue when this is dropped.
+pub struct BlockedPop {
+    id: u64,
+    // The database the client is blocked in.
+    index: usize,
+    receiver: oneshot::Receiver<(String, Bytes)>,
+    db: Arc<DbInner>,
+}
+
+impl BlockedPop {
+    /// Waits for a value, returning the key it was popped from.
+    /// Returns `None` if the timeout elapses first; no timeout means waiting forever.
+    pub async fn wait(mut self, timeout: Option<Duration>) -> io::Result<Option<(String, Bytes)>> {
+        let received = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut self.receiver).await.ok(),
+            None => Some((&mut self.receiver).await),
+        };
+
+        match received {
+            Some(Ok(value)) => Ok(Some(value)),
+            // The sender is dropped without a value when the destination of a move holds the wrong type.
+            Some(Err(_)) => Err(wrong_type()),
+            None => {
+                // A push could have served us right as the timeout elapsed. Once we are
+                // unregistered nothing else can be sent, so this is the last chance to get it.
+                self.unblock();
+                Ok(self.receiver.try_recv().ok())
+            }
+        }
+    }
+
+    fn unblock(&self) {
+        self.db.data.write().unwrap().databases[self.index]
+            .blocked
+            .remove(self.id);
+    }
+}
+
+impl Drop for BlockedPop {
+    fn drop(&mut self) {
+        self.unblock();
+    }
+}
+
+impl DbInner {
+    fn is_shutdown(&self) -> bool {
+        self.data.read().unwrap().shutdown
+    }
+
+    /// Signals the purge background task to shut down. This is called once the last `Db`
+    /// handle is dropped.
+    fn shutdown_purge_task(&self) {
+        let mut state = self.data.write().unwrap();
+        state.shutdown = true;
+        drop(state);
+
+        self.background_task.notify_one();
+    }
+
+    /// Takes a snapshot of the databases, to be written to the path that is returned.
+    ///
+    /// The keyspaces are cloned, so writers are only held up for as long as that takes.
+    fn start_snapshot(&self) -> io::Result<(PathBuf, Vec<Keyspace>)> {
+        let path = self.config.read().unwrap().rdb_path();
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if snapshots.in_progress {
+            return Err(io::Error::other("ERR Background save already in progress"));
+        }
+        snapshots.in_progress = true;
+        snapshots.last_attempt = Utc::now();
+        let keyspaces = self.data.read().unwrap().keyspaces();
+        Ok((path, keyspaces))
+    }
+
+    /// `changes` is the number of changes the saved keyspaces had been through.
+    fn finish_snapshot(&self, result: io::Result<()>, changes: u64) -> io::Result<()> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.in_progress = false;
+        snapshots.last_ok = result.is_ok();
+        if result.is_ok() {
+            snapshots.last_save = Utc::now();
+            snapshots.saved_changes = changes;
+        }
+        result
+    }
+
+    fn background_save(self: &Arc<Self>) -> io::Result<()> {
+        let (path, keyspaces) = self.start_snapshot()?;
+        let inner = self.clone();
+        std::thread::spawn(move || {
+            let written = write_snapshot(&path, &keyspaces);
+            if let Err(err) = inner.finish_snapshot(written, total_changes(&keyspaces)) {
+                error!("Background saving failed: {err}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Whether one of the `save` rules is met. Like redis, a failed snapshot is only retried
+    /// after a few seconds.
+    fn is_snapshot_due(&self) -> bool {
+        let rules = self.config.read().unwrap().save.clone();
+        let changes = self.data.read().unwrap().changes();
+        let snapshots = self.snapshots.lock().unwrap();
+        let now = Utc::now();
+        if snapshots.in_progress
+            || (!snapshots.last_ok && now - snapshots.last_attempt < chrono::Duration::seconds(5))
+        {
+            return false;
+        }
+        let unsaved = changes.saturating_sub(snapshots.saved_changes);
+        let elapsed = (now - snapshots.last_save).num_seconds().max(0) as u64;
+        rules
+            .iter()
+            .any(|rule| unsaved >= rule.changes && elapsed >= rule.seconds)
+    }
+
+    fn purge_expired_keys(&self) -> Option<Instant> {
+        let notifications = self.start_recording();
+        let mut data = self.data.write().unwrap();
+        if data.shutdown {
+            return None;
+        }
+        let now = Instant::now();
+        let next = data
+            .databases
+            .iter_mut()
+            .filter_map(|database| database.keyspace.purge_expired(now))
+            .min();
+        if notifications.is_some() {
+            let events = data.take_events();
+            drop(data);
+            self.notify(events, None, None);
+        }
+        next
+    }
+
+    /// Starts recording what happens to keys if anyone is to be told about it, in which case
+    /// the returned guard has to be held until the events are taken.
+    fn start_recording(&self) -> Option<MutexGuard<'_, ()>> {
+        let notified = !self
+            .config
+            .read()
+            .unwrap()
+            .notify_keyspace_events
+            .is_empty();
+        if !notified && self.tracking.is_empty() {
+            return None;
+        }
+        let notifications = self.notifications.lock().unwrap();
+        self.data.write().unwrap().record_events();
+        Some(notifications)
+    }
+
+    /// Publishes the keyspace notifications of the events, for keys changed by `command` if
+    /// any, and tells the clients tracking the keys about them, see [`Db::notify_writes`].
+    fn notify(
+        &self,
+        events: Vec<(usize, KeyEvent, String)>,
+        command: Option<&Frame>,
+        client: Option<u64>,
+    ) {
+        let notified = self.config.read().unwrap().notify_keyspace_events;
+        // A key changed several times by the command is only notified once.
+        let mut seen = HashSet::new();
+        let mut keys = vec![];
+        for (index, event, key) in events {
+            if !seen.insert((index, event, key.clone())) {
+                continue;
+            }
+            keys.push(key.clone());
+            let (class, name) = match event {
+                KeyEvent::Changed => {
+                    match command.and_then(|command| notify::command_event(command, &key)) {
+                        Some(event) => event,
+                        None => continue,
+                    }
+                }
+                KeyEvent::Expired => ('x', "expired".to_owned()),
+                KeyEvent::Evicted => ('e', "evicted".to_owned()),
+            };
+            notify::publish(&self.pubsub, notified, index, (class, &name), &key);
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        self.tracking.invalidate(&keys, client);
+    }
+}
+
+/// Parses a value that is used as an integer, e.g. by `INCR` or `HINCRBY`, the way redis does:
+/// only its canonical form is accepted, without a sign for positive numbers, spaces or leading
+/// zeros.
+pub fn parse_integer(value: &[u8]) -> Option<i64> {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    let canonical = match digits {
+        [] => false,
+        [b'0'] => digits.len() == value.len(),
+        [first, ..] => *first != b'0' && digits.iter().all(u8::is_ascii_digit),
+    };
+    if !canonical {
+        return None;
+    }
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// The options of `SET` and its variants.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetOptions {
+    pub condition: SetCondition,
+    /// How long until the key expires.
+    pub expire: Option<Duration>,
+    /// Whether the key keeps the time to live it had, when `expire` isn't set.
+    pub keep_ttl: bool,
+    /// Whether the previous value is returned, which fails if it isn't a string.
+    pub get: bool,
+}
+
+/// When `SET` writes the key.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    /// Only when the key does not exist.
+    Nx,
+    /// Only when the key already exists.
+    Xx,
+}
+
+/// The conditions that `EXPIRE` and its variants accept.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ExpireCondition {
+    #[default]
+    Always,
+    /// Only when the key has no expiry.
+    Nx,
+    /// Only when the key already has an expiry.
+    Xx,
+    /// Only when the new expiry is greater than the current one.
+    Gt,
+    /// Only when the new expiry is less than the current one.
+    Lt,
+}
+
+/// The sets stored at each of `keys`, `None` for the keys that do not exist. Fails if any of the
+/// keys holds another kind of value.
+fn sets<'a, 'k>(
+    data: &'a Keyspace,
+    keys: impl IntoIterator<Item = &'k str>,
+) -> io::Result<Vec<Option<&'a HashSet<Bytes>>>> {
+    keys.into_iter()
+        .map(|key| match data.get(key) {
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(wrong_type()),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+pub(crate) fn wrong_type() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, WRONG_TYPE)
+}
+
+async fn purge_expired_tasks(shared: Arc<DbInner>) {
+    while !shared.is_shutdown() {
+        if let Some(when) = shared.purge_expired_keys() {
+            tokio::select! {
+                _ = tokio::time::sleep_until(when.into()) => {}
+                _ = shared.background_task.notified() => {}
+            }
+        } else {
+            shared.background_task.notified().await;
+        }
+    }
+
+    debug!("Background task is shutting down");
+}
+
+/// Saves a snapshot in the background whenever one of the `save` rules is met.
+async fn save_on_changes(shared: Arc<DbInner>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    while !shared.is_shutdown() {
+        interval.tick().await;
+        if shared.is_snapshot_due() {
+            if let Err(err) = shared.background_save() {
+                error!("Background saving failed: {err}");
+            }
+        }
+    }
+}
+
+/// Like redis, the master pings its replicas every 10 seconds through the replication stream,
+/// so that they can tell the link is still up when there are no writes.
+async fn ping_replicas(shared: Arc<DbInner>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(10));
+    while !shared.is_shutdown() {
+        interval.tick().await;
+        let mut replication = shared.replication.lock().unwrap();
+        if replication.master().is_none() && !replication.replicas().is_empty() {
+            replication.feed(&[Frame::new_command([Bytes::from_static(b"PING")])]);
+        }
+    }
+}
+
+/// Syncs the AOF to disk once per second. This is done outside of the lock on the AOF,
+/// so that write commands don't have to wait for the disk.
+fn fsync_aof_every_second(shared: Arc<DbInner>) {
+    while !shared.is_shutdown() {
+        std::thread::sleep(Duration::from_secs(1));
+        // The AOF can be disabled, or its policy changed, with `CONFIG SET`.
+        let file = match shared.aof.lock().unwrap().as_ref() {
+            Some(aof) if aof.fsync() == AppendFsync::EverySec => aof.file(),
+            _ => continue,
+        };
+        if let Err(err) = file.and_then(|file| file.sync_data()) {
+            error!("Failed to fsync the AOF: {err}");
+        }
+    }
+}
+
+fn select_command(index: usize) -> Frame {
+    Frame::new_command([
+        Bytes::from_static(b"SELECT"),
+        Bytes::from(index.to_string()),
+    ])
+}
+
+pub(crate) fn no_group(key: &str, group: &[u8]) -> io::Error {
+    let group = String::from_utf8_lossy(group);
+    io::Error::other(format!(
+        "NOGROUP No such key '{key}' or consumer group '{group}'"
+    ))
+}
+
+/// An `XGROUP` subcommand taking a single argument after the key and the group.
+fn xgroup_command(subcommand: &'static [u8], key: &str, group: &Bytes, argument: Bytes) -> Frame {
+    Frame::new_command([
+        Bytes::from_static(b"XGROUP"),
+        Bytes::from_static(subcommand),
+        Bytes::from(key.to_owned()),
+        group.clone(),
+        argument,
+    ])
+}
+
+/// Makes an entry pending for a consumer the way it is now, whether or not it was pending
+/// before, and moves the last delivered ID of the group along. This is how redis propagates
+/// the entries it delivers.
+fn xclaim_command(
+    key: &str,
+    group: &Bytes,
+    id: StreamId,
+    pending: &PendingEntry,
+    last_delivered: StreamId,
+) -> Frame {
+    Frame::new_command([
+        Bytes::from_static(b"XCLAIM"),
+        Bytes::from(key.to_owned()),
+        group.clone(),
+        pending.consumer.clone(),
+        Bytes::from_static(b"0"),
+        id.to_bytes(),
+        Bytes::from_static(b"TIME"),
+        Bytes::from(pending.delivered_at.timestamp_millis().to_string()),
+        Bytes::from_static(b"RETRYCOUNT"),
+        Bytes::from(pending.deliveries.to_string()),
+        Bytes::from_static(b"FORCE"),
+        Bytes::from_static(b"JUSTID"),
+        Bytes::from_static(b"LASTID"),
+        last_delivered.to_bytes(),
+    ])
+}
+
+fn pexpireat(key: &str, at: DateTime<Utc>) -> Frame {
+    Frame::new_command([
+        Bytes::from_static(b"PEXPIREAT"),
+        Bytes::from(key.to_owned()),
+        Bytes::from(at.timestamp_millis().to_string()),
+    ])
+}
+
+/// The snapshot is written to a temporary file first, so that a failure halfway through
+/// doesn't leave us with a truncated snapshot.
+fn write_snapshot(path: &Path, keyspaces: &[Keyspace]) -> io::Result<()> {
+    let temp_path = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    rdb::write(BufWriter::new(File::create(&temp_path)?), keyspaces)?;
+    std::fs::rename(&temp_path, path)
+}
+
+fn total_changes(keyspaces: &[Keyspace]) -> u64 {
+    keyspaces.iter().map(Keyspace::changes).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use bytes::Bytes;
+
+    use crate::frame::Frame;
+
+    use super::SetOptions;
+
+    #[tokio::test]
+    async fn test_key_expiry() {
+        let db = super::Db::default();
+        let value = Bytes::from("value");
+        db.set(
+            "key".to_owned(),
+            value.clone(),
+            SetOptions {
+                expire: Some(Duration::from_secs(1)),
+                ..SetOptions::default()
+            },
+        )
+        .unwrap();
+        let result = db.with_data_mut(|data| data.get("key").cloned()).unwrap();
+        assert_eq!(result, super::Value::String(value));
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let result = db.with_data_mut(|data| data.get("key").cloned());
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_commands_are_not_propagated() {
+        let db = super::Db::default();
+        db.inner.data.write().unwrap().propagated = Some(vec![]);
+        let command = |name: &'static str| Frame::new_command([Bytes::from(name)]);
+
+        let result = db.log_verbatim(command("FAILS"), || {
+            db.log_command(command("EFFECT"));
+            Err::<(), _>(io::Error::other("ERR no such key"))
+        });
+        assert!(result.is_err());
+        db.log_verbatim(command("SUCCEEDS"), || {
+            db.log_command(command("EFFECT"));
+            Ok(())
+        })
+        .unwrap();
+
+        let propagated = db.inner.data.write().unwrap().propagated.take();
+        assert_eq!(
+            propagated.unwrap(),
+            [
+                super::select_command(0),
+                command("EFFECT"),
+                command("SUCCEEDS"),
+                command("EFFECT"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range_expiry_is_rejected() {
+        let db = super::Db::default();
+        let result = db.set(
+            "key".to_owned(),
+            Bytes::from("value"),
+            SetOptions {
+                expire: Some(Duration::from_millis(i64::MAX as u64)),
+                ..SetOptions::default()
+            },
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "ERR invalid expire time in 'set' command"
+        );
+        assert!(db.with_data(|data| data.is_empty()));
+
+        // The lock isn't poisoned by the failed write.
+        db.set(
+            "key".to_owned(),
+            Bytes::from("value"),
+            SetOptions::default(),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_overwriting_a_key_clears_its_expiry() {
+        let db = super::Db::default();
+        db.set(
+            "key".to_owned(),
+            Bytes::from("old"),
+            SetOptions {
+                expire: Some(Duration::from_millis(100)),
+                ..SetOptions::default()
+            },
+        )
+        .unwrap();
+        db.set("key".to_owned(), Bytes::from("new"), SetOptions::default())
+            .unwrap();
+        assert_eq!(db.ttl("key"), Some(None));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let result = db.with_data(|data| data.get("key").cloned());
+        assert_eq!(result, Some(super::Value::String(Bytes::from("new"))));
+    }
+
+    #[tokio::test]
+    async fn test_databases_are_independent() {
+        let db = super::Db::default();
+        let other = db.select(1);
+        db.set(
+            "key".to_owned(),
+            Bytes::from("first"),
+            SetOptions::default(),
+        )
+        .unwrap();
+        other
+            .set(
+                "key".to_owned(),
+                Bytes::from("second"),
+                SetOptions::default(),
+            )
+            .unwrap();
+
+        other.rename("key", "renamed".to_owned(), false).unwrap();
+        assert!(db.with_data(|data| data.contains_key("key")));
+        assert!(other.with_data(|data| data.contains_key("renamed")));
+
+        db.flush(false);
+        assert!(db.with_data(|data| data.is_empty()));
+        assert!(!other.with_data(|data| data.is_empty()));
+        db.flush(true);
+        assert!(other.with_data(|data| data.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_list_data_type() {
+        let db = super::Db::default();
+        db.set(
+            "string".to_owned(),
+            Bytes::from("value"),
+            SetOptions::default(),
+        )
+        .unwrap();
+        let result = db.with_list_data_mut("string".to_owned(), |list| (list.len(), false));
+        assert_eq!(result.unwrap_err().to_string(), super::WRONG_TYPE);
+
+        let len = db
+            .with_list_data_mut("list".to_owned(), |list| {
+                list.push_back(Bytes::from("a"));
+                (list.len(), true)
+            })
+            .unwrap();
+        assert_eq!(len, 1);
+
+        db.with_list_data_mut("list".to_owned(), |list| (list.pop_front(), true))
+            .unwrap();
+        let exists = db.with_data(|data| data.contains_key("list"));
+        assert!(!exists, "empty lists are removed from the keyspace");
+    }
+
+    #[tokio::test]
+    async fn test_blocked_clients_are_served_in_order() {
+        use super::{BlockingPop, ListEnd};
+
+        let db = super::Db::default();
+        let block = |keys: &[&str]| {
+            let keys = keys.iter().map(|key| key.to_string()).collect();
+            match db.pop_or_block(keys, ListEnd::Left, None).unwrap() {
+                BlockingPop::Blocked(blocked) => blocked,
+                BlockingPop::Ready(..) => panic!("the lists are empty"),
+            }
+        };
+        let first = block(&["a", "b"]);
+        let second = block(&["b"]);
+        let timed_out = block(&["c"]);
+
+        let len = db
+            .push(
+                "b".to_owned(),
+                vec![Bytes::from("1"), Bytes::from("2")],
+                ListEnd::Right,
+            )
+            .unwrap();
+        assert_eq!(len, 2);
+
+        let first = first.wait(None).await.unwrap();
+        assert_eq!(first, Some(("b".to_owned(), Bytes::from("1"))));
+        let second = second.wait(None).await.unwrap();
+        assert_eq!(second, Some(("b".to_owned(), Bytes::from("2"))));
+        let timed_out = timed_out.wait(Some(Duration::from_millis(10))).await;
+        assert_eq!(timed_out.unwrap(), None);
+
+        db.push("c".to_owned(), vec![Bytes::from("3")], ListEnd::Right)
+            .unwrap();
+        let len = db.with_list_data("c", |list| list.map(|list| list.len()));
+        assert_eq!(
+            len.unwrap(),
+            Some(1),
+            "clients that timed out are no longer served"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Keyspace notifications: the changes made to keys are published to pub/sub channels, e.g.
+//! `__keyspace@0__:mykey` gets `set` once `mykey` is set in the first database, and
+//! `__keyevent@0__:set` gets `mykey`, as `notify-keyspace-events` allows.
+//!
+//! Unlike redis, whose commands fire their events one by one, the keys a command changes are
+//! recorded by the [`Keyspace`], and a command fires a single event per key it changed, named
+//! after the command. See [`Db::notify_writes`].
+//!
+//! [`Keyspace`]: super::Keyspace
+//! [`Db::notify_writes`]: super::Db::notify_writes
+
+use bytes::Bytes;
+
+use crate::{acl, config::KeyspaceEvents, frame::Frame, pubsub::PubSub};
+
+/// The class and name of the event for a key the command changed, which is mostly the name of
+/// the command, the way redis names them, e.g. `incrby` for `INCR` or `rename_to` for the new
+/// name of a key renamed with `RENAME`.
+pub(super) fn command_event(command: &Frame, key: &str) -> Option<(char, String)> {
+    let Frame::Array(args) = command else {
+        return None;
+    };
+    let arg = |index: usize| match args.get(index) {
+        Some(Frame::BulkString(arg)) => String::from_utf8_lossy(arg).to_lowercase(),
+        _ => String::new(),
+    };
+    let name = arg(0);
+    // For the commands moving a value from a key to another, whether it is the first one.
+    let is_source = || {
+        acl::command_keys(command)
+            .first()
+            .is_some_and(|source| source == key.as_bytes())
+    };
+    let end = |arg: String| if arg == "left" { 'l' } else { 'r' };
+
+    let event = match name.as_str() {
+        "setnx" | "setex" | "psetex" | "getset" | "mset" | "msetnx" => "set".to_owned(),
+        "incr" | "decr" | "decrby" => "incrby".to_owned(),
+        "getdel" | "migrate" => "del".to_owned(),
+        "getex" if args.len() > 2 => {
+            let persist = (2..args.len()).any(|index| arg(index) == "persist");
+            if persist { "persist" } else { "expire" }.to_owned()
+        }
+        "pexpire" | "expireat" | "pexpireat" => "expire".to_owned(),
+        "rename" | "renamenx" if is_source() => "rename_from".to_owned(),
+        "rename" | "renamenx" => "rename_to".to_owned(),
+        "smove" if is_source() => "srem".to_owned(),
+        "smove" => "sadd".to_owned(),
+        "blpop" => "lpop".to_owned(),
+        "brpop" => "rpop".to_owned(),
+        "blmove" if is_source() => format!("{}pop", end(arg(3))),
+        "blmove" => format!("{}push", end(arg(4))),
+        "zincrby" => "zincr".to_owned(),
+        "xgroup" => format!("xgroup-{}", arg(1)),
+        "xreadgroup" => "xgroup-createconsumer".to_owned(),
+        "restore-asking" => "restore".to_owned(),
+        _ => name.clone(),
+    };
+    let class = acl::command_categories(&name)
+        .iter()
+        .find_map(|category| match *category {
+            "keyspace" => Some('g'),
+            "string" => Some('$'),
+            "list" => Some('l'),
+            "set" => Some('s'),
+            "hash" => Some('h'),
+            "sortedset" => Some('z'),
+            "stream" => Some('t'),
+            _ => None,
+        })?;
+    Some((class, event))
+}
+
+/// Publishes the event of the class that happened to the key in the database numbered `index`,
+/// if `events` allows it.
+pub(super) fn publish(
+    pubsub: &PubSub,
+    events: KeyspaceEvents,
+    index: usize,
+    (class, event): (char, &str),
+    key: &str,
+) {
+    if !events.is_enabled(class) {
+        return;
+    }
+    if events.keyspace() {
+        let channel = format!("__keyspace@{index}__:{key}");
+        pubsub.publish(Bytes::from(channel), Bytes::from(event.to_owned()));
+    }
+    if events.keyevent() {
+        let channel = format!("__keyevent@{index}__:{event}");
+        pubsub.publish(Bytes::from(channel), Bytes::from(key.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use rstest::rstest;
+
+    use super::command_event;
+    use crate::frame::Frame;
+
+    #[rstest]
+    #[case(&["SET", "key", "value"], "key", Some(('$', "set")))]
+    #[case(&["incr", "key"], "key", Some(('$', "incrby")))]
+    #[case(&["DEL", "a", "key"], "key", Some(('g', "del")))]
+    #[case(&["RENAME", "key", "new"], "key", Some(('g', "rename_from")))]
+    #[case(&["RENAME", "key", "new"], "new", Some(('g', "rename_to")))]
+    #[case(&["BLMOVE", "a", "b", "LEFT", "RIGHT", "0"], "b", Some(('l', "rpush")))]
+    #[case(&["XGROUP", "CREATE", "key", "group", "$"], "key", Some(('t', "xgroup-create")))]
+    #[case(&["GETEX", "key", "PERSIST"], "key", Some(('$', "persist")))]
+    #[case(&["PING"], "key", None)]
+    fn test_command_event(
+        #[case] command: &[&str],
+        #[case] key: &str,
+        #[case] expected: Option<(char, &str)>,
+    ) {
+        let command = Frame::new_command(command.iter().map(|arg| Bytes::from(arg.to_string())));
+        assert_eq!(
+            command_event(&command, key),
+            expected.map(|(class, event)| (class, event.to_owned()))
+        );
+    }
+}
@@ -0,0 +1,1238 @@
+//! Snapshots in the RDB format, so that dumps can be exchanged with a genuine redis server.
+//!
+//! We write every value with the plain encodings that redis has always been able to load,
+//! and read the compact ones (ziplists, listpacks, intsets and quicklists) that newer
+//! versions of redis produce. Streams have no plain encoding, so they are written as listpacks
+//! the way redis 5 does.
+//!
+//! See https://rdb.fnordig.de/file_format.html for a description of the format.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Read, Write},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+use super::{
+    stream::{Consumer, PendingEntry},
+    Fields, Keyspace, NewId, SortedSet, Stream, StreamId, Value,
+};
+
+const MAGIC: &[u8] = b"REDIS";
+/// The version we write. Redis 5 and later can load it.
+const VERSION: u32 = 9;
+/// The most recent version we know how to read, written by redis 7.4.
+const MAX_VERSION: u32 = 12;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION: u8 = 0xF5;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZE_DB: u8 = 0xFB;
+const OPCODE_EXPIRE_TIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRE_TIME: u8 = 0xFD;
+const OPCODE_SELECT_DB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+// The special encodings of a length prefixed string.
+const ENCODING_INT8: u8 = 0;
+const ENCODING_INT16: u8 = 1;
+const ENCODING_INT32: u8 = 2;
+const ENCODING_LZF: u8 = 3;
+
+// How the nodes of a quicklist are stored.
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+const QUICKLIST_NODE_PACKED: u64 = 2;
+
+// The flags of the entries of a stream node.
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+/// How many entries we put in each node of a stream, redis' default `stream-node-max-entries`.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+/// Writes every key of the databases, along with its time to live. Like redis, empty
+/// databases are left out.
+pub fn write<W: Write>(writer: W, databases: &[Keyspace]) -> io::Result<()> {
+    let mut writer = Writer {
+        inner: writer,
+        crc: 0,
+    };
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(format!("{VERSION:04}").as_bytes())?;
+    writer.write_aux("redis-bits", "64")?;
+    writer.write_aux("ctime", &Utc::now().timestamp().to_string())?;
+
+    for (db, keyspace) in databases.iter().enumerate() {
+        if keyspace.iter().next().is_none() {
+            continue;
+        }
+        let expires = keyspace
+            .iter()
+            .filter(|(key, _)| keyspace.deadline(key).is_some())
+            .count();
+        writer.write_all(&[OPCODE_SELECT_DB])?;
+        writer.write_length(db as u64)?;
+        writer.write_all(&[OPCODE_RESIZE_DB])?;
+        writer.write_length(keyspace.iter().count() as u64)?;
+        writer.write_length(expires as u64)?;
+
+        for (key, value) in keyspace.iter() {
+            if let Some(at) = keyspace.deadline(key) {
+                writer.write_all(&[OPCODE_EXPIRE_TIME_MS])?;
+                writer.write_all(&at.timestamp_millis().to_le_bytes())?;
+            }
+            writer.write_value(key, value)?;
+        }
+    }
+
+    writer.write_all(&[OPCODE_EOF])?;
+    let crc = writer.crc;
+    writer.inner.write_all(&crc.to_le_bytes())?;
+    writer.inner.flush()
+}
+
+/// Reads a snapshot into `databases` keyspaces. Keys that have already expired are left out.
+///
+/// Just like redis, a snapshot with keys in a database beyond those is rejected.
+pub fn read<R: Read>(reader: R, databases: usize) -> io::Result<Vec<Keyspace>> {
+    let mut reader = Reader {
+        inner: reader,
+        crc: 0,
+    };
+
+    let mut magic = [0; 5];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data("Wrong signature trying to load DB from file"));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version: u32 = std::str::from_utf8(&version)
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| invalid_data("Invalid RDB version"))?;
+    if !(1..=MAX_VERSION).contains(&version) {
+        return Err(invalid_data(format!(
+            "Can't handle RDB format version {version}"
+        )));
+    }
+
+    let now = Utc::now();
+    let mut keyspaces: Vec<_> = (0..databases).map(|_| Keyspace::default()).collect();
+    let mut db = 0;
+    let mut expires_at = None;
+    loop {
+        let opcode = reader.read_u8()?;
+        match opcode {
+            OPCODE_EOF => break,
+            OPCODE_SELECT_DB => db = reader.read_length()?,
+            OPCODE_RESIZE_DB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            OPCODE_EXPIRE_TIME => {
+                let seconds = u32::from_le_bytes(reader.read_array()?);
+                expires_at = Some(DateTime::from_timestamp(seconds.into(), 0));
+            }
+            OPCODE_EXPIRE_TIME_MS => {
+                let millis = i64::from_le_bytes(reader.read_array()?);
+                expires_at = Some(DateTime::from_timestamp_millis(millis));
+            }
+            // Eviction hints are of no use to us.
+            OPCODE_IDLE => {
+                reader.read_length()?;
+            }
+            OPCODE_FREQ => {
+                reader.read_u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.read_length()?;
+                }
+            }
+            // Functions are not supported, so their code is skipped.
+            OPCODE_FUNCTION => {
+                reader.read_string()?;
+            }
+            OPCODE_MODULE_AUX => return Err(invalid_data("Modules are not supported")),
+            value_type => {
+                let key = reader.read_string()?;
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|_| invalid_data("Keys must be valid UTF-8"))?;
+                let value = reader.read_value(value_type)?;
+
+                let expires_at = expires_at.take();
+                if value.is_empty_collection() {
+                    continue;
+                }
+                let keyspace = keyspaces.get_mut(db).ok_or_else(|| {
+                    invalid_data(format!(
+                        "The snapshot has keys in database {db}, but there are only {databases}"
+                    ))
+                })?;
+                match expires_at {
+                    Some(Some(at)) if at > now => {
+                        keyspace.insert(key.clone(), value);
+                        keyspace.expire_at(&key, at);
+                    }
+                    Some(_) => {}
+                    None => {
+                        keyspace.insert(key, value);
+                    }
+                }
+            }
+        }
+    }
+
+    // Files written with checksums disabled have a checksum of 0.
+    let expected = reader.crc;
+    if version >= 5 {
+        let checksum = u64::from_le_bytes(reader.read_array()?);
+        if checksum != 0 && checksum != expected {
+            return Err(invalid_data("Wrong RDB checksum"));
+        }
+    }
+
+    Ok(keyspaces)
+}
+
+/// Serializes the value the way `DUMP` does: its type and encoding as in a snapshot, followed
+/// by the RDB version and a checksum, so that a genuine redis server can `RESTORE` it too.
+pub fn dump(value: &Value) -> Vec<u8> {
+    let mut writer = Writer {
+        inner: vec![],
+        crc: 0,
+    };
+    writer
+        .write_all(&[value_type(value)])
+        .and_then(|_| writer.write_payload(value))
+        .and_then(|_| writer.write_all(&(VERSION as u16).to_le_bytes()))
+        .expect("writing to a vector can't fail");
+    let crc = writer.crc;
+    let mut payload = writer.inner;
+    payload.extend_from_slice(&crc.to_le_bytes());
+    payload
+}
+
+/// Deserializes a value serialized by `DUMP`, checking its version and checksum first.
+pub fn restore(payload: &[u8]) -> io::Result<Value> {
+    let wrong = || invalid_data("DUMP payload version or checksum are wrong");
+    let (body, checksum) = payload
+        .split_last_chunk::<8>()
+        .filter(|(body, _)| body.len() > 2)
+        .ok_or_else(wrong)?;
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    if u32::from(version) > MAX_VERSION {
+        return Err(wrong());
+    }
+    if u64::from_le_bytes(*checksum) != crc64(0, body) {
+        return Err(wrong());
+    }
+
+    let mut reader = Reader {
+        inner: &body[..body.len() - 2],
+        crc: 0,
+    };
+    let value = reader
+        .read_u8()
+        .and_then(|value_type| reader.read_value(value_type))
+        .ok()
+        .filter(|_| reader.inner.is_empty())
+        .ok_or_else(|| invalid_data("Bad data format"))?;
+    Ok(value)
+}
+
+struct Writer<W> {
+    inner: W,
+    crc: u64,
+}
+
+impl<W: Write> Writer<W> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.crc = crc64(self.crc, buf);
+        self.inner.write_all(buf)
+    }
+
+    fn write_length(&mut self, length: u64) -> io::Result<()> {
+        if length < 1 << 6 {
+            self.write_all(&[length as u8])
+        } else if length < 1 << 14 {
+            self.write_all(&[(length >> 8) as u8 | 0x40, length as u8])
+        } else if let Ok(length) = u32::try_from(length) {
+            self.write_all(&[0x80])?;
+            self.write_all(&length.to_be_bytes())
+        } else {
+            self.write_all(&[0x81])?;
+            self.write_all(&length.to_be_bytes())
+        }
+    }
+
+    fn write_string(&mut self, string: &[u8]) -> io::Result<()> {
+        self.write_length(string.len() as u64)?;
+        self.write_all(string)
+    }
+
+    fn write_aux(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.write_all(&[OPCODE_AUX])?;
+        self.write_string(key.as_bytes())?;
+        self.write_string(value.as_bytes())
+    }
+
+    fn write_value(&mut self, key: &str, value: &Value) -> io::Result<()> {
+        self.write_all(&[value_type(value)])?;
+        self.write_string(key.as_bytes())?;
+        self.write_payload(value)
+    }
+
+    /// Writes the value without its type, which comes before the key.
+    fn write_payload(&mut self, value: &Value) -> io::Result<()> {
+        match value {
+            Value::String(string) => self.write_string(string),
+            Value::Integer(integer) => self.write_string(integer.to_string().as_bytes()),
+            Value::List(list) => {
+                self.write_length(list.len() as u64)?;
+                list.iter().try_for_each(|item| self.write_string(item))
+            }
+            Value::Set(set) => {
+                self.write_length(set.len() as u64)?;
+                set.iter().try_for_each(|member| self.write_string(member))
+            }
+            Value::Hash(hash) => {
+                self.write_length(hash.len() as u64)?;
+                hash.iter().try_for_each(|(field, value)| {
+                    self.write_string(field)?;
+                    self.write_string(value)
+                })
+            }
+            Value::SortedSet(sorted_set) => {
+                self.write_length(sorted_set.len() as u64)?;
+                sorted_set.iter().try_for_each(|(member, score)| {
+                    self.write_string(member)?;
+                    self.write_all(&score.to_le_bytes())
+                })
+            }
+            Value::Stream(stream) => self.write_stream(stream),
+        }
+    }
+
+    /// The entries are split into nodes, each a listpack keyed by the ID of its first entry,
+    /// followed by the consumer groups with their pending entries.
+    fn write_stream(&mut self, stream: &Stream) -> io::Result<()> {
+        let entries: Vec<_> = stream.range(StreamId::MIN, StreamId::MAX).collect();
+        let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+        self.write_length(nodes.len() as u64)?;
+        for node in nodes {
+            self.write_string(&node[0].0.to_be_bytes())?;
+            self.write_string(&stream_node(node))?;
+        }
+        self.write_length(stream.len() as u64)?;
+        self.write_id(stream.last_id())?;
+
+        self.write_length(stream.groups().count() as u64)?;
+        for (name, group) in stream.groups() {
+            self.write_string(name)?;
+            self.write_id(group.last_delivered())?;
+            self.write_length(group.pending().len() as u64)?;
+            for (id, pending) in group.pending() {
+                self.write_all(&id.to_be_bytes())?;
+                self.write_all(&pending.delivered_at.timestamp_millis().to_le_bytes())?;
+                self.write_length(pending.deliveries)?;
+            }
+            self.write_length(group.consumers().len() as u64)?;
+            for (name, consumer) in group.consumers() {
+                self.write_string(name)?;
+                self.write_all(&consumer.seen_at.timestamp_millis().to_le_bytes())?;
+                let pending: Vec<_> = group
+                    .pending()
+                    .iter()
+                    .filter(|(_, pending)| pending.consumer == name)
+                    .map(|(id, _)| id)
+                    .collect();
+                self.write_length(pending.len() as u64)?;
+                pending
+                    .into_iter()
+                    .try_for_each(|id| self.write_all(&id.to_be_bytes()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_id(&mut self, id: StreamId) -> io::Result<()> {
+        self.write_length(id.ms)?;
+        self.write_length(id.seq)
+    }
+}
+
+struct Reader<R> {
+    inner: R,
+    crc: u64,
+}
+
+impl<R: Read> Reader<R> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)?;
+        self.crc = crc64(self.crc, buf);
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    /// The length comes from the input, so the buffer only grows as far as there are bytes
+    /// to read rather than being allocated up front.
+    fn read_bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        (&mut self.inner).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.crc = crc64(self.crc, &buf);
+        Ok(buf)
+    }
+
+    /// Returns the length, or the special encoding of a string when the flag is set.
+    fn read_length_or_encoding(&mut self) -> io::Result<(u64, bool)> {
+        let first = self.read_u8()?;
+        match first >> 6 {
+            0 => Ok(((first & 0x3F).into(), false)),
+            1 => {
+                let second = self.read_u8()?;
+                Ok(((u64::from(first & 0x3F) << 8) | u64::from(second), false))
+            }
+            2 => match first {
+                0x80 => Ok((u32::from_be_bytes(self.read_array()?).into(), false)),
+                0x81 => Ok((u64::from_be_bytes(self.read_array()?), false)),
+                _ => Err(invalid_data("Unknown length encoding")),
+            },
+            _ => Ok(((first & 0x3F).into(), true)),
+        }
+    }
+
+    fn read_length(&mut self) -> io::Result<usize> {
+        match self.read_length_or_encoding()? {
+            (length, false) => {
+                usize::try_from(length).map_err(|_| invalid_data("Length is too large"))
+            }
+            (_, true) => Err(invalid_data(
+                "Expected a length but found an encoded string",
+            )),
+        }
+    }
+
+    fn read_string(&mut self) -> io::Result<Bytes> {
+        let (length, encoded) = self.read_length_or_encoding()?;
+        if !encoded {
+            let length =
+                usize::try_from(length).map_err(|_| invalid_data("Length is too large"))?;
+            return self.read_bytes(length).map(Bytes::from);
+        }
+
+        let integer = match length as u8 {
+            ENCODING_INT8 => i8::from_le_bytes(self.read_array()?).into(),
+            ENCODING_INT16 => i16::from_le_bytes(self.read_array()?).into(),
+            ENCODING_INT32 => i32::from_le_bytes(self.read_array()?),
+            ENCODING_LZF => {
+                let compressed_len = self.read_length()?;
+                let len = self.read_length()?;
+                let compressed = self.read_bytes(compressed_len)?;
+                return lzf_decompress(&compressed, len).map(Bytes::from);
+            }
+            _ => return Err(invalid_data("Unknown string encoding")),
+        };
+        Ok(Bytes::from(integer.to_string()))
+    }
+
+    fn read_id(&mut self) -> io::Result<StreamId> {
+        let ms = self.read_length_or_encoding()?.0;
+        let seq = self.read_length_or_encoding()?.0;
+        Ok(StreamId { ms, seq })
+    }
+
+    fn read_time(&mut self) -> io::Result<Option<DateTime<Utc>>> {
+        let millis = i64::from_le_bytes(self.read_array()?);
+        // Redis writes -1 for a consumer that was never active.
+        Ok(DateTime::from_timestamp_millis(millis).filter(|_| millis >= 0))
+    }
+
+    fn read_stream(&mut self, value_type: u8) -> io::Result<Stream> {
+        let corrupt = || invalid_data("Invalid stream");
+        let mut stream = Stream::default();
+        for _ in 0..self.read_length()? {
+            let master = self.read_string()?;
+            let master = StreamId::from_be_bytes(master[..].try_into().map_err(|_| corrupt())?);
+            let node = listpack_entries(&self.read_string()?)?;
+            for (id, fields) in stream_node_entries(master, &node)? {
+                stream
+                    .add(NewId::Explicit(id), fields)
+                    .map_err(|_| corrupt())?;
+            }
+        }
+        self.read_length()?;
+        stream.set_last_id(self.read_id()?);
+        if value_type != TYPE_STREAM_LISTPACKS {
+            // The first ID, the greatest deleted ID and the number of entries ever added.
+            self.read_id()?;
+            self.read_id()?;
+            self.read_length()?;
+        }
+
+        for _ in 0..self.read_length()? {
+            let name = self.read_string()?;
+            let last_delivered = self.read_id()?;
+            if value_type != TYPE_STREAM_LISTPACKS {
+                // The number of entries read by the group.
+                self.read_length()?;
+            }
+            let mut pending = HashMap::new();
+            for _ in 0..self.read_length()? {
+                let id = StreamId::from_be_bytes(self.read_array()?);
+                let delivered_at = self.read_time()?.ok_or_else(corrupt)?;
+                pending.insert(id, (delivered_at, self.read_length()? as u64));
+            }
+
+            stream.create_group(name.clone(), last_delivered);
+            let group = stream.group_mut(&name).expect("the group was just created");
+            for _ in 0..self.read_length()? {
+                let consumer = self.read_string()?;
+                let seen_at = self.read_time()?.ok_or_else(corrupt)?;
+                let active_at = if value_type == TYPE_STREAM_LISTPACKS_3 {
+                    self.read_time()?
+                } else {
+                    None
+                };
+                group.insert_consumer(consumer.clone(), Consumer { seen_at, active_at });
+                for _ in 0..self.read_length()? {
+                    let id = StreamId::from_be_bytes(self.read_array()?);
+                    let (delivered_at, deliveries) = pending.remove(&id).ok_or_else(corrupt)?;
+                    let pending = PendingEntry {
+                        consumer: consumer.clone(),
+                        delivered_at,
+                        deliveries,
+                    };
+                    group.insert_pending(id, pending);
+                }
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Doubles of the original sorted set encoding, stored as strings.
+    fn read_string_double(&mut self) -> io::Result<f64> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => {
+                let bytes = self.read_bytes(len.into())?;
+                parse_double(&bytes)
+            }
+        }
+    }
+
+    fn read_value(&mut self, value_type: u8) -> io::Result<Value> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(self.read_string()?),
+            TYPE_LIST => {
+                let len = self.read_length()?;
+                let list = (0..len)
+                    .map(|_| self.read_string())
+                    .collect::<io::Result<_>>()?;
+                Value::List(list)
+            }
+            TYPE_SET => {
+                let len = self.read_length()?;
+                let set = (0..len)
+                    .map(|_| self.read_string())
+                    .collect::<io::Result<_>>()?;
+                Value::Set(set)
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let len = self.read_length()?;
+                let mut sorted_set = SortedSet::default();
+                for _ in 0..len {
+                    let member = self.read_string()?;
+                    let score = if value_type == TYPE_ZSET {
+                        self.read_string_double()?
+                    } else {
+                        f64::from_le_bytes(self.read_array()?)
+                    };
+                    sorted_set.insert(member, score);
+                }
+                Value::SortedSet(sorted_set)
+            }
+            TYPE_HASH => {
+                let len = self.read_length()?;
+                let mut hash = HashMap::new();
+                for _ in 0..len {
+                    let field = self.read_string()?;
+                    let value = self.read_string()?;
+                    hash.insert(field, value);
+                }
+                Value::Hash(hash)
+            }
+            TYPE_LIST_ZIPLIST => Value::List(ziplist_entries(&self.read_string()?)?.into()),
+            TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+                let nodes = self.read_length()?;
+                let mut list = VecDeque::new();
+                for _ in 0..nodes {
+                    let container = if value_type == TYPE_LIST_QUICKLIST_2 {
+                        self.read_length()? as u64
+                    } else {
+                        QUICKLIST_NODE_PACKED
+                    };
+                    let node = self.read_string()?;
+                    match container {
+                        QUICKLIST_NODE_PLAIN => list.push_back(node),
+                        QUICKLIST_NODE_PACKED if value_type == TYPE_LIST_QUICKLIST => {
+                            list.extend(ziplist_entries(&node)?)
+                        }
+                        QUICKLIST_NODE_PACKED => list.extend(listpack_entries(&node)?),
+                        _ => return Err(invalid_data("Unknown quicklist node container")),
+                    }
+                }
+                Value::List(list)
+            }
+            TYPE_SET_INTSET => Value::Set(intset_entries(&self.read_string()?)?),
+            TYPE_SET_LISTPACK => {
+                let entries = listpack_entries(&self.read_string()?)?;
+                Value::Set(entries.into_iter().collect())
+            }
+            TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+                let packed = self.read_string()?;
+                let entries = if value_type == TYPE_HASH_ZIPLIST {
+                    ziplist_entries(&packed)?
+                } else {
+                    listpack_entries(&packed)?
+                };
+                let hash = pairs(entries)?.collect();
+                Value::Hash(hash)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.read_stream(value_type)?)
+            }
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let packed = self.read_string()?;
+                let entries = if value_type == TYPE_ZSET_ZIPLIST {
+                    ziplist_entries(&packed)?
+                } else {
+                    listpack_entries(&packed)?
+                };
+                let mut sorted_set = SortedSet::default();
+                for (member, score) in pairs(entries)? {
+                    sorted_set.insert(member, parse_double(&score)?);
+                }
+                Value::SortedSet(sorted_set)
+            }
+            value_type => {
+                return Err(invalid_data(format!(
+                    "Unsupported value type {value_type} in RDB file"
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn pairs(entries: Vec<Bytes>) -> io::Result<impl Iterator<Item = (Bytes, Bytes)>> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(invalid_data("Expected an even number of entries"));
+    }
+    let mut entries = entries.into_iter();
+    Ok(std::iter::from_fn(move || {
+        Some((entries.next()?, entries.next()?))
+    }))
+}
+
+fn parse_double(bytes: &[u8]) -> io::Result<f64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|double| match double {
+            "inf" | "+inf" => Some(f64::INFINITY),
+            "-inf" => Some(f64::NEG_INFINITY),
+            double => double.parse().ok(),
+        })
+        .ok_or_else(|| invalid_data("Invalid double"))
+}
+
+/// Returns the next `len` bytes, moving the cursor past them.
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> io::Result<&'a [u8]> {
+    let bytes = data
+        .get(*pos..*pos + len)
+        .ok_or_else(|| invalid_data("Unexpected end of packed encoding"))?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Reads a little endian signed integer that is `len` bytes long.
+fn take_int(data: &[u8], pos: &mut usize, len: usize) -> io::Result<i64> {
+    let bytes = take(data, pos, len)?;
+    let mut buf = [0; 8];
+    buf[..len].copy_from_slice(bytes);
+    // Shifting back and forth extends the sign of shorter integers.
+    let shift = 64 - 8 * len as u32;
+    Ok(i64::from_le_bytes(buf) << shift >> shift)
+}
+
+fn ziplist_entries(data: &[u8]) -> io::Result<Vec<Bytes>> {
+    // Skips the total size, the offset of the last entry and the number of entries.
+    let mut pos = 10;
+    let mut entries = vec![];
+    loop {
+        let first = take(data, &mut pos, 1)?[0];
+        if first == 0xFF {
+            return Ok(entries);
+        }
+        // The length of the previous entry.
+        if first == 0xFE {
+            take(data, &mut pos, 4)?;
+        }
+
+        let encoding = take(data, &mut pos, 1)?[0];
+        let len = match encoding >> 6 {
+            0 => Some(usize::from(encoding & 0x3F)),
+            1 => {
+                let next = take(data, &mut pos, 1)?[0];
+                Some((usize::from(encoding & 0x3F) << 8) | usize::from(next))
+            }
+            2 => {
+                let len = take(data, &mut pos, 4)?;
+                Some(u32::from_be_bytes(len.try_into().unwrap()) as usize)
+            }
+            _ => None,
+        };
+        let entry = match len {
+            Some(len) => Bytes::copy_from_slice(take(data, &mut pos, len)?),
+            None => {
+                let integer = match encoding {
+                    0xC0 => take_int(data, &mut pos, 2)?,
+                    0xD0 => take_int(data, &mut pos, 4)?,
+                    0xE0 => take_int(data, &mut pos, 8)?,
+                    0xF0 => take_int(data, &mut pos, 3)?,
+                    0xFE => take_int(data, &mut pos, 1)?,
+                    0xF1..=0xFD => i64::from(encoding & 0x0F) - 1,
+                    _ => return Err(invalid_data("Unknown ziplist entry encoding")),
+                };
+                Bytes::from(integer.to_string())
+            }
+        };
+        entries.push(entry);
+    }
+}
+
+fn listpack_entries(data: &[u8]) -> io::Result<Vec<Bytes>> {
+    // Skips the total size and the number of entries.
+    let mut pos = 6;
+    let mut entries = vec![];
+    loop {
+        let start = pos;
+        let encoding = take(data, &mut pos, 1)?[0];
+        if encoding == 0xFF {
+            return Ok(entries);
+        }
+
+        let entry = if encoding & 0x80 == 0 {
+            Bytes::from((encoding & 0x7F).to_string())
+        } else if encoding & 0xC0 == 0x80 {
+            let len = usize::from(encoding & 0x3F);
+            Bytes::copy_from_slice(take(data, &mut pos, len)?)
+        } else if encoding & 0xE0 == 0xC0 {
+            let next = take(data, &mut pos, 1)?[0];
+            let integer = (i64::from(encoding & 0x1F) << 8) | i64::from(next);
+            // A 13 bit signed integer.
+            let integer = if integer >= 1 << 12 {
+                integer - (1 << 13)
+            } else {
+                integer
+            };
+            Bytes::from(integer.to_string())
+        } else if encoding & 0xF0 == 0xE0 {
+            let next = take(data, &mut pos, 1)?[0];
+            let len = (usize::from(encoding & 0x0F) << 8) | usize::from(next);
+            Bytes::copy_from_slice(take(data, &mut pos, len)?)
+        } else {
+            let integer = match encoding {
+                0xF0 => {
+                    let len = take(data, &mut pos, 4)?;
+                    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+                    entries.push(Bytes::copy_from_slice(take(data, &mut pos, len)?));
+                    skip_listpack_backlen(data, &mut pos, start)?;
+                    continue;
+                }
+                0xF1 => take_int(data, &mut pos, 2)?,
+                0xF2 => take_int(data, &mut pos, 3)?,
+                0xF3 => take_int(data, &mut pos, 4)?,
+                0xF4 => take_int(data, &mut pos, 8)?,
+                _ => return Err(invalid_data("Unknown listpack entry encoding")),
+            };
+            Bytes::from(integer.to_string())
+        };
+        entries.push(entry);
+        skip_listpack_backlen(data, &mut pos, start)?;
+    }
+}
+
+/// Each listpack entry ends with its own length, so that it can be traversed backwards.
+fn skip_listpack_backlen(data: &[u8], pos: &mut usize, start: usize) -> io::Result<()> {
+    let entry_len = *pos - start;
+    let backlen = match entry_len {
+        0..=127 => 1,
+        128..=16_382 => 2,
+        16_383..=2_097_150 => 3,
+        2_097_151..=268_435_454 => 4,
+        _ => 5,
+    };
+    take(data, pos, backlen).map(|_| ())
+}
+
+/// A listpack being built, see `listpack_entries` for the format.
+#[derive(Default)]
+struct Listpack {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    fn push_int(&mut self, integer: i64) {
+        let mut entry = vec![];
+        if (0..=127).contains(&integer) {
+            entry.push(integer as u8);
+        } else if (-4096..4096).contains(&integer) {
+            let integer = integer as u16 & 0x1FFF;
+            entry.extend([0xC0 | (integer >> 8) as u8, integer as u8]);
+        } else if let Ok(integer) = i16::try_from(integer) {
+            entry.push(0xF1);
+            entry.extend(integer.to_le_bytes());
+        } else if (-(1 << 23)..1 << 23).contains(&integer) {
+            entry.push(0xF2);
+            entry.extend(&integer.to_le_bytes()[..3]);
+        } else if let Ok(integer) = i32::try_from(integer) {
+            entry.push(0xF3);
+            entry.extend(integer.to_le_bytes());
+        } else {
+            entry.push(0xF4);
+            entry.extend(integer.to_le_bytes());
+        }
+        self.push(&entry);
+    }
+
+    fn push_string(&mut self, string: &[u8]) {
+        let len = string.len();
+        let mut entry = if len < 64 {
+            vec![0x80 | len as u8]
+        } else if len < 4096 {
+            vec![0xE0 | (len >> 8) as u8, len as u8]
+        } else {
+            let mut entry = vec![0xF0];
+            entry.extend((len as u32).to_le_bytes());
+            entry
+        };
+        entry.extend_from_slice(string);
+        self.push(&entry);
+    }
+
+    // Each entry is followed by its length, split in groups of 7 bits from the most significant
+    // one, all but the last with their high bit set, so it can be read backwards.
+    fn push(&mut self, entry: &[u8]) {
+        self.data.extend_from_slice(entry);
+        let len = entry.len() as u64;
+        let groups = match len {
+            0..=127 => 1,
+            128..=16_382 => 2,
+            16_383..=2_097_150 => 3,
+            2_097_151..=268_435_454 => 4,
+            _ => 5,
+        };
+        for group in (0..groups).rev() {
+            let bits = (len >> (7 * group)) as u8 & 0x7F;
+            let continued = if group == groups - 1 { 0 } else { 0x80 };
+            self.data.push(bits | continued);
+        }
+        self.len += 1;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let total = 6 + self.data.len() + 1;
+        let mut listpack = Vec::with_capacity(total);
+        listpack.extend((total as u32).to_le_bytes());
+        listpack.extend((self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        listpack.extend(self.data);
+        listpack.push(0xFF);
+        listpack
+    }
+}
+
+/// Encodes the entries of a stream node as a listpack. It starts with the master entry, made
+/// of the number of entries, of deleted entries and the fields of the first entry, which the
+/// others can refer to. We always write the fields of every entry instead.
+fn stream_node(entries: &[(&StreamId, &Fields)]) -> Vec<u8> {
+    let (master, master_fields) = entries[0];
+    let mut listpack = Listpack::default();
+    listpack.push_int(entries.len() as i64);
+    listpack.push_int(0);
+    listpack.push_int(master_fields.len() as i64);
+    for (field, _) in master_fields {
+        listpack.push_string(field);
+    }
+    listpack.push_int(0);
+
+    for (id, fields) in entries {
+        listpack.push_int(0);
+        // The ID is relative to the master one.
+        listpack.push_int(id.ms.wrapping_sub(master.ms) as i64);
+        listpack.push_int(id.seq.wrapping_sub(master.seq) as i64);
+        listpack.push_int(fields.len() as i64);
+        for (field, value) in fields.iter() {
+            listpack.push_string(field);
+            listpack.push_string(value);
+        }
+        // The number of elements of the entry, so that it can be read backwards.
+        listpack.push_int(4 + 2 * fields.len() as i64);
+    }
+    listpack.finish()
+}
+
+/// Decodes the elements of a stream node, see `stream_node`, leaving out deleted entries.
+fn stream_node_entries(
+    master: StreamId,
+    elements: &[Bytes],
+) -> io::Result<Vec<(StreamId, Fields)>> {
+    let mut elements = elements.iter();
+    let mut next = || {
+        elements
+            .next()
+            .cloned()
+            .ok_or_else(|| invalid_data("Unexpected end of stream node"))
+    };
+    fn int(element: Bytes) -> io::Result<i64> {
+        std::str::from_utf8(&element)
+            .ok()
+            .and_then(|integer| integer.parse().ok())
+            .ok_or_else(|| invalid_data("Invalid stream node"))
+    }
+
+    // The number of entries and of deleted ones.
+    next()?;
+    next()?;
+    let master_fields = (0..int(next()?)?)
+        .map(|_| next())
+        .collect::<io::Result<Vec<_>>>()?;
+    next()?;
+
+    let mut entries = vec![];
+    while let Ok(flags) = next() {
+        let flags = int(flags)?;
+        let id = StreamId {
+            ms: master.ms.wrapping_add(int(next()?)? as u64),
+            seq: master.seq.wrapping_add(int(next()?)? as u64),
+        };
+        let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone(), next()?)))
+                .collect::<io::Result<Fields>>()?
+        } else {
+            (0..int(next()?)?)
+                .map(|_| Ok((next()?, next()?)))
+                .collect::<io::Result<Fields>>()?
+        };
+        next()?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.push((id, fields));
+        }
+    }
+    Ok(entries)
+}
+
+fn intset_entries(data: &[u8]) -> io::Result<HashSet<Bytes>> {
+    let mut pos = 0;
+    let width = take_int(data, &mut pos, 4)? as usize;
+    if ![2, 4, 8].contains(&width) {
+        return Err(invalid_data("Unknown intset encoding"));
+    }
+    let len = take_int(data, &mut pos, 4)?;
+    (0..len)
+        .map(|_| take_int(data, &mut pos, width).map(|integer| Bytes::from(integer.to_string())))
+        .collect()
+}
+
+fn lzf_decompress(input: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let corrupt = || invalid_data("Invalid LZF compressed string");
+    let mut output = vec![];
+    let mut pos = 0;
+    while pos < input.len() && output.len() <= len {
+        let control = usize::from(input[pos]);
+        pos += 1;
+
+        if control < 1 << 5 {
+            // A run of literal bytes.
+            let literal = input.get(pos..pos + control + 1).ok_or_else(corrupt)?;
+            output.extend_from_slice(literal);
+            pos += control + 1;
+            continue;
+        }
+
+        // A back reference into what has been decompressed so far.
+        let mut run = control >> 5;
+        if run == 7 {
+            run += usize::from(*input.get(pos).ok_or_else(corrupt)?);
+            pos += 1;
+        }
+        let offset = ((control & 0x1F) << 8) + usize::from(*input.get(pos).ok_or_else(corrupt)?);
+        pos += 1;
+        let start = output.len().checked_sub(offset + 1).ok_or_else(corrupt)?;
+        // The referenced bytes may overlap with the ones being copied.
+        for i in start..start + run + 2 {
+            output.push(output[i]);
+        }
+    }
+
+    if output.len() != len {
+        return Err(corrupt());
+    }
+    Ok(output)
+}
+
+/// The CRC-64 variant used by redis (Jones polynomial, reflected).
+fn value_type(value: &Value) -> u8 {
+    match value {
+        Value::String(_) | Value::Integer(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::Hash(_) => TYPE_HASH,
+        Value::SortedSet(_) => TYPE_ZSET_2,
+        Value::Stream(_) => TYPE_STREAM_LISTPACKS,
+    }
+}
+
+fn crc64(mut crc: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        crc = CRC64_TABLE[((crc ^ u64::from(*byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+const CRC64_TABLE: [u64; 256] = {
+    const POLY: u64 = 0x95AC_9329_AC4B_C9B5;
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::{
+        crc64, dump, listpack_entries, lzf_decompress, read, restore, write, ziplist_entries,
+        TYPE_STRING, VERSION,
+    };
+    use crate::db::{Keyspace, NewId, PendingEntry, SortedSet, Stream, StreamId, Value};
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(0, b"123456789"), 0xE9C6_D914_C4B8_D9CA);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut keyspace = Keyspace::default();
+        keyspace.insert("string".to_owned(), Value::String(Bytes::from("value")));
+        keyspace.insert(
+            "list".to_owned(),
+            Value::List(["a", "b"].map(Bytes::from).into()),
+        );
+        keyspace.insert(
+            "set".to_owned(),
+            Value::Set(["a", "b"].map(Bytes::from).into()),
+        );
+        keyspace.insert(
+            "hash".to_owned(),
+            Value::Hash([(Bytes::from("field"), Bytes::from("value"))].into()),
+        );
+        keyspace.insert(
+            "zset".to_owned(),
+            Value::SortedSet(SortedSet::from(vec![(Bytes::from("a"), 1.5)])),
+        );
+        keyspace.insert("stream".to_owned(), Value::Stream(stream()));
+        keyspace.expire_at("string", Utc::now() + Duration::seconds(100));
+
+        let mut file = vec![];
+        write(&mut file, &[Keyspace::default(), keyspace.clone()]).unwrap();
+        let [empty, restored] = <[_; 2]>::try_from(read(file.as_slice(), 2).unwrap()).unwrap();
+
+        assert!(empty.is_empty());
+        assert!(
+            read(file.as_slice(), 1).is_err(),
+            "there are too few databases"
+        );
+        assert_eq!(restored.iter().count(), 6);
+        for (key, value) in keyspace.iter() {
+            assert_eq!(restored.get(key), Some(value));
+        }
+        assert_eq!(
+            restored.deadline("string").map(|at| at.timestamp_millis()),
+            keyspace.deadline("string").map(|at| at.timestamp_millis())
+        );
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        // What redis replies to `DUMP` for a key set to 10, from its documentation.
+        let payload = b"\x00\xc0\n\t\x00\xbem\x06\x89Z(\x00\n";
+        assert_eq!(restore(payload).unwrap(), Value::String(Bytes::from("10")));
+
+        let value = Value::Stream(stream());
+        let mut payload = dump(&value);
+        assert_eq!(restore(&payload).unwrap(), value);
+        payload[3] ^= 1;
+        assert!(restore(&payload).is_err(), "the checksum doesn't match");
+
+        let len = payload.len();
+        payload[len - 8..].fill(0);
+        assert!(restore(&payload).is_err(), "the checksum is always checked");
+    }
+
+    #[test]
+    fn test_restore_rejects_lengths_past_the_payload() {
+        // A string said to be 4 TiB long, followed by the version and a valid checksum.
+        let mut payload = vec![TYPE_STRING, 0x81];
+        payload.extend_from_slice(&(1u64 << 42).to_be_bytes());
+        payload.extend_from_slice(b"abc");
+        payload.extend_from_slice(&(VERSION as u16).to_le_bytes());
+        payload.extend_from_slice(&crc64(0, &payload).to_le_bytes());
+        assert_eq!(
+            restore(&payload).unwrap_err().to_string(),
+            "Bad data format"
+        );
+    }
+
+    /// Enough entries to span several nodes, some far apart, with a deleted one and a group.
+    fn stream() -> Stream {
+        let mut stream = Stream::default();
+        for i in 1..=250 {
+            let id = StreamId {
+                ms: i * i * 1_000_003,
+                seq: i % 3,
+            };
+            let fields = vec![(
+                Bytes::from(format!("field{}", i % 2)),
+                Bytes::from(i.to_string()),
+            )];
+            stream.add(NewId::Explicit(id), fields).unwrap();
+        }
+        let last = stream.last_id();
+        stream.delete(&[last]);
+
+        // Times are saved to the millisecond.
+        let now = DateTime::from_timestamp_millis(Utc::now().timestamp_millis()).unwrap();
+        let first = stream.first_id().unwrap();
+        stream.create_group(Bytes::from("group"), first);
+        let group = stream.group_mut(b"group").unwrap();
+        group.create_consumer(Bytes::from("consumer"), now);
+        let pending = PendingEntry {
+            consumer: Bytes::from("consumer"),
+            delivered_at: now,
+            deliveries: 2,
+        };
+        group.insert_pending(first, pending);
+        stream
+    }
+
+    #[test]
+    fn test_corrupted_files_are_rejected() {
+        let mut file = vec![];
+        write(&mut file, &[Keyspace::default()]).unwrap();
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        assert!(read(file.as_slice(), 1).is_err());
+    }
+
+    #[test]
+    fn test_ziplist_entries() {
+        let ziplist = [
+            0, 0, 0, 0, 0, 0, 0, 0, 2, 0, // header
+            0, 0x02, b'h', b'i', // a 6 bit long string
+            4, 0xF4, // the immediate integer 3
+            2, 0xC0, 0x18, 0xFC, // the 16 bit integer -1000
+            0xFF,
+        ];
+        assert_eq!(ziplist_entries(&ziplist).unwrap(), ["hi", "3", "-1000"]);
+    }
+
+    #[test]
+    fn test_listpack_entries() {
+        let listpack = [
+            0, 0, 0, 0, 3, 0, // header
+            0x82, b'h', b'i', 3, // a 6 bit long string
+            0x05, 1, // a 7 bit unsigned integer
+            0xDF, 0xFF, 2, // the 13 bit integer -1
+            0xF1, 0x18, 0xFC, 3, // the 16 bit integer -1000
+            0xFF,
+        ];
+        assert_eq!(
+            listpack_entries(&listpack).unwrap(),
+            ["hi", "5", "-1", "-1000"]
+        );
+    }
+
+    #[test]
+    fn test_lzf_decompress() {
+        // "abcabcabc": three literal bytes followed by a back reference of six bytes.
+        let compressed = [2, b'a', b'b', b'c', 0x80, 2];
+        assert_eq!(lzf_decompress(&compressed, 9).unwrap(), b"abcabcabc");
+    }
+}
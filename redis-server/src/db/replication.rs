@@ -0,0 +1,482 @@
+//! The state of replication: the id and offset of the replication stream, the backlog that
+//! replicas catch up from after a short disconnection, and the replicas being fed.
+//!
+//! Like redis, the stream is made of the same commands that are appended to the AOF, and its
+//! offset counts the bytes it is made of. A replica takes on the id and offset of its master's
+//! stream, so that it can carry on from where it left off when it reconnects, and so that its
+//! own replicas can carry on with it once it is promoted.
+
+use std::{
+    collections::VecDeque,
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use rand::Rng;
+use tokio::sync::{mpsc, watch};
+
+use super::{rdb, Keyspace};
+use crate::{
+    connection::encode,
+    frame::{Frame, Protocol},
+};
+
+#[derive(Debug)]
+pub struct Replication {
+    replid: String,
+    // The id of the stream this one carries on from after a promotion, and the offset up to
+    // which the replicas of the previous master can continue with us.
+    replid2: Option<(String, u64)>,
+    offset: u64,
+    // Only created once a replica connects, since it is of no use until then.
+    backlog: Option<Backlog>,
+    backlog_size: usize,
+    replicas: Vec<Replica>,
+    master: Option<Master>,
+    // Lets the task following the master know when it changes.
+    master_changed: watch::Sender<Option<(String, u16)>>,
+}
+
+/// The link of a replica with its master.
+#[derive(Debug)]
+pub struct Master {
+    pub host: String,
+    pub port: u16,
+    pub state: LinkState,
+    pub last_io: Instant,
+}
+
+/// Where a replica is at with its master, as reported by `ROLE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkState {
+    Connect,
+    Connecting,
+    Sync,
+    Connected,
+}
+
+/// A replica that is fed the replication stream.
+#[derive(Debug)]
+pub struct Replica {
+    /// The id of the replica's connection.
+    pub id: u64,
+    pub ip: String,
+    /// The port the replica listens on, as given with `REPLCONF listening-port`.
+    pub port: u16,
+    /// The offset the replica says it has processed, with `REPLCONF ACK`.
+    pub ack_offset: u64,
+    pub last_ack: Instant,
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+/// What a replica is sent once it is registered with `PSYNC`.
+pub struct ReplicaFeed {
+    // The databases the replica has to load first, when it can't continue from the backlog.
+    snapshot: Option<Vec<Keyspace>>,
+    receiver: mpsc::UnboundedReceiver<Bytes>,
+}
+
+/// The last bytes of the replication stream.
+#[derive(Debug, Default)]
+struct Backlog {
+    buffer: VecDeque<u8>,
+}
+
+impl Replication {
+    pub fn new(master: Option<(String, u16)>, backlog_size: u64) -> Self {
+        let (master_changed, _) = watch::channel(master.clone());
+        Self {
+            replid: new_replid(),
+            replid2: None,
+            offset: 0,
+            backlog: None,
+            backlog_size: backlog_size as usize,
+            replicas: vec![],
+            master: master.map(|(host, port)| Master {
+                host,
+                port,
+                state: LinkState::Connect,
+                last_io: Instant::now(),
+            }),
+            master_changed,
+        }
+    }
+
+    pub fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    /// How many bytes the replication stream is made of.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The master, if we are a replica.
+    pub fn master(&self) -> Option<&Master> {
+        self.master.as_ref()
+    }
+
+    pub fn replicas(&self) -> &[Replica] {
+        &self.replicas
+    }
+
+    /// Whether the stream has to be fed, which is only once a replica has connected.
+    pub fn is_active(&self) -> bool {
+        self.backlog.is_some()
+    }
+
+    /// Notifies the receiver whenever `REPLICAOF` changes the master.
+    pub fn watch_master(&self) -> watch::Receiver<Option<(String, u16)>> {
+        self.master_changed.subscribe()
+    }
+
+    /// Starts replicating `master`, or stops replicating with `None`. Returns false if nothing
+    /// changed.
+    ///
+    /// A replica that is promoted starts a new stream, which its own replicas can continue
+    /// with since it carries on from the previous one.
+    pub fn set_master(&mut self, master: Option<(String, u16)>) -> bool {
+        let current = self
+            .master
+            .as_ref()
+            .map(|master| (master.host.clone(), master.port));
+        if current == master {
+            return false;
+        }
+        match &master {
+            Some((host, port)) => {
+                self.master = Some(Master {
+                    host: host.clone(),
+                    port: *port,
+                    state: LinkState::Connect,
+                    last_io: Instant::now(),
+                });
+            }
+            None => {
+                self.master = None;
+                let previous = std::mem::replace(&mut self.replid, new_replid());
+                self.replid2 = Some((previous, self.offset + 1));
+            }
+        }
+        self.master_changed.send_replace(master);
+        true
+    }
+
+    pub fn set_link_state(&mut self, state: LinkState) {
+        if let Some(master) = &mut self.master {
+            master.state = state;
+            master.last_io = Instant::now();
+        }
+    }
+
+    /// Records that the master was heard from.
+    pub fn touch_link(&mut self) {
+        if let Some(master) = &mut self.master {
+            master.last_io = Instant::now();
+        }
+    }
+
+    /// What to send with `PSYNC` to carry on with our stream, or `?` and `-1` for a full sync
+    /// when we don't have one yet.
+    pub fn psync_position(&self) -> (String, i64) {
+        match &self.backlog {
+            Some(_) => (self.replid.clone(), self.offset as i64 + 1),
+            None => ("?".to_owned(), -1),
+        }
+    }
+
+    /// Takes on the stream of the master, as of the snapshot it sent.
+    /// Our own replicas are dropped, since they have to sync again from scratch.
+    pub fn start_from_master(&mut self, replid: String, offset: u64) {
+        self.replid = replid;
+        self.replid2 = None;
+        self.offset = offset;
+        self.backlog = Some(Backlog::default());
+        self.replicas.clear();
+    }
+
+    /// Carries on with the master's stream from the backlog. The master has a new id if it was
+    /// promoted since, in which case our replicas can carry on with us as well.
+    pub fn continue_with_master(&mut self, replid: Option<String>) {
+        if let Some(replid) = replid.filter(|replid| *replid != self.replid) {
+            let previous = std::mem::replace(&mut self.replid, replid);
+            self.replid2 = Some((previous, self.offset + 1));
+        }
+        self.backlog.get_or_insert_with(Backlog::default);
+    }
+
+    /// Registers a replica that asked to carry on from `offset` of the stream `replid`.
+    /// Returns the reply to its `PSYNC`, and whether it has to be sent a snapshot first.
+    pub fn add_replica(
+        &mut self,
+        mut replica: Replica,
+        replid: &str,
+        offset: Option<u64>,
+    ) -> (Frame, bool) {
+        let backlog = self.backlog.get_or_insert_with(Backlog::default);
+        let first_offset = self.offset + 1 - backlog.buffer.len() as u64;
+        let same_stream = replid == self.replid
+            || self
+                .replid2
+                .as_ref()
+                .is_some_and(|(replid2, second_offset)| {
+                    replid == replid2 && offset.is_some_and(|offset| offset <= *second_offset)
+                });
+        let from_backlog = offset
+            .filter(|offset| same_stream && (first_offset..=self.offset + 1).contains(offset));
+
+        let reply = match from_backlog {
+            Some(offset) => {
+                let missed = backlog.since((offset - first_offset) as usize);
+                if !missed.is_empty() {
+                    let _ = replica.sender.send(missed);
+                }
+                replica.ack_offset = offset - 1;
+                format!("CONTINUE {}", self.replid)
+            }
+            None => format!("FULLRESYNC {} {}", self.replid, self.offset),
+        };
+        self.replicas.push(replica);
+        (Frame::SimpleString(reply), from_backlog.is_none())
+    }
+
+    pub fn remove_replica(&mut self, id: u64) {
+        self.replicas.retain(|replica| replica.id != id);
+    }
+
+    /// Records the offset a replica has processed, as sent with `REPLCONF ACK`.
+    pub fn ack(&mut self, id: u64, offset: u64) {
+        if let Some(replica) = self.replicas.iter_mut().find(|replica| replica.id == id) {
+            replica.ack_offset = offset;
+            replica.last_ack = Instant::now();
+        }
+    }
+
+    pub fn set_backlog_size(&mut self, size: u64) {
+        self.backlog_size = size as usize;
+        if let Some(backlog) = &mut self.backlog {
+            backlog.trim(self.backlog_size);
+        }
+    }
+
+    /// Adds the commands to the stream, and sends them to the replicas.
+    pub fn feed(&mut self, commands: &[Frame]) {
+        let mut buffer = BytesMut::new();
+        for command in commands {
+            encode(command, Protocol::Resp2, &mut buffer);
+        }
+        self.feed_bytes(buffer.freeze());
+    }
+
+    /// Adds commands that are already encoded to the stream, e.g. the ones a replica got from
+    /// its master, and sends them to the replicas.
+    pub fn feed_bytes(&mut self, bytes: Bytes) {
+        let Some(backlog) = &mut self.backlog else {
+            return;
+        };
+        backlog.buffer.extend(&bytes);
+        backlog.trim(self.backlog_size);
+        self.offset += bytes.len() as u64;
+        // Replicas that disconnected have dropped their receiver.
+        self.replicas
+            .retain(|replica| replica.sender.send(bytes.clone()).is_ok());
+    }
+
+    /// The fields of the replication section of `INFO`.
+    pub fn info(&self) -> Vec<(String, String)> {
+        let mut fields = vec![];
+        let mut field = |name: &str, value: String| fields.push((name.to_owned(), value));
+        match &self.master {
+            Some(master) => {
+                field("role", "slave".to_owned());
+                field("master_host", master.host.clone());
+                field("master_port", master.port.to_string());
+                let up = master.state == LinkState::Connected;
+                field(
+                    "master_link_status",
+                    if up { "up" } else { "down" }.to_owned(),
+                );
+                field(
+                    "master_last_io_seconds_ago",
+                    master.last_io.elapsed().as_secs().to_string(),
+                );
+                let syncing = master.state == LinkState::Sync;
+                field("master_sync_in_progress", (syncing as u8).to_string());
+                field("slave_repl_offset", self.offset.to_string());
+            }
+            None => field("role", "master".to_owned()),
+        }
+        field("connected_slaves", self.replicas.len().to_string());
+        for (i, replica) in self.replicas.iter().enumerate() {
+            field(
+                &format!("slave{i}"),
+                format!(
+                    "ip={},port={},state=online,offset={},lag={}",
+                    replica.ip,
+                    replica.port,
+                    replica.ack_offset,
+                    replica.lag().as_secs()
+                ),
+            );
+        }
+        field("master_replid", self.replid.clone());
+        let (replid2, second_offset) = match &self.replid2 {
+            Some((replid2, offset)) => (replid2.clone(), *offset as i64),
+            None => ("0".repeat(40), -1),
+        };
+        field("master_replid2", replid2);
+        field("master_repl_offset", self.offset.to_string());
+        field("second_repl_offset", second_offset.to_string());
+        let backlog_len = self
+            .backlog
+            .as_ref()
+            .map_or(0, |backlog| backlog.buffer.len());
+        field(
+            "repl_backlog_active",
+            (self.backlog.is_some() as u8).to_string(),
+        );
+        field("repl_backlog_size", self.backlog_size.to_string());
+        field(
+            "repl_backlog_first_byte_offset",
+            (self.offset + 1 - backlog_len as u64).to_string(),
+        );
+        field("repl_backlog_histlen", backlog_len.to_string());
+        fields
+    }
+}
+
+impl Replica {
+    /// Returns the replica along with the end of the channel its stream is read from.
+    pub fn new(id: u64, ip: String, port: u16) -> (Self, mpsc::UnboundedReceiver<Bytes>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let replica = Self {
+            id,
+            ip,
+            port,
+            ack_offset: 0,
+            last_ack: Instant::now(),
+            sender,
+        };
+        (replica, receiver)
+    }
+
+    /// How long ago the replica last acknowledged the stream.
+    pub fn lag(&self) -> Duration {
+        self.last_ack.elapsed()
+    }
+}
+
+impl ReplicaFeed {
+    pub fn new(snapshot: Option<Vec<Keyspace>>, receiver: mpsc::UnboundedReceiver<Bytes>) -> Self {
+        Self { snapshot, receiver }
+    }
+
+    /// Encodes the snapshot the replica has to load first, if it has to.
+    pub async fn snapshot(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let databases = self.snapshot.take()?;
+        let encoded = tokio::task::spawn_blocking(move || {
+            let mut rdb = vec![];
+            rdb::write(&mut rdb, &databases).map(|_| rdb)
+        })
+        .await;
+        Some(encoded.unwrap_or_else(|err| Err(io::Error::other(err))))
+    }
+
+    /// The next part of the stream, or `None` once the replica has been dropped.
+    pub async fn next(&mut self) -> Option<Bytes> {
+        self.receiver.recv().await
+    }
+}
+
+impl Backlog {
+    fn trim(&mut self, size: usize) {
+        let excess = self.buffer.len().saturating_sub(size);
+        self.buffer.drain(..excess);
+    }
+
+    /// The bytes from the one at `start` onwards.
+    fn since(&self, start: usize) -> Bytes {
+        self.buffer
+            .range(start..)
+            .copied()
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Replication ids are 40 random hexadecimal characters, like in redis.
+fn new_replid() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).expect("the digit is below 16"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Replica, Replication};
+    use crate::frame::Frame;
+
+    fn set(key: &'static str) -> Frame {
+        Frame::new_command([Bytes::from("SET"), Bytes::from(key), Bytes::from("value")])
+    }
+
+    fn psync(replication: &mut Replication, replid: &str, offset: u64) -> String {
+        let (replica, _) = Replica::new(1, "127.0.0.1".to_owned(), 6380);
+        let (reply, _) = replication.add_replica(replica, replid, Some(offset));
+        replication.remove_replica(1);
+        let Frame::SimpleString(reply) = reply else {
+            panic!("PSYNC replies with a simple string");
+        };
+        reply.split_whitespace().next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_replicas_continue_from_the_backlog_while_they_can() {
+        let mut replication = Replication::new(None, 100);
+        let (replica, _) = Replica::new(1, "127.0.0.1".to_owned(), 6380);
+        let (reply, full_sync) = replication.add_replica(replica, "?", None);
+        assert!(full_sync);
+        assert_eq!(
+            reply,
+            Frame::SimpleString(format!("FULLRESYNC {} 0", replication.replid()))
+        );
+
+        replication.feed(&[set("a")]);
+        let len = replication.offset();
+        let replid = replication.replid().to_owned();
+        assert_eq!(psync(&mut replication, &replid, 1), "CONTINUE");
+        assert_eq!(psync(&mut replication, &replid, len + 1), "CONTINUE");
+        assert_eq!(psync(&mut replication, "other", len + 1), "FULLRESYNC");
+
+        // The start of the stream no longer fits in the backlog.
+        for _ in 0..5 {
+            replication.feed(&[set("a")]);
+        }
+        assert_eq!(psync(&mut replication, &replid, 1), "FULLRESYNC");
+        assert_eq!(psync(&mut replication, &replid, len * 5 + 1), "CONTINUE");
+    }
+
+    #[test]
+    fn test_promoted_replica_carries_on_with_the_previous_stream() {
+        let mut replication = Replication::new(Some(("127.0.0.1".to_owned(), 6379)), 100);
+        replication.start_from_master("a".repeat(40), 10);
+        replication.feed(&[set("a")]);
+        let offset = replication.offset();
+
+        replication.set_master(None);
+        assert_ne!(replication.replid(), "a".repeat(40));
+        replication.feed(&[set("b")]);
+        assert_eq!(
+            psync(&mut replication, &"a".repeat(40), offset + 1),
+            "CONTINUE"
+        );
+        assert_eq!(
+            psync(&mut replication, &"a".repeat(40), offset + 2),
+            "FULLRESYNC"
+        );
+    }
+}
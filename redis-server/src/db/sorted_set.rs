@@ -0,0 +1,188 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    ops::Bound,
+};
+
+use bytes::Bytes;
+
+/// A score that can be used as part of an ordered index.
+/// NaN is never stored, so `total_cmp` gives us the same order as redis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score(pub f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Members ordered by score and then lexicographically, with a member -> score map
+/// for constant time score lookups.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    index: BTreeSet<(Score, Bytes)>,
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &Bytes) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Returns the previous score of the member if it was already in the set.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.index.remove(&(Score(previous), member.clone()));
+        }
+        self.index.insert((Score(score), member));
+        previous
+    }
+
+    /// Returns the score of the removed member.
+    pub fn remove(&mut self, member: &Bytes) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.index.remove(&(Score(score), member.clone()));
+        Some(score)
+    }
+
+    /// Iterates over the members from the lowest to the highest score.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        self.index.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// The position of the member when ordered from the lowest to the highest score.
+    pub fn rank(&self, member: &Bytes) -> Option<usize> {
+        let score = self.score(member)?;
+        // The index has no rank lookup, so we count the members that are ordered before this one.
+        Some(self.index.range(..(Score(score), member.clone())).count())
+    }
+
+    /// Members with a score within `min` and `max`, from the lowest to the highest score.
+    pub fn range_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> Vec<(&Bytes, f64)> {
+        // An empty member is ordered before any other member with the same score.
+        let start = match min {
+            Bound::Included(min) | Bound::Excluded(min) => {
+                Bound::Included((Score(min), Bytes::new()))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.index
+            .range((start, Bound::Unbounded))
+            .map(|(score, member)| (member, score.0))
+            .skip_while(|(_, score)| matches!(min, Bound::Excluded(min) if *score == min))
+            .take_while(|(_, score)| match max {
+                Bound::Included(max) => *score <= max,
+                Bound::Excluded(max) => *score < max,
+                Bound::Unbounded => true,
+            })
+            .collect()
+    }
+
+    /// Members within `min` and `max` when compared byte by byte. Like in redis, this is only
+    /// meaningful when every member has the same score.
+    pub fn range_by_lex(&self, min: Bound<&Bytes>, max: Bound<&Bytes>) -> Vec<(&Bytes, f64)> {
+        self.iter()
+            .filter(|(member, _)| match min {
+                Bound::Included(min) => *member >= min,
+                Bound::Excluded(min) => *member > min,
+                Bound::Unbounded => true,
+            })
+            .filter(|(member, _)| match max {
+                Bound::Included(max) => *member <= max,
+                Bound::Excluded(max) => *member < max,
+                Bound::Unbounded => true,
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<(Bytes, f64)>> for SortedSet {
+    fn from(members: Vec<(Bytes, f64)>) -> Self {
+        let mut sorted_set = SortedSet::default();
+        for (member, score) in members {
+            sorted_set.insert(member, score);
+        }
+        sorted_set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use bytes::Bytes;
+
+    use super::SortedSet;
+
+    fn sorted_set() -> SortedSet {
+        SortedSet::from(vec![
+            (Bytes::from("c"), 2.0),
+            (Bytes::from("a"), 1.0),
+            (Bytes::from("b"), 2.0),
+            (Bytes::from("d"), f64::INFINITY),
+        ])
+    }
+
+    fn members(range: Vec<(&Bytes, f64)>) -> Vec<&str> {
+        range
+            .into_iter()
+            .map(|(member, _)| std::str::from_utf8(member).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_orders_by_score_then_member() {
+        let sorted_set = sorted_set();
+        assert_eq!(sorted_set.rank(&Bytes::from("a")), Some(0));
+        assert_eq!(sorted_set.rank(&Bytes::from("b")), Some(1));
+        assert_eq!(sorted_set.rank(&Bytes::from("c")), Some(2));
+        assert_eq!(sorted_set.rank(&Bytes::from("d")), Some(3));
+        assert_eq!(sorted_set.rank(&Bytes::from("e")), None);
+    }
+
+    #[test]
+    fn test_insert_updates_the_index() {
+        let mut sorted_set = sorted_set();
+        assert_eq!(sorted_set.insert(Bytes::from("a"), 3.0), Some(1.0));
+        assert_eq!(members(sorted_set.iter().collect()), ["b", "c", "a", "d"]);
+        assert_eq!(sorted_set.remove(&Bytes::from("c")), Some(2.0));
+        assert_eq!(members(sorted_set.iter().collect()), ["b", "a", "d"]);
+    }
+
+    #[test]
+    fn test_range_by_score() {
+        let sorted_set = sorted_set();
+        let range = sorted_set.range_by_score(Bound::Included(1.0), Bound::Included(2.0));
+        assert_eq!(members(range), ["a", "b", "c"]);
+        let range = sorted_set.range_by_score(Bound::Excluded(1.0), Bound::Unbounded);
+        assert_eq!(members(range), ["b", "c", "d"]);
+        let range = sorted_set.range_by_score(Bound::Unbounded, Bound::Excluded(2.0));
+        assert_eq!(members(range), ["a"]);
+    }
+
+    #[test]
+    fn test_range_by_lex() {
+        let sorted_set = sorted_set();
+        let (b, c) = (Bytes::from("b"), Bytes::from("c"));
+        let range = sorted_set.range_by_lex(Bound::Included(&b), Bound::Excluded(&c));
+        assert_eq!(members(range), ["b"]);
+    }
+}
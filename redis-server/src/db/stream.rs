@@ -0,0 +1,646 @@
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+/// The ID of a stream entry: the unix time in milliseconds it was added at, followed by a
+/// sequence number telling apart the entries added within the same millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Parses `ms-seq`, or `ms` alone, in which case the sequence number is `default_seq`.
+    pub fn parse(id: &[u8], default_seq: u64) -> Option<Self> {
+        let id = std::str::from_utf8(id).ok()?;
+        let (ms, seq) = match id.split_once('-') {
+            Some((ms, seq)) => (ms, seq.parse().ok()?),
+            None => (id, default_seq),
+        };
+        Some(Self {
+            ms: ms.parse().ok()?,
+            seq,
+        })
+    }
+
+    /// The smallest ID greater than this one, `None` for [`StreamId::MAX`].
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(Self { seq, ..self }),
+            None => Some(Self {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// The greatest ID smaller than this one, `None` for [`StreamId::MIN`].
+    pub fn prev(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(Self { seq, ..self }),
+            None => Some(Self {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+
+    /// The form used as the key of the nodes of a stream in an RDB: both numbers in big endian.
+    pub fn to_be_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.ms.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.seq.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 16]) -> Self {
+        let (ms, seq) = bytes.split_at(8);
+        Self {
+            ms: u64::from_be_bytes(ms.try_into().unwrap()),
+            seq: u64::from_be_bytes(seq.try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> Bytes {
+        Bytes::from(self.to_string())
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The field-value pairs of an entry, in the order they were given.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// Entries as the commands reading them reply with them. The fields are `None` for pending
+/// entries that were deleted from the stream since they were delivered.
+pub type Entries = Vec<(StreamId, Option<Fields>)>;
+
+/// How `XADD` picks the ID of a new entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NewId {
+    /// `*`: the current time, or the last ID if the clock went backwards.
+    Auto,
+    /// `ms-*`: the next sequence number within the given millisecond.
+    Sequence(u64),
+    Explicit(StreamId),
+}
+
+/// Which entries `XADD` and `XTRIM` evict, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trim {
+    /// Keeps at most that many entries.
+    MaxLen(usize),
+    /// Evicts the entries with a smaller ID.
+    MinId(StreamId),
+}
+
+/// Where `XREAD` and `XREADGROUP` start reading a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadFrom {
+    /// The entries with a greater ID. For `XREADGROUP`, the consumer's pending entries.
+    After(StreamId),
+    /// `$`: only the entries added from now on.
+    Last,
+    /// `>`: the entries not delivered to any consumer of the group yet.
+    New,
+}
+
+/// The consumer `XREADGROUP` reads for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupReader {
+    pub group: Bytes,
+    pub consumer: Bytes,
+    /// Whether the delivered entries are acknowledged right away, instead of being pending.
+    pub no_ack: bool,
+}
+
+/// An append-only log of entries, along with the consumer groups reading it.
+///
+/// Unlike other collections, a stream is not removed from the keyspace once it is empty, so
+/// that its last ID and its groups are kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    // The ID of the last entry added, which may have been deleted since.
+    last_id: StreamId,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+/// Consumers sharing the entries of a stream: each entry is delivered to only one of them,
+/// and stays pending until it is acknowledged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumerGroup {
+    // The last entry delivered to one of the consumers.
+    last_delivered: StreamId,
+    // The entries that were delivered but not acknowledged yet.
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    pub delivered_at: DateTime<Utc>,
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consumer {
+    /// The last time the consumer read or claimed entries, even if there were none.
+    pub seen_at: DateTime<Utc>,
+    /// The last time entries were actually delivered to or claimed by the consumer.
+    pub active_at: Option<DateTime<Utc>>,
+}
+
+/// What `XCLAIM` changes about the entries it claims, besides their consumer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClaimOptions {
+    /// When the entries count as delivered, now if not set.
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// The delivery counter, which is otherwise incremented unless `just_id` is set.
+    pub deliveries: Option<u64>,
+    /// Whether entries that aren't pending are claimed anyway, if they exist.
+    pub force: bool,
+    pub just_id: bool,
+    /// Moves the last delivered ID of the group to this one, if it is greater.
+    pub last_id: Option<StreamId>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Only meant for loading a stream whose last entries were deleted.
+    pub(super) fn set_last_id(&mut self, id: StreamId) {
+        self.last_id = self.last_id.max(id);
+    }
+
+    pub fn first_id(&self) -> Option<StreamId> {
+        self.entries.keys().next().copied()
+    }
+
+    /// Appends an entry and returns its ID, which has to be greater than the last one.
+    pub fn add(&mut self, id: NewId, fields: Fields) -> io::Result<StreamId> {
+        let id = match id {
+            NewId::Auto => {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO)
+                    .as_millis() as u64;
+                if now > self.last_id.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else {
+                    self.last_id.next().ok_or_else(|| {
+                        io::Error::other(
+                            "ERR The stream has exhausted the last possible ID, unable to add more items",
+                        )
+                    })?
+                }
+            }
+            // A new stream has a last ID of 0-0, so `0-*` gives 0-1 just like in redis.
+            NewId::Sequence(ms) if ms == self.last_id.ms => StreamId {
+                ms,
+                seq: self.last_id.seq.checked_add(1).ok_or_else(too_small)?,
+            },
+            NewId::Sequence(ms) => StreamId { ms, seq: 0 },
+            NewId::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            return Err(io::Error::other(
+                "ERR The ID specified in XADD must be greater than 0-0",
+            ));
+        }
+        if id <= self.last_id {
+            return Err(too_small());
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    /// The entries from `start` to `end`, both included.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        (start <= end)
+            .then(|| self.entries.range(start..=end))
+            .into_iter()
+            .flatten()
+    }
+
+    /// The entries with an ID greater than `id`.
+    pub fn after(&self, id: StreamId) -> impl Iterator<Item = (&StreamId, &Fields)> {
+        after(&self.entries, id)
+    }
+
+    /// Returns how many of the entries were deleted.
+    pub fn delete(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter()
+            .filter(|id| self.entries.remove(id).is_some())
+            .count()
+    }
+
+    /// Evicts the oldest entries, at most `limit` of them, and returns how many were evicted.
+    pub fn trim(&mut self, trim: Trim, limit: Option<usize>) -> usize {
+        let mut evicted = 0;
+        while limit.is_none_or(|limit| evicted < limit) {
+            let evict = match (trim, self.entries.first_key_value()) {
+                (_, None) => false,
+                (Trim::MaxLen(max_len), _) => self.entries.len() > max_len,
+                (Trim::MinId(min_id), Some((first, _))) => *first < min_id,
+            };
+            if !evict {
+                break;
+            }
+            self.entries.pop_first();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Returns false if there already is a group with that name.
+    pub fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, ConsumerGroup::new(last_delivered));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Delivers up to `count` of the entries that no consumer of the group has been given yet,
+    /// and adds them to its pending entries unless `no_ack` is set. Returns `None` if there is
+    /// no such group.
+    pub fn deliver(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: Option<usize>,
+        no_ack: bool,
+        now: DateTime<Utc>,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let group = self.groups.get_mut(group)?;
+        let entries: Vec<_> = after(&self.entries, group.last_delivered)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+
+        if let Some((last, _)) = entries.last() {
+            group.last_delivered = *last;
+            group.touch(consumer, now).active_at = Some(now);
+        } else {
+            group.touch(consumer, now);
+        }
+        if !no_ack {
+            for (id, _) in &entries {
+                group.pending.insert(
+                    *id,
+                    PendingEntry {
+                        consumer: consumer.clone(),
+                        delivered_at: now,
+                        deliveries: 1,
+                    },
+                );
+            }
+        }
+        Some(entries)
+    }
+
+    /// The entries pending for the consumer after `after`, without counting them as delivered
+    /// again. Returns `None` if there is no such group.
+    pub fn pending_for(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        after: StreamId,
+        count: Option<usize>,
+        now: DateTime<Utc>,
+    ) -> Option<Entries> {
+        let group = self.groups.get_mut(group)?;
+        group.touch(consumer, now);
+        let entries = group
+            .pending
+            .iter()
+            .filter(|(id, pending)| **id > after && pending.consumer == consumer)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, _)| (*id, self.entries.get(id).cloned()))
+            .collect();
+        Some(entries)
+    }
+
+    /// Gives the pending entries that have been idle for at least `min_idle` to `consumer`.
+    /// Returns the claimed entries, with their fields unless `options.just_id` is set, and the
+    /// pending entries that were dropped because they were deleted from the stream.
+    /// Returns `None` if there is no such group.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        min_idle: Duration,
+        ids: &[StreamId],
+        options: ClaimOptions,
+        now: DateTime<Utc>,
+    ) -> Option<(Entries, Vec<StreamId>)> {
+        let group = self.groups.get_mut(group)?;
+        if let Some(last_id) = options.last_id {
+            group.last_delivered = group.last_delivered.max(last_id);
+        }
+
+        let mut claimed = vec![];
+        let mut deleted = vec![];
+        for id in ids {
+            let Some(fields) = self.entries.get(id) else {
+                if group.pending.remove(id).is_some() {
+                    deleted.push(*id);
+                }
+                continue;
+            };
+            if !group.pending.contains_key(id) {
+                if !options.force {
+                    continue;
+                }
+                let pending = PendingEntry {
+                    consumer: consumer.clone(),
+                    delivered_at: now,
+                    deliveries: 1,
+                };
+                group.pending.insert(*id, pending);
+            }
+            let pending = group.pending.get_mut(id).expect("the entry is pending");
+            let idle = (now - pending.delivered_at)
+                .to_std()
+                .unwrap_or(Duration::ZERO);
+            if !min_idle.is_zero() && idle < min_idle {
+                continue;
+            }
+            pending.consumer = consumer.clone();
+            pending.delivered_at = options.delivered_at.unwrap_or(now);
+            pending.deliveries = match options.deliveries {
+                Some(deliveries) => deliveries,
+                None if options.just_id => pending.deliveries,
+                None => pending.deliveries + 1,
+            };
+            claimed.push((*id, (!options.just_id).then(|| fields.clone())));
+        }
+
+        let seen = group.touch(consumer, now);
+        if !claimed.is_empty() {
+            seen.active_at = Some(now);
+        }
+        Some((claimed, deleted))
+    }
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Self {
+            last_delivered,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    pub fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    pub fn set_last_delivered(&mut self, id: StreamId) {
+        self.last_delivered = id;
+    }
+
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &BTreeMap<Bytes, Consumer> {
+        &self.consumers
+    }
+
+    /// Only meant for loading a group.
+    pub(super) fn insert_consumer(&mut self, name: Bytes, consumer: Consumer) {
+        self.consumers.insert(name, consumer);
+    }
+
+    /// Only meant for loading a group, whose consumers are loaded along with their entries.
+    pub(super) fn insert_pending(&mut self, id: StreamId, pending: PendingEntry) {
+        self.pending.insert(id, pending);
+    }
+
+    /// Returns false if the consumer already exists.
+    pub fn create_consumer(&mut self, name: Bytes, now: DateTime<Utc>) -> bool {
+        if self.consumers.contains_key(&name) {
+            return false;
+        }
+        self.consumers.insert(
+            name,
+            Consumer {
+                seen_at: now,
+                active_at: None,
+            },
+        );
+        true
+    }
+
+    /// Removes the consumer along with its pending entries, and returns how many it had.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        self.consumers.remove(name)?;
+        let before = self.pending.len();
+        self.pending.retain(|_, pending| pending.consumer != name);
+        Some(before - self.pending.len())
+    }
+
+    /// Removes the entries from the pending ones, and returns how many were pending.
+    pub fn ack(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter()
+            .filter(|id| self.pending.remove(id).is_some())
+            .count()
+    }
+
+    // Creates the consumer if needed, and records that it was just seen.
+    fn touch(&mut self, consumer: &Bytes, now: DateTime<Utc>) -> &mut Consumer {
+        let consumer = self.consumers.entry(consumer.clone()).or_insert(Consumer {
+            seen_at: now,
+            active_at: None,
+        });
+        consumer.seen_at = now;
+        consumer
+    }
+}
+
+fn after(
+    entries: &BTreeMap<StreamId, Fields>,
+    id: StreamId,
+) -> impl Iterator<Item = (&StreamId, &Fields)> {
+    id.next()
+        .map(|start| entries.range(start..))
+        .into_iter()
+        .flatten()
+}
+
+fn too_small() -> io::Error {
+    io::Error::other(
+        "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use chrono::Utc;
+
+    use super::{ClaimOptions, NewId, Stream, StreamId, Trim};
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn stream() -> Stream {
+        let mut stream = Stream::default();
+        for ms in 1..=5 {
+            let fields = vec![(Bytes::from("field"), Bytes::from(ms.to_string()))];
+            stream.add(NewId::Explicit(id(ms, 0)), fields).unwrap();
+        }
+        stream
+    }
+
+    #[test]
+    fn test_ids_only_go_up() {
+        let mut stream = stream();
+        assert!(stream.add(NewId::Explicit(id(5, 0)), vec![]).is_err());
+        assert_eq!(stream.add(NewId::Sequence(5), vec![]).unwrap(), id(5, 1));
+        assert_eq!(stream.add(NewId::Sequence(7), vec![]).unwrap(), id(7, 0));
+        assert!(stream.add(NewId::Auto, vec![]).unwrap() > id(7, 0));
+
+        let mut empty = Stream::default();
+        assert!(empty.add(NewId::Explicit(StreamId::MIN), vec![]).is_err());
+        assert_eq!(empty.add(NewId::Sequence(0), vec![]).unwrap(), id(0, 1));
+    }
+
+    #[test]
+    fn test_parse_and_neighbours() {
+        assert_eq!(StreamId::parse(b"12-3", 0), Some(id(12, 3)));
+        assert_eq!(StreamId::parse(b"12", u64::MAX), Some(id(12, u64::MAX)));
+        assert_eq!(StreamId::parse(b"12-", 0), None);
+        assert_eq!(StreamId::parse(b"x", 0), None);
+        assert_eq!(id(1, u64::MAX).next(), Some(id(2, 0)));
+        assert_eq!(id(2, 0).prev(), Some(id(1, u64::MAX)));
+        assert_eq!(StreamId::MAX.next(), None);
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut stream = stream();
+        assert_eq!(stream.trim(Trim::MaxLen(3), Some(1)), 1);
+        assert_eq!(stream.trim(Trim::MaxLen(3), None), 1);
+        assert_eq!(stream.first_id(), Some(id(3, 0)));
+        assert_eq!(stream.trim(Trim::MinId(id(5, 0)), None), 2);
+        assert_eq!(stream.len(), 1);
+        assert_eq!(stream.last_id(), id(5, 0));
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut stream = stream();
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        let now = Utc::now();
+        assert!(stream.create_group(Bytes::from("group"), id(2, 0)));
+
+        let delivered = stream
+            .deliver(b"group", &alice, Some(2), false, now)
+            .unwrap();
+        assert_eq!(delivered.len(), 2);
+        assert_eq!(delivered[0].0, id(3, 0));
+        let delivered = stream.deliver(b"group", &bob, None, false, now).unwrap();
+        assert_eq!(delivered.len(), 1);
+
+        let group = stream.group_mut(b"group").unwrap();
+        assert_eq!(group.last_delivered(), id(5, 0));
+        assert_eq!(group.ack(&[id(3, 0), id(3, 0)]), 1);
+
+        // Claiming requires the entry to have been idle long enough.
+        let later = now + chrono::Duration::seconds(10);
+        let (claimed, _) = stream
+            .claim(
+                b"group",
+                &bob,
+                Duration::from_secs(60),
+                &[id(4, 0)],
+                ClaimOptions::default(),
+                later,
+            )
+            .unwrap();
+        assert!(claimed.is_empty());
+        stream.delete(&[id(4, 0)]);
+        let (claimed, deleted) = stream
+            .claim(
+                b"group",
+                &bob,
+                Duration::from_secs(1),
+                &[id(4, 0), id(5, 0)],
+                ClaimOptions::default(),
+                later,
+            )
+            .unwrap();
+        assert_eq!(deleted, [id(4, 0)]);
+        assert_eq!(claimed.len(), 1);
+        let pending = &stream.group(b"group").unwrap().pending()[&id(5, 0)];
+        assert_eq!((&pending.consumer, pending.deliveries), (&bob, 2));
+
+        let history = stream
+            .pending_for(b"group", &alice, StreamId::MIN, None, later)
+            .unwrap();
+        assert!(history.is_empty());
+        let history = stream
+            .pending_for(b"group", &bob, StreamId::MIN, None, later)
+            .unwrap();
+        assert_eq!(history.len(), 1);
+    }
+}
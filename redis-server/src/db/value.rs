@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bytes::Bytes;
+
+use super::{
+    sorted_set::SortedSet,
+    stream::{Stream, StreamId},
+};
+
+pub const WRONG_TYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// A value stored in the keyspace.
+///
+/// Each collection is kept in its native representation, so commands can update it in place
+/// instead of decoding and re-encoding the whole value on every call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    /// A string holding a number, kept as one so that `INCR` and its variants don't parse it
+    /// every time, like redis' `int` encoding. It is a string for every other purpose.
+    Integer(i64),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    Set(HashSet<Bytes>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The name reported by the `TYPE` command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) | Value::Integer(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The contents of a string, whichever way it is stored, or `None` for other kinds of value.
+    pub fn as_string(&self) -> Option<Bytes> {
+        match self {
+            Value::String(bytes) => Some(bytes.clone()),
+            Value::Integer(integer) => Some(Bytes::from(integer.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Roughly how many bytes the value takes.
+    ///
+    /// Like redis' `MEMORY USAGE`, collections are estimated from a few of their elements,
+    /// so this doesn't get slower as they grow.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Value::String(bytes) => bytes.len(),
+            Value::Integer(_) => std::mem::size_of::<i64>(),
+            Value::List(list) => estimate(list.len(), list.iter().map(|item| item.len() + 16)),
+            Value::Hash(hash) => estimate(
+                hash.len(),
+                hash.iter()
+                    .map(|(field, value)| field.len() + value.len() + 32),
+            ),
+            Value::Set(set) => estimate(set.len(), set.iter().map(|member| member.len() + 16)),
+            Value::SortedSet(sorted_set) => estimate(
+                sorted_set.len(),
+                // Members are kept both by score and by name.
+                sorted_set.iter().map(|(member, _)| 2 * (member.len() + 24)),
+            ),
+            Value::Stream(stream) => estimate(
+                stream.len(),
+                stream
+                    .range(StreamId::MIN, StreamId::MAX)
+                    .map(|(_, fields)| {
+                        fields
+                            .iter()
+                            .map(|(field, value)| field.len() + value.len() + 16)
+                            .sum::<usize>()
+                            + 32
+                    }),
+            ),
+        }
+    }
+
+    /// Collections are removed from the keyspace once they are empty, just like in redis.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Integer(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(sorted_set) => sorted_set.is_empty(),
+            // Streams keep their last ID and their consumer groups, even without entries.
+            Value::Stream(_) => false,
+        }
+    }
+}
+
+/// Extrapolates the size of a collection of `len` elements from the first few of `sizes`.
+fn estimate(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    const SAMPLES: usize = 5;
+    let (count, total) = sizes
+        .take(SAMPLES)
+        .fold((0, 0), |(count, total), size| (count + 1, total + size));
+    if count == 0 {
+        return 0;
+    }
+    total * len / count
+}
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, bail, Context};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use std::iter::Peekable;
+use std::{fmt, iter::Peekable};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Frame {
@@ -11,8 +11,146 @@ pub enum Frame {
     Boolean(bool),
     Double(f64),
     Array(Vec<Frame>),
+    Map(Vec<(Frame, Frame)>),
+    /// An unordered collection of distinct frames.
+    Set(Vec<Frame>),
+    /// Out of band data like pub/sub messages, as opposed to a reply to a command.
+    Push(Vec<Frame>),
     Null,
+    /// A null where an array is expected, e.g. a blocking command that timed out. RESP2 has a
+    /// null array for it, while RESP3 has a single kind of null.
+    NullArray,
     BulkString(Bytes),
+    /// A string meant to be shown as is, along with its three letter encoding, e.g. `txt`.
+    Verbatim {
+        encoding: String,
+        text: Bytes,
+    },
+    /// An integer too large for an `Integer`, in base 10.
+    BigNumber(String),
+}
+
+/// The version of the protocol a client speaks, chosen with `HELLO`.
+///
+/// RESP3 adds types that RESP2 clients don't understand, so these are downgraded for them:
+/// maps, sets and pushed data become arrays, nulls become null bulk strings, and so on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+/// Returned when the buffer does not yet hold a complete frame.
+/// More data needs to be read from the stream before trying again.
+#[derive(Debug)]
+pub struct Incomplete;
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Incomplete content")
+    }
+}
+
+impl std::error::Error for Incomplete {}
+
+/// The longest bulk string a client can send, redis' default `proto-max-bulk-len`.
+const MAX_BULK_LENGTH: i64 = 512 * 1024 * 1024;
+/// The most elements an aggregate can be declared to have, as in redis.
+const MAX_AGGREGATE_LENGTH: i64 = i32::MAX as i64;
+/// The longest line, e.g. a simple string or the header of a bulk string, as in redis.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Parses the frames sent on a connection as their bytes arrive.
+///
+/// The elements of an aggregate are taken out of the buffer as soon as they are complete,
+/// so that each of them is parsed once however many reads it takes to receive the frame.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// The aggregates being received, innermost last.
+    stack: Vec<Aggregate>,
+}
+
+impl Parser {
+    /// Returns the next frame, or `None` if more bytes need to be read first. What was parsed
+    /// is removed from the buffer either way.
+    pub fn parse(&mut self, buffer: &mut BytesMut) -> anyhow::Result<Option<Frame>> {
+        loop {
+            let mut iter = buffer.iter().peekable();
+            let token = match token(&mut iter) {
+                Ok(token) => token,
+                Err(err) if err.is::<Incomplete>() => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let consumed = buffer.len() - iter.len();
+            buffer.advance(consumed);
+
+            let mut frame = match token {
+                Token::Frame(frame) => frame,
+                Token::Aggregate(aggregate) if aggregate.is_complete() => aggregate.finish(),
+                Token::Aggregate(aggregate) => {
+                    self.stack.push(aggregate);
+                    continue;
+                }
+            };
+            loop {
+                let Some(aggregate) = self.stack.last_mut() else {
+                    return Ok(Some(frame));
+                };
+                aggregate.items.push(frame);
+                if !aggregate.is_complete() {
+                    break;
+                }
+                frame = self
+                    .stack
+                    .pop()
+                    .expect("the aggregate was just seen")
+                    .finish();
+            }
+        }
+    }
+
+    /// Whether a frame has been partly received.
+    pub fn is_parsing(&self) -> bool {
+        !self.stack.is_empty()
+    }
+}
+
+/// An array, map, set or push whose elements are being parsed.
+#[derive(Debug)]
+struct Aggregate {
+    kind: u8,
+    /// How many frames it holds, which is twice the declared length for maps.
+    length: usize,
+    items: Vec<Frame>,
+}
+
+impl Aggregate {
+    fn is_complete(&self) -> bool {
+        self.items.len() == self.length
+    }
+
+    fn finish(self) -> Frame {
+        match self.kind {
+            b'*' => Frame::Array(self.items),
+            b'~' => Frame::Set(self.items),
+            b'>' => Frame::Push(self.items),
+            _ => {
+                let mut items = self.items.into_iter();
+                let mut entries = vec![];
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    entries.push((key, value));
+                }
+                Frame::Map(entries)
+            }
+        }
+    }
+}
+
+/// A frame, or the header of an aggregate whose elements follow it.
+enum Token {
+    Frame(Frame),
+    Aggregate(Aggregate),
 }
 
 impl Frame {
@@ -21,6 +159,20 @@ impl Frame {
         deserialize(&mut x)
     }
 
+    /// Parses a single frame from the start of `content`.
+    ///
+    /// Returns the frame together with the number of bytes it consumed, so that the caller
+    /// can advance its buffer and keep the remainder (e.g. the next pipelined command).
+    /// `Ok(None)` means the frame is not complete yet.
+    pub fn parse(content: &[u8]) -> anyhow::Result<Option<(Frame, usize)>> {
+        let mut iter = content.iter().peekable();
+        match deserialize(&mut iter) {
+            Ok(frame) => Ok(Some((frame, content.len() - iter.len()))),
+            Err(err) if err.is::<Incomplete>() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
     pub fn new_error(message: String) -> Frame {
         Frame::Error(message)
     }
@@ -35,21 +187,44 @@ impl Frame {
             Frame::Boolean(_) => "Boolean",
             Frame::Double(_) => "Double",
             Frame::Array(_) => "Array",
+            Frame::Map(_) => "Map",
+            Frame::Set(_) => "Set",
+            Frame::Push(_) => "Push",
             Frame::Null => "Null",
+            Frame::NullArray => "NullArray",
+            Frame::Verbatim { .. } => "Verbatim",
+            Frame::BigNumber(_) => "BigNumber",
         }
     }
 
     pub(crate) fn new_bulk_string(content: Bytes) -> Frame {
         Frame::BulkString(content)
     }
+
+    /// A command the way clients send it: an array of bulk strings.
+    pub(crate) fn new_command(args: impl IntoIterator<Item = Bytes>) -> Frame {
+        Frame::Array(args.into_iter().map(Frame::BulkString).collect())
+    }
 }
 
 impl Frame {}
 
 fn deserialize(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Frame> {
+    match token(iter)? {
+        Token::Frame(frame) => Ok(frame),
+        Token::Aggregate(mut aggregate) => {
+            while !aggregate.is_complete() {
+                aggregate.items.push(deserialize(iter)?);
+            }
+            Ok(aggregate.finish())
+        }
+    }
+}
+
+fn token(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Token> {
     let ch = match iter.next() {
         Some(ch) => ch,
-        None => bail!("Expected a character, but got nothing"),
+        None => return Err(Incomplete.into()),
     };
 
     let content = match ch {
@@ -65,26 +240,54 @@ fn deserialize(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Fram
         }
         b'#' => boolean(iter).map(Frame::Boolean),
         b',' => double(iter).map(Frame::Double),
-        b'*' => array(iter).map(Frame::Array),
-        b'_' => {
-            let _ = null(iter);
-            Ok(Frame::Null)
-        }
+        b'*' if iter.peek() == Some(&&b'-') => null_array(iter).map(|_| Frame::NullArray),
+        b'*' | b'%' | b'~' | b'>' => return aggregate(*ch, iter).map(Token::Aggregate),
+        b'=' => verbatim(iter),
+        b'(' => big_number(iter).map(Frame::BigNumber),
+        b'_' => null(iter).map(|_| Frame::Null),
         ch => Err(anyhow!("Invalid character: {}", ch)),
     };
-    content
+    content.map(Token::Frame)
 }
 
 fn bulk_string(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Bytes> {
     let length = integer(iter)?;
-    if length < 0 {
-        bail!("Invalid length for bulk string: {}", length);
+    if !(0..=MAX_BULK_LENGTH).contains(&length) {
+        bail!("invalid bulk length");
     }
-    let content = get_bytes(iter)?;
-    if content.len() as i64 != length {
+    let length = length as usize;
+    // The content is binary safe, so we rely on the length rather than looking for `\r\n`.
+    if iter.len() < length + 2 {
+        return Err(Incomplete.into());
+    }
+    let content = iter.by_ref().take(length).copied().collect::<Vec<_>>();
+    if iter.next() != Some(&b'\r') || iter.next() != Some(&b'\n') {
         bail!("Invalid content length for bulk string: {:?}", content);
     }
-    Ok(content)
+    Ok(Bytes::from(content))
+}
+
+fn verbatim(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Frame> {
+    let content = bulk_string(iter)?;
+    // The encoding is always three bytes long, followed by a colon.
+    if content.len() < 4 || content[3] != b':' {
+        bail!("Invalid verbatim string: {:?}", content);
+    }
+    let encoding =
+        String::from_utf8(content[..3].to_vec()).context("Failed to convert to a string")?;
+    Ok(Frame::Verbatim {
+        encoding,
+        text: content.slice(4..),
+    })
+}
+
+fn big_number(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<String> {
+    let number = string(iter)?;
+    let digits = number.strip_prefix(['+', '-']).unwrap_or(&number);
+    if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        bail!("Invalid big number: {}", number);
+    }
+    Ok(number)
 }
 
 fn null(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<()> {
@@ -95,17 +298,19 @@ fn null(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn array(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Vec<Frame>> {
+/// Reads the header of an aggregate. Its elements are only allocated as they are parsed,
+/// rather than trusting the length it declares.
+fn aggregate(kind: u8, iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Aggregate> {
     let length = integer(iter)?;
-    if length < 0 {
-        bail!("Invalid length for array: {}", length);
+    if !(0..=MAX_AGGREGATE_LENGTH).contains(&length) {
+        bail!("invalid multibulk length");
     }
-    let mut result = Vec::with_capacity(length as usize);
-    for _ in 0..length {
-        let frame = deserialize(iter);
-        result.push(frame?);
-    }
-    Ok(result)
+    let length = length as usize;
+    Ok(Aggregate {
+        kind,
+        length: if kind == b'%' { length * 2 } else { length },
+        items: vec![],
+    })
 }
 
 fn double(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<f64> {
@@ -160,6 +365,14 @@ fn null_bulk_string(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result
     Ok(())
 }
 
+fn null_array(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<()> {
+    let length = integer(iter)?;
+    if length != -1 {
+        bail!("Invalid length for null array: {}", length);
+    }
+    Ok(())
+}
+
 fn get_bytes(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Bytes> {
     let mut result = BytesMut::new();
 
@@ -169,20 +382,21 @@ fn get_bytes(iter: &mut Peekable<std::slice::Iter<u8>>) -> anyhow::Result<Bytes>
                 let _dash_n = iter.next();
                 return Ok(result.freeze());
             }
+            _ if result.len() >= MAX_LINE_LENGTH => bail!("too big inline request"),
             ch => {
                 result.put_u8(*ch);
             }
         }
     }
 
-    bail!("Incomplete content")
+    Err(Incomplete.into())
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::frame::Frame;
-    use bytes::BytesMut;
+    use crate::frame::{Frame, Parser};
+    use bytes::{Bytes, BytesMut};
     use rstest::rstest;
 
     #[rstest]
@@ -192,6 +406,7 @@ mod tests {
     #[case(":-1000\r\n", Frame::Integer(-1000))]
     #[case("$6\r\nfoobar\r\n", Frame::BulkString(bytes::Bytes::from("foobar".as_bytes())))]
     #[case("$-1\r\n", Frame::NullBulkString)]
+    #[case("*-1\r\n", Frame::NullArray)]
     #[case("#t\r\n", Frame::Boolean(true))]
     #[case("#f\r\n", Frame::Boolean(false))]
     #[case(",3.15\r\n", Frame::Double(3.15_f64))]
@@ -202,6 +417,21 @@ mod tests {
         Frame::SimpleString("Foo".into()),
         Frame::Error("Bar".into())
     ]))]
+    #[case("%1\r\n+Foo\r\n:1\r\n", Frame::Map(vec![
+        (Frame::SimpleString("Foo".into()), Frame::Integer(1))
+    ]))]
+    #[case("~1\r\n:1\r\n", Frame::Set(vec![Frame::Integer(1)]))]
+    #[case(">2\r\n+a\r\n+b\r\n", Frame::Push(vec![
+        Frame::SimpleString("a".into()),
+        Frame::SimpleString("b".into())
+    ]))]
+    #[case("=8\r\ntxt:Some\r\n", Frame::Verbatim {
+        encoding: "txt".into(),
+        text: Bytes::from("Some")
+    })]
+    #[case("(-3492890328409238509324850943850943825024385\r\n", Frame::BigNumber(
+        "-3492890328409238509324850943850943825024385".into()
+    ))]
     #[case("_\r\n", Frame::Null)]
     fn test_content(#[case] input: &'static str, #[case] expected: crate::frame::Frame) {
         let input = BytesMut::from(input.as_bytes());
@@ -212,4 +442,87 @@ mod tests {
             .unwrap();
         assert_eq!(result, expected);
     }
+
+    #[rstest]
+    #[case("")]
+    #[case("$6\r\nfoo")]
+    #[case("$6\r\nfoobar")]
+    #[case("*2\r\n$3\r\nGET\r\n")]
+    #[case("*2\r\n$3\r\nGET\r\n$4\r\nna")]
+    fn test_parse_incomplete(#[case] input: &'static str) {
+        let result = Frame::parse(input.as_bytes()).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_parse_pipelined() {
+        let input = b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$4\r\na\r\nb\r\n*1";
+        let (first, consumed) = Frame::parse(input).unwrap().unwrap();
+        assert_eq!(
+            first,
+            Frame::Array(vec![Frame::BulkString(Bytes::from("PING"))])
+        );
+        assert_eq!(consumed, 14);
+
+        let rest = &input[consumed..];
+        let (second, consumed) = Frame::parse(rest).unwrap().unwrap();
+        assert_eq!(
+            second,
+            Frame::Array(vec![
+                Frame::BulkString(Bytes::from("ECHO")),
+                Frame::BulkString(Bytes::from("a\r\nb")),
+            ])
+        );
+        assert_eq!(Frame::parse(&rest[consumed..]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Frame::parse(b"?what\r\n").is_err());
+    }
+
+    #[rstest]
+    #[case(b"*100000000000\r\n".to_vec(), "invalid multibulk length")]
+    #[case(b"%-2\r\n".to_vec(), "invalid multibulk length")]
+    #[case(b"*1\r\n$536870913\r\n".to_vec(), "invalid bulk length")]
+    #[case(b"$-2\r\n".to_vec(), "Invalid length for null bulk string: -2")]
+    #[case([&b"+"[..], &[b'a'; 64 * 1024 + 1], b"\r\n"].concat(), "too big inline request")]
+    fn test_parse_oversized(#[case] input: Vec<u8>, #[case] expected: &str) {
+        let err = Frame::parse(&input).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        let err = Parser::default()
+            .parse(&mut BytesMut::from(&input[..]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[test]
+    fn test_parser_resumes_where_it_left_off() {
+        let input = b"*2\r\n$3\r\nGET\r\n*2\r\n:1\r\n%1\r\n+a\r\n_\r\n+OK\r\n";
+        let mut parser = Parser::default();
+        let mut buffer = BytesMut::new();
+        let mut frames = vec![];
+        for byte in input {
+            buffer.extend_from_slice(&[*byte]);
+            while let Some(frame) = parser.parse(&mut buffer).unwrap() {
+                frames.push(frame);
+            }
+            // Complete elements don't stay in the buffer to be parsed again.
+            assert!(buffer.len() < 9, "{buffer:?} is left");
+        }
+        assert!(!parser.is_parsing());
+        assert_eq!(
+            frames,
+            [
+                Frame::Array(vec![
+                    Frame::BulkString(Bytes::from("GET")),
+                    Frame::Array(vec![
+                        Frame::Integer(1),
+                        Frame::Map(vec![(Frame::SimpleString("a".into()), Frame::Null)]),
+                    ]),
+                ]),
+                Frame::SimpleString("OK".into()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,125 @@
+/// Matches `string` against a glob-style pattern the way redis does, e.g. for `PSUBSCRIBE`:
+///
+/// - `?` matches any single character and `*` any number of characters.
+/// - `[abc]` matches one of the characters, `[a-z]` a range and `[^a]` anything else.
+/// - `\` escapes the character that follows it.
+///
+/// Only the last star seen is ever backtracked to, since any later match of it would also be
+/// found through the stars that follow, so this runs in `O(pattern * string)` at worst rather
+/// than trying every way of splitting the string between the stars.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // The pattern right after the last star, and where the string it is matched against starts.
+    let mut backtrack = None;
+    while s < string.len() {
+        let next = match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, s));
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => {
+                let (matched, rest) = match_class(&pattern[p + 1..], string[s]);
+                matched.then_some(pattern.len() - rest.len())
+            }
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == string[s]).then_some(p + 2),
+            Some(ch) => (*ch == string[s]).then_some(p + 1),
+            None => None,
+        };
+        match (next, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            // Let the star take one more character and try again from there.
+            (None, Some((star, start))) => {
+                p = star;
+                s = start + 1;
+                backtrack = Some((star, s));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == b'*')
+}
+
+/// Matches a character against a class like `[a-z]`, starting right after the `[`.
+/// Returns whether it matched and the rest of the pattern after the closing `]`.
+fn match_class(class: &[u8], ch: u8) -> (bool, &[u8]) {
+    let (negate, mut class) = match class.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    loop {
+        match class {
+            // Like redis, an unterminated class ends with the pattern.
+            [] => break,
+            [b']', rest @ ..] => {
+                class = rest;
+                break;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == ch;
+                class = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&ch);
+                class = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == ch;
+                class = rest;
+            }
+        }
+    }
+    (matched != negate, class)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::matches;
+
+    #[rstest]
+    #[case("*", "anything", true)]
+    #[case("news.*", "news.sports", true)]
+    #[case("news.*", "weather", false)]
+    #[case("h?llo", "hello", true)]
+    #[case("h?llo", "hllo", false)]
+    #[case("h[ae]llo", "hallo", true)]
+    #[case("h[ae]llo", "hillo", false)]
+    #[case("h[^e]llo", "hallo", true)]
+    #[case("h[^e]llo", "hello", false)]
+    #[case("h[a-b]llo", "hbllo", true)]
+    #[case("h[b-a]llo", "hbllo", true)]
+    #[case("h\\*llo", "h*llo", true)]
+    #[case("h\\*llo", "hello", false)]
+    #[case("a**b", "axxb", true)]
+    #[case("*", "", true)]
+    #[case("a*", "", false)]
+    #[case("*b*", "abc", true)]
+    #[case("*.txt", "a.txt.gz", false)]
+    #[case("a*b?d", "axbxbcd", true)]
+    #[case("[a", "a", true)]
+    #[case("\\", "\\", true)]
+    fn test_matches(#[case] pattern: &str, #[case] string: &str, #[case] expected: bool) {
+        assert_eq!(matches(pattern.as_bytes(), string.as_bytes()), expected);
+    }
+
+    #[test]
+    fn test_many_stars_dont_backtrack_exponentially() {
+        let pattern = format!("{}b", "a*".repeat(30));
+        let string = "a".repeat(1000);
+        assert!(!matches(pattern.as_bytes(), string.as_bytes()));
+        assert!(matches(pattern.as_bytes(), format!("{string}b").as_bytes()));
+    }
+}
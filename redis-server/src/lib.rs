@@ -1,4 +1,14 @@
+pub mod acl;
+pub mod clients;
+pub mod cluster;
 pub mod cmd;
+pub mod config;
 pub mod connection;
 pub mod db;
 pub mod frame;
+pub mod glob;
+pub mod pubsub;
+pub mod replication;
+pub mod scripting;
+pub mod slowlog;
+pub mod tracking;
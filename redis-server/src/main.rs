@@ -1,84 +1,258 @@
 use std::{
-    net::{TcpListener, TcpStream},
+    net::SocketAddr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use crossbeam::channel::{bounded, Receiver};
-use redis_server::{cmd::Command, db::Db, frame::Frame};
+use anyhow::bail;
+use log::{error, info};
+use redis_server::{
+    acl,
+    clients::ClientInfo,
+    cluster,
+    cmd::{Command, Propagation, READ_ONLY},
+    config::Config,
+    connection::Connection,
+    db::Db,
+    frame::Frame,
+    replication,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
+};
 
-/// Tokio is needed for the background tasks of purging expired keys. More on this can be seen in the `db` module.
-/// Everything else is synchronous.
+/// Every client is served by its own task, so idle clients only cost their buffers.
+/// Commands themselves are executed synchronously: they only hold the `Db` locks for as long
+/// as it takes to change the keyspace, and never wait on the network.
+///
+/// On Ctrl-C, the server stops accepting clients, waits for the commands being executed to
+/// finish and saves a final snapshot before exiting.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
-    let listener = TcpListener::bind("127.0.0.1:6379")?;
-    let (sender, receiver) = bounded::<TcpStream>(200000);
-    let db = Arc::new(Db::new()?);
+    let config = Config::from_args(std::env::args().skip(1))?;
+    let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+    let db = Arc::new(Db::new(&config)?);
+    if config.appendonly {
+        replay_aof(&db).await?;
+        db.enable_aof()?;
+    }
 
-    let mut threads = Vec::with_capacity(100);
+    // Connections are told to stop through `shutdown`. Each one holds a clone of `done`,
+    // so the receiving end is closed once all of them are gone.
+    let (shutdown, _) = watch::channel(false);
+    let (done, mut all_done) = mpsc::channel::<()>(1);
 
-    for _ in 0..threads.capacity() {
-        let receiver = receiver.clone();
+    // Replicas follow the master set on startup or with `REPLICAOF`.
+    tokio::spawn({
         let db = db.clone();
-        let thread = std::thread::spawn(move || {
-            stream_receiver(receiver, db);
-        });
-        threads.push(thread);
-    }
+        let shutdown = shutdown.subscribe();
+        let done = done.clone();
+        async move {
+            replication::follow_master(db, shutdown).await;
+            drop(done);
+        }
+    });
+
+    // In cluster mode, nodes keep each other up to date over the cluster bus.
+    tokio::spawn({
+        let db = db.clone();
+        let shutdown = shutdown.subscribe();
+        let done = done.clone();
+        async move {
+            cluster::run_bus(db, shutdown).await;
+            drop(done);
+        }
+    });
 
-    for stream in listener.incoming() {
-        let stream = stream?;
-        sender.send(stream).unwrap();
+    tokio::select! {
+        result = accept(&listener, &db, &shutdown, &done) => result?,
+        _ = tokio::signal::ctrl_c() => info!("Shutting down"),
     }
 
-    for thread in threads {
-        thread.join().unwrap();
+    drop(listener);
+    let _ = shutdown.send(true);
+    drop(done);
+    let _ = all_done.recv().await;
+
+    if let Err(err) = db.shutdown() {
+        error!("Failed to save before shutting down: {err}");
     }
     Ok(())
 }
 
-fn stream_receiver(receiver: Receiver<TcpStream>, db: Arc<Db>) {
-    for stream in receiver {
+async fn accept(
+    listener: &TcpListener,
+    db: &Arc<Db>,
+    shutdown: &watch::Sender<bool>,
+    done: &mpsc::Sender<()>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
         let db = db.clone();
-        handle_stream(stream, db)
+        let shutdown = shutdown.subscribe();
+        let done = done.clone();
+        tokio::spawn(async move {
+            handle_stream(stream, addr, db, shutdown).await;
+            drop(done);
+        });
     }
 }
 
-fn handle_stream(stream: TcpStream, db: Arc<Db>) {
-    let mut connection = redis_server::connection::Connection::new(stream);
+/// Executes the commands from the AOF. Their replies are discarded, and so are their errors,
+/// since they are the same ones the clients got when the commands were first executed.
+async fn replay_aof(db: &Db) -> std::io::Result<()> {
+    let mut connection = Connection::new(tokio::io::empty());
+    for frame in db.load_aof()? {
+        let logged_frame = frame.clone();
+        let Ok(command) = Command::from_frame(frame) else {
+            continue;
+        };
+        // Transactions are appended between `MULTI` and `EXEC`, and are only executed if the
+        // whole transaction made it to the file.
+        if let Some(command) = command.queue(&mut connection, Some(&logged_frame)) {
+            let db = db.select(connection.db_index());
+            let _ = command.execute_and_notify(&mut connection, &db, &logged_frame);
+        }
+        connection.flush().await?;
+    }
+    Ok(())
+}
 
+/// Serves the client until it disconnects, is killed with `CLIENT KILL`, or until the server
+/// shuts down. Shutting down only interrupts a client that is waiting, whether for its next
+/// command or on a blocking command.
+async fn handle_stream(
+    stream: TcpStream,
+    addr: SocketAddr,
+    db: Arc<Db>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut connection = Connection::new(stream);
+    connection.set_addr(addr);
+    connection.set_user(db.acl().initial_user());
+    let client = db.clients().register(connection.client_info(""));
+    let mut command_name = String::new();
     loop {
-        let bytes = connection.read_bytes().unwrap();
-        if let Some(bytes) = bytes {
-            let frame = match Frame::deserialize(&bytes)
-                .context(format!("Failed to deserialize {bytes:?}"))
-            {
-                Ok(frame) => frame,
-                Err(err) => {
-                    connection.send_error(err.to_string().as_str()).unwrap();
-                    continue;
-                }
-            };
+        db.clients().update(connection.client_info(&command_name));
+        let frame = tokio::select! {
+            frame = connection.read_frame() => frame,
+            _ = client.killed() => break,
+            _ = shutdown.changed() => break,
+        };
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(err) => {
+                // The rest of the buffer can't be trusted once a frame is malformed,
+                // so just like redis we reply with the error and close the connection.
+                connection.send_error(format!("ERR Protocol error: {err}").as_str());
+                break;
+            }
+        };
+        command_name = name(&frame);
 
-            let command = match Command::from_frame(frame) {
-                Ok(command) => command,
-                Err(err) => {
-                    connection.send_error(err.to_string().as_str()).unwrap();
-                    continue;
+        // The frame is kept around so that it can be appended to the AOF and fed to the
+        // replicas as is, as well as to the clients monitoring the server.
+        let logged_frame = frame.clone();
+        let command =
+            Command::from_client_frame(frame, connection.user(), &db).and_then(|command| {
+                if command.is_write() && db.is_read_only_replica() {
+                    bail!(READ_ONLY);
                 }
-            };
-
-            if let Err(err) = command
-                .execute(&mut connection, &db)
-                .context("Failed to execute command")
-            {
-                connection.send_error(err.to_string().as_str()).unwrap();
+                // In cluster mode, clients are redirected to the node serving the keys.
+                let asking = connection.take_asking() || command.is_asking();
+                db.check_slot(&acl::command_keys(&logged_frame), asking)?;
+                Ok(command)
+            });
+        let command = match command {
+            Ok(command) => command,
+            Err(err) => {
+                if let Some(transaction) = connection.transaction_mut() {
+                    transaction.fail();
+                }
+                connection.send_error(err.to_string().as_str());
                 continue;
             }
+        };
+        let Some(command) = command.queue(&mut connection, Some(&logged_frame)) else {
+            continue;
+        };
+        let db = db.select(connection.db_index());
+        let monitored = command.is_monitored();
+        let shown = command.redact(&logged_frame).into_owned();
+        let allowed_while_busy = command.is_allowed_while_busy();
+        let exclusive = command.is_exclusive();
+
+        let run = || {
+            let started = Instant::now();
+            let result = command.make_room(&db).and_then(|_| {
+                let propagation = command.propagation();
+                let execute = || command.execute_and_notify(&mut connection, &db, &logged_frame);
+                match propagation {
+                    Propagation::None => execute(),
+                    Propagation::Verbatim => db.log_write(Some(logged_frame.clone()), execute),
+                    Propagation::Effects => db.log_write(None, execute),
+                }
+            });
+            (result, started.elapsed())
+        };
+        let (result, duration) = if allowed_while_busy {
+            run()
         } else {
-            break;
+            db.run_client_command(exclusive, run)
+                .await
+                .unwrap_or_else(|err| (Err(err), Duration::ZERO))
+        };
+        match result {
+            Ok(Some(frame)) => connection.write_frame(frame),
+            Ok(None) => {}
+            Err(err) => connection.send_error(err.to_string().as_str()),
         }
+        db.slowlog()
+            .log(&shown, duration, Some(addr), connection.name());
+        let monitored = Some(&shown).filter(|_| monitored);
+        db.clients().executed(db.index(), Some(addr), monitored);
+
+        if let Some(feed) = connection.take_replica_feed() {
+            // The client is a replica, which is only sent the replication stream from now on.
+            db.clients().update(ClientInfo {
+                replica: true,
+                ..connection.client_info(&command_name)
+            });
+            replication::serve_replica(connection, feed, &db, &client, shutdown).await;
+            return;
+        }
+
+        if let Some(reply) = connection.take_parked() {
+            db.clients().update(ClientInfo {
+                blocked: true,
+                ..connection.client_info(&command_name)
+            });
+            // The client is blocked, so its reply is sent before reading anything else.
+            if connection.flush().await.is_err() {
+                break;
+            }
+            let frame = tokio::select! {
+                frame = reply => frame,
+                _ = client.killed() => break,
+                _ = shutdown.changed() => break,
+            };
+            connection.write_frame(frame);
+        }
+    }
+    let _ = connection.flush().await;
+}
+
+/// The name of the command the frame is for, as shown by `CLIENT LIST`.
+fn name(frame: &Frame) -> String {
+    match frame {
+        Frame::Array(args) => match args.first() {
+            Some(Frame::BulkString(name)) => String::from_utf8_lossy(name).to_lowercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
     }
 }
@@ -0,0 +1,426 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use crate::{
+    config::{Config, OutputBufferLimit},
+    connection::encode,
+    frame::{Frame, Protocol},
+    glob,
+};
+
+/// What a client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    Channel(Bytes),
+    /// A glob-style pattern matched against the channels messages are published to.
+    Pattern(Bytes),
+}
+
+/// The channels and patterns clients are subscribed to.
+///
+/// Each subscriber gets its messages through a channel, so publishing never waits on a slow
+/// client. Its connection writes them to the socket while waiting for the next command.
+/// Just like in redis, a client that doesn't keep up is disconnected once what is queued for
+/// it goes over `client-output-buffer-limit pubsub`, rather than letting it grow unbounded.
+#[derive(Debug, Clone, Default)]
+pub struct PubSub {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    next_id: u64,
+    subscriptions: HashMap<Target, HashMap<u64, Outbox>>,
+    limit: OutputBufferLimit,
+}
+
+/// Where the messages of a subscriber are sent, along with their size in bytes.
+#[derive(Debug, Clone)]
+struct Outbox {
+    sender: UnboundedSender<(Frame, u64)>,
+    queue: Arc<Queue>,
+}
+
+/// What is queued for a subscriber, which is shared by its [`Outbox`] and itself.
+#[derive(Debug, Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    /// Notified once the subscriber went over the limit.
+    overflowed: Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    bytes: u64,
+    /// Since when more than the soft limit is queued.
+    over_soft_limit: Option<Instant>,
+    /// Once set, nothing more is queued and the subscriber is disconnected.
+    overflowed: bool,
+}
+
+impl Outbox {
+    fn send(&self, frame: Frame, size: u64, limit: OutputBufferLimit) {
+        let mut state = self.queue.state.lock().unwrap();
+        if state.overflowed {
+            return;
+        }
+        state.bytes += size;
+        let now = Instant::now();
+        if limit.soft > 0 && state.bytes > limit.soft {
+            state.over_soft_limit.get_or_insert(now);
+        } else {
+            state.over_soft_limit = None;
+        }
+        let over_soft_limit = state
+            .over_soft_limit
+            .is_some_and(|since| now - since >= Duration::from_secs(limit.soft_seconds));
+        if (limit.hard > 0 && state.bytes > limit.hard) || over_soft_limit {
+            state.overflowed = true;
+            self.queue.overflowed.notify_one();
+            return;
+        }
+        // The client may have just disconnected.
+        let _ = self.sender.send((frame, size));
+    }
+}
+
+impl PubSub {
+    /// Sends the message to every client subscribed to the channel, or to a pattern matching it.
+    /// Returns the number of clients that received it.
+    pub fn publish(&self, channel: Bytes, message: Bytes) -> usize {
+        let registry = self.inner.lock().unwrap();
+        // Like redis, subscribers of the channel get the message before those of patterns.
+        let channel_subscribers = registry
+            .subscriptions
+            .get_key_value(&Target::Channel(channel.clone()));
+        let pattern_subscribers = registry.subscriptions.iter().filter(|(target, _)| {
+            matches!(target, Target::Pattern(pattern) if glob::matches(pattern, &channel))
+        });
+
+        let mut receivers = 0;
+        let mut encoded = BytesMut::new();
+        for (target, subscribers) in channel_subscribers.into_iter().chain(pattern_subscribers) {
+            let frame = match target {
+                Target::Channel(_) => Frame::Push(vec![
+                    Frame::BulkString(Bytes::from_static(b"message")),
+                    Frame::BulkString(channel.clone()),
+                    Frame::BulkString(message.clone()),
+                ]),
+                Target::Pattern(pattern) => Frame::Push(vec![
+                    Frame::BulkString(Bytes::from_static(b"pmessage")),
+                    Frame::BulkString(pattern.clone()),
+                    Frame::BulkString(channel.clone()),
+                    Frame::BulkString(message.clone()),
+                ]),
+            };
+            encoded.clear();
+            encode(&frame, Protocol::Resp3, &mut encoded);
+            // The client may have just disconnected or gone over the limit, it still counts.
+            for outbox in subscribers.values() {
+                outbox.send(frame.clone(), encoded.len() as u64, registry.limit);
+                receivers += 1;
+            }
+        }
+        receivers
+    }
+
+    /// The channels with at least one subscriber, optionally only the ones matching `pattern`.
+    pub fn channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .subscriptions
+            .keys()
+            .filter_map(|target| match target {
+                Target::Channel(channel) => Some(channel),
+                Target::Pattern(_) => None,
+            })
+            .filter(|channel| pattern.is_none_or(|pattern| glob::matches(pattern, channel)))
+            .cloned()
+            .collect()
+    }
+
+    /// The number of clients subscribed to the channel. Patterns are not taken into account.
+    pub fn subscribers(&self, channel: Bytes) -> usize {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .subscriptions
+            .get(&Target::Channel(channel))
+            .map_or(0, HashMap::len)
+    }
+
+    /// The number of patterns with at least one subscriber.
+    pub fn patterns(&self) -> usize {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .subscriptions
+            .keys()
+            .filter(|target| matches!(target, Target::Pattern(_)))
+            .count()
+    }
+
+    /// Applies `client-output-buffer-limit pubsub`.
+    pub fn configure(&self, config: &Config) {
+        self.inner.lock().unwrap().limit = config.client_output_buffer_limit_pubsub;
+    }
+
+    fn register(&self, id: u64, target: Target, outbox: Outbox) {
+        let mut registry = self.inner.lock().unwrap();
+        registry
+            .subscriptions
+            .entry(target)
+            .or_default()
+            .insert(id, outbox);
+    }
+
+    fn unregister(&self, id: u64, target: &Target) {
+        let mut registry = self.inner.lock().unwrap();
+        if let Some(subscribers) = registry.subscriptions.get_mut(target) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                registry.subscriptions.remove(target);
+            }
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        let mut registry = self.inner.lock().unwrap();
+        registry.next_id += 1;
+        registry.next_id
+    }
+}
+
+/// The state of a connection that has subscribed to channels or patterns.
+///
+/// The confirmations are returned to be written by the connection right away, before any
+/// message published to the new subscriptions can be received.
+pub struct Subscriber {
+    id: u64,
+    pubsub: PubSub,
+    targets: HashSet<Target>,
+    outbox: Outbox,
+    receiver: UnboundedReceiver<(Frame, u64)>,
+}
+
+impl Subscriber {
+    pub fn new(pubsub: PubSub) -> Self {
+        let (sender, receiver) = unbounded_channel();
+        Self {
+            id: pubsub.next_id(),
+            pubsub,
+            targets: HashSet::new(),
+            outbox: Outbox {
+                sender,
+                queue: Arc::default(),
+            },
+            receiver,
+        }
+    }
+
+    /// Waits for the next message published to the client. Returns `None` once the client went
+    /// over the limit, after which it has to be disconnected.
+    pub async fn receive(&mut self) -> Option<Frame> {
+        // We hold a sender ourselves, so the channel is never closed.
+        let (frame, size) = self
+            .receiver
+            .recv()
+            .await
+            .expect("the subscriber holds a sender");
+        let mut state = self.outbox.queue.state.lock().unwrap();
+        if state.overflowed {
+            return None;
+        }
+        state.bytes -= size;
+        if state.bytes == 0 {
+            state.over_soft_limit = None;
+        }
+        Some(frame)
+    }
+
+    /// Waits until the client goes over the limit, even while it isn't receiving its messages
+    /// because it doesn't read its replies.
+    pub async fn overflowed(&self) {
+        self.outbox.queue.overflowed.notified().await
+    }
+
+    /// The number of channels and patterns the client is subscribed to.
+    pub fn count(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// The number of patterns the client is subscribed to.
+    pub fn patterns(&self) -> usize {
+        self.targets
+            .iter()
+            .filter(|target| matches!(target, Target::Pattern(_)))
+            .count()
+    }
+
+    pub fn subscribe(&mut self, target: Target) -> Frame {
+        if self.targets.insert(target.clone()) {
+            self.pubsub
+                .register(self.id, target.clone(), self.outbox.clone());
+        }
+        self.confirmation(&target, true)
+    }
+
+    pub fn unsubscribe(&mut self, target: &Target) -> Frame {
+        if self.targets.remove(target) {
+            self.pubsub.unregister(self.id, target);
+        }
+        self.confirmation(target, false)
+    }
+
+    /// Unsubscribes from every channel, or every pattern.
+    pub fn unsubscribe_all(&mut self, patterns: bool) -> Vec<Frame> {
+        let targets: Vec<_> = self
+            .targets
+            .iter()
+            .filter(|target| matches!(target, Target::Pattern(_)) == patterns)
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            // The client still gets a confirmation, without a channel.
+            let kind = if patterns {
+                "punsubscribe"
+            } else {
+                "unsubscribe"
+            };
+            return vec![Frame::Push(vec![
+                Frame::BulkString(Bytes::from_static(kind.as_bytes())),
+                Frame::NullBulkString,
+                Frame::Integer(self.count() as i64),
+            ])];
+        }
+        targets
+            .iter()
+            .map(|target| self.unsubscribe(target))
+            .collect()
+    }
+
+    fn confirmation(&self, target: &Target, subscribe: bool) -> Frame {
+        let (kind, name) = match (target, subscribe) {
+            (Target::Channel(channel), true) => ("subscribe", channel),
+            (Target::Channel(channel), false) => ("unsubscribe", channel),
+            (Target::Pattern(pattern), true) => ("psubscribe", pattern),
+            (Target::Pattern(pattern), false) => ("punsubscribe", pattern),
+        };
+        Frame::Push(vec![
+            Frame::BulkString(Bytes::from_static(kind.as_bytes())),
+            Frame::BulkString(name.clone()),
+            Frame::Integer(self.count() as i64),
+        ])
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for target in self.targets.drain() {
+            self.pubsub.unregister(self.id, &target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{PubSub, Subscriber, Target};
+    use crate::{
+        config::{Config, OutputBufferLimit},
+        frame::Frame,
+    };
+
+    #[tokio::test]
+    async fn test_messages_are_delivered_to_channels_and_patterns() {
+        let bulk = |value: &'static str| Frame::BulkString(Bytes::from(value));
+        let pubsub = PubSub::default();
+        let mut channel = Subscriber::new(pubsub.clone());
+        let mut pattern = Subscriber::new(pubsub.clone());
+        assert_eq!(
+            channel.subscribe(Target::Channel(Bytes::from("news"))),
+            Frame::Push(vec![bulk("subscribe"), bulk("news"), Frame::Integer(1)])
+        );
+        assert_eq!(
+            pattern.subscribe(Target::Pattern(Bytes::from("news*"))),
+            Frame::Push(vec![bulk("psubscribe"), bulk("news*"), Frame::Integer(1)])
+        );
+        pattern.subscribe(Target::Channel(Bytes::from("news")));
+
+        // Channel subscribers get the message before pattern ones, even from the same client.
+        assert_eq!(pubsub.publish(Bytes::from("news"), Bytes::from("hi")), 3);
+        let message = Frame::Push(vec![bulk("message"), bulk("news"), bulk("hi")]);
+        assert_eq!(channel.receive().await, Some(message.clone()));
+        assert_eq!(pattern.receive().await, Some(message));
+        assert_eq!(
+            pattern.receive().await,
+            Some(Frame::Push(vec![
+                bulk("pmessage"),
+                bulk("news*"),
+                bulk("news"),
+                bulk("hi"),
+            ]))
+        );
+
+        assert_eq!(
+            pubsub.publish(Bytes::from("news.sports"), Bytes::from("goal")),
+            1
+        );
+        assert_eq!(
+            pattern.receive().await,
+            Some(Frame::Push(vec![
+                bulk("pmessage"),
+                bulk("news*"),
+                bulk("news.sports"),
+                bulk("goal"),
+            ]))
+        );
+        assert_eq!(
+            pubsub.publish(Bytes::from("weather"), Bytes::from("rain")),
+            0
+        );
+
+        pattern.unsubscribe(&Target::Pattern(Bytes::from("news*")));
+        drop(channel);
+        assert_eq!(pubsub.publish(Bytes::from("news"), Bytes::from("bye")), 1);
+        assert_eq!(
+            pattern.receive().await,
+            Some(Frame::Push(vec![
+                bulk("message"),
+                bulk("news"),
+                bulk("bye")
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_over_the_limit_is_disconnected() {
+        let pubsub = PubSub::default();
+        pubsub.configure(&Config {
+            client_output_buffer_limit_pubsub: OutputBufferLimit {
+                hard: 100,
+                soft: 0,
+                soft_seconds: 0,
+            },
+            ..Config::default()
+        });
+        let mut subscriber = Subscriber::new(pubsub.clone());
+        subscriber.subscribe(Target::Channel(Bytes::from("channel")));
+
+        let message = Bytes::from(vec![b'a'; 40]);
+        pubsub.publish(Bytes::from("channel"), message.clone());
+        assert!(subscriber.receive().await.is_some());
+        pubsub.publish(Bytes::from("channel"), message.clone());
+        pubsub.publish(Bytes::from("channel"), message.clone());
+        subscriber.overflowed().await;
+        assert_eq!(subscriber.receive().await, None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Replication over the network: a master feeds its replication stream to the replicas that
+//! connect to it, and a replica follows the stream of its master. See [`Replication`] for the
+//! stream itself.
+//!
+//! [`Replication`]: crate::db::Replication
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail};
+use bytes::{Buf, Bytes, BytesMut};
+use log::{error, info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::watch,
+    time::Instant,
+};
+
+use crate::{
+    clients::ClientHandle,
+    cmd::{replconf::Replconf, Command},
+    connection::{encode, Connection},
+    db::{Db, LinkState, ReplicaFeed},
+    frame::{Frame, Protocol},
+};
+
+/// How long a replica waits to hear from its master before reconnecting. Masters ping their
+/// replicas every 10 seconds when there are no writes.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Sends the replication stream to a replica that was registered with `PSYNC`, until it
+/// disconnects. All the replica sends from then on is `REPLCONF ACK`.
+pub async fn serve_replica(
+    mut connection: Connection,
+    mut feed: ReplicaFeed,
+    db: &Db,
+    client: &ClientHandle,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let id = connection.id();
+    match feed.snapshot().await {
+        // Unlike a bulk string, the snapshot isn't followed by a CRLF.
+        Some(Ok(rdb)) => {
+            connection.write_bytes(format!("${}\r\n", rdb.len()).as_bytes());
+            connection.write_bytes(&rdb);
+        }
+        Some(Err(err)) => {
+            error!("Failed to write the snapshot for replica {id}: {err}");
+            db.with_replication(|replication| replication.remove_replica(id));
+            return;
+        }
+        None => {}
+    }
+
+    loop {
+        if connection.flush().await.is_err() {
+            break;
+        }
+        tokio::select! {
+            bytes = feed.next() => match bytes {
+                Some(bytes) => connection.write_bytes(&bytes),
+                // The replica was dropped, e.g. because we synced with a new master.
+                None => break,
+            },
+            frame = connection.read_frame() => match frame {
+                Ok(Some(frame)) => {
+                    if let Ok(Command::Replconf(replconf)) = Command::from_frame(frame) {
+                        let _ = replconf.execute(&mut connection, db);
+                    }
+                }
+                _ => break,
+            },
+            _ = client.killed() => break,
+            _ = shutdown.changed() => break,
+        }
+    }
+    db.with_replication(|replication| replication.remove_replica(id));
+}
+
+/// Keeps the keyspace in sync with the master set with `REPLICAOF`, reconnecting whenever the
+/// link is lost, until the server shuts down.
+pub async fn follow_master(db: Arc<Db>, mut shutdown: watch::Receiver<bool>) {
+    let mut master = db.with_replication(|replication| replication.watch_master());
+    loop {
+        let target = master.borrow_and_update().clone();
+        let link = async {
+            match &target {
+                Some((host, port)) => stay_in_sync(&db, host, *port).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = link => {}
+            changed = master.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = shutdown.changed() => return,
+        }
+    }
+}
+
+async fn stay_in_sync(db: &Db, host: &str, port: u16) {
+    // Commands are executed against a connection of their own, whose replies are discarded.
+    // It outlives the link, so that the database the master selected is kept when carrying
+    // on from where we left off.
+    let mut applier = Connection::new(tokio::io::empty());
+    loop {
+        if let Err(err) = sync(db, &mut applier, host, port).await {
+            warn!("Lost the link with the master {host}:{port}: {err}");
+        }
+        db.with_replication(|replication| replication.set_link_state(LinkState::Connect));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Syncs with the master, from a snapshot or from where we left off, and then applies its
+/// stream. Only returns once the link is lost.
+async fn sync(db: &Db, applier: &mut Connection, host: &str, port: u16) -> anyhow::Result<()> {
+    db.with_replication(|replication| replication.set_link_state(LinkState::Connecting));
+    let stream = TcpStream::connect((host, port)).await?;
+    // Clients monitoring the replica see the commands as coming from the master.
+    applier.set_addr(stream.peer_addr()?);
+    let mut master = MasterLink::new(stream);
+    master.request(["PING"]).await?;
+    let listening_port = db.config().port.to_string();
+    master
+        .request(["REPLCONF", "listening-port", &listening_port])
+        .await?;
+    master.request(["REPLCONF", "capa", "psync2"]).await?;
+
+    let (replid, offset) = db.with_replication(|replication| replication.psync_position());
+    let reply = match master
+        .request(["PSYNC", &replid, &offset.to_string()])
+        .await?
+    {
+        Frame::SimpleString(reply) => reply,
+        frame => bail!("Unexpected reply to PSYNC: {frame:?}"),
+    };
+    match reply.split_whitespace().collect::<Vec<_>>()[..] {
+        ["FULLRESYNC", replid, offset] => {
+            db.with_replication(|replication| replication.set_link_state(LinkState::Sync));
+            let rdb = master.read_snapshot().await?;
+            db.sync_from_master(replid.to_owned(), offset.parse()?, &rdb)?;
+            info!("Synced with the master {host}:{port} from a snapshot");
+        }
+        ["CONTINUE", ..] => {
+            let replid = reply.split_whitespace().nth(1).map(str::to_owned);
+            db.with_replication(|replication| replication.continue_with_master(replid));
+            info!("Carrying on with the master {host}:{port} from where we left off");
+        }
+        _ => bail!("Unexpected reply to PSYNC: {reply}"),
+    }
+    db.with_replication(|replication| replication.set_link_state(LinkState::Connected));
+
+    let mut ack = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let deadline = master.last_io + TIMEOUT;
+        tokio::select! {
+            frame = master.read_frame() => {
+                let (frame, raw) = frame?;
+                db.with_replication(|replication| replication.touch_link());
+                let command = Command::from_frame(frame.clone());
+                // The acknowledged offset doesn't include the request itself.
+                if let Ok(Command::Replconf(Replconf::GetAck)) = &command {
+                    master.ack(db).await?;
+                }
+                apply(db, applier, command, &frame, raw);
+                applier.flush().await?;
+            }
+            _ = ack.tick() => master.ack(db).await?,
+            _ = tokio::time::sleep_until(deadline) => {
+                bail!("Timeout, nothing was received for {} seconds", TIMEOUT.as_secs());
+            }
+        }
+    }
+}
+
+/// Executes a command of the master's stream, `raw` being the bytes it was sent as.
+fn apply(
+    db: &Db,
+    applier: &mut Connection,
+    command: anyhow::Result<Command>,
+    frame: &Frame,
+    raw: Bytes,
+) {
+    let command = match command {
+        Ok(command) => command,
+        Err(err) => {
+            warn!("Ignoring a command from the master: {err}");
+            db.apply_from_master(frame, raw, false, || applier.db_index());
+            return;
+        }
+    };
+    // Pings and acknowledgement requests only keep the link alive.
+    let log = !matches!(command, Command::Ping(_) | Command::Replconf(_));
+    let db = &db.select(applier.db_index());
+    db.run_command(command.is_exclusive(), || {
+        db.apply_from_master(frame, raw, log, || {
+            // Transactions are sent between `MULTI` and `EXEC`, just like in the AOF.
+            if let Some(command) = command.queue(applier, Some(frame)) {
+                let monitored = Some(frame).filter(|_| command.is_monitored());
+                if let Err(err) = command.execute_and_notify(applier, db, frame) {
+                    warn!("A command from the master failed: {err}");
+                }
+                db.clients().executed(db.index(), applier.addr(), monitored);
+            }
+            applier.db_index()
+        })
+    });
+}
+
+/// The connection of a replica with its master. Unlike with clients, the exact bytes of
+/// every frame matter, since the replication offset counts them.
+struct MasterLink {
+    stream: TcpStream,
+    buffer: BytesMut,
+    last_io: Instant,
+}
+
+impl MasterLink {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: BytesMut::with_capacity(4 * 1024),
+            last_io: Instant::now(),
+        }
+    }
+
+    async fn send<const N: usize>(&mut self, args: [&str; N]) -> anyhow::Result<()> {
+        let mut buffer = BytesMut::new();
+        let frame = Frame::new_command(args.map(|arg| Bytes::from(arg.to_owned())));
+        encode(&frame, Protocol::Resp2, &mut buffer);
+        self.stream.write_all(&buffer).await?;
+        Ok(())
+    }
+
+    /// Sends a command of the handshake and waits for its reply, which must not be an error.
+    async fn request<const N: usize>(&mut self, args: [&str; N]) -> anyhow::Result<Frame> {
+        self.send(args).await?;
+        let (reply, _) = self.read_frame().await?;
+        if let Frame::Error(err) = reply {
+            bail!("The master replied to {} with: {err}", args[0]);
+        }
+        Ok(reply)
+    }
+
+    /// Tells the master how much of the stream we have processed.
+    async fn ack(&mut self, db: &Db) -> anyhow::Result<()> {
+        let offset = db.with_replication(|replication| replication.offset());
+        self.send(["REPLCONF", "ACK", &offset.to_string()]).await
+    }
+
+    /// Returns the next frame along with the bytes it was sent as.
+    /// This can be cancelled without losing any data.
+    async fn read_frame(&mut self) -> anyhow::Result<(Frame, Bytes)> {
+        loop {
+            if let Some((frame, len)) = Frame::parse(&self.buffer)? {
+                return Ok((frame, self.buffer.split_to(len).freeze()));
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Reads the snapshot of a full sync, which is sent as `$<len>\r\n` followed by its bytes.
+    async fn read_snapshot(&mut self) -> anyhow::Result<Bytes> {
+        let end = loop {
+            if let Some(end) = self.buffer.windows(2).position(|bytes| bytes == b"\r\n") {
+                break end;
+            }
+            self.fill().await?;
+        };
+        let header = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+        let len: usize = header
+            .strip_prefix('$')
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| anyhow!("Unexpected snapshot header '{header}'"))?;
+        self.buffer.advance(end + 2);
+        while self.buffer.len() < len {
+            self.fill().await?;
+        }
+        Ok(self.buffer.split_to(len).freeze())
+    }
+
+    async fn fill(&mut self) -> anyhow::Result<()> {
+        if self.stream.read_buf(&mut self.buffer).await? == 0 {
+            bail!("The master closed the connection");
+        }
+        self.last_io = Instant::now();
+        Ok(())
+    }
+}
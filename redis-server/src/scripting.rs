@@ -0,0 +1,452 @@
+//! Lua scripts, executed by `EVAL` and `EVALSHA`.
+//!
+//! Just like in redis, scripts run atomically: no other command runs until the script is done.
+//! Every script runs in a fresh Lua state, so nothing a script leaves behind is seen by the next.
+//! A script that runs for longer than `lua-time-limit` gets other clients replied `BUSY`, and
+//! can be stopped with `SCRIPT KILL` as long as it didn't write yet.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value, VmState};
+use tokio::sync::Notify;
+
+use crate::{
+    cmd::{Command, Propagation, READ_ONLY},
+    connection::Connection,
+    db::Db,
+    frame::Frame,
+};
+
+/// How many Lua instructions run between two checks of the running time of a script.
+const HOOK_INSTRUCTIONS: u32 = 10_000;
+
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+/// The scripts loaded with `SCRIPT LOAD` or executed with `EVAL`, by their SHA1 digest.
+#[derive(Debug, Clone, Default)]
+pub struct Scripts {
+    inner: Arc<Mutex<HashMap<String, Bytes>>>,
+    running: Arc<Mutex<Option<Running>>>,
+    /// Notified when the running script finishes or becomes busy.
+    changed: Arc<Notify>,
+}
+
+/// The script being executed.
+#[derive(Debug)]
+struct Running {
+    /// When other clients start being replied `BUSY`, if ever.
+    deadline: Option<Instant>,
+    busy: bool,
+    /// Whether the script called a write command, after which it can't be killed.
+    wrote: bool,
+    killed: bool,
+}
+
+/// Marks the script as done when dropped.
+struct RunningGuard<'a>(&'a Scripts);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap() = None;
+        self.0.changed.notify_waiters();
+    }
+}
+
+impl Scripts {
+    /// Caches the script and returns its SHA1 digest.
+    pub fn load(&self, script: Bytes) -> String {
+        let sha = sha1_smol::Sha1::from(&script).digest().to_string();
+        self.inner.lock().unwrap().insert(sha.clone(), script);
+        sha
+    }
+
+    pub fn get(&self, sha: &str) -> Option<Bytes> {
+        self.inner.lock().unwrap().get(&sha.to_lowercase()).cloned()
+    }
+
+    pub fn contains(&self, sha: &str) -> bool {
+        self.inner.lock().unwrap().contains_key(&sha.to_lowercase())
+    }
+
+    pub fn flush(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+
+    /// Waits for the running script, if any, to finish. Fails with `BUSY` once it ran for
+    /// longer than `lua-time-limit`, whether it already had or does while waiting.
+    pub async fn wait(&self) -> io::Result<()> {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            // Registered before looking at the script, so that a change right after isn't missed.
+            changed.as_mut().enable();
+            match self.running.lock().unwrap().as_ref() {
+                None => return Ok(()),
+                Some(running) if running.busy => {
+                    return Err(io::Error::other(
+                        "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSCRIPT.",
+                    ));
+                }
+                Some(_) => {}
+            }
+            changed.await;
+        }
+    }
+
+    /// Stops the running script, unless it already wrote.
+    pub fn kill(&self) -> io::Result<()> {
+        match self.running.lock().unwrap().as_mut() {
+            None => Err(io::Error::other(
+                "NOTBUSY No scripts in execution right now.",
+            )),
+            Some(running) if running.wrote => Err(io::Error::other(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSCRIPT command.",
+            )),
+            Some(running) => {
+                running.killed = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn start(&self, time_limit: u64) -> RunningGuard<'_> {
+        let deadline = Some(time_limit)
+            .filter(|&limit| limit > 0)
+            // A limit too far in the future is as good as none.
+            .and_then(|limit| Instant::now().checked_add(Duration::from_millis(limit)));
+        *self.running.lock().unwrap() = Some(Running {
+            deadline,
+            busy: false,
+            wrote: false,
+            killed: false,
+        });
+        RunningGuard(self)
+    }
+
+    /// Called by the hook of the running script, which is stopped with an error once killed.
+    fn check(&self) -> mlua::Result<VmState> {
+        let mut running = self.running.lock().unwrap();
+        let Some(running) = running.as_mut() else {
+            return Ok(VmState::Continue);
+        };
+        if running.killed {
+            return Err(mlua::Error::runtime(KILLED));
+        }
+        if !running.busy
+            && running
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            running.busy = true;
+            self.changed.notify_waiters();
+        }
+        Ok(VmState::Continue)
+    }
+
+    /// Called before the running script executes a write command. Checked along with the kill
+    /// flag, so that a killed script can't write anymore.
+    fn write(&self) -> io::Result<()> {
+        let mut running = self.running.lock().unwrap();
+        match running.as_mut() {
+            Some(running) if running.killed => Err(io::Error::other(KILLED)),
+            Some(running) => {
+                running.wrote = true;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_killed(&self) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|running| running.killed)
+    }
+}
+
+/// Runs the script with the `KEYS` and `ARGV` tables, returning its result converted to a frame.
+pub fn run(
+    script: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    conn: &mut Connection,
+    db: &Db,
+) -> io::Result<Frame> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )
+    .map_err(lua_error)?;
+
+    let function = lua
+        .load(script)
+        .set_name("@user_script")
+        .into_function()
+        .map_err(|err| io::Error::other(format!("ERR Error compiling script: {err}")))?;
+
+    let scripts = db.scripts().clone();
+    let _running = db.scripts().start(db.config().lua_time_limit);
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+        move |_, _| scripts.check(),
+    )
+    .map_err(lua_error)?;
+
+    // Like in redis 7, a script that selects another database doesn't change the client's.
+    let db_index = conn.db_index();
+    let reply = lua
+        .scope(|scope| {
+            let globals = lua.globals();
+            // Scripts have no access to the file system.
+            globals.set("dofile", Value::Nil)?;
+            globals.set("loadfile", Value::Nil)?;
+            globals.set("KEYS", lua.create_sequence_from(strings(&lua, &keys)?)?)?;
+            globals.set("ARGV", lua.create_sequence_from(strings(&lua, &args)?)?)?;
+
+            let redis = lua.create_table()?;
+            redis.set(
+                "pcall",
+                scope.create_function_mut(|lua, args: MultiValue| {
+                    let reply = call(lua, args, conn, &db.select(conn.db_index()))
+                        .unwrap_or_else(|err| Frame::Error(err.to_string()));
+                    to_lua(lua, reply)
+                })?,
+            )?;
+            redis.set(
+                "error_reply",
+                lua.create_function(|lua, message: mlua::LuaString| {
+                    reply_table(lua, "err", message)
+                })?,
+            )?;
+            redis.set(
+                "status_reply",
+                lua.create_function(|lua, message: mlua::LuaString| {
+                    reply_table(lua, "ok", message)
+                })?,
+            )?;
+            globals.set("redis", redis)?;
+            // Errors of the command are raised as the `{err = ...}` table `redis.pcall`
+            // returns, so that they are replied as is if the script doesn't catch them.
+            lua.load(
+                r#"
+                function redis.call(...)
+                    local reply = redis.pcall(...)
+                    if type(reply) == "table" and reply.err then
+                        error(reply, 2)
+                    end
+                    return reply
+                end
+                "#,
+            )
+            .exec()?;
+
+            let pcall: mlua::Function = globals.get("pcall")?;
+            let (ok, result): (bool, Value) = pcall.call(function)?;
+            // The script may have caught the error raised by the hook.
+            if db.scripts().is_killed() {
+                return Ok(Frame::Error(KILLED.to_owned()));
+            }
+            if ok {
+                return Ok(from_lua(result));
+            }
+            let message = match &result {
+                Value::Table(table) => table.raw_get::<Option<String>>("err")?,
+                _ => None,
+            };
+            Ok(match message {
+                Some(message) => Frame::Error(message),
+                None => Frame::Error(format!(
+                    "ERR Error running script: {}",
+                    result.to_string().unwrap_or_default()
+                )),
+            })
+        })
+        .map_err(lua_error);
+    conn.set_db_index(db_index);
+
+    match reply? {
+        Frame::Error(message) => Err(io::Error::other(message)),
+        reply => Ok(reply),
+    }
+}
+
+/// Executes a command called by the script with `redis.call` or `redis.pcall`.
+fn call(lua: &Lua, args: MultiValue, conn: &mut Connection, db: &Db) -> io::Result<Frame> {
+    if args.is_empty() {
+        return Err(io::Error::other(
+            "ERR Please specify at least one argument for this redis lib call",
+        ));
+    }
+    let args = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => lua
+                .coerce_string(arg)
+                .ok()
+                .flatten()
+                .map(|arg| Frame::BulkString(Bytes::copy_from_slice(&arg.as_bytes()))),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            io::Error::other("ERR Lua redis lib command arguments must be strings or integers")
+        })?;
+
+    let frame = Frame::Array(args);
+    let logged_frame = frame.clone();
+    let command = Command::from_client_frame(frame, conn.user(), db)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    if !command.is_allowed_in_script() {
+        return Err(io::Error::other(
+            "ERR This Redis command is not allowed from script",
+        ));
+    }
+    if command.is_write() && db.is_read_only_replica() {
+        return Err(io::Error::other(READ_ONLY));
+    }
+    if command.is_write() {
+        db.scripts().write()?;
+    }
+
+    command.make_room(db)?;
+    let monitored = command.is_monitored();
+    let reply = match command.propagation() {
+        Propagation::Verbatim => db.log_verbatim(logged_frame.clone(), || {
+            command.execute_and_notify(conn, db, &logged_frame)
+        }),
+        _ => command.execute_and_notify(conn, db, &logged_frame),
+    };
+    let monitored = Some(&logged_frame).filter(|_| monitored);
+    db.clients().executed(db.index(), None, monitored);
+    match reply? {
+        Some(reply) => Ok(reply),
+        // Blocking commands don't block within a script, they time out right away instead.
+        None => {
+            conn.take_parked();
+            Ok(Frame::Null)
+        }
+    }
+}
+
+/// Converts a reply to a Lua value the way redis does, e.g. nil replies become `false`.
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value> {
+    let value = match frame {
+        Frame::SimpleString(status) => Value::Table(reply_table(lua, "ok", status)?),
+        Frame::Error(message) => Value::Table(reply_table(lua, "err", message)?),
+        Frame::Integer(integer) => Value::Integer(integer),
+        Frame::Boolean(boolean) => Value::Boolean(boolean),
+        Frame::Double(double) => Value::Number(double),
+        Frame::BulkString(bytes) | Frame::Verbatim { text: bytes, .. } => {
+            Value::String(lua.create_string(&bytes)?)
+        }
+        Frame::BigNumber(number) => Value::String(lua.create_string(number)?),
+        Frame::Null | Frame::NullBulkString | Frame::NullArray => Value::Boolean(false),
+        Frame::Array(frames) | Frame::Set(frames) | Frame::Push(frames) => Value::Table(
+            lua.create_sequence_from(
+                frames
+                    .into_iter()
+                    .map(|frame| to_lua(lua, frame))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+        // Maps are seen the way RESP2 clients see them, as a flat array.
+        Frame::Map(pairs) => to_lua(
+            lua,
+            Frame::Array(
+                pairs
+                    .into_iter()
+                    .flat_map(|(key, value)| [key, value])
+                    .collect(),
+            ),
+        )?,
+    };
+    Ok(value)
+}
+
+/// Converts what the script returned to a reply. Numbers are truncated to integers, and
+/// arrays stop at their first nil, just like in redis.
+fn from_lua(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(integer) => Frame::Integer(integer),
+        Value::Number(number) => Frame::Integer(number as i64),
+        Value::String(string) => Frame::BulkString(Bytes::copy_from_slice(&string.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Some(message)) = table.raw_get::<Option<String>>("err") {
+                return Frame::Error(message);
+            }
+            if let Ok(Some(status)) = table.raw_get::<Option<String>>("ok") {
+                return Frame::SimpleString(status);
+            }
+            Frame::Array(
+                table
+                    .sequence_values::<Value>()
+                    .map_while(Result::ok)
+                    .map(from_lua)
+                    .collect(),
+            )
+        }
+        _ => Frame::Null,
+    }
+}
+
+fn reply_table(lua: &Lua, field: &str, message: impl mlua::IntoLua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set(field, message)?;
+    Ok(table)
+}
+
+fn strings(lua: &Lua, values: &[Bytes]) -> mlua::Result<Vec<mlua::LuaString>> {
+    values
+        .iter()
+        .map(|value| lua.create_string(value))
+        .collect()
+}
+
+fn lua_error(err: mlua::Error) -> io::Error {
+    io::Error::other(format!("ERR {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use mlua::Lua;
+
+    use super::{from_lua, to_lua};
+    use crate::frame::Frame;
+
+    #[test]
+    fn test_lua_values_are_converted_like_redis() {
+        let lua = Lua::new();
+        let value = lua
+            .load(r#"return {1, 2.9, "x", true, false, {ok = "OK"}, nil, 3}"#)
+            .eval()
+            .unwrap();
+        assert_eq!(
+            from_lua(value),
+            Frame::Array(vec![
+                Frame::Integer(1),
+                Frame::Integer(2),
+                Frame::BulkString(Bytes::from("x")),
+                Frame::Integer(1),
+                Frame::Null,
+                Frame::SimpleString("OK".to_owned()),
+            ])
+        );
+
+        let reply = Frame::Array(vec![Frame::NullBulkString, Frame::Integer(7)]);
+        let value = to_lua(&lua, reply).unwrap();
+        assert_eq!(
+            from_lua(value),
+            Frame::Array(vec![Frame::Null, Frame::Integer(7)])
+        );
+    }
+}
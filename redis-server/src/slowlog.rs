@@ -0,0 +1,160 @@
+//! The commands that took longer than `slowlog-log-slower-than` to execute, which `SLOWLOG GET`
+//! lists. Only the most recent `slowlog-max-len` are kept.
+
+use std::{collections::VecDeque, net::SocketAddr, sync::Mutex, time::Duration};
+
+use bytes::Bytes;
+use chrono::Utc;
+
+use crate::{config::Config, frame::Frame};
+
+/// Like in redis, only the first arguments of a command, and the start of long arguments,
+/// are kept.
+const MAX_ARGS: usize = 32;
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Default)]
+pub struct SlowLog {
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    // The most recent entry first.
+    entries: VecDeque<Entry>,
+    next_id: u64,
+    // `None` when the log is disabled.
+    slower_than: Option<Duration>,
+    max_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u64,
+    /// When the command was executed, as a unix timestamp in seconds.
+    pub timestamp: i64,
+    pub duration: Duration,
+    pub args: Vec<Bytes>,
+    pub addr: Option<SocketAddr>,
+    pub name: Option<Bytes>,
+}
+
+impl SlowLog {
+    /// Applies `slowlog-log-slower-than` and `slowlog-max-len`, dropping the oldest entries if
+    /// there are too many.
+    pub fn configure(&self, config: &Config) {
+        let mut log = self.inner.lock().unwrap();
+        log.slower_than = u64::try_from(config.slowlog_log_slower_than)
+            .ok()
+            .map(Duration::from_micros);
+        log.max_len = config.slowlog_max_len;
+        let max_len = log.max_len;
+        log.entries.truncate(max_len);
+    }
+
+    /// Adds the command if it took longer than `slowlog-log-slower-than`.
+    pub fn log(
+        &self,
+        command: &Frame,
+        duration: Duration,
+        addr: Option<SocketAddr>,
+        name: Option<&Bytes>,
+    ) {
+        let mut log = self.inner.lock().unwrap();
+        if log
+            .slower_than
+            .is_none_or(|slower_than| duration < slower_than)
+        {
+            return;
+        }
+        let Frame::Array(frames) = command else {
+            return;
+        };
+
+        let mut args: Vec<_> = frames
+            .iter()
+            .take(if frames.len() > MAX_ARGS {
+                MAX_ARGS - 1
+            } else {
+                MAX_ARGS
+            })
+            .map(|frame| match frame {
+                Frame::BulkString(arg) if arg.len() > MAX_ARG_LEN => Bytes::from(format!(
+                    "{}... ({} more bytes)",
+                    String::from_utf8_lossy(&arg[..MAX_ARG_LEN]),
+                    arg.len() - MAX_ARG_LEN
+                )),
+                Frame::BulkString(arg) => arg.clone(),
+                Frame::SimpleString(arg) => Bytes::from(arg.clone()),
+                _ => Bytes::new(),
+            })
+            .collect();
+        if frames.len() > MAX_ARGS {
+            args.push(Bytes::from(format!(
+                "... ({} more arguments)",
+                frames.len() - MAX_ARGS + 1
+            )));
+        }
+
+        let id = log.next_id;
+        log.next_id += 1;
+        log.entries.push_front(Entry {
+            id,
+            timestamp: Utc::now().timestamp(),
+            duration,
+            args,
+            addr,
+            name: name.cloned(),
+        });
+        let max_len = log.max_len;
+        log.entries.truncate(max_len);
+    }
+
+    /// The `count` most recent entries, or all of them.
+    pub fn get(&self, count: Option<usize>) -> Vec<Entry> {
+        let log = self.inner.lock().unwrap();
+        let count = count.unwrap_or(log.entries.len());
+        log.entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every entry. Ids keep growing.
+    pub fn reset(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::SlowLog;
+    use crate::{config::Config, frame::Frame};
+
+    #[test]
+    fn test_long_commands_are_truncated() {
+        let slowlog = SlowLog::default();
+        slowlog.configure(&Config {
+            slowlog_log_slower_than: 0,
+            ..Config::default()
+        });
+        let mut args = vec![Frame::BulkString(Bytes::from("a".repeat(130)))];
+        args.extend((0..40).map(|i| Frame::BulkString(Bytes::from(i.to_string()))));
+        slowlog.log(&Frame::Array(args), Duration::ZERO, None, None);
+
+        let args = &slowlog.get(None)[0].args;
+        assert_eq!(args.len(), 32);
+        assert_eq!(args[0], format!("{}... (2 more bytes)", "a".repeat(128)));
+        assert_eq!(args[30], "29");
+        assert_eq!(args[31], "... (10 more arguments)");
+    }
+}
@@ -0,0 +1,239 @@
+//! Client side caching: the clients that turned on `CLIENT TRACKING` are told when keys they
+//! may have cached change, so that they can drop them.
+//!
+//! By default, the server remembers the keys each client read, and tells it about those only,
+//! once: a key is forgotten until the client reads it again. In broadcasting mode, clients are
+//! told about every key starting with one of their prefixes instead, whether they read it or not.
+//!
+//! Like with redis, keys are tracked by name whatever database they are in, and the messages
+//! are pushed to the clients with RESP3.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::frame::Frame;
+
+/// The clients tracking keys, and the keys they read.
+#[derive(Debug, Clone, Default)]
+pub struct Tracking {
+    inner: Arc<Mutex<Table>>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    clients: HashMap<u64, Client>,
+    // The clients in the default mode that read each key since it last changed.
+    keys: HashMap<Bytes, HashSet<u64>>,
+}
+
+#[derive(Debug)]
+struct Client {
+    sender: UnboundedSender<Frame>,
+    options: TrackingOptions,
+}
+
+/// How a client tracks keys, set with `CLIENT TRACKING ON`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    /// Whether the client is told about the keys matching `prefixes` rather than the ones
+    /// it read.
+    pub bcast: bool,
+    /// The prefixes of the keys the client is told about in broadcasting mode. Every key
+    /// matches when there are none.
+    pub prefixes: Vec<Bytes>,
+    /// Whether the client isn't told about the keys it changed itself.
+    pub noloop: bool,
+}
+
+impl Tracking {
+    /// Whether no client is tracking keys, in which case changes don't need to be recorded.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().clients.is_empty()
+    }
+
+    /// Tells the clients tracking the keys that they changed, unless `by` them and they asked
+    /// not to be told about their own changes. Every client gets a single message.
+    pub fn invalidate(&self, keys: &[String], by: Option<u64>) {
+        let mut table = self.inner.lock().unwrap();
+        if table.clients.is_empty() {
+            return;
+        }
+        let mut invalidated: HashMap<u64, Vec<Frame>> = HashMap::new();
+        for key in keys {
+            let key = Bytes::from(key.clone());
+            let readers = table.keys.remove(&key).unwrap_or_default();
+            let broadcast = table.clients.iter().filter(|(_, client)| {
+                client.options.bcast
+                    && (client.options.prefixes.is_empty()
+                        || client
+                            .options
+                            .prefixes
+                            .iter()
+                            .any(|prefix| key.starts_with(prefix)))
+            });
+            let ids: Vec<_> = broadcast.map(|(id, _)| *id).chain(readers).collect();
+            for id in ids {
+                let noloop = table
+                    .clients
+                    .get(&id)
+                    .is_some_and(|client| client.options.noloop);
+                if !(noloop && by == Some(id)) {
+                    invalidated
+                        .entry(id)
+                        .or_default()
+                        .push(Frame::BulkString(key.clone()));
+                }
+            }
+        }
+        for (id, keys) in invalidated {
+            if let Some(client) = table.clients.get(&id) {
+                let _ = client.sender.send(invalidation(Frame::Array(keys)));
+            }
+        }
+    }
+
+    /// Tells every client tracking keys to drop all of them, once the databases were flushed.
+    pub fn invalidate_all(&self) {
+        let mut table = self.inner.lock().unwrap();
+        table.keys.clear();
+        for client in table.clients.values() {
+            let _ = client.sender.send(invalidation(Frame::Null));
+        }
+    }
+}
+
+/// The state of a connection that turned on tracking. It stops once this is dropped.
+#[derive(Debug)]
+pub struct Tracker {
+    id: u64,
+    tracking: Tracking,
+    options: TrackingOptions,
+    receiver: UnboundedReceiver<Frame>,
+}
+
+impl Tracker {
+    pub fn new(id: u64, tracking: Tracking, options: TrackingOptions) -> Self {
+        let (sender, receiver) = unbounded_channel();
+        let client = Client {
+            sender,
+            options: options.clone(),
+        };
+        tracking.inner.lock().unwrap().clients.insert(id, client);
+        Self {
+            id,
+            tracking,
+            options,
+            receiver,
+        }
+    }
+
+    pub fn options(&self) -> &TrackingOptions {
+        &self.options
+    }
+
+    /// Remembers that the client read the keys, so that it is told once they change. Clients
+    /// in broadcasting mode are told about keys by their prefixes instead.
+    pub fn track(&self, keys: Vec<Bytes>) {
+        if self.options.bcast || keys.is_empty() {
+            return;
+        }
+        let mut table = self.tracking.inner.lock().unwrap();
+        for key in keys {
+            table.keys.entry(key).or_default().insert(self.id);
+        }
+    }
+
+    /// Waits for the next invalidation message.
+    pub async fn receive(&mut self) -> Frame {
+        // The table holds the sender until we are dropped.
+        self.receiver
+            .recv()
+            .await
+            .expect("the table holds a sender")
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        let mut table = self.tracking.inner.lock().unwrap();
+        table.clients.remove(&self.id);
+        if !self.options.bcast {
+            table.keys.retain(|_, readers| {
+                readers.remove(&self.id);
+                !readers.is_empty()
+            });
+        }
+    }
+}
+
+/// The message pushed to a client about the keys, or `Null` for every key.
+fn invalidation(keys: Frame) -> Frame {
+    Frame::Push(vec![
+        Frame::BulkString(Bytes::from_static(b"invalidate")),
+        keys,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Tracker, Tracking, TrackingOptions};
+    use crate::frame::Frame;
+
+    fn invalidated(keys: &[&str]) -> Frame {
+        Frame::Push(vec![
+            Frame::BulkString(Bytes::from_static(b"invalidate")),
+            Frame::Array(
+                keys.iter()
+                    .map(|key| Frame::BulkString(Bytes::from(key.to_string())))
+                    .collect(),
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_clients_are_told_about_the_keys_they_track() {
+        let tracking = Tracking::default();
+        let mut reader = Tracker::new(1, tracking.clone(), TrackingOptions::default());
+        let mut broadcast = Tracker::new(
+            2,
+            tracking.clone(),
+            TrackingOptions {
+                bcast: true,
+                prefixes: vec![Bytes::from("user:")],
+                noloop: true,
+            },
+        );
+        reader.track(vec![Bytes::from("user:1"), Bytes::from("other")]);
+
+        tracking.invalidate(&["user:1".to_owned(), "user:2".to_owned()], Some(1));
+        assert_eq!(reader.receive().await, invalidated(&["user:1"]));
+        assert_eq!(
+            broadcast.receive().await,
+            invalidated(&["user:1", "user:2"])
+        );
+
+        // Keys are only invalidated once until they are read again, and clients can skip their
+        // own changes.
+        tracking.invalidate(&["user:1".to_owned(), "other".to_owned()], Some(2));
+        assert_eq!(reader.receive().await, invalidated(&["other"]));
+        assert!(broadcast.receiver.try_recv().is_err());
+
+        drop(reader);
+        assert!(tracking.inner.lock().unwrap().keys.is_empty());
+        tracking.invalidate_all();
+        assert_eq!(
+            broadcast.receive().await,
+            Frame::Push(vec![
+                Frame::BulkString(Bytes::from_static(b"invalidate")),
+                Frame::Null
+            ])
+        );
+    }
+}